@@ -0,0 +1,504 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use actix::Addr;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    game::{chess_piece::Color, time_control::TimeControl, GameResult, Variant},
+    rating::RatingPool,
+    utils::error::{ARENA_OVER_ERROR, NOT_IN_ARENA_ERROR},
+    ws::{ArenaBroadcastMessage, ArenaConnection},
+};
+
+const WIN_POINTS: u32 = 2;
+const DRAW_POINTS: u32 = 1;
+// Wins in a row after which a player is on fire and scores double
+const STREAK_LENGTH: u32 = 2;
+// Extra point for winning a berserked game
+const BERSERK_BONUS: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+impl Outcome {
+    fn of(result: GameResult, color: Color) -> Outcome {
+        if result == GameResult::Draw {
+            Outcome::Draw
+        } else if result == GameResult::won_by(color) {
+            Outcome::Win
+        } else {
+            Outcome::Loss
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArenaPlayer {
+    pub user_id: String,
+    pub rating: f64,
+    pub score: u32,
+    // wins in a row
+    pub streak: u32,
+    // the points of every finished game in order
+    pub sheet: Vec<u32>,
+    pub berserks: u32,
+    pub withdrawn: bool,
+    whites: u32,
+    last_opponent: Option<String>,
+}
+impl ArenaPlayer {
+    pub fn on_fire(&self) -> bool {
+        self.streak >= STREAK_LENGTH
+    }
+    fn finish_game(&mut self, outcome: Outcome, berserked: bool) {
+        let multiplier = if self.on_fire() { 2 } else { 1 };
+        let points = match outcome {
+            Outcome::Win if berserked => WIN_POINTS * multiplier + BERSERK_BONUS,
+            Outcome::Win => WIN_POINTS * multiplier,
+            Outcome::Draw => DRAW_POINTS * multiplier,
+            Outcome::Loss => 0,
+        };
+
+        self.score += points;
+        self.sheet.push(points);
+        self.streak = match outcome {
+            Outcome::Win => self.streak + 1,
+            _ => 0,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArenaGame {
+    pub white: String,
+    pub black: String,
+    pub white_berserk: bool,
+    pub black_berserk: bool,
+}
+impl ArenaGame {
+    pub fn seat(&self, user_id: &str) -> Option<Color> {
+        if self.white == user_id {
+            Some(Color::WHITE)
+        } else if self.black == user_id {
+            Some(Color::BLACK)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArenaStanding {
+    pub rank: usize,
+    pub user_id: String,
+    pub score: u32,
+    pub streak: u32,
+    pub on_fire: bool,
+    pub sheet: Vec<u32>,
+    pub berserks: u32,
+    pub playing: bool,
+    pub withdrawn: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArenaStatus {
+    pub arena_id: String,
+    pub name: String,
+    pub time_control: TimeControl,
+    pub variant: Variant,
+    pub rated: bool,
+    pub seconds_left: u64,
+    pub finished: bool,
+    pub standings: Vec<ArenaStanding>,
+}
+
+pub struct ArenaMember {
+    // None for connections that only watch the standings
+    pub user_id: Option<String>,
+    pub addr: Addr<ArenaConnection>,
+}
+
+/// A time-boxed tournament in which players are paired again as soon as they
+/// finish a game, for as long as the arena runs.
+pub struct Arena {
+    pub id: Uuid,
+    pub name: String,
+    pub time_control: TimeControl,
+    pub variant: Variant,
+    pub rated: bool,
+    pub ends_at: Instant,
+    // in the order they joined
+    pub players: Vec<ArenaPlayer>,
+    // players waiting for their next game
+    pub queue: Vec<String>,
+    // games in progress by game id
+    pub games: HashMap<String, ArenaGame>,
+    pub members: HashMap<Uuid, ArenaMember>,
+}
+
+impl Arena {
+    pub fn new(id: Uuid, name: String, time_control: TimeControl, duration: Duration) -> Arena {
+        Arena {
+            id,
+            name,
+            time_control,
+            variant: Variant::Standard,
+            rated: true,
+            ends_at: Instant::now() + duration,
+            players: vec![],
+            queue: vec![],
+            games: HashMap::new(),
+            members: HashMap::new(),
+        }
+    }
+    pub fn pool(&self) -> RatingPool {
+        RatingPool {
+            category: self.time_control.category(),
+            variant: self.variant,
+        }
+    }
+    pub fn is_over(&self, now: Instant) -> bool {
+        now >= self.ends_at
+    }
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.is_over(now) && self.games.is_empty()
+    }
+    pub fn is_playing(&self, user_id: &str) -> bool {
+        self.games.values().any(|game| game.seat(user_id).is_some())
+    }
+    fn player_mut(&mut self, user_id: &str) -> Option<&mut ArenaPlayer> {
+        self.players
+            .iter_mut()
+            .find(|player| player.user_id == user_id)
+    }
+    /// Adds a player to the arena, or brings a withdrawn one back, and queues
+    /// them for pairing.
+    pub fn join(&mut self, user_id: &str, rating: f64, now: Instant) -> Result<(), &'static str> {
+        if self.is_over(now) {
+            return Err(ARENA_OVER_ERROR);
+        }
+
+        match self.player_mut(user_id) {
+            Some(player) => player.withdrawn = false,
+            None => self.players.push(ArenaPlayer {
+                user_id: user_id.to_string(),
+                rating,
+                score: 0,
+                streak: 0,
+                sheet: vec![],
+                berserks: 0,
+                withdrawn: false,
+                whites: 0,
+                last_opponent: None,
+            }),
+        }
+        if !self.is_playing(user_id) && !self.queue.iter().any(|queued| queued == user_id) {
+            self.queue.push(user_id.to_string());
+        }
+        Ok(())
+    }
+    /// Stops pairing a player. Their points stay in the standings.
+    pub fn withdraw(&mut self, user_id: &str) -> Result<(), &'static str> {
+        match self.player_mut(user_id) {
+            None => return Err(NOT_IN_ARENA_ERROR),
+            Some(player) => player.withdrawn = true,
+        }
+        self.queue.retain(|queued| queued != user_id);
+        Ok(())
+    }
+    /// Pairs the waiting players with each other, closest in score first, and
+    /// returns the boards as (white, black). Everyone left over keeps waiting.
+    pub fn pair(&mut self, now: Instant) -> Vec<(String, String)> {
+        if self.is_over(now) {
+            return vec![];
+        }
+
+        let mut waiting: Vec<&ArenaPlayer> = self
+            .players
+            .iter()
+            .filter(|player| self.queue.contains(&player.user_id))
+            .collect();
+        waiting.sort_by(|a, b| b.score.cmp(&a.score).then(b.rating.total_cmp(&a.rating)));
+
+        let mut boards = vec![];
+        while waiting.len() >= 2 {
+            let player = waiting.remove(0);
+            // avoid an immediate rematch unless there is no one else
+            let opponent = waiting
+                .iter()
+                .position(|opponent| player.last_opponent.as_ref() != Some(&opponent.user_id))
+                .unwrap_or(0);
+            let opponent = waiting.remove(opponent);
+
+            if player.whites <= opponent.whites {
+                boards.push((player.user_id.clone(), opponent.user_id.clone()));
+            } else {
+                boards.push((opponent.user_id.clone(), player.user_id.clone()));
+            }
+        }
+
+        self.queue
+            .retain(|queued| !boards.iter().any(|(w, b)| w == queued || b == queued));
+        boards
+    }
+    pub fn start_game(&mut self, game_id: String, white: &str, black: &str) {
+        if let Some(player) = self.player_mut(white) {
+            player.whites += 1;
+            player.last_opponent = Some(black.to_string());
+        }
+        if let Some(player) = self.player_mut(black) {
+            player.last_opponent = Some(white.to_string());
+        }
+        self.games.insert(
+            game_id,
+            ArenaGame {
+                white: white.to_string(),
+                black: black.to_string(),
+                white_berserk: false,
+                black_berserk: false,
+            },
+        );
+    }
+    pub fn set_berserk(&mut self, game_id: &str, color: Color) {
+        let game = match self.games.get_mut(game_id) {
+            None => return,
+            Some(game) => game,
+        };
+        let user_id = match color {
+            Color::WHITE => {
+                game.white_berserk = true;
+                game.white.clone()
+            }
+            Color::BLACK => {
+                game.black_berserk = true;
+                game.black.clone()
+            }
+        };
+        if let Some(player) = self.player_mut(&user_id) {
+            player.berserks += 1;
+        }
+    }
+    /// Scores a finished game and puts both players back in the queue, unless
+    /// they withdrew or the arena is over.
+    pub fn record_result(&mut self, game_id: &str, result: GameResult, now: Instant) {
+        let game = match self.games.remove(game_id) {
+            None => return,
+            Some(game) => game,
+        };
+
+        let over = self.is_over(now);
+        for (user_id, color, berserked) in [
+            (&game.white, Color::WHITE, game.white_berserk),
+            (&game.black, Color::BLACK, game.black_berserk),
+        ] {
+            let player = match self.player_mut(user_id) {
+                None => continue,
+                Some(player) => player,
+            };
+            player.finish_game(Outcome::of(result, color), berserked);
+            if !player.withdrawn && !over {
+                self.queue.push(user_id.clone());
+            }
+        }
+    }
+    pub fn standings(&self) -> Vec<ArenaStanding> {
+        let mut players: Vec<&ArenaPlayer> = self.players.iter().collect();
+        // ties stay in the order the players joined
+        players.sort_by_key(|player| Reverse(player.score));
+
+        players
+            .into_iter()
+            .enumerate()
+            .map(|(index, player)| ArenaStanding {
+                rank: index + 1,
+                user_id: player.user_id.clone(),
+                score: player.score,
+                streak: player.streak,
+                on_fire: player.on_fire(),
+                sheet: player.sheet.clone(),
+                berserks: player.berserks,
+                playing: self.is_playing(&player.user_id),
+                withdrawn: player.withdrawn,
+            })
+            .collect()
+    }
+    pub fn status(&self, now: Instant) -> ArenaStatus {
+        ArenaStatus {
+            arena_id: self.id.to_string(),
+            name: self.name.clone(),
+            time_control: self.time_control,
+            variant: self.variant,
+            rated: self.rated,
+            seconds_left: self.ends_at.saturating_duration_since(now).as_secs(),
+            finished: self.is_finished(now),
+            standings: self.standings(),
+        }
+    }
+    pub fn broadcast<T: Serialize>(&self, message: &T) {
+        let text = serde_json::to_string(message).unwrap();
+        self.members
+            .values()
+            .for_each(|e| e.addr.do_send(ArenaBroadcastMessage::new(text.clone())));
+    }
+    pub fn send_to_user<T: Serialize>(&self, user_id: &str, message: &T) {
+        let text = serde_json::to_string(message).unwrap();
+        self.members
+            .values()
+            .filter(|e| e.user_id.as_deref() == Some(user_id))
+            .for_each(|e| e.addr.do_send(ArenaBroadcastMessage::new(text.clone())));
+    }
+}
+
+#[cfg(test)]
+mod test_arena {
+    use std::time::{Duration, Instant};
+
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, time_control::TimeControl, GameResult};
+
+    use super::Arena;
+
+    fn arena(players: &[&str]) -> Arena {
+        let mut arena = Arena::new(
+            Uuid::new_v4(),
+            "Test arena".to_string(),
+            TimeControl {
+                initial_secs: 180,
+                increment_secs: 0,
+            },
+            Duration::from_secs(3600),
+        );
+        for player in players {
+            arena.join(player, 1500.0, Instant::now()).unwrap();
+        }
+        arena
+    }
+
+    fn score(arena: &Arena, user_id: &str) -> u32 {
+        arena
+            .players
+            .iter()
+            .find(|player| player.user_id == user_id)
+            .unwrap()
+            .score
+    }
+
+    #[test]
+    fn test_streak_doubles_points() {
+        let mut arena = arena(&["alice", "bob"]);
+        let now = Instant::now();
+        for (round, result) in [
+            GameResult::WhiteWon,
+            GameResult::BlackWon,
+            GameResult::WhiteWon,
+            GameResult::Draw,
+            GameResult::Draw,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (white, black) = arena.pair(now).pop().unwrap();
+            // keep alice on white so the results read from her side
+            let result = match (white.as_str(), result) {
+                ("alice", result) => result,
+                (_, GameResult::WhiteWon) => GameResult::BlackWon,
+                (_, GameResult::BlackWon) => GameResult::WhiteWon,
+                (_, result) => result,
+            };
+            let game_id = round.to_string();
+            arena.start_game(game_id.clone(), &white, &black);
+            arena.record_result(&game_id, result, now);
+        }
+
+        // win, loss, win, draw, draw: the streak broke with the loss
+        assert_eq!(score(&arena, "alice"), 2 + 2 + 1 + 1);
+
+        let mut arena = arena_with_wins(3);
+        // two wins, then a third one worth double
+        assert_eq!(score(&arena, "alice"), 2 + 2 + 4);
+        assert!(arena.players[0].on_fire());
+        let (white, black) = arena.pair(now).pop().unwrap();
+        arena.start_game("draw".to_string(), &white, &black);
+        arena.record_result("draw", GameResult::Draw, now);
+        assert_eq!(score(&arena, "alice"), 2 + 2 + 4 + 2);
+        assert!(!arena.players[0].on_fire());
+    }
+
+    fn arena_with_wins(wins: usize) -> Arena {
+        let mut arena = arena(&["alice", "bob"]);
+        let now = Instant::now();
+        for round in 0..wins {
+            let (white, black) = arena.pair(now).pop().unwrap();
+            let result = if white == "alice" {
+                GameResult::WhiteWon
+            } else {
+                GameResult::BlackWon
+            };
+            let game_id = round.to_string();
+            arena.start_game(game_id.clone(), &white, &black);
+            arena.record_result(&game_id, result, now);
+        }
+        arena
+    }
+
+    #[test]
+    fn test_berserk_bonus() {
+        let mut arena = arena(&["alice", "bob"]);
+        let now = Instant::now();
+        let (white, black) = arena.pair(now).pop().unwrap();
+        arena.start_game("game".to_string(), &white, &black);
+        arena.set_berserk("game", Color::WHITE);
+        arena.set_berserk("game", Color::BLACK);
+        arena.record_result("game", GameResult::WhiteWon, now);
+
+        assert_eq!(score(&arena, &white), 3);
+        assert_eq!(score(&arena, &black), 0);
+    }
+
+    #[test]
+    fn test_pairs_by_score_and_avoids_rematches() {
+        let mut arena = arena(&["alice", "bob", "carol", "dave"]);
+        let now = Instant::now();
+        let boards = arena.pair(now);
+        assert_eq!(boards.len(), 2);
+        assert!(arena.queue.is_empty());
+
+        for (index, (white, black)) in boards.iter().enumerate() {
+            arena.start_game(index.to_string(), white, black);
+        }
+        arena.record_result("0", GameResult::WhiteWon, now);
+        // only two players are waiting, so they play even though
+        // the other game is still running
+        let (white, black) = arena.pair(now).pop().unwrap();
+        assert_eq!((&white, &black), (&boards[0].1, &boards[0].0));
+        arena.start_game("2".to_string(), &white, &black);
+
+        arena.record_result("1", GameResult::WhiteWon, now);
+        arena.record_result("2", GameResult::Draw, now);
+        // the two winners of the first round meet rather than replaying
+        // the game that just finished
+        for (white, black) in arena.pair(now) {
+            let opponents = [white, black];
+            assert!(opponents.contains(&boards[0].0) || opponents.contains(&boards[0].1));
+            assert!(opponents.contains(&boards[1].0) || opponents.contains(&boards[1].1));
+        }
+    }
+
+    #[test]
+    fn test_no_pairing_after_the_end() {
+        let mut arena = arena(&["alice", "bob"]);
+        let later = Instant::now() + Duration::from_secs(7200);
+        assert!(arena.pair(later).is_empty());
+        assert!(arena.join("carol", 1500.0, later).is_err());
+        assert!(arena.is_finished(later));
+    }
+}
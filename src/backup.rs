@@ -0,0 +1,600 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use actix_web::cookie::time::{OffsetDateTime, UtcOffset};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    correspondence::{format_timestamp, parse_timestamp},
+    game::{
+        chess_piece::Color,
+        eco::Opening,
+        event::{GameEvent, SNAPSHOT_INTERVAL},
+        time_control::TimeControl,
+        Game, GameResult, Variant,
+    },
+    server::Server,
+    utils::error::{INVALID_EXPORT_ERROR, INVALID_PGN_ERROR, INVALID_SAN_ERROR},
+};
+
+// How many games are read from the database at a time while exporting
+pub const EXPORT_PAGE_SIZE: u32 = 100;
+const PGN_LINE_WIDTH: usize = 80;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Pgn,
+    // one JSON object per game and line
+    #[default]
+    Ndjson,
+}
+impl Format {
+    /// `.pgn` files hold PGN, anything else NDJSON.
+    pub fn of_path(path: &str) -> Format {
+        if path.ends_with(".pgn") {
+            Format::Pgn
+        } else {
+            Format::Ndjson
+        }
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Pgn => "application/x-chess-pgn",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// A game with everything needed to restore it, moves in UCI notation.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ExportedGame {
+    pub game_id: String,
+    pub admin_color: String,
+    pub white_user_id: Option<String>,
+    pub black_user_id: Option<String>,
+    pub variant: Option<String>,
+    pub time_control: Option<String>,
+    pub days_per_move: Option<u32>,
+    pub rated: bool,
+    pub private: bool,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+    pub created_at: String,
+    pub moves: Vec<String>,
+}
+
+/// A move of an imported game together with what is stored alongside it.
+pub struct ReplayedMove {
+    pub ply: u32,
+    pub turn: u32,
+    pub notation: String,
+    pub player: &'static str,
+    pub event: GameEvent,
+    pub position_hash: u64,
+    pub opening: Option<Opening>,
+    pub snapshot: Option<String>,
+}
+
+fn parse_uci(uci: &str) -> Result<(&str, &str, char), &'static str> {
+    match (uci.get(..2), uci.get(2..4), uci.len()) {
+        (Some(from), Some(to), 4) => Ok((from, to, ' ')),
+        (Some(from), Some(to), 5) => Ok((from, to, uci[4..].to_ascii_uppercase().pop().unwrap())),
+        _ => Err(INVALID_SAN_ERROR),
+    }
+}
+
+impl ExportedGame {
+    /// The game before its first move.
+    pub fn setup(&self) -> Result<Game, &'static str> {
+        let game_id = Uuid::parse_str(&self.game_id).map_err(|_| INVALID_EXPORT_ERROR)?;
+        let admin_color = Color::from_name(&self.admin_color).ok_or(INVALID_EXPORT_ERROR)?;
+        let mut game = Game::new(game_id, admin_color);
+        game.players.white = self.white_user_id.clone();
+        game.players.black = self.black_user_id.clone();
+        game.variant = match self.variant.as_deref() {
+            None => Variant::default(),
+            Some(name) => Variant::from_name(name).ok_or(INVALID_EXPORT_ERROR)?,
+        };
+        let time_control = match self.time_control.as_deref() {
+            None => None,
+            Some(name) => Some(TimeControl::from_name(name).ok_or(INVALID_EXPORT_ERROR)?),
+        };
+        game.set_time_control(time_control);
+        game.days_per_move = self.days_per_move;
+        game.rated = self.rated;
+        game.private = self.private;
+        Ok(game)
+    }
+    /// Plays every move again, so that only legal games are restored. A game
+    /// that ends in checkmate has to carry the matching result.
+    pub fn replay(&self) -> Result<(Game, Vec<ReplayedMove>), &'static str> {
+        parse_timestamp(&self.created_at).ok_or(INVALID_EXPORT_ERROR)?;
+        let mut game = self.setup()?;
+        let result = match self.result.as_deref() {
+            None => None,
+            Some(name) => Some(GameResult::from_name(name).ok_or(INVALID_EXPORT_ERROR)?),
+        };
+
+        let mut replayed = vec![];
+        for uci in &self.moves {
+            let (from, to, promotion) = parse_uci(uci)?;
+            let player = match game.next_to_move {
+                Color::WHITE => "WHITE",
+                Color::BLACK => "BLACK",
+            };
+            game.validate_and_make_move(from, to, promotion)?;
+            let ply = game.ply();
+            replayed.push(ReplayedMove {
+                ply,
+                turn: game.turn_number,
+                notation: game.previous_move.clone(),
+                player,
+                event: GameEvent::Move {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    promotion,
+                },
+                position_hash: game.zobrist(),
+                opening: game.opening,
+                snapshot: (ply % SNAPSHOT_INTERVAL == 0).then(|| game.to_fen()),
+            });
+        }
+        if game.game_result.is_some() && game.game_result != result {
+            return Err(INVALID_EXPORT_ERROR);
+        }
+        Ok((game, replayed))
+    }
+    pub fn render(&self, format: Format) -> Result<String, &'static str> {
+        match format {
+            Format::Pgn => self.to_pgn(),
+            Format::Ndjson => Ok(format!("{}\n", serde_json::to_string(self).unwrap())),
+        }
+    }
+    pub fn to_pgn(&self) -> Result<String, &'static str> {
+        let mut game = self.setup()?;
+        let mut movetext = vec![];
+        for uci in &self.moves {
+            let (from, to, promotion) = parse_uci(uci)?;
+            let san = game.san(from, to, promotion)?;
+            match game.next_to_move {
+                Color::WHITE => movetext.push(format!("{}. {}", game.turn_number + 1, san)),
+                Color::BLACK if movetext.is_empty() => {
+                    movetext.push(format!("{}... {}", game.turn_number, san))
+                }
+                Color::BLACK => movetext.push(san),
+            }
+            game.make_move(from, to, promotion);
+        }
+        let result = self.result.clone().unwrap_or("*".to_string());
+        movetext.push(result.clone());
+
+        let created_at = parse_timestamp(&self.created_at).ok_or(INVALID_EXPORT_ERROR)?;
+        // YYYY-MM-DDTHH:MM:SS in UTC
+        let created_at = format_timestamp(created_at.to_offset(UtcOffset::UTC));
+        let date = created_at[..10].replace('-', ".");
+        let player = |user_id: &Option<String>| user_id.clone().unwrap_or("?".to_string());
+        let mut tags = vec![
+            (
+                "Event",
+                if self.rated {
+                    "Rated game"
+                } else {
+                    "Casual game"
+                }
+                .to_string(),
+            ),
+            ("Site", "?".to_string()),
+            ("Date", date.clone()),
+            ("Round", "-".to_string()),
+            ("White", player(&self.white_user_id)),
+            ("Black", player(&self.black_user_id)),
+            ("Result", result),
+            ("UTCDate", date),
+            ("UTCTime", created_at[11..19].to_string()),
+            ("GameId", self.game_id.clone()),
+            ("AdminColor", self.admin_color.clone()),
+            (
+                "Variant",
+                self.variant.clone().unwrap_or(Variant::default().to_str()),
+            ),
+            (
+                "TimeControl",
+                self.time_control.clone().unwrap_or("-".to_string()),
+            ),
+        ];
+        let optional = [
+            (
+                "DaysPerMove",
+                self.days_per_move.map(|days| days.to_string()),
+            ),
+            ("Private", self.private.then(|| "true".to_string())),
+            ("Termination", self.termination.clone()),
+            ("ECO", self.eco.clone()),
+            ("Opening", self.opening.clone()),
+        ];
+        tags.extend(
+            optional
+                .into_iter()
+                .filter_map(|(tag, value)| Some((tag, value?))),
+        );
+
+        let mut pgn = String::new();
+        for (tag, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", tag, value));
+        }
+        pgn.push('\n');
+        let mut line = String::new();
+        for token in movetext {
+            if !line.is_empty() && line.len() + token.len() + 1 > PGN_LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+        Ok(pgn)
+    }
+}
+
+/// Reads the games of a PGN or NDJSON file. Every game is parsed on its own,
+/// so one broken game does not keep the others from being imported.
+pub fn parse_games(text: &str, format: Format) -> Vec<Result<ExportedGame, &'static str>> {
+    match format {
+        Format::Ndjson => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|_| INVALID_EXPORT_ERROR))
+            .collect(),
+        Format::Pgn => parse_pgn(text),
+    }
+}
+
+fn parse_pgn(text: &str) -> Vec<Result<ExportedGame, &'static str>> {
+    let mut games = vec![];
+    let mut tags: Vec<Option<(String, String)>> = vec![];
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('%') {
+            continue;
+        }
+        if line.starts_with('[') {
+            // tags after moves start the next game
+            if !movetext.trim().is_empty() {
+                games.push(pgn_game(&tags, &movetext));
+                tags.clear();
+                movetext.clear();
+            }
+            tags.push(parse_tag(line));
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !tags.is_empty() || !movetext.trim().is_empty() {
+        games.push(pgn_game(&tags, &movetext));
+    }
+    games
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+// the moves and the result of a movetext, without comments, variations,
+// move numbers and annotations
+fn movetext_tokens(movetext: &str) -> Vec<String> {
+    let mut cleaned = String::new();
+    let (mut in_comment, mut in_line_comment, mut variation_depth) = (false, false, 0);
+    for ch in movetext.chars() {
+        match ch {
+            '\n' if in_line_comment => in_line_comment = false,
+            _ if in_line_comment => (),
+            '}' if in_comment => in_comment = false,
+            _ if in_comment => (),
+            '{' => in_comment = true,
+            ';' => in_line_comment = true,
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            _ if variation_depth > 0 => (),
+            _ => cleaned.push(ch),
+        }
+    }
+    cleaned
+        .split_whitespace()
+        .map(|token| match token {
+            "1-0" | "0-1" | "1/2-1/2" | "*" => token,
+            _ => token.trim_start_matches(|ch: char| ch.is_ascii_digit() || ch == '.'),
+        })
+        .filter(|token| !token.is_empty() && !token.starts_with('$'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn pgn_game(
+    tags: &[Option<(String, String)>],
+    movetext: &str,
+) -> Result<ExportedGame, &'static str> {
+    let tags = tags
+        .iter()
+        .cloned()
+        .collect::<Option<Vec<(String, String)>>>()
+        .ok_or(INVALID_PGN_ERROR)?;
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty() && value != "?" && value != "-")
+    };
+    // games from another position cannot be restored
+    if tag("FEN").is_some() {
+        return Err(INVALID_PGN_ERROR);
+    }
+
+    let created_at = match (tag("UTCDate").or(tag("Date")), tag("UTCTime")) {
+        (Some(date), time) => {
+            let timestamp = format!(
+                "{}T{}Z",
+                date.replace('.', "-"),
+                time.unwrap_or("00:00:00".to_string())
+            );
+            parse_timestamp(&timestamp).ok_or(INVALID_PGN_ERROR)?
+        }
+        (None, _) => OffsetDateTime::now_utc(),
+    };
+    let mut game = ExportedGame {
+        game_id: tag("GameId").unwrap_or(Uuid::new_v4().to_string()),
+        admin_color: tag("AdminColor").unwrap_or(Color::WHITE.to_str()),
+        white_user_id: tag("White"),
+        black_user_id: tag("Black"),
+        variant: tag("Variant"),
+        time_control: tag("TimeControl"),
+        days_per_move: match tag("DaysPerMove") {
+            None => None,
+            Some(days) => Some(days.parse().map_err(|_| INVALID_PGN_ERROR)?),
+        },
+        rated: tag("Event").is_some_and(|event| event.starts_with("Rated")),
+        private: tag("Private").is_some_and(|private| private == "true"),
+        result: tag("Result").filter(|result| result != "*"),
+        termination: tag("Termination"),
+        eco: tag("ECO"),
+        opening: tag("Opening"),
+        created_at: format_timestamp(created_at),
+        moves: vec![],
+    };
+
+    let mut position = game.setup()?;
+    for token in movetext_tokens(movetext) {
+        if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+            if game.result.is_none() && token != "*" {
+                game.result = Some(token);
+            }
+            break;
+        }
+        let (from, to, promotion) = position.parse_san(&token)?;
+        position.validate_and_make_move(&from, &to, promotion)?;
+        let promotion = match promotion {
+            ' ' => String::new(),
+            piece => piece.to_ascii_lowercase().to_string(),
+        };
+        game.moves.push(format!("{}{}{}", from, to, promotion));
+    }
+    Ok(game)
+}
+
+/// How an import went. Games whose id is already taken are skipped.
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: Vec<ImportFailure>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportFailure {
+    // position of the game in the file, from 1
+    pub game: usize,
+    pub game_id: Option<String>,
+    pub error: String,
+}
+
+/// `--export FILE` and `--import FILE`, the format follows the file extension.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Export(String),
+    Import(String),
+}
+
+/// Anything but an export or import is left to the other commands.
+pub fn parse_args(args: &[String]) -> Result<Option<Command>, String> {
+    let command = match args.first().map(String::as_str) {
+        Some("--export") => Command::Export,
+        Some("--import") => Command::Import,
+        _ => return Ok(None),
+    };
+    match args {
+        [_, path] => Ok(Some(command(path.clone()))),
+        [_] => Err(format!("Missing file for {}", args[0])),
+        _ => Err(format!("Unexpected argument: {}", args[2])),
+    }
+}
+
+pub async fn run(server: &Server, command: Command) -> Result<(), String> {
+    match command {
+        Command::Export(path) => {
+            let format = Format::of_path(&path);
+            let file = File::create(&path).map_err(|e| e.to_string())?;
+            let mut writer = BufWriter::new(file);
+            let (mut cursor, mut exported) = (None, 0);
+            loop {
+                let (games, next) = server
+                    .export_games(cursor.as_ref(), EXPORT_PAGE_SIZE)
+                    .await?;
+                for game in &games {
+                    match game.render(format) {
+                        Err(e) => error!("Could not export game {}: {}", game.game_id, e),
+                        Ok(text) => {
+                            writer
+                                .write_all(text.as_bytes())
+                                .map_err(|e| e.to_string())?;
+                            exported += 1;
+                        }
+                    }
+                }
+                match next {
+                    None => break,
+                    Some(next) => cursor = Some(next),
+                }
+            }
+            writer.flush().map_err(|e| e.to_string())?;
+            info!("Exported {} games to {}", exported, path);
+            Ok(())
+        }
+        Command::Import(path) => {
+            let text = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            let report = server
+                .import_games(parse_games(&text, Format::of_path(&path)))
+                .await;
+            for failure in &report.failed {
+                error!("Could not import game {}: {}", failure.game, failure.error);
+            }
+            info!(
+                "Imported {} games from {}, skipped {} existing ones, {} failed",
+                report.imported,
+                path,
+                report.skipped,
+                report.failed.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::{parse_args, parse_games, Command, ExportedGame, Format};
+    use crate::utils::error::{INVALID_EXPORT_ERROR, INVALID_PGN_ERROR};
+
+    fn scholars_mate() -> ExportedGame {
+        ExportedGame {
+            game_id: "6f1c2d3e-4b5a-4c6d-8e7f-9a0b1c2d3e4f".to_string(),
+            admin_color: "white".to_string(),
+            white_user_id: Some("alice".to_string()),
+            black_user_id: Some("bob".to_string()),
+            variant: Some("standard".to_string()),
+            time_control: None,
+            days_per_move: None,
+            rated: true,
+            private: false,
+            result: Some("1-0".to_string()),
+            termination: Some("checkmate".to_string()),
+            eco: Some("C20".to_string()),
+            opening: Some("King's Pawn Game".to_string()),
+            created_at: "2024-03-01T12:30:05Z".to_string(),
+            moves: ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"]
+                .map(str::to_string)
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_pgn_round_trip() {
+        let game = scholars_mate();
+        let pgn = game.to_pgn().unwrap();
+        assert!(pgn.contains("[Result \"1-0\"]"));
+        assert!(pgn.contains("[UTCTime \"12:30:05\"]"));
+        assert!(pgn.contains("1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0"));
+
+        let parsed = parse_games(&format!("{}{}", pgn, pgn), Format::Pgn);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0], Ok(game.clone()));
+        assert_eq!(parsed[1], Ok(game));
+    }
+
+    #[test]
+    fn test_parse_pgn_from_elsewhere() {
+        let pgn = "[Event \"Casual game\"]\n[White \"?\"]\n[Result \"*\"]\n\n\
+            1. e4 {best by test} e5 (1... c5 2. Nf3) 2. Nf3 $1 Nc6 ; a comment\n3. O-O-O *\n";
+        let parsed = parse_games(pgn, Format::Pgn);
+        // castling is not legal yet
+        assert!(parsed[0].is_err());
+
+        let pgn = pgn.replace("3. O-O-O", "3. Bb5 a6 4. O-O");
+        let game = parse_games(&pgn, Format::Pgn).pop().unwrap().unwrap();
+        assert_eq!(
+            game.moves,
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "e1g1"]
+        );
+        assert_eq!((game.white_user_id, game.result), (None, None));
+        assert!(!game.rated);
+
+        let from_position = "[FEN \"8/8/8/8/8/8/8/K6k w - - 0 1\"]\n\n*\n";
+        assert_eq!(
+            parse_games(from_position, Format::Pgn),
+            vec![Err(INVALID_PGN_ERROR)]
+        );
+    }
+
+    #[test]
+    fn test_ndjson_round_trip() {
+        let game = scholars_mate();
+        let text = format!("{}\n{}", game.render(Format::Ndjson).unwrap(), "not json");
+        assert_eq!(
+            parse_games(&text, Format::Ndjson),
+            vec![Ok(game), Err(INVALID_EXPORT_ERROR)]
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let (game, moves) = scholars_mate().replay().unwrap();
+        assert_eq!(moves.len(), 7);
+        assert_eq!(moves[6].player, "WHITE");
+        assert_eq!(moves[6].turn, 4);
+        assert!(game.game_result.is_some());
+
+        let mut illegal = scholars_mate();
+        illegal.moves[2] = "f1f4".to_string();
+        assert!(illegal.replay().is_err());
+
+        // a checkmate has to match the declared result
+        let mut wrong_result = scholars_mate();
+        wrong_result.result = Some("0-1".to_string());
+        assert_eq!(wrong_result.replay().err(), Some(INVALID_EXPORT_ERROR));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_args(&args(&["--export", "games.pgn"])),
+            Ok(Some(Command::Export("games.pgn".to_string())))
+        );
+        assert_eq!(
+            parse_args(&args(&["--import", "games.ndjson"])),
+            Ok(Some(Command::Import("games.ndjson".to_string())))
+        );
+        assert_eq!(parse_args(&args(&["--migrate"])), Ok(None));
+        assert!(parse_args(&args(&["--import"])).is_err());
+        assert_eq!(Format::of_path("games.pgn"), Format::Pgn);
+    }
+}
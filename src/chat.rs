@@ -0,0 +1,148 @@
+use std::{
+    collections::VecDeque,
+    env,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::error::{CHAT_TOO_LONG_ERROR, EMPTY_CHAT_ERROR};
+
+pub const MAX_CHAT_LENGTH: usize = 500;
+pub const CHAT_RATE_LIMIT: usize = 5;
+pub const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatScope {
+    Players,
+    Spectators,
+    All,
+}
+impl ChatScope {
+    pub fn to_str(&self) -> String {
+        match self {
+            ChatScope::Players => "players".to_string(),
+            ChatScope::Spectators => "spectators".to_string(),
+            ChatScope::All => "all".to_string(),
+        }
+    }
+}
+
+/// Runs over every chat message before it is stored and sent out. A filter can
+/// either rewrite the message or reject it altogether.
+pub trait ChatFilter: Send + Sync {
+    fn filter(&self, message: &str) -> Result<String, &'static str>;
+}
+
+/// Replaces every blocked word with asterisks, ignoring case. The words are read
+/// from the comma separated CHAT_BLOCKED_WORDS env var.
+pub struct WordListFilter {
+    blocked_words: Vec<String>,
+}
+impl WordListFilter {
+    pub fn new(blocked_words: Vec<String>) -> WordListFilter {
+        WordListFilter {
+            blocked_words: blocked_words
+                .into_iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+    pub fn from_env() -> WordListFilter {
+        let words = env::var("CHAT_BLOCKED_WORDS").unwrap_or_default();
+        WordListFilter::new(words.split(',').map(|word| word.to_string()).collect())
+    }
+}
+impl ChatFilter for WordListFilter {
+    fn filter(&self, message: &str) -> Result<String, &'static str> {
+        let filtered = message
+            .split(' ')
+            .map(|word| {
+                // keep punctuation around the word, so "word!" becomes "****!"
+                let core = word.trim_matches(|ch: char| !ch.is_alphanumeric());
+                if !core.is_empty() && self.blocked_words.contains(&core.to_lowercase()) {
+                    word.replacen(core, &"*".repeat(core.chars().count()), 1)
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        Ok(filtered)
+    }
+}
+
+/// Trims the message and makes sure it is neither empty nor too long.
+pub fn check_chat_message(message: &str) -> Result<&str, &'static str> {
+    let message = message.trim();
+    if message.is_empty() {
+        return Err(EMPTY_CHAT_ERROR);
+    }
+    if message.chars().count() > MAX_CHAT_LENGTH {
+        return Err(CHAT_TOO_LONG_ERROR);
+    }
+
+    Ok(message)
+}
+
+/// Sliding window limit of CHAT_RATE_LIMIT messages per CHAT_RATE_WINDOW.
+#[derive(Clone, Debug, Default)]
+pub struct ChatRateLimiter {
+    sent: VecDeque<Instant>,
+}
+impl ChatRateLimiter {
+    pub fn allow(&mut self, now: Instant) -> bool {
+        while let Some(oldest) = self.sent.front() {
+            if now.duration_since(*oldest) < CHAT_RATE_WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= CHAT_RATE_LIMIT {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_chat {
+    use std::time::Instant;
+
+    use super::{
+        check_chat_message, ChatFilter, ChatRateLimiter, WordListFilter, CHAT_RATE_LIMIT,
+        CHAT_RATE_WINDOW, MAX_CHAT_LENGTH,
+    };
+
+    #[test]
+    fn test_word_filter() {
+        let filter = WordListFilter::new(vec!["Blunder".to_string(), " ".to_string()]);
+
+        let filtered = filter.filter("What a BLUNDER! nice game").unwrap();
+        assert_eq!(filtered, "What a *******! nice game");
+    }
+
+    #[test]
+    fn test_chat_message_length() {
+        assert!(check_chat_message("   ").is_err());
+        assert!(check_chat_message(&"a".repeat(MAX_CHAT_LENGTH + 1)).is_err());
+        assert_eq!(check_chat_message(" gg "), Ok("gg"));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = ChatRateLimiter::default();
+        let now = Instant::now();
+
+        for _ in 0..CHAT_RATE_LIMIT {
+            assert!(limiter.allow(now));
+        }
+        assert!(!limiter.allow(now));
+        assert!(limiter.allow(now + CHAT_RATE_WINDOW));
+    }
+}
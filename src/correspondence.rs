@@ -0,0 +1,138 @@
+use actix_web::cookie::time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+use crate::game::chess_piece::Color;
+
+// Vacation days every player may take within any 365 days
+pub const VACATION_DAYS_PER_YEAR: i64 = 30;
+pub const MAX_DAYS_PER_MOVE: u32 = 14;
+
+/// A stretch of time in which the deadlines of a player are paused.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vacation {
+    pub starts_at: OffsetDateTime,
+    pub ends_at: OffsetDateTime,
+}
+
+/// The state of a correspondence game as far as its deadline is concerned.
+#[derive(Clone, Debug)]
+pub struct PendingMove {
+    pub game_id: String,
+    pub to_move: Color,
+    pub user_id: Option<String>,
+    // the last move, or the start of the game before the first one
+    pub since: OffsetDateTime,
+    pub days_per_move: u32,
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(timestamp, &Rfc3339).ok()
+}
+
+pub fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    timestamp.format(&Rfc3339).unwrap()
+}
+
+/// When the player to move forfeits: `days_per_move` days after `since`,
+/// pushed back by any vacation the player takes before then.
+pub fn deadline(
+    since: OffsetDateTime,
+    days_per_move: u32,
+    vacations: &[Vacation],
+) -> OffsetDateTime {
+    let mut vacations: Vec<&Vacation> = vacations
+        .iter()
+        .filter(|vacation| vacation.ends_at > since)
+        .collect();
+    vacations.sort_by_key(|vacation| vacation.starts_at);
+
+    let mut deadline = since + Duration::days(days_per_move as i64);
+    for vacation in vacations {
+        if vacation.starts_at >= deadline {
+            break;
+        }
+        deadline += vacation.ends_at - vacation.starts_at.max(since);
+    }
+    deadline
+}
+
+/// How many vacation days a player took in the 365 days before `now`,
+/// counting started days as full ones.
+pub fn vacation_days_used(vacations: &[Vacation], now: OffsetDateTime) -> i64 {
+    let year_ago = now - Duration::days(365);
+    let used: Duration = vacations
+        .iter()
+        .filter(|vacation| vacation.ends_at > year_ago)
+        .map(|vacation| vacation.ends_at - vacation.starts_at.max(year_ago))
+        .sum();
+
+    let days = used.whole_days();
+    if used > Duration::days(days) {
+        days + 1
+    } else {
+        days
+    }
+}
+
+pub fn on_vacation(vacations: &[Vacation], now: OffsetDateTime) -> bool {
+    vacations
+        .iter()
+        .any(|vacation| vacation.starts_at <= now && now < vacation.ends_at)
+}
+
+#[cfg(test)]
+mod test_correspondence {
+    use actix_web::cookie::time::{Duration, OffsetDateTime};
+
+    use super::{deadline, on_vacation, vacation_days_used, Vacation};
+
+    fn vacation(start: OffsetDateTime, days: i64) -> Vacation {
+        Vacation {
+            starts_at: start,
+            ends_at: start + Duration::days(days),
+        }
+    }
+
+    #[test]
+    fn test_deadline_without_vacation() {
+        let since = OffsetDateTime::now_utc();
+        assert_eq!(deadline(since, 3, &[]), since + Duration::days(3));
+    }
+
+    #[test]
+    fn test_vacation_extends_deadline() {
+        let since = OffsetDateTime::now_utc();
+        let vacations = [
+            // already over before the last move, does not count
+            vacation(since - Duration::days(10), 5),
+            // started before the last move, only the rest of it counts
+            vacation(since - Duration::days(1), 2),
+            // starts after the deadline, does not count
+            vacation(since + Duration::days(5), 3),
+        ];
+        assert_eq!(deadline(since, 3, &vacations), since + Duration::days(4));
+
+        // a vacation that starts inside the extension still counts
+        let vacations = [
+            vacation(since + Duration::days(1), 2),
+            vacation(since + Duration::days(4), 1),
+        ];
+        assert_eq!(deadline(since, 3, &vacations), since + Duration::days(6));
+    }
+
+    #[test]
+    fn test_vacation_days_used() {
+        let now = OffsetDateTime::now_utc();
+        let vacations = [
+            vacation(now - Duration::days(400), 10),
+            vacation(now - Duration::days(366), 3),
+            vacation(now - Duration::days(30), 5),
+            Vacation {
+                starts_at: now - Duration::hours(1),
+                ends_at: now + Duration::hours(2),
+            },
+        ];
+        assert_eq!(vacation_days_used(&vacations, now), 2 + 5 + 1);
+        assert!(on_vacation(&vacations, now));
+        assert!(!on_vacation(&vacations, now + Duration::days(1)));
+    }
+}
@@ -1,215 +1,2527 @@
 use std::{env, time::SystemTime};
 
 use actix_web::cookie::time::{format_description::well_known::Rfc3339, OffsetDateTime};
-use libsql::{de, params, Builder, Connection};
+use async_trait::async_trait;
+use futures_util::lock::{Mutex, MutexGuard};
+use libsql::{
+    de, params, params::IntoParams, params_from_iter, Builder, Connection, Transaction, Value,
+};
 use log::error;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::utils::error::INTERNAL_SERVER_ERROR;
+use crate::{
+    backup::{ExportedGame, ReplayedMove},
+    correspondence::{parse_timestamp, PendingMove, Vacation},
+    game::{
+        chess_piece::Color,
+        eco::Opening,
+        event::{GameEvent, LoggedEvent, Snapshot},
+        time_control::TimeControl,
+        Game, Termination, Variant,
+    },
+    migrations,
+    rating::{RatedGame, Rating, RatingPool},
+    stats::MoveTime,
+    store::{
+        AccountStore, ArchiveStore, CompetitionStore, CorrespondenceStore, ExplorerStore,
+        GameStore, NewMove,
+    },
+    user::{User, UserRole},
+    utils::error::INTERNAL_SERVER_ERROR,
+};
 
 pub async fn connect_db(env: &str) -> Connection {
-    let url = env::var("TURSO_DATABASE_URL").expect("TURSO_DATABASE_URL not set");
-    let token = env::var("TURSO_AUTH_TOKEN").expect("TURSO_AUTH_TOKEN not set");
+    if env == "dev" {
+        connect_local("local.db").await
+    } else {
+        let url = env::var("TURSO_DATABASE_URL").expect("TURSO_DATABASE_URL not set");
+        let token = env::var("TURSO_AUTH_TOKEN").expect("TURSO_AUTH_TOKEN not set");
+        connect_remote(url, token).await
+    }
+}
+
+/// Opens a local SQLite file, `:memory:` for a database that lives as long as the connection.
+pub async fn connect_local(path: &str) -> Connection {
+    let db = Builder::new_local(path)
+        .build()
+        .await
+        .expect("Could not connect to local database");
+    db.connect().unwrap()
+}
+
+pub async fn connect_remote(url: String, token: String) -> Connection {
+    let db = Builder::new_remote(url, token)
+        .build()
+        .await
+        .expect("Could not connect to prod database");
+    db.connect().unwrap()
+}
+
+pub struct DB {
+    conn: Connection,
+    // held by every write, see `DB::transaction`
+    writing: Mutex<()>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Move {
+    pub ply: u32,
+    pub move_notation: String,
+    pub turn: u32,
+    pub player: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ChatMessage {
+    pub scope: String,
+    pub author: String,
+    pub message: String,
+    pub created_at: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiKey {
+    pub key_id: String,
+    pub label: String,
+    pub role: String,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+}
+impl ApiKey {
+    pub fn role(&self) -> UserRole {
+        UserRole::from_name(&self.role).unwrap_or(UserRole::Viewer)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RatingHistoryEntry {
+    pub pool: String,
+    pub game_id: String,
+    pub rating: f64,
+    pub deviation: f64,
+    pub created_at: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LeaderboardEntry {
+    pub user_id: String,
+    pub display_name: String,
+    pub rating: f64,
+    pub deviation: f64,
+    pub games: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UserGame {
+    pub game_id: String,
+    pub color: String,
+    pub opponent_id: Option<String>,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+    pub created_at: String,
+}
+
+/// An unfinished game nobody moved in for too long.
+#[derive(Deserialize, Debug)]
+pub struct StaleGame {
+    pub game_id: String,
+    pub moves: u32,
+}
+
+/// An unfinished game with a days-per-move time control.
+#[derive(Deserialize, Debug)]
+pub struct CorrespondenceGame {
+    pub game_id: String,
+    pub days_per_move: u32,
+    pub white_user_id: Option<String>,
+    pub black_user_id: Option<String>,
+    pub created_at: String,
+    pub last_moved: Option<String>,
+    pub last_moved_at: Option<String>,
+}
+impl CorrespondenceGame {
+    pub fn pending_move(&self) -> Option<PendingMove> {
+        let to_move = match self.last_moved.as_deref() {
+            Some("WHITE") => Color::BLACK,
+            _ => Color::WHITE,
+        };
+        let since = self.last_moved_at.as_ref().unwrap_or(&self.created_at);
+        Some(PendingMove {
+            game_id: self.game_id.clone(),
+            user_id: match to_move {
+                Color::WHITE => self.white_user_id.clone(),
+                Color::BLACK => self.black_user_id.clone(),
+            },
+            to_move,
+            since: parse_timestamp(since)?,
+            days_per_move: self.days_per_move,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AwaitingGame {
+    pub game_id: String,
+    pub color: String,
+    pub opponent_id: Option<String>,
+    pub days_per_move: Option<u32>,
+    pub created_at: String,
+    pub last_moved_at: Option<String>,
+}
+
+/// What the game archive can be searched by, every filter is optional.
+#[derive(Default, Debug)]
+pub struct GameFilter {
+    pub finished: Option<bool>,
+    pub player: Option<String>,
+    pub result: Option<String>,
+    pub variant: Option<Variant>,
+    pub eco: Option<String>,
+    pub created_from: Option<String>,
+    pub created_until: Option<String>,
+    // the Zobrist hash of a position reached at any point of the game
+    pub position_hash: Option<u64>,
+    // private games are left out unless the viewer played in them
+    pub include_private: bool,
+    pub viewer: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ArchivedGame {
+    pub game_id: String,
+    pub white_user_id: Option<String>,
+    pub black_user_id: Option<String>,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+    pub variant: Option<String>,
+    pub time_control: Option<String>,
+    pub rated: Option<i64>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+    pub moves: u32,
+    pub created_at: String,
+}
+
+/// A move played from a position, summed over all finished games.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExplorerMove {
+    pub uci: String,
+    pub notation: String,
+    pub games: u64,
+    pub white_wins: u64,
+    pub draws: u64,
+    pub black_wins: u64,
+    pub rating_sum: f64,
+    pub rated_games: u64,
+}
+
+/// The open seat of a private game. Only the hash of its token is stored.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Invite {
+    pub game_id: String,
+    pub created_by: String,
+    pub seat: String,
+    pub password_hash: Option<String>,
+    pub expires_at: Option<String>,
+    pub accepted_by: Option<String>,
+}
+impl Invite {
+    pub fn seat(&self) -> Option<Color> {
+        Color::from_name(&self.seat)
+    }
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        match self.expires_at.as_deref().map(parse_timestamp) {
+            None => false,
+            Some(Some(expires_at)) => expires_at <= now,
+            // an expiry we cannot read should not keep the invite open forever
+            Some(None) => true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct DBVacation {
+    starts_at: String,
+    ends_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DBGameSetup {
+    admin_color: String,
+    white_user_id: Option<String>,
+    black_user_id: Option<String>,
+    variant: Option<String>,
+    time_control: Option<String>,
+    days_per_move: Option<u32>,
+    rated: Option<i64>,
+    private: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DBExportedGame {
+    game_id: String,
+    admin_color: String,
+    white_user_id: Option<String>,
+    black_user_id: Option<String>,
+    variant: Option<String>,
+    time_control: Option<String>,
+    days_per_move: Option<u32>,
+    rated: Option<i64>,
+    private: Option<i64>,
+    result: Option<String>,
+    termination: Option<String>,
+    eco: Option<String>,
+    opening: Option<String>,
+    created_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DBEvent {
+    seq: u64,
+    payload: String,
+    created_at: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DBGame {
+    pub game_id: String,
+    pub created_at: String,
+    pub last_moved: Option<String>,
+    pub admin_color: String,
+}
+
+// white wins, draws and black wins of a result
+fn result_counts(result: &str) -> (i64, i64, i64) {
+    match result {
+        "1-0" => (1, 0, 0),
+        "0-1" => (0, 0, 1),
+        _ => (0, 1, 0),
+    }
+}
+
+/// Takes the moves of a game back out of the opening explorer, if they were added.
+async fn remove_from_explorer(conn: &Connection, id: &str) -> Result<(), libsql::Error> {
+    let mut rows = conn
+        .query(
+            "SELECT E.average_rating, G.result
+            FROM ExplorerGame E JOIN Game G ON G.game_id = E.game_id
+            WHERE E.game_id = ?1",
+            params![id],
+        )
+        .await?;
+    let (average_rating, result) = match rows.next().await? {
+        None => return Ok(()),
+        Some(row) => (row.get::<Option<f64>>(0)?, row.get::<String>(1)?),
+    };
+    let (white_wins, draws, black_wins) = result_counts(&result);
+    conn.execute(
+        "UPDATE ExplorerMove SET
+            games = games - 1,
+            white_wins = white_wins - ?2,
+            draws = draws - ?3,
+            black_wins = black_wins - ?4,
+            rating_sum = rating_sum - COALESCE(?5, 0),
+            rated_games = rated_games - ?6
+        WHERE (position_hash, uci) IN (
+            SELECT P.position_hash, M.uci
+            FROM GamePosition P JOIN Move M ON M.game_id = P.game_id AND M.ply = P.ply + 1
+            WHERE P.game_id = ?1 AND M.uci IS NOT NULL
+        )",
+        params![
+            id,
+            white_wins,
+            draws,
+            black_wins,
+            average_rating,
+            average_rating.is_some() as i64
+        ],
+    )
+    .await?;
+    conn.execute("DELETE FROM ExplorerMove WHERE games <= 0", ())
+        .await?;
+    conn.execute("DELETE FROM ExplorerGame WHERE game_id = ?1", params![id])
+        .await?;
+    Ok(())
+}
+
+/// The condition that leaves out the private games of others, `None` if
+/// private games are included anyway. Its values are added to `values`.
+fn private_condition(
+    include_private: bool,
+    viewer: Option<&str>,
+    values: &mut Vec<Value>,
+) -> Option<&'static str> {
+    if include_private {
+        return None;
+    }
+    match viewer {
+        None => Some("COALESCE(G.private, 0) = 0"),
+        Some(viewer) => {
+            values.push(viewer.into());
+            values.push(viewer.into());
+            Some("(COALESCE(G.private, 0) = 0 OR G.white_user_id = ? OR G.black_user_id = ?)")
+        }
+    }
+}
+
+impl DB {
+    /// Connects to the local file in dev and to Turso otherwise.
+    pub async fn new() -> DB {
+        let env = env::var("ENV").expect("DEV not set");
+        DB::with_connection(connect_db(&env).await).await
+    }
+    pub async fn local(path: &str) -> DB {
+        DB::with_connection(connect_local(path).await).await
+    }
+    pub async fn remote(url: String, token: String) -> DB {
+        DB::with_connection(connect_remote(url, token).await).await
+    }
+    async fn with_connection(conn: Connection) -> DB {
+        migrations::migrate(&conn)
+            .await
+            .expect("Could not migrate database");
+        DB {
+            conn,
+            writing: Mutex::new(()),
+        }
+    }
+    /// Every task shares one connection, and a statement run on it while a
+    /// transaction is open becomes part of that transaction. So transactions
+    /// and single writes wait for each other.
+    async fn transaction(&self) -> Result<(MutexGuard<'_, ()>, Transaction), libsql::Error> {
+        let writing = self.writing.lock().await;
+        Ok((writing, self.conn.transaction().await?))
+    }
+    async fn execute(&self, sql: &str, params: impl IntoParams) -> Result<u64, libsql::Error> {
+        let _writing = self.writing.lock().await;
+        self.conn.execute(sql, params).await
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for DB {
+    async fn get_user_games(
+        &self,
+        id: &str,
+        include_private: bool,
+        viewer: Option<&str>,
+    ) -> Result<Vec<UserGame>, &'static str> {
+        let mut conditions = vec![
+            "(G.white_user_id = ? OR G.black_user_id = ?)",
+            "G.deleted_at IS NULL",
+        ];
+        let mut values: Vec<Value> = vec![id.into(), id.into(), id.into(), id.into()];
+        if let Some(condition) = private_condition(include_private, viewer, &mut values) {
+            conditions.push(condition);
+        }
+        let rows = self
+            .conn
+            .query(
+                &format!(
+                    "SELECT game_id,
+                        CASE WHEN white_user_id = ? THEN 'white' ELSE 'black' END AS color,
+                        CASE WHEN white_user_id = ? THEN black_user_id ELSE white_user_id END AS opponent_id,
+                        result, termination, eco, opening, created_at
+                    FROM Game G
+                    WHERE {}
+                    ORDER BY created_at DESC",
+                    conditions.join(" AND ")
+                ),
+                params_from_iter(values),
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get games of user {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<UserGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push(de::from_row::<UserGame>(&row).unwrap());
+        }
+
+        Ok(games)
+    }
+    async fn search_games(
+        &self,
+        filter: &GameFilter,
+        newest_first: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(u64, Vec<ArchivedGame>), &'static str> {
+        let mut conditions: Vec<&str> = vec!["G.deleted_at IS NULL"];
+        let mut values: Vec<Value> = vec![];
+        match filter.finished {
+            None => (),
+            Some(true) => conditions.push("G.result IS NOT NULL"),
+            Some(false) => conditions.push("G.result IS NULL"),
+        }
+        if let Some(player) = &filter.player {
+            conditions.push("(G.white_user_id = ? OR G.black_user_id = ?)");
+            values.push(player.as_str().into());
+            values.push(player.as_str().into());
+        }
+        if let Some(result) = &filter.result {
+            conditions.push("G.result = ?");
+            values.push(result.as_str().into());
+        }
+        if let Some(variant) = filter.variant {
+            conditions.push("G.variant = ?");
+            values.push(variant.to_str().into());
+        }
+        // a full code like B90, or a volume or group like B or B9
+        if let Some(eco) = &filter.eco {
+            conditions.push("G.eco LIKE ? || '%'");
+            values.push(eco.as_str().into());
+        }
+        if let Some(from) = &filter.created_from {
+            conditions.push("G.created_at >= ?");
+            values.push(from.as_str().into());
+        }
+        if let Some(until) = &filter.created_until {
+            conditions.push("G.created_at < ?");
+            values.push(until.as_str().into());
+        }
+        if let Some(hash) = filter.position_hash {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM GamePosition P WHERE P.game_id = G.game_id AND P.position_hash = ?)",
+            );
+            values.push((hash as i64).into());
+        }
+        if let Some(condition) = private_condition(
+            filter.include_private,
+            filter.viewer.as_deref(),
+            &mut values,
+        ) {
+            conditions.push(condition);
+        }
+        let where_clause = format!("WHERE {}", conditions.join(" AND "));
+
+        let total = self
+            .conn
+            .query(
+                &format!("SELECT COUNT(*) FROM Game G {}", where_clause),
+                params_from_iter(values.clone()),
+            )
+            .await;
+        let total = match total {
+            Err(e) => {
+                error!("Could not count games matching {:?}: {}", filter, e);
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Ok(mut rows) => match rows.next().await {
+                Ok(Some(row)) => row.get::<u64>(0).unwrap(),
+                _ => 0,
+            },
+        };
+
+        values.push(limit.into());
+        values.push(offset.into());
+        let rows = self
+            .conn
+            .query(
+                &format!(
+                    "SELECT G.game_id, G.white_user_id, G.black_user_id, G.result, G.termination,
+                        G.variant, G.time_control, G.rated, G.eco, G.opening, G.created_at,
+                        (SELECT COUNT(*) FROM Move M WHERE M.game_id = G.game_id) AS moves
+                    FROM Game G
+                    {}
+                    ORDER BY G.created_at {}, G.game_id
+                    LIMIT ? OFFSET ?",
+                    where_clause,
+                    if newest_first { "DESC" } else { "ASC" }
+                ),
+                params_from_iter(values),
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not search games matching {:?}: {}", filter, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<ArchivedGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push(de::from_row::<ArchivedGame>(&row).unwrap());
+        }
+
+        Ok((total, games))
+    }
+    async fn soft_delete_game(&self, id: &str) -> Result<bool, &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            let deleted = tx
+                .execute(
+                    "UPDATE Game SET deleted_at = ?1 WHERE game_id = ?2 AND deleted_at IS NULL",
+                    params![now_str, id],
+                )
+                .await?;
+            remove_from_explorer(&tx, id).await?;
+            tx.commit().await?;
+            Ok(deleted > 0)
+        }
+        .await;
+        result.map_err(|e: libsql::Error| {
+            error!("Could not delete game {}: {}", id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn delete_game(&self, id: &str) -> Result<bool, &'static str> {
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            remove_from_explorer(&tx, id).await?;
+            for table in [
+                "Move",
+                "Action",
+                "Chat",
+                "GameEvent",
+                "GameSnapshot",
+                "GamePosition",
+                "Invite",
+                "ArenaGame",
+            ] {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE game_id = ?1", table),
+                    params![id],
+                )
+                .await?;
+            }
+            let deleted = tx
+                .execute("DELETE FROM Game WHERE game_id = ?1", params![id])
+                .await?;
+            tx.commit().await?;
+            Ok(deleted > 0)
+        }
+        .await;
+        result.map_err(|e: libsql::Error| {
+            error!("Could not delete game {} from DB: {}", id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn is_rated_game(&self, id: &str) -> Result<bool, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT 1 FROM RatingHistory WHERE game_id = ?1 LIMIT 1",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get the rating history of game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        Ok(rows.unwrap().next().await.unwrap().is_some())
+    }
+    async fn is_competition_game(&self, id: &str) -> Result<bool, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT 1 FROM Game WHERE game_id = ?1 AND simul_id IS NOT NULL
+                UNION ALL SELECT 1 FROM TournamentPairing WHERE game_id = ?1 LIMIT 1",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!(
+                "Could not get the tournament or simul of game {}: {}",
+                id, e
+            );
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        Ok(rows.unwrap().next().await.unwrap().is_some())
+    }
+    async fn get_export_page(
+        &self,
+        after: Option<&(String, String)>,
+        limit: u32,
+    ) -> Result<Vec<ExportedGame>, &'static str> {
+        let (created_at, game_id) = match after {
+            None => (None, None),
+            Some((created_at, game_id)) => (Some(created_at.as_str()), Some(game_id.as_str())),
+        };
+        let rows = self
+            .conn
+            .query(
+                "SELECT game_id, admin_color, white_user_id, black_user_id, variant, time_control,
+                    days_per_move, rated, private, result, termination, eco, opening, created_at
+                FROM Game
+                WHERE deleted_at IS NULL AND (?1 IS NULL OR (created_at, game_id) > (?1, ?2))
+                ORDER BY created_at, game_id
+                LIMIT ?3",
+                params![created_at, game_id, limit],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get games to export: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<ExportedGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            let game = de::from_row::<DBExportedGame>(&row).unwrap();
+            games.push(ExportedGame {
+                game_id: game.game_id,
+                admin_color: game.admin_color,
+                white_user_id: game.white_user_id,
+                black_user_id: game.black_user_id,
+                variant: game.variant,
+                time_control: game.time_control,
+                days_per_move: game.days_per_move,
+                rated: game.rated == Some(1),
+                private: game.private == Some(1),
+                result: game.result,
+                termination: game.termination,
+                eco: game.eco,
+                opening: game.opening,
+                created_at: game.created_at,
+                moves: vec![],
+            });
+        }
+
+        Ok(games)
+    }
+    async fn get_move_ucis(&self, id: &str) -> Result<Vec<Option<String>>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT uci FROM Move WHERE game_id = ?1 ORDER BY ply",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get the moves of game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut moves = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            moves.push(row.get::<Option<String>>(0).unwrap());
+        }
+
+        Ok(moves)
+    }
+    async fn get_result(&self, id: &str) -> Result<Option<String>, &'static str> {
+        let rows = self
+            .conn
+            .query("SELECT result FROM Game WHERE game_id = ?1", params![id])
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get the result of game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        match rows.next().await.unwrap() {
+            None => Ok(None),
+            Some(row) => Ok(row.get::<Option<String>>(0).unwrap()),
+        }
+    }
+    async fn get_move_times(&self, id: &str) -> Result<Vec<MoveTime>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT ply, player, time_spent_ms FROM Move WHERE game_id = ?1 ORDER BY ply",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get the move times of game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut times: Vec<MoveTime> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            times.push(de::from_row::<MoveTime>(&row).unwrap());
+        }
+
+        Ok(times)
+    }
+    async fn import_game(
+        &self,
+        game: &ExportedGame,
+        start_hash: u64,
+        moves: &[ReplayedMove],
+    ) -> Result<bool, &'static str> {
+        let (eco, opening) = match moves.last().and_then(|last| last.opening) {
+            None => (None, None),
+            Some(opening) => (Some(opening.eco), Some(opening.name)),
+        };
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            let inserted = tx
+                .execute(
+                    "INSERT OR IGNORE INTO Game(game_id, admin_color, result, termination, white_user_id, black_user_id,
+                        variant, time_control, days_per_move, rated, private, eco, opening, created_at)
+                    VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    params![
+                        game.game_id.as_str(),
+                        game.admin_color.as_str(),
+                        game.result.clone(),
+                        game.termination.clone(),
+                        game.white_user_id.clone(),
+                        game.black_user_id.clone(),
+                        game.variant.clone(),
+                        game.time_control.clone(),
+                        game.days_per_move,
+                        game.rated,
+                        game.private,
+                        eco,
+                        opening,
+                        game.created_at.as_str()
+                    ],
+                )
+                .await?;
+            if inserted == 0 {
+                return Ok(false);
+            }
+            tx.execute(
+                "INSERT INTO GamePosition(game_id, ply, position_hash) VALUES(?1, 0, ?2)",
+                params![game.game_id.as_str(), start_hash as i64],
+            )
+            .await?;
+            for (seq, played) in (1..).zip(moves) {
+                let (eco, opening) = match played.opening {
+                    None => (None, None),
+                    Some(opening) => (Some(opening.eco), Some(opening.name)),
+                };
+                tx.execute(
+                    "INSERT INTO Move(ply, turn, move_notation, player, game_id, created_at, uci) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        played.ply,
+                        played.turn,
+                        played.notation.as_str(),
+                        played.player,
+                        game.game_id.as_str(),
+                        game.created_at.as_str(),
+                        played.event.uci()
+                    ],
+                )
+                .await?;
+                tx.execute(
+                    "INSERT INTO GameEvent(game_id, seq, kind, payload, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        game.game_id.as_str(),
+                        seq,
+                        played.event.kind(),
+                        serde_json::to_string(&played.event).unwrap(),
+                        game.created_at.as_str()
+                    ],
+                )
+                .await?;
+                tx.execute(
+                    "INSERT INTO GamePosition(game_id, ply, position_hash) VALUES(?1, ?2, ?3)",
+                    params![game.game_id.as_str(), played.ply, played.position_hash as i64],
+                )
+                .await?;
+                if let Some(fen) = &played.snapshot {
+                    tx.execute(
+                        "INSERT INTO GameSnapshot(game_id, seq, ply, fen, eco, opening, created_at) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![game.game_id.as_str(), seq, played.ply, fen.as_str(), eco, opening, game.created_at.as_str()],
+                    )
+                    .await?;
+                }
+            }
+            // results that were not reached on the board, like a resignation
+            if let Some(result) = &game.result {
+                let event = GameEvent::Finish {
+                    result: result.clone(),
+                    termination: game.termination.clone().unwrap_or_default(),
+                };
+                tx.execute(
+                    "INSERT INTO GameEvent(game_id, seq, kind, payload, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        game.game_id.as_str(),
+                        moves.len() as i64 + 1,
+                        event.kind(),
+                        serde_json::to_string(&event).unwrap(),
+                        game.created_at.as_str()
+                    ],
+                )
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(true)
+        }
+        .await;
+        result.map_err(|e: libsql::Error| {
+            error!("Could not import game {} into DB: {}", game.game_id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn get_stale_games(&self, before: &str) -> Result<Vec<StaleGame>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT G.game_id, COUNT(M.ply) AS moves
+                FROM Game G
+                LEFT JOIN Move M ON M.game_id = G.game_id
+                WHERE G.result IS NULL AND G.termination IS NULL AND G.deleted_at IS NULL
+                    AND G.days_per_move IS NULL
+                    AND (G.white_user_id IS NOT NULL OR G.black_user_id IS NOT NULL)
+                GROUP BY G.game_id, G.created_at
+                HAVING COALESCE(MAX(M.created_at), G.created_at) < ?1",
+                params![before],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get stale games from DB: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<StaleGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push(de::from_row::<StaleGame>(&row).unwrap());
+        }
+
+        Ok(games)
+    }
+}
+
+#[async_trait]
+impl ExplorerStore for DB {
+    async fn get_explorer_moves(
+        &self,
+        position_hash: u64,
+    ) -> Result<Vec<ExplorerMove>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT uci, notation, games, white_wins, draws, black_wins, rating_sum, rated_games
+                FROM ExplorerMove
+                WHERE position_hash = ?1
+                ORDER BY games DESC, uci",
+                params![position_hash as i64],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!(
+                "Could not get explorer moves of {:016x}: {}",
+                position_hash, e
+            );
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut moves: Vec<ExplorerMove> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            moves.push(de::from_row::<ExplorerMove>(&row).unwrap());
+        }
+
+        Ok(moves)
+    }
+    async fn get_unexplored_games(&self) -> Result<Vec<(String, String)>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT game_id, result FROM Game
+                WHERE result IS NOT NULL AND COALESCE(private, 0) = 0 AND deleted_at IS NULL
+                    AND game_id NOT IN (SELECT game_id FROM ExplorerGame)",
+                (),
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get unexplored games: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push((row.get::<String>(0).unwrap(), row.get::<String>(1).unwrap()));
+        }
+
+        Ok(games)
+    }
+    async fn save_positions(
+        &self,
+        id: &str,
+        positions: &[(u32, u64)],
+        moves: &[(u32, String)],
+    ) -> Result<(), &'static str> {
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            tx.execute("DELETE FROM GamePosition WHERE game_id = ?1", params![id])
+                .await?;
+            for (ply, hash) in positions {
+                tx.execute(
+                    "INSERT INTO GamePosition(game_id, ply, position_hash) VALUES(?1, ?2, ?3)",
+                    params![id, *ply, *hash as i64],
+                )
+                .await?;
+            }
+            for (ply, uci) in moves {
+                tx.execute(
+                    "UPDATE Move SET uci = ?1 WHERE game_id = ?2 AND ply = ?3",
+                    params![uci.as_str(), id, *ply],
+                )
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("Could not save the positions of game {}: {}", id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+}
+
+#[async_trait]
+impl CorrespondenceStore for DB {
+    async fn get_correspondence_games(&self) -> Result<Vec<CorrespondenceGame>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT G.game_id, G.days_per_move, G.white_user_id, G.black_user_id, G.created_at,
+                    M.player AS last_moved, M.created_at AS last_moved_at
+                FROM Game G
+                LEFT JOIN (
+                    SELECT game_id, player, created_at
+                    FROM Move
+                    WHERE (game_id, ply) IN (
+                        SELECT game_id, MAX(ply)
+                        FROM Move
+                        GROUP BY game_id
+                    )
+                ) M ON G.game_id = M.game_id
+                WHERE G.result IS NULL AND G.termination IS NULL AND G.deleted_at IS NULL AND G.days_per_move IS NOT NULL",
+                (),
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get correspondence games from DB: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<CorrespondenceGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push(de::from_row::<CorrespondenceGame>(&row).unwrap());
+        }
+
+        Ok(games)
+    }
+    async fn get_awaiting_games(&self, user_id: &str) -> Result<Vec<AwaitingGame>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT G.game_id,
+                    CASE WHEN G.white_user_id = ?1 THEN 'white' ELSE 'black' END AS color,
+                    CASE WHEN G.white_user_id = ?1 THEN G.black_user_id ELSE G.white_user_id END AS opponent_id,
+                    G.days_per_move, G.created_at, M.created_at AS last_moved_at
+                FROM Game G
+                LEFT JOIN (
+                    SELECT game_id, player, created_at
+                    FROM Move
+                    WHERE (game_id, ply) IN (
+                        SELECT game_id, MAX(ply)
+                        FROM Move
+                        GROUP BY game_id
+                    )
+                ) M ON G.game_id = M.game_id
+                WHERE G.result IS NULL AND G.termination IS NULL AND G.deleted_at IS NULL AND (
+                    (G.white_user_id = ?1 AND (M.player IS NULL OR M.player = 'BLACK'))
+                    OR (G.black_user_id = ?1 AND M.player = 'WHITE')
+                )
+                ORDER BY COALESCE(M.created_at, G.created_at)",
+                params![user_id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!(
+                "Could not get games awaiting a move of user {}: {}",
+                user_id, e
+            );
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut games: Vec<AwaitingGame> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            games.push(de::from_row::<AwaitingGame>(&row).unwrap());
+        }
+
+        Ok(games)
+    }
+    async fn add_vacation(
+        &self,
+        user_id: &str,
+        starts_at: &str,
+        ends_at: &str,
+    ) -> Result<(), &'static str> {
+        match self
+            .execute(
+                "INSERT INTO Vacation(user_id, starts_at, ends_at) VALUES(?1, ?2, ?3)",
+                params![user_id, starts_at, ends_at],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not add vacation of user {}: {}", user_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn get_vacations(&self, user_id: &str) -> Result<Vec<Vacation>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT starts_at, ends_at FROM Vacation WHERE user_id = ?1 ORDER BY starts_at",
+                params![user_id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get vacations of user {}: {}", user_id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut vacations: Vec<Vacation> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            let vacation = de::from_row::<DBVacation>(&row).unwrap();
+            match (
+                parse_timestamp(&vacation.starts_at),
+                parse_timestamp(&vacation.ends_at),
+            ) {
+                (Some(starts_at), Some(ends_at)) => vacations.push(Vacation { starts_at, ends_at }),
+                _ => error!("Skipping vacation of user {} with invalid dates", user_id),
+            }
+        }
+
+        Ok(vacations)
+    }
+}
+
+#[async_trait]
+impl CompetitionStore for DB {
+    async fn create_simul(
+        &self,
+        id: &str,
+        host_name: &str,
+        walking_order: bool,
+        host_time_budget_secs: Option<u64>,
+        game_ids: &[String],
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        if let Err(e) = self
+            .execute(
+                "INSERT INTO Simul(simul_id, host_name, walking_order, host_time_budget_secs, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    host_name,
+                    walking_order,
+                    host_time_budget_secs.map(|secs| secs as i64),
+                    now_str
+                ],
+            )
+            .await
+        {
+            error!("Could not add simul {} to DB: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+
+        for game_id in game_ids {
+            if let Err(e) = self
+                .execute(
+                    "UPDATE Game SET simul_id = ?1 WHERE game_id = ?2",
+                    params![id, game_id.as_str()],
+                )
+                .await
+            {
+                error!("Could not add game {} to simul {}: {}", game_id, id, e);
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+        }
+
+        Ok(())
+    }
+    async fn create_tournament(
+        &self,
+        id: &str,
+        name: &str,
+        format: &str,
+        rounds: u32,
+        players: &[String],
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        if let Err(e) = self
+            .execute(
+                "INSERT INTO Tournament(tournament_id, name, format, rounds, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![id, name, format, rounds, now_str],
+            )
+            .await
+        {
+            error!("Could not add tournament {} to DB: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
 
-    let conn: Connection;
-    if env == "dev" {
-        let db = Builder::new_local("local.db")
-            .build()
+        for (seed, user_id) in players.iter().enumerate() {
+            if let Err(e) = self
+                .execute(
+                    "INSERT INTO TournamentPlayer(tournament_id, user_id, seed) VALUES(?1, ?2, ?3)",
+                    params![id, user_id.as_str(), seed as u32],
+                )
+                .await
+            {
+                error!(
+                    "Could not add player {} to tournament {}: {}",
+                    user_id, id, e
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+        }
+
+        Ok(())
+    }
+    async fn insert_pairing(
+        &self,
+        tournament_id: &str,
+        round: u32,
+        white_user_id: &str,
+        black_user_id: Option<&str>,
+        game_id: Option<&str>,
+        result: Option<String>,
+    ) -> Result<(), &'static str> {
+        match self
+            .execute(
+                "INSERT INTO TournamentPairing(tournament_id, round, white_user_id, black_user_id, game_id, result)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    tournament_id,
+                    round,
+                    white_user_id,
+                    black_user_id.map(|id| id.to_string()),
+                    game_id.map(|id| id.to_string()),
+                    result
+                ],
+            )
             .await
-            .expect("Could not connect to local database");
-        conn = db.connect().unwrap();
-    } else {
-        let db = Builder::new_remote(url, token)
-            .build()
+        {
+            Err(e) => {
+                error!("Could not add pairing to tournament {}: {}", tournament_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn create_arena(
+        &self,
+        id: &str,
+        name: &str,
+        time_control: &str,
+        duration_secs: u64,
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "INSERT INTO Arena(arena_id, name, time_control, duration_secs, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![id, name, time_control, duration_secs as i64, now_str],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not add arena {} to DB: {}", id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn insert_arena_game(&self, arena_id: &str, game_id: &str) -> Result<(), &'static str> {
+        match self
+            .execute(
+                "INSERT INTO ArenaGame(arena_id, game_id) VALUES(?1, ?2)",
+                params![arena_id, game_id],
+            )
+            .await
+        {
+            Err(e) => {
+                error!(
+                    "Could not add game {} to arena {}: {}",
+                    game_id, arena_id, e
+                );
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountStore for DB {
+    async fn create_api_key(
+        &self,
+        id: &str,
+        label: &str,
+        key_hash: &str,
+        role: &str,
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "INSERT INTO ApiKey(key_id, label, key_hash, role, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![id, label, key_hash, role, now_str],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not add api key {} to DB: {}", label, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT key_id, label, role, created_at, revoked_at FROM ApiKey WHERE key_hash = ?1",
+                params![key_hash],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get api key: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        match rows.next().await.unwrap() {
+            None => Ok(None),
+            Some(row) => Ok(Some(de::from_row::<ApiKey>(&row).unwrap())),
+        }
+    }
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT key_id, label, role, created_at, revoked_at FROM ApiKey ORDER BY created_at",
+                (),
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get api keys: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut keys: Vec<ApiKey> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            keys.push(de::from_row::<ApiKey>(&row).unwrap());
+        }
+
+        Ok(keys)
+    }
+    async fn revoke_api_key(&self, id: &str) -> Result<bool, &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "UPDATE ApiKey SET revoked_at = ?1 WHERE key_id = ?2 AND revoked_at IS NULL",
+                params![now_str, id],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not revoke api key {}: {}", id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(changed) => Ok(changed > 0),
+        }
+    }
+    async fn get_rating_history(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<RatingHistoryEntry>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT pool, game_id, rating, deviation, created_at FROM RatingHistory
+                WHERE user_id = ?1 ORDER BY history_id",
+                params![user_id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get rating history of user {}: {}", user_id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut history: Vec<RatingHistoryEntry> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            history.push(de::from_row::<RatingHistoryEntry>(&row).unwrap());
+        }
+
+        Ok(history)
+    }
+    async fn get_leaderboard(
+        &self,
+        pool: RatingPool,
+        max_deviation: f64,
+        limit: u32,
+    ) -> Result<Vec<LeaderboardEntry>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT R.user_id, U.display_name, R.rating, R.deviation, R.games
+                FROM Rating R
+                JOIN User U ON R.user_id = U.user_id
+                WHERE R.pool = ?1 AND R.deviation <= ?2
+                ORDER BY R.rating DESC
+                LIMIT ?3",
+                params![pool.to_str(), max_deviation, limit],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get leaderboard of {}: {}", pool.to_str(), e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut leaderboard: Vec<LeaderboardEntry> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            leaderboard.push(de::from_row::<LeaderboardEntry>(&row).unwrap());
+        }
+
+        Ok(leaderboard)
+    }
+}
+
+#[async_trait]
+impl GameStore for DB {
+    async fn create_game(&self, game: &Game) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let id = game.id.to_string();
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            tx.execute(
+                "INSERT INTO Game(game_id, admin_color, result, white_user_id, black_user_id, variant, time_control, days_per_move, rated, private, created_at)
+                VALUES(?1, ?2, null, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    id.as_str(),
+                    game.admin_color.to_str(),
+                    game.players.white.clone(),
+                    game.players.black.clone(),
+                    game.variant.to_str(),
+                    game.time_control.map(|tc| tc.to_str()),
+                    game.days_per_move,
+                    game.rated,
+                    game.private,
+                    now_str
+                ],
+            )
+            .await?;
+            tx.execute(
+                "INSERT INTO GamePosition(game_id, ply, position_hash) VALUES(?1, ?2, ?3)",
+                params![id.as_str(), game.ply(), game.zobrist() as i64],
+            )
+            .await?;
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("Could not add game {} to DB: {}", id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn finish_game(
+        &self,
+        result: &str,
+        termination: &str,
+        id: &str,
+    ) -> Result<bool, &'static str> {
+        match self
+            .execute(
+                "UPDATE Game SET result = ?1, termination = ?2 WHERE game_id = ?3 AND result IS NULL",
+                params![result, termination, id],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not finish game {} in DB: {}", id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(changed) => Ok(changed > 0),
+        }
+    }
+    async fn abort_game(&self, id: &str) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "UPDATE Game SET termination = ?1, deleted_at = ?2 WHERE game_id = ?3 AND result IS NULL",
+                params![Termination::Aborted.to_str(), now_str, id],
+            )
             .await
-            .expect("Could not connect to prod database");
-        conn = db.connect().unwrap();
+        {
+            Err(e) => {
+                error!("Could not abort game {} in DB: {}", id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn insert_move(&self, id: &str, new_move: NewMove<'_>) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            tx.execute(
+                "INSERT INTO Move(ply, turn, move_notation, player, game_id, created_at, uci, time_spent_ms)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    new_move.ply,
+                    new_move.turn,
+                    new_move.move_notation,
+                    new_move.player,
+                    id,
+                    now_str.as_str(),
+                    new_move.event.uci(),
+                    new_move.time_spent_ms.map(|ms| ms as i64)
+                ],
+            )
+            .await?;
+            if let Some((result, termination)) = new_move.finished {
+                tx.execute(
+                    "UPDATE Game SET result = ?1, termination = ?2 WHERE game_id = ?3",
+                    params![result, termination, id],
+                )
+                .await?;
+            }
+            tx.execute(
+                "INSERT INTO GameEvent(game_id, seq, kind, payload, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    new_move.seq as i64,
+                    new_move.event.kind(),
+                    serde_json::to_string(new_move.event).unwrap(),
+                    now_str.as_str()
+                ],
+            )
+            .await?;
+            tx.execute(
+                "INSERT INTO GamePosition(game_id, ply, position_hash) VALUES(?1, ?2, ?3)",
+                params![id, new_move.ply, new_move.position_hash as i64],
+            )
+            .await?;
+            let (eco, opening) = match new_move.opening {
+                None => (None, None),
+                Some(opening) => (Some(opening.eco), Some(opening.name)),
+            };
+            tx.execute(
+                "UPDATE Game SET eco = ?1, opening = ?2 WHERE game_id = ?3",
+                params![eco, opening, id],
+            )
+            .await?;
+            if let Some(fen) = new_move.snapshot {
+                tx.execute(
+                    "INSERT INTO GameSnapshot(game_id, seq, ply, fen, eco, opening, created_at) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![id, new_move.seq as i64, new_move.ply, fen, eco, opening, now_str.as_str()],
+                )
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!(
+                "Could not insert move {} in game {} into DB: {}",
+                new_move.move_notation, id, e
+            );
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn get_moves(&self, id: &str) -> Result<Vec<Move>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT * FROM Move WHERE game_id = ?1
+                ORDER BY ply",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get moves from game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut moves: Vec<Move> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            moves.push(de::from_row::<Move>(&row).unwrap());
+        }
+
+        Ok(moves)
+    }
+    async fn get_active_games(&self) -> Result<Vec<DBGame>, &'static str> {
+        let games = self
+            .conn
+            .query(
+                "SELECT G.game_id AS game_id, G.admin_color, G.created_at, M.player AS last_moved
+                FROM Game G
+                LEFT JOIN (
+                    SELECT game_id, player
+                    FROM Move
+                    WHERE (game_id, ply) IN (
+                        SELECT game_id, MAX(ply)
+                        FROM Move
+                        GROUP BY game_id
+                    )
+                ) M ON G.game_id = M.game_id
+                WHERE G.result IS NULL AND G.termination IS NULL AND G.deleted_at IS NULL",
+                (),
+            )
+            .await;
+
+        if let Err(e) = games {
+            error!("Could not get active games from DB: {}", e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut games = games.unwrap();
+
+        let mut game: Vec<DBGame> = vec![];
+        while let Some(row) = games.next().await.unwrap() {
+            game.push(de::from_row::<DBGame>(&row).unwrap());
+        }
+
+        Ok(game)
     }
+    async fn get_game(&self, id: &str) -> Result<Option<Game>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT admin_color, white_user_id, black_user_id, variant, time_control,
+                    days_per_move, rated, private
+                FROM Game WHERE game_id = ?1",
+                params![id],
+            )
+            .await;
 
-    conn
-}
+        if let Err(e) = rows {
+            error!("Could not get game {} from DB: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let setup = match rows.unwrap().next().await.unwrap() {
+            None => return Ok(None),
+            Some(row) => de::from_row::<DBGameSetup>(&row).unwrap(),
+        };
 
-pub async fn seed_db(db: &Connection) {
-    db.execute_batch(
-        r#"
-    DROP TABLE IF EXISTS Move;
-    DROP TABLE IF EXISTS Game;"#,
-    )
-    .await
-    .unwrap();
-
-    db.execute(
-        "CREATE TABLE IF NOT EXISTS Game(
-        game_id TEXT PRIMARY KEY,
-        admin_color TEXT,
-        result TEXT,
-        created_at TEXT)",
-        (),
-    )
-    .await
-    .expect("Cant seed Game Table");
-
-    db.execute(
-        "CREATE TABLE IF NOT EXISTS Move(
-        move_id INTEGER PRIMARY KEY AUTOINCREMENT,
-        game_id VARCHAR(255),
-        turn INTEGER,
-        player VARCHAR(10),
-        move_notation VARCHAR(10),
-        created_at TEXT,
-        FOREIGN KEY(game_id) REFERENCES Game(game_id)
-    );",
-        (),
-    )
-    .await
-    .expect("Cant seed Move Table");
-}
+        let (game_id, admin_color) =
+            match (Uuid::parse_str(id), Color::from_name(&setup.admin_color)) {
+                (Ok(game_id), Some(admin_color)) => (game_id, admin_color),
+                _ => {
+                    error!("Game {} in DB has an invalid id or admin color", id);
+                    return Err(INTERNAL_SERVER_ERROR);
+                }
+            };
+        let mut game = Game::new(game_id, admin_color);
+        game.players.white = setup.white_user_id;
+        game.players.black = setup.black_user_id;
+        game.variant = setup
+            .variant
+            .as_deref()
+            .and_then(Variant::from_name)
+            .unwrap_or_default();
+        game.set_time_control(
+            setup
+                .time_control
+                .as_deref()
+                .and_then(TimeControl::from_name),
+        );
+        game.days_per_move = setup.days_per_move;
+        game.rated = setup.rated == Some(1);
+        game.private = setup.private == Some(1);
+        Ok(Some(game))
+    }
+    async fn append_event(
+        &self,
+        id: &str,
+        seq: Option<u64>,
+        event: &GameEvent,
+    ) -> Result<u64, &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let result = async {
+            let _writing = self.writing.lock().await;
+            let mut rows = self
+                .conn
+                .query(
+                    "INSERT INTO GameEvent(game_id, seq, kind, payload, created_at)
+                    VALUES(?1, COALESCE(?2, (SELECT COALESCE(MAX(seq), 0) + 1 FROM GameEvent WHERE game_id = ?1)), ?3, ?4, ?5)
+                    RETURNING seq",
+                    params![
+                        id,
+                        seq.map(|seq| seq as i64),
+                        event.kind(),
+                        serde_json::to_string(event).unwrap(),
+                        now_str
+                    ],
+                )
+                .await?;
+            match rows.next().await? {
+                None => Ok(0),
+                Some(row) => row.get::<u64>(0),
+            }
+        }
+        .await;
+        result.map_err(|e: libsql::Error| {
+            error!("Could not log {} event of game {}: {}", event.kind(), id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn get_events(&self, id: &str, after_seq: u64) -> Result<Vec<LoggedEvent>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT seq, payload, created_at FROM GameEvent
+                WHERE game_id = ?1 AND seq > ?2
+                ORDER BY seq",
+                params![id, after_seq as i64],
+            )
+            .await;
 
-pub struct DB {
-    pub conn: Connection,
-}
+        if let Err(e) = rows {
+            error!("Could not get events of game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Move {
-    pub move_notation: String,
-    pub turn: u32,
-    pub player: String,
-}
+        let mut events: Vec<LoggedEvent> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            let logged = de::from_row::<DBEvent>(&row).unwrap();
+            match serde_json::from_str(&logged.payload) {
+                Err(e) => {
+                    error!("Could not read event {} of game {}: {}", logged.seq, id, e);
+                    return Err(INTERNAL_SERVER_ERROR);
+                }
+                Ok(event) => events.push(LoggedEvent {
+                    seq: logged.seq,
+                    event,
+                    created_at: logged.created_at,
+                }),
+            }
+        }
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct DBGame {
-    game_id: String,
-    created_at: String,
-    last_moved: Option<String>,
-    admin_color: String,
-}
+        Ok(events)
+    }
+    async fn get_snapshot(
+        &self,
+        id: &str,
+        until_seq: Option<u64>,
+    ) -> Result<Option<Snapshot>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT seq, ply, fen, eco, opening FROM GameSnapshot
+                WHERE game_id = ?1 AND (?2 IS NULL OR seq <= ?2)
+                ORDER BY seq DESC LIMIT 1",
+                params![id, until_seq.map(|seq| seq as i64)],
+            )
+            .await;
 
-impl DB {
-    pub async fn new() -> DB {
-        let env = env::var("ENV").expect("DEV not set");
-        let conn = connect_db(&env).await;
-        if env == "dev" {
-            seed_db(&conn).await;
+        match rows {
+            Err(e) => {
+                error!("Could not get snapshot of game {}: {}", id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(mut rows) => Ok(rows
+                .next()
+                .await
+                .unwrap()
+                .map(|row| de::from_row::<Snapshot>(&row).unwrap())),
         }
-        DB { conn }
     }
-    pub async fn create_game(&self, id: &str, color: &str) -> Result<(), &'static str> {
-        let system_time = SystemTime::now();
-        let datetime = OffsetDateTime::from(system_time);
-        let now_str = datetime.format(&Rfc3339).unwrap();
+    async fn delete_snapshots(&self, id: &str, after_ply: u32) -> Result<(), &'static str> {
         match self
-            .conn
             .execute(
-                "INSERT INTO Game(game_id, admin_color, result, created_at) VALUES(?1, ?2, null, ?3)",
-                params![id, color, now_str],
+                "DELETE FROM GameSnapshot WHERE game_id = ?1 AND ply > ?2",
+                params![id, after_ply],
             )
             .await
         {
             Err(e) => {
-                error!("Could not add game {} to DB: {}", id, e);
+                error!("Could not delete snapshots of game {}: {}", id, e);
                 Err(INTERNAL_SERVER_ERROR)
             }
             Ok(_) => Ok(()),
         }
     }
-    pub async fn finish_game(&self, result: &str, id: &str) -> Result<(), &'static str> {
+    async fn insert_chat(
+        &self,
+        id: &str,
+        scope: &str,
+        author: &str,
+        message: &str,
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
         match self
-            .conn
             .execute(
-                "UPDATE Game SET result = ?1 WHERE game_id = ?2",
-                params![result, id],
+                "INSERT INTO Chat(game_id, scope, author, message, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![id, scope, author, message, now_str],
             )
             .await
         {
             Err(e) => {
-                error!("Could not finish game {} in DB: {}", id, e);
+                error!("Could not insert chat message in game {} into DB: {}", id, e);
                 Err(INTERNAL_SERVER_ERROR)
             }
             Ok(_) => Ok(()),
         }
     }
-    pub async fn insert_move(
+    async fn get_chat(&self, id: &str) -> Result<Vec<ChatMessage>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT scope, author, message, created_at FROM Chat WHERE game_id = ?1
+                ORDER BY chat_id",
+                params![id],
+            )
+            .await;
+
+        if let Err(e) = rows {
+            error!("Could not get chat from game {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        let mut messages: Vec<ChatMessage> = vec![];
+        while let Some(row) = rows.next().await.unwrap() {
+            messages.push(de::from_row::<ChatMessage>(&row).unwrap());
+        }
+
+        Ok(messages)
+    }
+    async fn insert_action(
         &self,
-        turn: u32,
         id: &str,
-        new_move: &str,
         player: &str,
+        action: &str,
     ) -> Result<(), &'static str> {
         let system_time = SystemTime::now();
         let datetime = OffsetDateTime::from(system_time);
         let now_str = datetime.format(&Rfc3339).unwrap();
         match self
-            .conn
             .execute(
-                "INSERT INTO Move(turn, move_notation, player, game_id, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
-                params![turn, new_move, player, id, now_str],
+                "INSERT INTO Action(game_id, player, action, created_at) VALUES(?1, ?2, ?3, ?4)",
+                params![id, player, action, now_str],
             )
             .await
         {
             Err(e) => {
                 error!(
-                    "Could not insert move {} in game {} into DB: {}",
-                    new_move, id, e
+                    "Could not insert action {} in game {} into DB: {}",
+                    action, id, e
                 );
                 Err(INTERNAL_SERVER_ERROR)
             }
             Ok(_) => Ok(()),
         }
     }
-    pub async fn get_moves(&self, id: &str) -> Result<Vec<Move>, &'static str> {
+    async fn delete_last_moves(
+        &self,
+        id: &str,
+        count: u32,
+        opening: Option<Opening>,
+    ) -> Result<(), &'static str> {
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            tx.execute(
+                "DELETE FROM Move WHERE move_id IN (
+                    SELECT move_id FROM Move WHERE game_id = ?1
+                    ORDER BY ply DESC LIMIT ?2
+                )",
+                params![id, count],
+            )
+            .await?;
+            // the positions of the moves taken back were never reached
+            tx.execute(
+                "DELETE FROM GamePosition WHERE game_id = ?1
+                    AND ply > (SELECT COALESCE(MAX(ply), 0) FROM Move WHERE game_id = ?1)",
+                params![id],
+            )
+            .await?;
+            tx.execute(
+                "UPDATE Game SET eco = ?1, opening = ?2 WHERE game_id = ?3",
+                params![
+                    opening.map(|opening| opening.eco),
+                    opening.map(|opening| opening.name),
+                    id
+                ],
+            )
+            .await?;
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("Could not take back {} moves in game {}: {}", count, id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn get_rating(
+        &self,
+        user_id: &str,
+        pool: RatingPool,
+    ) -> Result<Option<Rating>, &'static str> {
         let rows = self
             .conn
             .query(
-                "SELECT * FROM Move WHERE game_id = ?1 
-                ORDER BY created_at",
-                params![id],
+                "SELECT rating, deviation, volatility FROM Rating WHERE user_id = ?1 AND pool = ?2",
+                params![user_id, pool.to_str()],
             )
             .await;
 
         if let Err(e) = rows {
-            error!("Could not get moves from game {}: {}", id, e);
+            error!("Could not get rating of user {}: {}", user_id, e);
             return Err(INTERNAL_SERVER_ERROR);
         }
         let mut rows = rows.unwrap();
 
-        let mut moves: Vec<Move> = vec![];
-        while let Some(row) = rows.next().await.unwrap() {
-            moves.push(de::from_row::<Move>(&row).unwrap());
+        match rows.next().await.unwrap() {
+            None => Ok(None),
+            Some(row) => Ok(Some(de::from_row::<Rating>(&row).unwrap())),
         }
+    }
+    async fn rate_game(
+        &self,
+        game: &RatedGame,
+    ) -> Result<Option<[(Rating, Rating); 2]>, &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let pool = game.pool.to_str();
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            let mut rated = tx
+                .query(
+                    "SELECT 1 FROM RatingHistory WHERE game_id = ?1 LIMIT 1",
+                    params![game.game_id.as_str()],
+                )
+                .await?;
+            if rated.next().await?.is_some() {
+                return Ok(None);
+            }
+            let mut ratings = [Rating::default(); 2];
+            for (user_id, rating) in [&game.white, &game.black].into_iter().zip(&mut ratings) {
+                let mut rows = tx
+                    .query(
+                        "SELECT rating, deviation, volatility FROM Rating WHERE user_id = ?1 AND pool = ?2",
+                        params![user_id.as_str(), pool.as_str()],
+                    )
+                    .await?;
+                if let Some(row) = rows.next().await? {
+                    *rating = de::from_row::<Rating>(&row).unwrap();
+                }
+            }
 
-        Ok(moves)
+            let [white, black] = ratings;
+            let (new_white, new_black) = game.rate(white, black);
+            for (user_id, rating) in [(&game.white, new_white), (&game.black, new_black)] {
+                tx.execute(
+                    "INSERT INTO Rating(user_id, pool, rating, deviation, volatility, games, updated_at)
+                    VALUES(?1, ?2, ?3, ?4, ?5, 1, ?6)
+                    ON CONFLICT(user_id, pool) DO UPDATE SET
+                        rating = excluded.rating,
+                        deviation = excluded.deviation,
+                        volatility = excluded.volatility,
+                        games = games + 1,
+                        updated_at = excluded.updated_at",
+                    params![
+                        user_id.as_str(),
+                        pool.as_str(),
+                        rating.rating,
+                        rating.deviation,
+                        rating.volatility,
+                        now_str.as_str()
+                    ],
+                )
+                .await?;
+                tx.execute(
+                    "INSERT INTO RatingHistory(user_id, pool, game_id, rating, deviation, volatility, created_at)
+                    VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        user_id.as_str(),
+                        pool.as_str(),
+                        game.game_id.as_str(),
+                        rating.rating,
+                        rating.deviation,
+                        rating.volatility,
+                        now_str.as_str()
+                    ],
+                )
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(Some([(white, new_white), (black, new_black)]))
+        }
+        .await;
+        result.map_err(|e: libsql::Error| {
+            error!("Could not rate game {}: {}", game.game_id, e);
+            INTERNAL_SERVER_ERROR
+        })
     }
-    pub async fn get_active_games(&self) -> Result<Vec<DBGame>, &'static str> {
-        let games = self
+    async fn add_to_explorer(
+        &self,
+        game_id: &str,
+        result: &str,
+        average_rating: Option<f64>,
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        let (white_wins, draws, black_wins) = result_counts(result);
+        let result = async {
+            let (_writing, tx) = self.transaction().await?;
+            let added = tx
+                .execute(
+                    "INSERT OR IGNORE INTO ExplorerGame(game_id, indexed_at, average_rating)
+                    SELECT game_id, ?2, ?3 FROM Game
+                    WHERE game_id = ?1 AND COALESCE(private, 0) = 0 AND deleted_at IS NULL",
+                    params![game_id, now_str.as_str(), average_rating],
+                )
+                .await?;
+            if added > 0 {
+                // the same move from a repeated position counts once per game
+                tx.execute(
+                    "INSERT INTO ExplorerMove(position_hash, uci, notation, games, white_wins, draws, black_wins, rating_sum, rated_games)
+                    SELECT P.position_hash, M.uci, MIN(M.move_notation), 1, ?2, ?3, ?4, COALESCE(?5, 0), ?6
+                    FROM GamePosition P JOIN Move M ON M.game_id = P.game_id AND M.ply = P.ply + 1
+                    WHERE P.game_id = ?1 AND M.uci IS NOT NULL
+                    GROUP BY P.position_hash, M.uci
+                    ON CONFLICT(position_hash, uci) DO UPDATE SET
+                        games = games + 1,
+                        white_wins = white_wins + excluded.white_wins,
+                        draws = draws + excluded.draws,
+                        black_wins = black_wins + excluded.black_wins,
+                        rating_sum = rating_sum + excluded.rating_sum,
+                        rated_games = rated_games + excluded.rated_games",
+                    params![
+                        game_id,
+                        white_wins,
+                        draws,
+                        black_wins,
+                        average_rating,
+                        average_rating.is_some() as i64
+                    ],
+                )
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        result.map_err(|e| {
+            error!("Could not add game {} to the explorer: {}", game_id, e);
+            INTERNAL_SERVER_ERROR
+        })
+    }
+    async fn finish_pairing(&self, game_id: &str, result: &str) -> Result<(), &'static str> {
+        match self
+            .execute(
+                "UPDATE TournamentPairing SET result = ?1 WHERE game_id = ?2",
+                params![result, game_id],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not finish pairing of game {}: {}", game_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn create_invite(
+        &self,
+        token_hash: &str,
+        game_id: &str,
+        created_by: &str,
+        seat: &str,
+        password_hash: Option<String>,
+        expires_at: Option<String>,
+    ) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "INSERT INTO Invite(token_hash, game_id, created_by, seat, password_hash, expires_at, created_at)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![token_hash, game_id, created_by, seat, password_hash, expires_at, now_str],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not add invite to game {}: {}", game_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn get_invite(&self, token_hash: &str) -> Result<Option<Invite>, &'static str> {
+        let rows = self
             .conn
             .query(
-                "SELECT G.game_id AS game_id, G.admin_color, G.created_at, M.player AS last_moved
-                FROM Game G
-                LEFT JOIN (
-                    SELECT game_id, player
-                    FROM Move
-                    WHERE (game_id, created_at) IN (
-                        SELECT game_id, MAX(created_at)
-                        FROM Move
-                        GROUP BY game_id
-                    )
-                ) M ON G.game_id = M.game_id
-                WHERE G.result IS NULL",
-                (),
+                "SELECT game_id, created_by, seat, password_hash, expires_at, accepted_by
+                FROM Invite WHERE token_hash = ?1",
+                params![token_hash],
             )
             .await;
 
-        if let Err(e) = games {
-            error!("Could not get active games from DB: {}", e);
+        if let Err(e) = rows {
+            error!("Could not get invite: {}", e);
             return Err(INTERNAL_SERVER_ERROR);
         }
-        let mut games = games.unwrap();
+        let mut rows = rows.unwrap();
 
-        let mut game: Vec<DBGame> = vec![];
-        while let Some(row) = games.next().await.unwrap() {
-            game.push(de::from_row::<DBGame>(&row).unwrap());
+        match rows.next().await.unwrap() {
+            None => Ok(None),
+            Some(row) => Ok(Some(de::from_row::<Invite>(&row).unwrap())),
+        }
+    }
+    async fn accept_invite(&self, token_hash: &str, user_id: &str) -> Result<bool, &'static str> {
+        match self
+            .execute(
+                "UPDATE Invite SET accepted_by = ?1 WHERE token_hash = ?2 AND accepted_by IS NULL",
+                params![user_id, token_hash],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not accept invite: {}", e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(changed) => Ok(changed > 0),
+        }
+    }
+    async fn set_player(
+        &self,
+        game_id: &str,
+        color: Color,
+        user_id: &str,
+    ) -> Result<(), &'static str> {
+        let query = match color {
+            Color::WHITE => "UPDATE Game SET white_user_id = ?1 WHERE game_id = ?2",
+            Color::BLACK => "UPDATE Game SET black_user_id = ?1 WHERE game_id = ?2",
+        };
+        match self.execute(query, params![user_id, game_id]).await {
+            Err(e) => {
+                error!("Could not seat user {} in game {}: {}", user_id, game_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+    async fn create_user(&self, user: &User) -> Result<(), &'static str> {
+        let system_time = SystemTime::now();
+        let datetime = OffsetDateTime::from(system_time);
+        let now_str = datetime.format(&Rfc3339).unwrap();
+        match self
+            .execute(
+                "INSERT INTO User(user_id, display_name, email, role, created_at) VALUES(?1, ?2, ?3, ?4, ?5)",
+                params![
+                    user.user_id.as_str(),
+                    user.display_name.as_str(),
+                    user.email.as_str(),
+                    user.role.as_str(),
+                    now_str
+                ],
+            )
+            .await
+        {
+            Err(e) => {
+                error!("Could not add user {} to DB: {}", user.user_id, e);
+                Err(INTERNAL_SERVER_ERROR)
+            }
+            Ok(_) => Ok(()),
         }
+    }
+    async fn get_user(&self, id: &str) -> Result<Option<User>, &'static str> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT user_id, display_name, email, role FROM User WHERE user_id = ?1",
+                params![id],
+            )
+            .await;
 
-        Ok(game)
+        if let Err(e) = rows {
+            error!("Could not get user {}: {}", id, e);
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        let mut rows = rows.unwrap();
+
+        match rows.next().await.unwrap() {
+            None => Ok(None),
+            Some(row) => Ok(Some(de::from_row::<User>(&row).unwrap())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_db {
+    use uuid::Uuid;
+
+    use crate::{
+        backup::ExportedGame,
+        game::{chess_piece::Color, event::GameEvent, Game, Players},
+        store::{ArchiveStore, ExplorerStore, GameStore, NewMove},
+    };
+
+    use super::{GameFilter, DB};
+
+    async fn play(db: &DB, game: &mut Game, moves: &[(&str, &str)]) {
+        let id = game.id.to_string();
+        for (from, to) in moves {
+            game.validate_and_make_move(from, to, ' ').unwrap();
+            let event = GameEvent::Move {
+                from: from.to_string(),
+                to: to.to_string(),
+                promotion: ' ',
+            };
+            let new_move = NewMove {
+                ply: game.ply(),
+                turn: game.turn_number,
+                move_notation: &game.previous_move,
+                player: "WHITE",
+                finished: None,
+                seq: game.ply() as u64,
+                event: &event,
+                snapshot: None,
+                position_hash: game.zobrist(),
+                opening: game.opening,
+                time_spent_ms: None,
+            };
+            db.insert_move(&id, new_move).await.unwrap();
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_search_games() {
+        let db = DB::local(":memory:").await;
+        let mut italian = Game::new(Uuid::new_v4(), Color::WHITE);
+        italian.players.white = Some("alice".to_string());
+        db.create_game(&italian).await.unwrap();
+        play(
+            &db,
+            &mut italian,
+            &[("e2", "e4"), ("e7", "e5"), ("g1", "f3")],
+        )
+        .await;
+        db.finish_game("1-0", "resignation", &italian.id.to_string())
+            .await
+            .unwrap();
+
+        let mut private = Game::new(Uuid::new_v4(), Color::WHITE);
+        private.players.black = Some("bob".to_string());
+        private.private = true;
+        db.create_game(&private).await.unwrap();
+        // the same position, reached in another order
+        play(
+            &db,
+            &mut private,
+            &[("g1", "f3"), ("e7", "e5"), ("e2", "e4")],
+        )
+        .await;
+
+        let (total, games) = db
+            .search_games(&GameFilter::default(), true, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(games[0].game_id, italian.id.to_string());
+        assert_eq!(games[0].moves, 3);
+
+        let everything = GameFilter {
+            include_private: true,
+            ..GameFilter::default()
+        };
+        let (total, games) = db.search_games(&everything, true, 1, 1).await.unwrap();
+        assert_eq!((total, games.len()), (2, 1));
+
+        let by_position = GameFilter {
+            position_hash: Some(italian.zobrist()),
+            viewer: Some("bob".to_string()),
+            ..GameFilter::default()
+        };
+        assert_eq!(
+            db.search_games(&by_position, true, 10, 0).await.unwrap().0,
+            2
+        );
+        let by_eco = GameFilter {
+            eco: Some("C4".to_string()),
+            ..GameFilter::default()
+        };
+        let (_, games) = db.search_games(&by_eco, true, 10, 0).await.unwrap();
+        assert_eq!(games[0].opening.as_deref(), Some("King's Knight Opening"));
+        let finished = GameFilter {
+            finished: Some(true),
+            result: Some("1-0".to_string()),
+            player: Some("alice".to_string()),
+            ..everything
+        };
+        assert_eq!(db.search_games(&finished, true, 10, 0).await.unwrap().0, 1);
+
+        // positions of moves taken back are no longer found
+        db.delete_last_moves(&private.id.to_string(), 1, None)
+            .await
+            .unwrap();
+        let (total, games) = db.search_games(&by_position, true, 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(games[0].game_id, italian.id.to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_user_games() {
+        let db = DB::local(":memory:").await;
+        let mut public = Game::new(Uuid::new_v4(), Color::WHITE);
+        public.players.white = Some("alice".to_string());
+        db.create_game(&public).await.unwrap();
+        let mut private = Game::new(Uuid::new_v4(), Color::WHITE);
+        private.players.white = Some("alice".to_string());
+        private.players.black = Some("bob".to_string());
+        private.private = true;
+        db.create_game(&private).await.unwrap();
+
+        let count = |viewer: Option<&'static str>, include_private: bool| {
+            let db = &db;
+            async move {
+                db.get_user_games("alice", include_private, viewer)
+                    .await
+                    .unwrap()
+                    .len()
+            }
+        };
+        // only the players of a private game and admins see it
+        assert_eq!(count(Some("carol"), false).await, 1);
+        assert_eq!(count(None, false).await, 1);
+        assert_eq!(count(Some("bob"), false).await, 2);
+        assert_eq!(count(Some("alice"), false).await, 2);
+        assert_eq!(count(None, true).await, 2);
+
+        let games = db.get_user_games("bob", false, Some("bob")).await.unwrap();
+        assert_eq!(games[0].color, "black");
+        assert_eq!(games[0].opponent_id.as_deref(), Some("alice"));
+    }
+
+    #[actix_web::test]
+    async fn test_import_game() {
+        let db = DB::local(":memory:").await;
+        let exported = ExportedGame {
+            game_id: Uuid::new_v4().to_string(),
+            admin_color: "white".to_string(),
+            white_user_id: Some("alice".to_string()),
+            black_user_id: None,
+            variant: None,
+            time_control: None,
+            days_per_move: Some(3),
+            rated: false,
+            private: false,
+            result: Some("0-1".to_string()),
+            termination: Some("resignation".to_string()),
+            eco: None,
+            opening: None,
+            created_at: "2024-03-01T12:30:05Z".to_string(),
+            moves: vec!["e2e4".to_string(), "e7e5".to_string()],
+        };
+        let start_hash = exported.setup().unwrap().zobrist();
+        let (_, moves) = exported.replay().unwrap();
+        assert!(db.import_game(&exported, start_hash, &moves).await.unwrap());
+        assert!(!db.import_game(&exported, start_hash, &moves).await.unwrap());
+
+        let page = db.get_export_page(None, 10).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].days_per_move, Some(3));
+        assert_eq!(page[0].result.as_deref(), Some("0-1"));
+        assert_eq!(page[0].eco.as_deref(), Some("C20"));
+        let cursor = (page[0].created_at.clone(), page[0].game_id.clone());
+        assert!(db
+            .get_export_page(Some(&cursor), 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.get_move_ucis(&exported.game_id).await.unwrap(),
+            vec![Some("e2e4".to_string()), Some("e7e5".to_string())]
+        );
+
+        // the event log rebuilds the finished game
+        let game = db.get_game(&exported.game_id).await.unwrap().unwrap();
+        let events = db.get_events(&exported.game_id, 0).await.unwrap();
+        assert_eq!(events.len(), 3);
+        let game = game.replay(None, &events).unwrap();
+        assert_eq!(game.ply(), 2);
+        assert_eq!(
+            game.game_result.map(|result| result.to_str()).as_deref(),
+            Some("0-1")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_delete_games() {
+        let db = DB::local(":memory:").await;
+        let start = Game::new(Uuid::new_v4(), Color::WHITE).zobrist();
+        let mut finished = Game::new(Uuid::new_v4(), Color::WHITE);
+        db.create_game(&finished).await.unwrap();
+        play(&db, &mut finished, &[("e2", "e4"), ("e7", "e5")]).await;
+        let finished_id = finished.id.to_string();
+        db.finish_game("1-0", "resignation", &finished_id)
+            .await
+            .unwrap();
+        db.add_to_explorer(&finished_id, "1-0", Some(1500.0))
+            .await
+            .unwrap();
+        let mut abandoned = Game::new(Uuid::new_v4(), Color::WHITE);
+        abandoned.players = Players {
+            white: Some("alice".to_string()),
+            black: Some("bob".to_string()),
+        };
+        db.create_game(&abandoned).await.unwrap();
+        play(&db, &mut abandoned, &[("d2", "d4")]).await;
+        let abandoned_id = abandoned.id.to_string();
+        let mut voting = Game::new(Uuid::new_v4(), Color::WHITE);
+        db.create_game(&voting).await.unwrap();
+        play(&db, &mut voting, &[("c2", "c4")]).await;
+        let voting_id = voting.id.to_string();
+
+        // nothing is stale before now, and every unfinished game of users after it
+        assert!(db
+            .get_stale_games("2000-01-01T00:00:00Z")
+            .await
+            .unwrap()
+            .is_empty());
+        let stale = db.get_stale_games("9999-01-01T00:00:00Z").await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(
+            (stale[0].game_id.as_str(), stale[0].moves),
+            (abandoned_id.as_str(), 1)
+        );
+        db.abort_game(&abandoned_id).await.unwrap();
+        db.abort_game(&voting_id).await.unwrap();
+        assert!(db.get_active_games().await.unwrap().is_empty());
+        assert!(db
+            .get_stale_games("9999-01-01T00:00:00Z")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let everything = GameFilter {
+            include_private: true,
+            ..GameFilter::default()
+        };
+        assert_eq!(
+            db.search_games(&everything, true, 10, 0).await.unwrap().0,
+            1
+        );
+        assert_eq!(db.get_explorer_moves(start).await.unwrap().len(), 1);
+        assert!(db.soft_delete_game(&finished_id).await.unwrap());
+        assert!(!db.soft_delete_game(&finished_id).await.unwrap());
+        assert_eq!(
+            db.search_games(&everything, true, 10, 0).await.unwrap().0,
+            0
+        );
+        assert!(db.get_explorer_moves(start).await.unwrap().is_empty());
+        assert!(db.get_game(&finished_id).await.unwrap().is_some());
+
+        assert!(db.delete_game(&finished_id).await.unwrap());
+        assert!(db.get_game(&finished_id).await.unwrap().is_none());
+        assert!(db.get_moves(&finished_id).await.unwrap().is_empty());
+        assert!(!db.delete_game(&finished_id).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_explorer() {
+        let db = DB::local(":memory:").await;
+        let start = Game::new(Uuid::new_v4(), Color::WHITE).zobrist();
+        for (moves, result) in [
+            (&[("e2", "e4"), ("e7", "e5")][..], "1-0"),
+            (&[("e2", "e4"), ("c7", "c5")][..], "1/2-1/2"),
+            (&[("d2", "d4")][..], "0-1"),
+        ] {
+            let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+            db.create_game(&game).await.unwrap();
+            play(&db, &mut game, moves).await;
+            let id = game.id.to_string();
+            db.finish_game(result, "resignation", &id).await.unwrap();
+            db.add_to_explorer(&id, result, Some(1600.0)).await.unwrap();
+            // adding a game again changes nothing
+            db.add_to_explorer(&id, result, Some(1600.0)).await.unwrap();
+        }
+        let mut private = Game::new(Uuid::new_v4(), Color::WHITE);
+        private.private = true;
+        db.create_game(&private).await.unwrap();
+        play(&db, &mut private, &[("e2", "e4")]).await;
+        db.finish_game("1-0", "resignation", &private.id.to_string())
+            .await
+            .unwrap();
+        db.add_to_explorer(&private.id.to_string(), "1-0", None)
+            .await
+            .unwrap();
+
+        let moves = db.get_explorer_moves(start).await.unwrap();
+        assert_eq!(
+            moves
+                .iter()
+                .map(|played| (played.uci.as_str(), played.games))
+                .collect::<Vec<_>>(),
+            vec![("e2e4", 2), ("d2d4", 1)]
+        );
+        assert_eq!(
+            (moves[0].white_wins, moves[0].draws, moves[0].black_wins),
+            (1, 1, 0)
+        );
+        assert_eq!((moves[0].rating_sum, moves[0].rated_games), (3200.0, 2));
+
+        let after_e4 = {
+            let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+            game.validate_and_make_move("e2", "e4", ' ').unwrap();
+            game.zobrist()
+        };
+        let replies = db.get_explorer_moves(after_e4).await.unwrap();
+        assert_eq!(replies.len(), 2);
+        assert!(replies.iter().all(|played| played.games == 1));
+        assert!(db.get_unexplored_games().await.unwrap().is_empty());
     }
 }
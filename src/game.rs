@@ -1,13 +1,30 @@
 pub mod chess_piece;
+pub mod clock;
+pub mod eco;
+pub mod event;
+pub mod fen;
+pub mod san;
+pub mod time_control;
 pub mod validation;
+pub mod zobrist;
 
+#[cfg(test)]
+mod action_tests;
 #[cfg(test)]
 mod full_game_tests;
 
 use crate::game::chess_piece::{ChessPiece, Color, Piece};
+use crate::game::clock::Clock;
+use crate::game::eco::Opening;
+use crate::game::time_control::TimeControl;
 use crate::utils::convert_notation::{get_promotion_piece, get_squares_from_notation};
-use crate::utils::error::{CHECK_ERROR, NO_PIECE_SELECTED_ERROR};
+use crate::utils::error::{
+    ABORT_ERROR, BERSERK_ERROR, CHECK_ERROR, GAME_FINISHED_ERROR, NOTHING_TO_TAKE_BACK_ERROR,
+    NO_CLOCK_ERROR, NO_DRAW_OFFER_ERROR, NO_PIECE_SELECTED_ERROR, NO_TAKEBACK_OFFER_ERROR,
+    OWN_OFFER_ERROR,
+};
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
 use uuid::Uuid;
 
 use self::validation::bishop::validate_bishop_move;
@@ -23,6 +40,9 @@ pub struct Game {
     pub id: Uuid,
     pub admin_color: Color,
     pub game_result: Option<GameResult>,
+    pub termination: Option<Termination>,
+    pub draw_offer: Option<Color>,
+    pub takeback_offer: Option<Color>,
     pub turn_number: u32,
     pub next_to_move: Color,
     pub previous_move: String,
@@ -31,9 +51,95 @@ pub struct Game {
     pub can_en_passant: bool,
     pub king_position: KingPosition,
     pub field: Vec<Vec<Option<ChessPiece>>>,
+    pub move_history: Vec<MoveRecord>,
+    pub players: Players,
+    pub time_control: Option<TimeControl>,
+    pub clock: Option<Clock>,
+    // correspondence games give each player this many days for every move
+    pub days_per_move: Option<u32>,
+    pub variant: Variant,
+    pub rated: bool,
+    // private games are only open to their players and whoever accepts their invite
+    pub private: bool,
+    pub opening: Option<Opening>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    #[default]
+    Standard,
+}
+impl Variant {
+    pub fn to_str(&self) -> String {
+        match self {
+            Variant::Standard => "standard".to_string(),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "standard" => Some(Variant::Standard),
+            _ => None,
+        }
+    }
+}
+
+/// The user ids seated at a game. Games of the voting frontend have none.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Players {
+    pub white: Option<String>,
+    pub black: Option<String>,
+}
+impl Players {
+    pub fn get(&self, color: Color) -> Option<&String> {
+        match color {
+            Color::WHITE => self.white.as_ref(),
+            Color::BLACK => self.black.as_ref(),
+        }
+    }
+    pub fn set(&mut self, color: Color, user_id: String) {
+        match color {
+            Color::WHITE => self.white = Some(user_id),
+            Color::BLACK => self.black = Some(user_id),
+        }
+    }
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.white.as_deref() == Some(user_id) || self.black.as_deref() == Some(user_id)
+    }
+    pub fn seat(&self, user_id: &str) -> Option<Color> {
+        if self.white.as_deref() == Some(user_id) {
+            Some(Color::WHITE)
+        } else if self.black.as_deref() == Some(user_id) {
+            Some(Color::BLACK)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MoveRecord {
+    pub from: String,
+    pub to: String,
+    pub promotion: char,
+    pub move_notation: String,
+    #[serde(skip)]
+    pub undo: UndoInfo,
+}
+
+// Everything make_move overwrites that cannot be derived from the move itself
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndoInfo {
+    pub moved_piece: ChessPiece,
+    pub captured: Option<(ChessPiece, (usize, usize))>,
+    pub can_castle: CastlingRights,
+    pub can_en_passant: bool,
+    pub previous_move: String,
+    pub previous_move_was_enpassant: bool,
+    pub opening: Option<Opening>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct CastlingRights {
     pub white_can_short_castle: bool,
     pub white_can_long_castle: bool,
@@ -51,12 +157,79 @@ pub struct KingPosition {
 pub enum GameResult {
     WhiteWon,
     BlackWon,
+    Draw,
 }
 impl GameResult {
     pub fn to_str(&self) -> String {
         match self {
             GameResult::WhiteWon => "1-0".to_string(),
             GameResult::BlackWon => "0-1".to_string(),
+            GameResult::Draw => "1/2-1/2".to_string(),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<GameResult> {
+        match name {
+            "1-0" => Some(GameResult::WhiteWon),
+            "0-1" => Some(GameResult::BlackWon),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+    pub fn won_by(color: Color) -> GameResult {
+        match color {
+            Color::WHITE => GameResult::WhiteWon,
+            Color::BLACK => GameResult::BlackWon,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum Termination {
+    Checkmate,
+    Resignation,
+    DrawAgreement,
+    Timeout,
+    // left before both players moved, without a result
+    Aborted,
+    // nobody moved for too long and the player to move lost
+    Abandoned,
+}
+impl Termination {
+    pub fn to_str(&self) -> String {
+        match self {
+            Termination::Checkmate => "checkmate".to_string(),
+            Termination::Resignation => "resignation".to_string(),
+            Termination::DrawAgreement => "draw_agreement".to_string(),
+            Termination::Timeout => "timeout".to_string(),
+            Termination::Aborted => "aborted".to_string(),
+            Termination::Abandoned => "abandoned".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    RequestTakeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    Abort,
+}
+impl GameAction {
+    pub fn to_str(&self) -> String {
+        match self {
+            GameAction::Resign => "resign".to_string(),
+            GameAction::OfferDraw => "offer_draw".to_string(),
+            GameAction::AcceptDraw => "accept_draw".to_string(),
+            GameAction::DeclineDraw => "decline_draw".to_string(),
+            GameAction::RequestTakeback => "request_takeback".to_string(),
+            GameAction::AcceptTakeback => "accept_takeback".to_string(),
+            GameAction::DeclineTakeback => "decline_takeback".to_string(),
+            GameAction::Abort => "abort".to_string(),
         }
     }
 }
@@ -65,14 +238,22 @@ impl Game {
     pub fn new(uuid: Uuid, color: Color) -> Game {
         create_new_game(uuid, color)
     }
+    /// Whether the game has a result or was aborted.
+    pub fn is_over(&self) -> bool {
+        self.game_result.is_some() || self.termination == Some(Termination::Aborted)
+    }
     pub fn validate_and_make_move(
         &mut self,
         algebraic_from: &str,
         algebraic_to: &str,
         promotion_ch: char,
     ) -> Result<(), &'static str> {
+        if self.is_over() {
+            return Err(GAME_FINISHED_ERROR);
+        }
         self.validate_move(algebraic_from, algebraic_to, promotion_ch)?;
         self.make_move(algebraic_from, algebraic_to, promotion_ch);
+        self.classify_opening();
 
         Ok(())
     }
@@ -109,6 +290,15 @@ impl Game {
     pub fn make_move(&mut self, algebraic_from: &str, algebraic_to: &str, promotion_ch: char) {
         // we can unwrap here since we perform this function in the validation function as well
         let (from, to) = get_squares_from_notation(algebraic_from, algebraic_to).unwrap();
+        let mut undo = UndoInfo {
+            moved_piece: self.field[from.0][from.1].unwrap(),
+            captured: self.field[to.0][to.1].map(|piece| (piece, to)),
+            can_castle: self.can_castle.clone(),
+            can_en_passant: self.can_en_passant,
+            previous_move: self.previous_move.clone(),
+            previous_move_was_enpassant: self.previous_move_was_enpassant,
+            opening: self.opening,
+        };
         self.can_en_passant = false;
         self.previous_move_was_enpassant = false;
 
@@ -125,6 +315,7 @@ impl Game {
             && self.field[from.0][from.1].unwrap().piece == Piece::PAWN
             && from.1 != to.1
         {
+            undo.captured = self.field[from.0][to.1].map(|piece| (piece, (from.0, to.1)));
             self.field[from.0][to.1] = None;
             self.previous_move = "x".to_string();
             self.previous_move_was_enpassant = true;
@@ -170,17 +361,190 @@ impl Game {
             }
         }
 
+        self.move_history.push(MoveRecord {
+            from: algebraic_from.to_string(),
+            to: algebraic_to.to_string(),
+            promotion: promotion_ch,
+            move_notation: self.previous_move.clone(),
+            undo,
+        });
+
+        // making a move implicitly declines whatever the opponent offered
+        if self.draw_offer == Some(self.next_to_move) {
+            self.draw_offer = None;
+        }
+        if self.takeback_offer == Some(self.next_to_move) {
+            self.takeback_offer = None;
+        }
+
         if is_mate(self) {
-            match self.next_to_move {
-                Color::WHITE => {
-                    self.game_result = Some(GameResult::BlackWon);
+            self.game_result = Some(GameResult::won_by(self.next_to_move.opposite()));
+            self.termination = Some(Termination::Checkmate);
+        }
+    }
+    pub fn undo_move(&mut self) -> Result<(), &'static str> {
+        let record = self.move_history.pop().ok_or(NOTHING_TO_TAKE_BACK_ERROR)?;
+        let (from, to) = get_squares_from_notation(&record.from, &record.to)?;
+        let undo = record.undo;
+
+        self.field[from.0][from.1] = Some(undo.moved_piece);
+        self.field[to.0][to.1] = None;
+        if let Some((piece, square)) = undo.captured {
+            self.field[square.0][square.1] = Some(piece);
+        }
+
+        if undo.moved_piece.piece == Piece::KING {
+            // put the rook back in case we castled
+            match (from, to) {
+                ((row, 4), (_, 6)) => {
+                    self.field[row][7] = self.field[row][5];
+                    self.field[row][5] = None;
                 }
-                Color::BLACK => {
-                    self.game_result = Some(GameResult::WhiteWon);
+                ((row, 4), (_, 2)) => {
+                    self.field[row][0] = self.field[row][3];
+                    self.field[row][3] = None;
                 }
+                _ => (),
+            }
+            match undo.moved_piece.color {
+                Color::WHITE => self.king_position.white_king_position = from,
+                Color::BLACK => self.king_position.black_king_position = from,
+            }
+        }
+
+        if undo.moved_piece.color == Color::WHITE {
+            self.turn_number -= 1;
+        }
+        self.next_to_move = undo.moved_piece.color;
+        self.can_castle = undo.can_castle;
+        self.can_en_passant = undo.can_en_passant;
+        self.previous_move = undo.previous_move;
+        self.previous_move_was_enpassant = undo.previous_move_was_enpassant;
+        self.opening = undo.opening;
+        self.game_result = None;
+        self.termination = None;
+
+        Ok(())
+    }
+    /// Sets the time control and a fresh clock for it.
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>) {
+        self.time_control = time_control;
+        self.clock = time_control.map(Clock::new);
+    }
+    /// Halves the time of `player` in exchange for an extra point in arenas.
+    /// Only possible before the player made their first move.
+    pub fn berserk(&mut self, player: Color, now: Instant) -> Result<(), &'static str> {
+        if self.is_over() {
+            return Err(GAME_FINISHED_ERROR);
+        }
+        let first_move_made = match player {
+            Color::WHITE => !self.move_history.is_empty(),
+            Color::BLACK => self.move_history.len() > 1,
+        };
+        if first_move_made {
+            return Err(BERSERK_ERROR);
+        }
+
+        match &mut self.clock {
+            None => Err(NO_CLOCK_ERROR),
+            Some(clock) => {
+                clock.berserk(player, now);
+                Ok(())
             }
         }
     }
+    /// Ends the game because `player` ran out of time.
+    pub fn time_out(&mut self, player: Color) -> Result<(), &'static str> {
+        if self.is_over() {
+            return Err(GAME_FINISHED_ERROR);
+        }
+
+        self.game_result = Some(GameResult::won_by(player.opposite()));
+        self.termination = Some(Termination::Timeout);
+        self.draw_offer = None;
+        self.takeback_offer = None;
+        Ok(())
+    }
+    /// Applies a non-move action of `player` to the game and returns how many
+    /// half moves were taken back by it.
+    pub fn perform_action(
+        &mut self,
+        player: Color,
+        action: GameAction,
+    ) -> Result<usize, &'static str> {
+        if self.is_over() {
+            return Err(GAME_FINISHED_ERROR);
+        }
+
+        match action {
+            GameAction::Resign => {
+                self.game_result = Some(GameResult::won_by(player.opposite()));
+                self.termination = Some(Termination::Resignation);
+            }
+            GameAction::OfferDraw => {
+                if self.draw_offer == Some(player.opposite()) {
+                    return self.perform_action(player, GameAction::AcceptDraw);
+                }
+                self.draw_offer = Some(player);
+            }
+            GameAction::AcceptDraw => {
+                Self::check_offer(self.draw_offer, player, NO_DRAW_OFFER_ERROR)?;
+                self.draw_offer = None;
+                self.game_result = Some(GameResult::Draw);
+                self.termination = Some(Termination::DrawAgreement);
+            }
+            GameAction::DeclineDraw => {
+                Self::check_offer(self.draw_offer, player, NO_DRAW_OFFER_ERROR)?;
+                self.draw_offer = None;
+            }
+            GameAction::RequestTakeback => {
+                if !self
+                    .move_history
+                    .iter()
+                    .any(|record| record.undo.moved_piece.color == player)
+                {
+                    return Err(NOTHING_TO_TAKE_BACK_ERROR);
+                }
+                self.takeback_offer = Some(player);
+            }
+            GameAction::AcceptTakeback => {
+                let requester = player.opposite();
+                Self::check_offer(self.takeback_offer, player, NO_TAKEBACK_OFFER_ERROR)?;
+                self.takeback_offer = None;
+                self.draw_offer = None;
+
+                // take back the requesters last move, and the reply to it if there was one
+                let half_moves = if self.next_to_move == requester { 2 } else { 1 };
+                for _ in 0..half_moves {
+                    self.undo_move()?;
+                }
+                return Ok(half_moves);
+            }
+            GameAction::DeclineTakeback => {
+                Self::check_offer(self.takeback_offer, player, NO_TAKEBACK_OFFER_ERROR)?;
+                self.takeback_offer = None;
+            }
+            GameAction::Abort => {
+                if self.move_history.len() >= 2 {
+                    return Err(ABORT_ERROR);
+                }
+                self.termination = Some(Termination::Aborted);
+            }
+        }
+
+        Ok(0)
+    }
+    fn check_offer(
+        offer: Option<Color>,
+        player: Color,
+        missing_offer_error: &'static str,
+    ) -> Result<(), &'static str> {
+        match offer {
+            None => Err(missing_offer_error),
+            Some(offered_by) if offered_by == player => Err(OWN_OFFER_ERROR),
+            Some(_) => Ok(()),
+        }
+    }
     fn make_rook_move(&mut self, from: (usize, usize)) {
         self.previous_move.insert(0, 'R');
 
@@ -268,6 +632,9 @@ fn create_new_game(uuid: Uuid, color: Color) -> Game {
         id: uuid,
         admin_color: color,
         game_result: None,
+        termination: None,
+        draw_offer: None,
+        takeback_offer: None,
         turn_number: 0,
         previous_move: "".to_string(),
         previous_move_was_enpassant: false,
@@ -279,6 +646,15 @@ fn create_new_game(uuid: Uuid, color: Color) -> Game {
             black_can_long_castle: true,
         },
         can_en_passant: false,
+        move_history: vec![],
+        players: Players::default(),
+        time_control: None,
+        clock: None,
+        days_per_move: None,
+        variant: Variant::Standard,
+        rated: false,
+        private: false,
+        opening: None,
         king_position: {
             KingPosition {
                 white_king_position: (7, 4),
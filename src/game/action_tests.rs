@@ -0,0 +1,161 @@
+use uuid::Uuid;
+
+use crate::game::{
+    chess_piece::{ChessPiece, Color, Piece},
+    Game, GameAction, GameResult, Termination,
+};
+
+#[test]
+fn resign() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+
+    game.perform_action(Color::BLACK, GameAction::Resign)
+        .expect("Expected black to be able to resign");
+    assert_eq!(game.game_result, Some(GameResult::WhiteWon));
+    assert_eq!(game.termination, Some(Termination::Resignation));
+
+    if game.validate_and_make_move("e7", "e5", ' ').is_ok() {
+        panic!("Expected moves to fail after resigning");
+    }
+}
+
+#[test]
+fn draw_offer_accepted() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.perform_action(Color::WHITE, GameAction::OfferDraw)
+        .expect("Expected white to be able to offer a draw");
+
+    if game
+        .perform_action(Color::WHITE, GameAction::AcceptDraw)
+        .is_ok()
+    {
+        panic!("Expected white to not be able to accept their own draw offer");
+    }
+
+    game.perform_action(Color::BLACK, GameAction::AcceptDraw)
+        .expect("Expected black to be able to accept the draw");
+    assert_eq!(game.game_result, Some(GameResult::Draw));
+    assert_eq!(game.termination, Some(Termination::DrawAgreement));
+}
+
+#[test]
+fn draw_offer_declined_by_moving() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.perform_action(Color::WHITE, GameAction::OfferDraw)
+        .expect("Expected white to be able to offer a draw");
+    game.validate_and_make_move("e7", "e5", ' ').expect("e5");
+
+    assert_eq!(game.draw_offer, None);
+    if game
+        .perform_action(Color::BLACK, GameAction::AcceptDraw)
+        .is_ok()
+    {
+        panic!("Expected the draw offer to be gone after black moved");
+    }
+}
+
+#[test]
+fn takeback_after_reply() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    let start = game.clone();
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.validate_and_make_move("e7", "e5", ' ').expect("e5");
+
+    game.perform_action(Color::WHITE, GameAction::RequestTakeback)
+        .expect("Expected white to be able to request a takeback");
+    let taken_back = game
+        .perform_action(Color::BLACK, GameAction::AcceptTakeback)
+        .expect("Expected black to be able to accept the takeback");
+
+    assert_eq!(taken_back, 2);
+    assert_eq!(game.field, start.field);
+    assert_eq!(game.next_to_move, Color::WHITE);
+    assert_eq!(game.turn_number, 0);
+    assert!(game.move_history.is_empty());
+}
+
+#[test]
+fn takeback_without_moves() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+
+    if game
+        .perform_action(Color::BLACK, GameAction::RequestTakeback)
+        .is_ok()
+    {
+        panic!("Expected black to have nothing to take back");
+    }
+}
+
+#[test]
+fn undo_castling() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.field[7][5] = None;
+    game.field[7][6] = None;
+    let before = game.clone();
+
+    game.validate_and_make_move("e1", "g1", ' ').expect("0-0");
+    game.undo_move().expect("Expected castling to be undone");
+
+    assert_eq!(game.field, before.field);
+    assert_eq!(game.can_castle, before.can_castle);
+    assert_eq!(game.king_position.white_king_position, (7, 4));
+    assert_eq!(game.previous_move, before.previous_move);
+}
+
+#[test]
+fn undo_en_passant() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.validate_and_make_move("a7", "a6", ' ').expect("a6");
+    game.validate_and_make_move("e4", "e5", ' ').expect("e5");
+    game.validate_and_make_move("d7", "d5", ' ').expect("d5");
+    let before = game.clone();
+
+    game.validate_and_make_move("e5", "d6", ' ').expect("xd6");
+    assert_eq!(game.field[3][3], None);
+    game.undo_move().expect("Expected en passant to be undone");
+
+    assert_eq!(game.field, before.field);
+    assert!(game.can_en_passant);
+    assert_eq!(game.previous_move, "d5");
+}
+
+#[test]
+fn undo_promotion_with_capture() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.field[1][0] = Some(ChessPiece {
+        piece: Piece::PAWN,
+        color: Color::WHITE,
+    });
+    let before = game.clone();
+
+    game.validate_and_make_move("a7", "b8", 'Q').expect("xb8=Q");
+    game.undo_move().expect("Expected promotion to be undone");
+
+    assert_eq!(game.field, before.field);
+    assert_eq!(game.next_to_move, Color::WHITE);
+}
+
+#[test]
+fn abort() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.perform_action(Color::BLACK, GameAction::Abort)
+        .expect("Expected black to be able to abort before their first move");
+    assert_eq!(game.game_result, None);
+    assert_eq!(game.termination, Some(Termination::Aborted));
+    assert!(game.is_over());
+    if game.validate_and_make_move("e7", "e5", ' ').is_ok() {
+        panic!("Expected moves to fail after aborting");
+    }
+
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.validate_and_make_move("e7", "e5", ' ').expect("e5");
+    if game.perform_action(Color::WHITE, GameAction::Abort).is_ok() {
+        panic!("Expected aborting to fail once both players moved");
+    }
+}
@@ -16,7 +16,7 @@ pub enum Piece {
     PAWN,
 }
 
-#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Color {
     BLACK,
     WHITE,
@@ -29,6 +29,19 @@ impl Color {
             Color::BLACK => "black".to_string(),
         }
     }
+    pub fn from_name(name: &str) -> Option<Color> {
+        match name {
+            "white" => Some(Color::WHITE),
+            "black" => Some(Color::BLACK),
+            _ => None,
+        }
+    }
+    pub fn opposite(&self) -> Color {
+        match self {
+            Color::WHITE => Color::BLACK,
+            Color::BLACK => Color::WHITE,
+        }
+    }
     pub fn opposite_color(&self) -> String {
         match self {
             Color::WHITE => "black".to_string(),
@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use crate::game::{chess_piece::Color, time_control::TimeControl};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ClockSide {
+    remaining: Duration,
+    increment: Duration,
+}
+
+/// A chess clock for games with a time control. It starts with white's first
+/// move, after that only the side to move has its time running and every move
+/// adds the increment to the mover's time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clock {
+    white: ClockSide,
+    black: ClockSide,
+    // whose time is running and since when
+    running: Option<(Color, Instant)>,
+}
+impl Clock {
+    pub fn new(time_control: TimeControl) -> Clock {
+        let side = ClockSide {
+            remaining: Duration::from_secs(time_control.initial_secs),
+            increment: Duration::from_secs(time_control.increment_secs),
+        };
+        Clock {
+            white: side,
+            black: side,
+            running: None,
+        }
+    }
+    fn side_mut(&mut self, color: Color) -> &mut ClockSide {
+        match color {
+            Color::WHITE => &mut self.white,
+            Color::BLACK => &mut self.black,
+        }
+    }
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let side = match color {
+            Color::WHITE => self.white,
+            Color::BLACK => self.black,
+        };
+        match self.running {
+            Some((running, since)) if running == color => side
+                .remaining
+                .saturating_sub(now.saturating_duration_since(since)),
+            _ => side.remaining,
+        }
+    }
+    /// How long `color` has been thinking, `None` while their time is not running.
+    pub fn elapsed(&self, color: Color, now: Instant) -> Option<Duration> {
+        match self.running {
+            Some((running, since)) if running == color => {
+                Some(now.saturating_duration_since(since))
+            }
+            _ => None,
+        }
+    }
+    /// Stops the time of `mover` after their move and starts the opponent's.
+    pub fn press(&mut self, mover: Color, now: Instant) {
+        if self.running() == Some(mover) {
+            let remaining = self.remaining(mover, now);
+            let side = self.side_mut(mover);
+            side.remaining = remaining + side.increment;
+        }
+        self.running = Some((mover.opposite(), now));
+    }
+    pub fn stop(&mut self, now: Instant) {
+        if let Some(color) = self.running() {
+            let remaining = self.remaining(color, now);
+            self.side_mut(color).remaining = remaining;
+        }
+        self.running = None;
+    }
+    /// The player whose time ran out, if any.
+    pub fn flagged(&self, now: Instant) -> Option<Color> {
+        self.running()
+            .filter(|color| self.remaining(*color, now).is_zero())
+    }
+    /// Halves the time left of `color` and drops their increment.
+    pub fn berserk(&mut self, color: Color, now: Instant) {
+        let remaining = self.remaining(color, now);
+        if self.running() == Some(color) {
+            self.running = Some((color, now));
+        }
+        let side = self.side_mut(color);
+        side.remaining = remaining / 2;
+        side.increment = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod test_clock {
+    use std::time::{Duration, Instant};
+
+    use crate::game::{chess_piece::Color, time_control::TimeControl};
+
+    use super::Clock;
+
+    fn clock() -> Clock {
+        Clock::new(TimeControl {
+            initial_secs: 60,
+            increment_secs: 2,
+        })
+    }
+
+    #[test]
+    fn test_press() {
+        let mut clock = clock();
+        let start = Instant::now();
+
+        // white's first move is free
+        clock.press(Color::WHITE, start + Duration::from_secs(10));
+        assert_eq!(
+            clock.remaining(Color::WHITE, start),
+            Duration::from_secs(60)
+        );
+        assert_eq!(clock.running(), Some(Color::BLACK));
+
+        clock.press(Color::BLACK, start + Duration::from_secs(15));
+        assert_eq!(
+            clock.remaining(Color::BLACK, start),
+            Duration::from_secs(57)
+        );
+
+        let now = start + Duration::from_secs(25);
+        assert_eq!(clock.remaining(Color::WHITE, now), Duration::from_secs(50));
+        assert_eq!(
+            clock.elapsed(Color::WHITE, now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(clock.elapsed(Color::BLACK, now), None);
+        clock.stop(now);
+        assert_eq!(clock.running(), None);
+        assert_eq!(
+            clock.remaining(Color::WHITE, now + Duration::from_secs(100)),
+            Duration::from_secs(50)
+        );
+    }
+
+    #[test]
+    fn test_flag() {
+        let mut clock = clock();
+        let start = Instant::now();
+        clock.press(Color::WHITE, start);
+
+        assert_eq!(clock.flagged(start + Duration::from_secs(59)), None);
+        assert_eq!(
+            clock.flagged(start + Duration::from_secs(60)),
+            Some(Color::BLACK)
+        );
+    }
+
+    #[test]
+    fn test_berserk() {
+        let mut clock = clock();
+        let start = Instant::now();
+        clock.berserk(Color::BLACK, start);
+        clock.press(Color::WHITE, start);
+
+        assert_eq!(
+            clock.remaining(Color::BLACK, start),
+            Duration::from_secs(30)
+        );
+        // no increment after berserking
+        clock.press(Color::BLACK, start + Duration::from_secs(10));
+        assert_eq!(
+            clock.remaining(Color::BLACK, start),
+            Duration::from_secs(20)
+        );
+    }
+}
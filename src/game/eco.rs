@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+use crate::game::{chess_piece::Color, Game};
+
+// one opening per line: ECO code, name and its moves in UCI notation, tab separated
+const ECO_TABLE: &str = include_str!("eco.tsv");
+
+/// A named opening of the Encyclopaedia of Chess Openings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+}
+impl Opening {
+    pub fn find(eco: &str, name: &str) -> Option<Opening> {
+        table()
+            .map(|(opening, _)| opening)
+            .find(|opening| opening.eco == eco && opening.name == name)
+    }
+}
+
+/// Whether `code` is an ECO code or the start of one, like `B`, `B9` or `B90`.
+pub fn is_eco_prefix(code: &str) -> bool {
+    let mut chars = code.chars();
+    let volume = chars.next().filter(|volume| ('A'..='E').contains(volume));
+    volume.is_some() && code.len() <= 3 && chars.all(|ch| ch.is_ascii_digit())
+}
+
+fn table() -> impl Iterator<Item = (Opening, &'static str)> {
+    ECO_TABLE.lines().filter_map(|line| {
+        let mut columns = line.split('\t');
+        let opening = Opening {
+            eco: columns.next()?,
+            name: columns.next()?,
+        };
+        Some((opening, columns.next()?))
+    })
+}
+
+// the openings by the position their moves lead to, so transpositions are
+// classified too. Several lines can reach a position, the last one wins.
+static OPENINGS: Lazy<HashMap<u64, Opening>> = Lazy::new(|| {
+    table()
+        .map(|(opening, moves)| {
+            let mut game = Game::new(Uuid::nil(), Color::WHITE);
+            for uci in moves.split_whitespace() {
+                let promotion = uci.chars().nth(4).map_or(' ', |ch| ch.to_ascii_uppercase());
+                if let Err(e) = game.validate_move(&uci[..2], &uci[2..4], promotion) {
+                    panic!("Invalid move {} of {}: {}", uci, opening.name, e);
+                }
+                game.make_move(&uci[..2], &uci[2..4], promotion);
+            }
+            (game.zobrist(), opening)
+        })
+        .collect()
+});
+
+impl Game {
+    /// Tags the game with the opening of its position, if it has a name. The
+    /// tag stays when the game leaves the book.
+    pub fn classify_opening(&mut self) {
+        if let Some(opening) = OPENINGS.get(&self.zobrist()) {
+            self.opening = Some(*opening);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_eco {
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, Game, GameAction};
+
+    use super::{is_eco_prefix, table, Opening, OPENINGS};
+
+    fn play(game: &mut Game, moves: &[(&str, &str)]) {
+        for (from, to) in moves {
+            game.validate_and_make_move(from, to, ' ').unwrap();
+        }
+    }
+
+    #[test]
+    fn test_table_is_valid() {
+        // building the index replays every line
+        assert!(!OPENINGS.is_empty());
+        for (opening, _) in table() {
+            assert_eq!(opening.eco.len(), 3);
+            assert_eq!(Opening::find(opening.eco, opening.name), Some(opening));
+        }
+    }
+
+    #[test]
+    fn test_opening_follows_moves() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        play(
+            &mut game,
+            &[("e2", "e4"), ("c7", "c5"), ("g1", "f3"), ("d7", "d6")],
+        );
+        play(
+            &mut game,
+            &[("d2", "d4"), ("c5", "d4"), ("f3", "d4"), ("g8", "f6")],
+        );
+        play(&mut game, &[("b1", "c3")]);
+        assert_eq!(game.opening.unwrap().eco, "B54");
+        play(&mut game, &[("a7", "a6")]);
+        assert_eq!(
+            game.opening.unwrap().name,
+            "Sicilian Defense: Najdorf Variation"
+        );
+        // leaving the book keeps the last opening
+        play(&mut game, &[("c1", "e3")]);
+        assert_eq!(game.opening.unwrap().eco, "B90");
+
+        // takes back both Be3 and a6
+        game.perform_action(Color::BLACK, GameAction::RequestTakeback)
+            .unwrap();
+        game.perform_action(Color::WHITE, GameAction::AcceptTakeback)
+            .unwrap();
+        assert_eq!(game.opening.unwrap().eco, "B54");
+    }
+
+    #[test]
+    fn test_eco_prefix() {
+        for code in ["B", "B9", "B90", "E00"] {
+            assert!(is_eco_prefix(code));
+        }
+        for code in ["", "F1", "B900", "B%", "b9"] {
+            assert!(!is_eco_prefix(code));
+        }
+    }
+
+    #[test]
+    fn test_transposition() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        play(&mut game, &[("g1", "f3"), ("d7", "d5"), ("d2", "d4")]);
+        assert_eq!(game.opening.unwrap().eco, "D02");
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::chess_piece::Color;
+use crate::game::eco::Opening;
+use crate::game::{Game, GameAction, GameResult};
+use crate::utils::error::INVALID_EVENT_ERROR;
+
+// How many half moves lie between two snapshots of a game
+pub const SNAPSHOT_INTERVAL: u32 = 20;
+
+/// Everything that happened in a game. Folding the events of a game in order
+/// over a new `Game` gives its current state.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    Move {
+        from: String,
+        to: String,
+        promotion: char,
+    },
+    Action {
+        player: String,
+        action: GameAction,
+    },
+    Flag {
+        player: String,
+    },
+    Berserk {
+        player: String,
+    },
+    // a result set by an admin or the server, like a missed correspondence deadline
+    Finish {
+        result: String,
+        termination: String,
+    },
+    Chat {
+        scope: String,
+        author: String,
+        message: String,
+    },
+}
+impl GameEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GameEvent::Move { .. } => "move",
+            GameEvent::Action { .. } => "action",
+            GameEvent::Flag { .. } => "flag",
+            GameEvent::Berserk { .. } => "berserk",
+            GameEvent::Finish { .. } => "finish",
+            GameEvent::Chat { .. } => "chat",
+        }
+    }
+    /// A move in UCI notation, like `e2e4` or `e7e8q`.
+    pub fn uci(&self) -> Option<String> {
+        match self {
+            GameEvent::Move {
+                from,
+                to,
+                promotion: ' ',
+            } => Some(format!("{}{}", from, to)),
+            GameEvent::Move {
+                from,
+                to,
+                promotion,
+            } => Some(format!("{}{}{}", from, to, promotion.to_ascii_lowercase())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LoggedEvent {
+    pub seq: u64,
+    pub event: GameEvent,
+    pub created_at: String,
+}
+
+/// The position of a game after the event with sequence number `seq`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Snapshot {
+    pub seq: u64,
+    pub ply: u32,
+    pub fen: String,
+    // the opening the game was in, which the position alone does not tell
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+}
+
+impl Game {
+    /// Applies an event that already happened. Moves are validated again to
+    /// catch a corrupted log, everything else is taken as a fact.
+    pub fn apply_event(&mut self, event: &GameEvent) -> Result<(), &'static str> {
+        let color = |player: &str| Color::from_name(player).ok_or(INVALID_EVENT_ERROR);
+        match event {
+            GameEvent::Move {
+                from,
+                to,
+                promotion,
+            } => self.validate_and_make_move(from, to, *promotion),
+            // the move the request refers to may lie before a snapshot
+            GameEvent::Action {
+                player,
+                action: GameAction::RequestTakeback,
+            } => {
+                self.takeback_offer = Some(color(player)?);
+                Ok(())
+            }
+            GameEvent::Action { player, action } => {
+                self.perform_action(color(player)?, *action).map(|_| ())
+            }
+            GameEvent::Flag { player } => self.time_out(color(player)?),
+            GameEvent::Finish { result, .. } => {
+                self.game_result = GameResult::from_name(result);
+                Ok(())
+            }
+            GameEvent::Berserk { .. } | GameEvent::Chat { .. } => Ok(()),
+        }
+    }
+    /// Rebuilds a game from an optional snapshot and the events after it.
+    pub fn replay(
+        mut self,
+        snapshot: Option<&Snapshot>,
+        events: &[LoggedEvent],
+    ) -> Result<Game, &'static str> {
+        let after = match snapshot {
+            None => 0,
+            Some(snapshot) => {
+                self.load_fen(&snapshot.fen)?;
+                self.opening = match (&snapshot.eco, &snapshot.opening) {
+                    (Some(eco), Some(name)) => Opening::find(eco, name),
+                    _ => None,
+                };
+                snapshot.seq
+            }
+        };
+        for logged in events.iter().filter(|logged| logged.seq > after) {
+            self.apply_event(&logged.event)?;
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test_event {
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, Game, GameAction, GameResult};
+
+    use super::{GameEvent, LoggedEvent, Snapshot};
+
+    fn log(events: Vec<GameEvent>) -> Vec<LoggedEvent> {
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| LoggedEvent {
+                seq: i as u64 + 1,
+                event,
+                created_at: "".to_string(),
+            })
+            .collect()
+    }
+
+    fn play(from: &str, to: &str) -> GameEvent {
+        GameEvent::Move {
+            from: from.to_string(),
+            to: to.to_string(),
+            promotion: ' ',
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let events = log(vec![
+            play("e2", "e4"),
+            play("e7", "e5"),
+            GameEvent::Chat {
+                scope: "players".to_string(),
+                author: "white".to_string(),
+                message: "hi".to_string(),
+            },
+            play("g1", "f3"),
+            GameEvent::Action {
+                player: "black".to_string(),
+                action: GameAction::Resign,
+            },
+        ]);
+        let game = Game::new(Uuid::new_v4(), Color::WHITE)
+            .replay(None, &events)
+            .unwrap();
+        assert_eq!(game.ply(), 3);
+        assert_eq!(game.game_result, Some(GameResult::WhiteWon));
+
+        // replaying from a snapshot ends in the same position
+        let mut until_snapshot = Game::new(Uuid::new_v4(), Color::WHITE)
+            .replay(None, &events[..2])
+            .unwrap();
+        let snapshot = Snapshot {
+            seq: 2,
+            ply: until_snapshot.ply(),
+            fen: until_snapshot.to_fen(),
+            eco: None,
+            opening: None,
+        };
+        until_snapshot = Game::new(Uuid::new_v4(), Color::WHITE)
+            .replay(Some(&snapshot), &events)
+            .unwrap();
+        assert_eq!(until_snapshot.to_fen(), game.to_fen());
+        assert_eq!(until_snapshot.game_result, game.game_result);
+    }
+
+    #[test]
+    fn test_corrupted_log() {
+        let events = log(vec![play("e2", "e4"), play("e2", "e4")]);
+        assert!(Game::new(Uuid::new_v4(), Color::WHITE)
+            .replay(None, &events)
+            .is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::game::chess_piece::{ChessPiece, Color, Piece};
+use crate::game::{CastlingRights, Game, KingPosition};
+use crate::utils::convert_notation::get_notation_from_square;
+use crate::utils::error::INVALID_FEN_ERROR;
+
+fn piece_char(piece: ChessPiece) -> char {
+    let ch = match piece.piece {
+        Piece::KING => 'k',
+        Piece::QUEEN => 'q',
+        Piece::ROOK => 'r',
+        Piece::BISHOP => 'b',
+        Piece::KNIGHT => 'n',
+        Piece::PAWN => 'p',
+    };
+    match piece.color {
+        Color::WHITE => ch.to_ascii_uppercase(),
+        Color::BLACK => ch,
+    }
+}
+
+fn char_piece(ch: char) -> Option<ChessPiece> {
+    let piece = match ch.to_ascii_lowercase() {
+        'k' => Piece::KING,
+        'q' => Piece::QUEEN,
+        'r' => Piece::ROOK,
+        'b' => Piece::BISHOP,
+        'n' => Piece::KNIGHT,
+        'p' => Piece::PAWN,
+        _ => return None,
+    };
+    let color = if ch.is_ascii_uppercase() {
+        Color::WHITE
+    } else {
+        Color::BLACK
+    };
+    Some(ChessPiece { piece, color })
+}
+
+pub(super) fn parse_square(notation: &str) -> Option<(usize, usize)> {
+    let mut chars = notation.chars();
+    let file = chars.next().filter(|file| ('a'..='h').contains(file))?;
+    let rank = chars
+        .next()?
+        .to_digit(10)
+        .filter(|rank| (1..=8).contains(rank))?;
+    if chars.next().is_some() {
+        return None;
+    }
+    Some((8 - rank as usize, file as usize - 'a' as usize))
+}
+
+impl Game {
+    /// How many half moves were played, derived from the position so that it
+    /// also holds for games loaded from a FEN.
+    pub fn ply(&self) -> u32 {
+        match self.next_to_move {
+            Color::WHITE => self.turn_number * 2,
+            Color::BLACK => self.turn_number * 2 - 1,
+        }
+    }
+    /// The position in Forsyth-Edwards Notation.
+    pub fn to_fen(&self) -> String {
+        let board = self
+            .field
+            .iter()
+            .map(|row| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for square in row {
+                    match square {
+                        None => empty += 1,
+                        Some(piece) => {
+                            if empty > 0 {
+                                rank.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            rank.push(piece_char(*piece));
+                        }
+                    }
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let side = match self.next_to_move {
+            Color::WHITE => "w",
+            Color::BLACK => "b",
+        };
+
+        let castling: String = [
+            (self.can_castle.white_can_short_castle, 'K'),
+            (self.can_castle.white_can_long_castle, 'Q'),
+            (self.can_castle.black_can_short_castle, 'k'),
+            (self.can_castle.black_can_long_castle, 'q'),
+        ]
+        .iter()
+        .filter(|(allowed, _)| *allowed)
+        .map(|(_, ch)| *ch)
+        .collect();
+
+        // the previous move names the square of the pawn that can be taken,
+        // FEN names the square behind it
+        let en_passant = self
+            .previous_move
+            .get(..2)
+            .and_then(parse_square)
+            .filter(|_| self.can_en_passant)
+            .and_then(|(row, col)| {
+                let row = match self.next_to_move {
+                    Color::BLACK => row + 1,
+                    Color::WHITE => row - 1,
+                };
+                get_notation_from_square((row, col)).ok()
+            });
+
+        let halfmove_clock = self
+            .move_history
+            .iter()
+            .rev()
+            .take_while(|record| {
+                record.undo.moved_piece.piece != Piece::PAWN && record.undo.captured.is_none()
+            })
+            .count();
+        let fullmove = match self.next_to_move {
+            Color::WHITE => self.turn_number + 1,
+            Color::BLACK => self.turn_number,
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            board,
+            side,
+            if castling.is_empty() { "-" } else { &castling },
+            en_passant.as_deref().unwrap_or("-"),
+            halfmove_clock,
+            fullmove
+        )
+    }
+    /// Sets up the position of a FEN. The move history starts empty, so moves
+    /// before the position cannot be taken back.
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), &'static str> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(INVALID_FEN_ERROR);
+        }
+
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 8 {
+            return Err(INVALID_FEN_ERROR);
+        }
+        let mut field = vec![vec![None; 8]; 8];
+        let mut white_king = None;
+        let mut black_king = None;
+        for (row, rank) in rows.iter().enumerate() {
+            let mut col = 0;
+            for ch in rank.chars() {
+                if let Some(empty) = ch.to_digit(10) {
+                    col += empty as usize;
+                    continue;
+                }
+                let piece = char_piece(ch).ok_or(INVALID_FEN_ERROR)?;
+                if col >= 8 {
+                    return Err(INVALID_FEN_ERROR);
+                }
+                if piece.piece == Piece::KING {
+                    let king = match piece.color {
+                        Color::WHITE => &mut white_king,
+                        Color::BLACK => &mut black_king,
+                    };
+                    if king.replace((row, col)).is_some() {
+                        return Err(INVALID_FEN_ERROR);
+                    }
+                }
+                field[row][col] = Some(piece);
+                col += 1;
+            }
+            if col != 8 {
+                return Err(INVALID_FEN_ERROR);
+            }
+        }
+        let (white_king, black_king) = match (white_king, black_king) {
+            (Some(white), Some(black)) => (white, black),
+            _ => return Err(INVALID_FEN_ERROR),
+        };
+
+        let next_to_move = match fields[1] {
+            "w" => Color::WHITE,
+            "b" => Color::BLACK,
+            _ => return Err(INVALID_FEN_ERROR),
+        };
+
+        let castling = fields[2];
+        if castling != "-" && !castling.chars().all(|ch| "KQkq".contains(ch)) {
+            return Err(INVALID_FEN_ERROR);
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            square => {
+                let (row, col) = parse_square(square).ok_or(INVALID_FEN_ERROR)?;
+                let pawn_row = match (next_to_move, row) {
+                    (Color::WHITE, 2) => 3,
+                    (Color::BLACK, 5) => 4,
+                    _ => return Err(INVALID_FEN_ERROR),
+                };
+                Some(get_notation_from_square((pawn_row, col))?)
+            }
+        };
+
+        fields[4].parse::<u32>().map_err(|_| INVALID_FEN_ERROR)?;
+        let fullmove = fields[5]
+            .parse::<u32>()
+            .ok()
+            .filter(|fullmove| *fullmove > 0)
+            .ok_or(INVALID_FEN_ERROR)?;
+
+        self.field = field;
+        self.king_position = KingPosition {
+            white_king_position: white_king,
+            black_king_position: black_king,
+        };
+        self.next_to_move = next_to_move;
+        self.can_castle = CastlingRights {
+            white_can_short_castle: castling.contains('K'),
+            white_can_long_castle: castling.contains('Q'),
+            black_can_short_castle: castling.contains('k'),
+            black_can_long_castle: castling.contains('q'),
+        };
+        self.can_en_passant = en_passant.is_some();
+        self.previous_move = en_passant.unwrap_or_default();
+        self.previous_move_was_enpassant = false;
+        self.turn_number = match next_to_move {
+            Color::WHITE => fullmove - 1,
+            Color::BLACK => fullmove,
+        };
+        self.move_history = vec![];
+        self.draw_offer = None;
+        self.takeback_offer = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_fen {
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, Game};
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn test_start_position() {
+        let game = Game::new(Uuid::new_v4(), Color::WHITE);
+        assert_eq!(game.to_fen(), START_FEN);
+        assert_eq!(game.ply(), 0);
+    }
+
+    #[test]
+    fn test_fen_after_moves() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        for (from, to) in [("e2", "e4"), ("g8", "f6"), ("e4", "e5"), ("d7", "d5")] {
+            game.validate_and_make_move(from, to, ' ').unwrap();
+        }
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"
+        );
+        assert_eq!(game.ply(), 4);
+
+        game.validate_and_make_move("e1", "e2", ' ').unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPPKPPP/RNBQ1BNR b kq - 1 3"
+        );
+        assert_eq!(game.ply(), 5);
+    }
+
+    #[test]
+    fn test_load_fen() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        let fen = "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        game.load_fen(fen).unwrap();
+        // the halfmove clock is not kept, everything else round trips
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(game.ply(), 4);
+
+        // en passant works from a loaded position
+        game.validate_and_make_move("e5", "d6", ' ').unwrap();
+        assert_eq!(game.field[3][3], None);
+
+        assert!(game.load_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(game
+            .load_fen(START_FEN.replace(" w ", " x ").as_str())
+            .is_err());
+        assert!(game
+            .load_fen("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .is_err());
+    }
+}
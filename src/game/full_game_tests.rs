@@ -404,3 +404,45 @@ fn hammer_vs_carlsen() {
 
     assert_eq!(game.game_result, Some(GameResult::WhiteWon));
 }
+
+#[test]
+fn check_that_can_only_be_blocked() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+
+    game.validate_and_make_move("e2", "e4", ' ').expect("e4");
+    game.validate_and_make_move("d7", "d6", ' ').expect("d6");
+
+    // the king has no square and nothing takes the bishop, but c6 blocks
+    game.validate_and_make_move("f1", "b5", ' ').expect("Bb5+");
+    assert_eq!(game.previous_move, "Bb5+");
+    assert_eq!(game.game_result, None);
+
+    game.validate_and_make_move("c7", "c6", ' ').expect("c6");
+    assert_eq!(game.previous_move, "c6");
+}
+
+#[test]
+fn check_that_only_a_pawn_can_block() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+
+    game.validate_and_make_move("f2", "f3", ' ').expect("f3");
+    game.validate_and_make_move("e7", "e5", ' ').expect("e5");
+    game.validate_and_make_move("a2", "a3", ' ').expect("a3");
+
+    // f2 is covered by the queen too, so only g3 saves the king
+    game.validate_and_make_move("d8", "h4", ' ').expect("Qh4+");
+    assert_eq!(game.game_result, None);
+    game.validate_and_make_move("g2", "g3", ' ').expect("g3");
+}
+
+#[test]
+fn fools_mate() {
+    let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+
+    game.validate_and_make_move("f2", "f3", ' ').expect("f3");
+    game.validate_and_make_move("e7", "e5", ' ').expect("e5");
+    game.validate_and_make_move("g2", "g4", ' ').expect("g4");
+    game.validate_and_make_move("d8", "h4", ' ').expect("Qh4#");
+
+    assert_eq!(game.game_result, Some(GameResult::BlackWon));
+}
@@ -0,0 +1,187 @@
+use crate::game::chess_piece::{Color, Piece};
+use crate::game::{Game, Termination};
+use crate::utils::convert_notation::{get_notation_from_square, get_squares_from_notation};
+use crate::utils::error::INVALID_SAN_ERROR;
+
+fn piece_letter(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::KING => Some('K'),
+        Piece::QUEEN => Some('Q'),
+        Piece::ROOK => Some('R'),
+        Piece::BISHOP => Some('B'),
+        Piece::KNIGHT => Some('N'),
+        Piece::PAWN => None,
+    }
+}
+
+// annotations and check marks do not change which move is meant
+fn strip_suffixes(san: &str) -> &str {
+    san.trim_end_matches(['+', '#', '!', '?'])
+}
+
+impl Game {
+    /// A legal move in Standard Algebraic Notation, as PGN writes it.
+    pub fn san(&self, from: &str, to: &str, promotion: char) -> Result<String, &'static str> {
+        self.validate_move(from, to, promotion)?;
+        let (from_square, to_square) = get_squares_from_notation(from, to)?;
+        let moved = self.field[from_square.0][from_square.1].ok_or(INVALID_SAN_ERROR)?;
+
+        let mut san = String::new();
+        let castles = moved.piece == Piece::KING && from_square.1.abs_diff(to_square.1) == 2;
+        if castles {
+            san.push_str(if to_square.1 == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            let captures = self.field[to_square.0][to_square.1].is_some()
+                || (moved.piece == Piece::PAWN && from_square.1 != to_square.1);
+            match piece_letter(moved.piece) {
+                None if captures => san.push_str(&from[..1]),
+                None => (),
+                Some(letter) => {
+                    san.push(letter);
+                    san.push_str(&self.disambiguation(from_square, to));
+                }
+            }
+            if captures {
+                san.push('x');
+            }
+            san.push_str(to);
+            if moved.piece == Piece::PAWN && (to_square.0 == 0 || to_square.0 == 7) {
+                san.push('=');
+                san.push(promotion.to_ascii_uppercase());
+            }
+        }
+
+        let mut after = self.clone();
+        after.make_move(from, to, promotion);
+        if after.termination == Some(Termination::Checkmate) {
+            san.push('#');
+        } else if after.previous_move.ends_with('+') {
+            san.push('+');
+        }
+        Ok(san)
+    }
+    // the file, the rank or both of the moving piece, if another one of the
+    // same kind could move to the same square
+    fn disambiguation(&self, from: (usize, usize), to: &str) -> String {
+        let moved = self.field[from.0][from.1];
+        let mut rivals = vec![];
+        for row in 0..8 {
+            for col in 0..8 {
+                if (row, col) == from || self.field[row][col] != moved {
+                    continue;
+                }
+                let square = get_notation_from_square((row, col)).unwrap();
+                if self.validate_move(&square, to, ' ').is_ok() {
+                    rivals.push((row, col));
+                }
+            }
+        }
+
+        let from_notation = get_notation_from_square(from).unwrap();
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|rival| rival.1 != from.1) {
+            from_notation[..1].to_string()
+        } else if rivals.iter().all(|rival| rival.0 != from.0) {
+            from_notation[1..].to_string()
+        } else {
+            from_notation
+        }
+    }
+    /// Finds the legal move a SAN token like `Nbd7`, `exd5` or `e8=Q+` stands for.
+    pub fn parse_san(&self, san: &str) -> Result<(String, String, char), &'static str> {
+        let token = strip_suffixes(san);
+        let back_rank = match self.next_to_move {
+            Color::WHITE => "1",
+            Color::BLACK => "8",
+        };
+        let castling_to = match token {
+            "O-O" | "0-0" => Some("g"),
+            "O-O-O" | "0-0-0" => Some("c"),
+            _ => None,
+        };
+        if let Some(file) = castling_to {
+            let (from, to) = (format!("e{}", back_rank), format!("{}{}", file, back_rank));
+            self.validate_move(&from, &to, ' ')
+                .map_err(|_| INVALID_SAN_ERROR)?;
+            return Ok((from, to, ' '));
+        }
+
+        let (target, promotion) = match token.split_once('=') {
+            Some((target, promotion)) => (target, promotion.chars().next()),
+            None => (token, None),
+        };
+        let promotion = promotion.map_or(' ', |ch| ch.to_ascii_uppercase());
+        let to = target
+            .get(target.len().saturating_sub(2)..)
+            .ok_or(INVALID_SAN_ERROR)?;
+        for row in 0..8 {
+            for col in 0..8 {
+                match self.field[row][col] {
+                    Some(piece) if piece.color == self.next_to_move => (),
+                    _ => continue,
+                }
+                let from = get_notation_from_square((row, col)).unwrap();
+                match self.san(&from, to, promotion) {
+                    Ok(candidate) if strip_suffixes(&candidate) == token => {
+                        return Ok((from, to.to_string(), promotion));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Err(INVALID_SAN_ERROR)
+    }
+}
+
+#[cfg(test)]
+mod test_san {
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, Game};
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for san in moves {
+            let (from, to, promotion) = game.parse_san(san).unwrap();
+            assert_eq!(&game.san(&from, &to, promotion).unwrap(), san);
+            game.validate_and_make_move(&from, &to, promotion).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        play(
+            &mut game,
+            &[
+                "e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O", "f6",
+            ],
+        );
+        assert_eq!(game.ply(), 10);
+        assert!(game.parse_san("Nf3").is_err());
+    }
+
+    #[test]
+    fn test_checkmate_and_disambiguation() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        play(&mut game, &["f3", "e5", "g4", "Qh4#"]);
+        assert!(game.game_result.is_some());
+
+        let mut knights = Game::new(Uuid::new_v4(), Color::WHITE);
+        knights
+            .load_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1")
+            .unwrap();
+        play(&mut knights, &["Nbd2"]);
+        let mut rooks = Game::new(Uuid::new_v4(), Color::WHITE);
+        rooks.load_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        play(&mut rooks, &["R5a3"]);
+    }
+
+    #[test]
+    fn test_promotion_and_en_passant() {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        game.load_fen("8/1P2k3/8/3pP3/8/8/8/4K3 w - d6 0 1")
+            .unwrap();
+        play(&mut game, &["exd6+", "Kxd6", "b8=Q+"]);
+    }
+}
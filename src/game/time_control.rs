@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// Base time and increment per move, both in seconds.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub initial_secs: u64,
+    pub increment_secs: u64,
+}
+impl TimeControl {
+    pub fn to_str(&self) -> String {
+        format!("{}+{}", self.initial_secs, self.increment_secs)
+    }
+    pub fn from_name(name: &str) -> Option<TimeControl> {
+        let (initial, increment) = name.split_once('+')?;
+        Some(TimeControl {
+            initial_secs: initial.parse().ok()?,
+            increment_secs: increment.parse().ok()?,
+        })
+    }
+    /// Same split as most servers use: the expected duration of a 40 move game.
+    pub fn category(&self) -> TimeControlCategory {
+        match self.initial_secs + 40 * self.increment_secs {
+            0..=179 => TimeControlCategory::Bullet,
+            180..=479 => TimeControlCategory::Blitz,
+            480..=1499 => TimeControlCategory::Rapid,
+            _ => TimeControlCategory::Classical,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeControlCategory {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+    // games without a clock
+    Correspondence,
+}
+impl TimeControlCategory {
+    pub fn to_str(&self) -> String {
+        match self {
+            TimeControlCategory::Bullet => "bullet".to_string(),
+            TimeControlCategory::Blitz => "blitz".to_string(),
+            TimeControlCategory::Rapid => "rapid".to_string(),
+            TimeControlCategory::Classical => "classical".to_string(),
+            TimeControlCategory::Correspondence => "correspondence".to_string(),
+        }
+    }
+    pub fn of(time_control: Option<TimeControl>) -> TimeControlCategory {
+        time_control.map_or(TimeControlCategory::Correspondence, |tc| tc.category())
+    }
+}
@@ -29,7 +29,7 @@ pub fn is_mate(game: &Game) -> bool {
     }
 
     let threatening_pieces = can_be_captured_by(enemy_color, king_position, game);
-    if threatening_pieces.len() == 0 {
+    if threatening_pieces.is_empty() {
         return false;
     }
 
@@ -50,8 +50,8 @@ pub fn is_mate(game: &Game) -> bool {
     for surr_sq in surrounding_squares {
         if is_in_bounds(surr_sq.0, surr_sq.1)
             && game.field[surr_sq.0 as usize][surr_sq.1 as usize].is_none()
-            && can_be_captured_by(enemy_color, (surr_sq.0 as usize, surr_sq.1 as usize), game).len()
-                == 0
+            && can_be_captured_by(enemy_color, (surr_sq.0 as usize, surr_sq.1 as usize), game)
+                .is_empty()
         {
             return false;
         }
@@ -77,12 +77,66 @@ pub fn is_mate(game: &Game) -> bool {
             get_notation_from_square((threatening_pieces[0].row, threatening_pieces[0].col))
                 .unwrap();
         println!("{:?} {:?}", algebraic_from, algebraic_to);
-        if can_king_be_captured_after_move(game, &algebraic_from, &algebraic_to, 'Q').len() == 0 {
+        if can_king_be_captured_after_move(game, &algebraic_from, &algebraic_to, 'Q').is_empty() {
             return false;
         };
     }
 
-    true
+    // a check from a distance can also be blocked
+    !can_be_blocked(game, king_position, &threatening_pieces[0])
+}
+
+fn can_be_blocked(game: &Game, king_position: (usize, usize), threat: &CapturePiece) -> bool {
+    if threat.piece == Piece::KNIGHT {
+        return false;
+    }
+    let row_step = (threat.row as i32 - king_position.0 as i32).signum();
+    let col_step = (threat.col as i32 - king_position.1 as i32).signum();
+    // the row a pawn of the side to move advances by
+    let pawn_step = match game.next_to_move {
+        Color::WHITE => -1,
+        Color::BLACK => 1,
+    };
+
+    let mut row = king_position.0 as i32 + row_step;
+    let mut col = king_position.1 as i32 + col_step;
+    while (row, col) != (threat.row as i32, threat.col as i32) {
+        let square = (row as usize, col as usize);
+        let algebraic_to = get_notation_from_square(square).unwrap();
+
+        // pieces that reach the square, except pawns which only reach it by pushing
+        let mut blockers: Vec<(usize, usize)> = can_be_captured_by(game.next_to_move, square, game)
+            .into_iter()
+            .filter(|piece| piece.piece != Piece::PAWN && piece.piece != Piece::KING)
+            .map(|piece| (piece.row, piece.col))
+            .collect();
+        for distance in [1, 2] {
+            let pawn_row = row - pawn_step * distance;
+            if is_in_bounds(pawn_row, col) {
+                blockers.push((pawn_row as usize, col as usize));
+            }
+        }
+
+        for blocker in blockers {
+            let is_own_piece = matches!(
+                game.field[blocker.0][blocker.1],
+                Some(piece) if piece.color == game.next_to_move
+            );
+            let algebraic_from = get_notation_from_square(blocker).unwrap();
+            if is_own_piece
+                && game
+                    .validate_move(&algebraic_from, &algebraic_to, 'Q')
+                    .is_ok()
+            {
+                return true;
+            }
+        }
+
+        row += row_step;
+        col += col_step;
+    }
+
+    false
 }
 
 pub fn can_king_be_captured_after_move(
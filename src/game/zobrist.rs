@@ -0,0 +1,215 @@
+use crate::game::chess_piece::{ChessPiece, Color, Piece};
+use crate::game::event::LoggedEvent;
+use crate::game::fen::parse_square;
+use crate::game::Game;
+
+/// Position hashes and UCI moves of a game, each with its ply.
+pub type PlayedPositions = (Vec<(u32, u64)>, Vec<(u32, String)>);
+
+const PIECE_KEYS: usize = 12 * 64;
+const SIDE_KEY: usize = PIECE_KEYS;
+const CASTLING_KEYS: usize = SIDE_KEY + 1;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4;
+
+// fixed pseudo random keys, hashes are stored so they must never change
+const KEYS: [u64; EN_PASSANT_KEYS + 8] = {
+    let mut keys = [0; EN_PASSANT_KEYS + 8];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+    while i < keys.len() {
+        // splitmix64
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+};
+
+fn piece_index(piece: ChessPiece) -> usize {
+    let kind = match piece.piece {
+        Piece::KING => 0,
+        Piece::QUEEN => 1,
+        Piece::ROOK => 2,
+        Piece::BISHOP => 3,
+        Piece::KNIGHT => 4,
+        Piece::PAWN => 5,
+    };
+    match piece.color {
+        Color::WHITE => kind,
+        Color::BLACK => 6 + kind,
+    }
+}
+
+impl Game {
+    /// Zobrist hash of the position, equal for transpositions. Like the FEN
+    /// without its move counters, but the en passant file only counts when a
+    /// capture is actually possible.
+    pub fn zobrist(&self) -> u64 {
+        let mut hash = 0;
+        for (row, rank) in self.field.iter().enumerate() {
+            for (col, square) in rank.iter().enumerate() {
+                if let Some(piece) = square {
+                    hash ^= KEYS[piece_index(*piece) * 64 + row * 8 + col];
+                }
+            }
+        }
+        if self.next_to_move == Color::BLACK {
+            hash ^= KEYS[SIDE_KEY];
+        }
+        let castling = [
+            self.can_castle.white_can_short_castle,
+            self.can_castle.white_can_long_castle,
+            self.can_castle.black_can_short_castle,
+            self.can_castle.black_can_long_castle,
+        ];
+        for (i, allowed) in castling.iter().enumerate() {
+            if *allowed {
+                hash ^= KEYS[CASTLING_KEYS + i];
+            }
+        }
+        if let Some(col) = self.en_passant_file() {
+            hash ^= KEYS[EN_PASSANT_KEYS + col];
+        }
+        hash
+    }
+    /// Replays the whole event log of a game and returns the hash of every
+    /// position it reached by ply, and the moves in UCI notation by the ply
+    /// they led to. Moves that were taken back are left out.
+    pub fn replay_positions(
+        mut self,
+        events: &[LoggedEvent],
+    ) -> Result<PlayedPositions, &'static str> {
+        let mut positions = vec![(self.ply(), self.zobrist())];
+        let mut moves = vec![];
+        for logged in events {
+            self.apply_event(&logged.event)?;
+            let ply = self.ply();
+            positions.retain(|(reached, _)| *reached < ply);
+            moves.retain(|(reached, _)| *reached <= ply);
+            positions.push((ply, self.zobrist()));
+            if let Some(uci) = logged.event.uci() {
+                moves.push((ply, uci));
+            }
+        }
+        Ok((positions, moves))
+    }
+    fn en_passant_file(&self) -> Option<usize> {
+        if !self.can_en_passant {
+            return None;
+        }
+        let (row, col) = self.previous_move.get(..2).and_then(parse_square)?;
+        let capturer = Some(ChessPiece {
+            piece: Piece::PAWN,
+            color: self.next_to_move,
+        });
+        let neighbours = [col.checked_sub(1), Some(col + 1).filter(|col| *col < 8)];
+        neighbours
+            .iter()
+            .flatten()
+            .any(|neighbour| self.field[row][*neighbour] == capturer)
+            .then_some(col)
+    }
+}
+
+#[cfg(test)]
+mod test_zobrist {
+    use uuid::Uuid;
+
+    use crate::game::{
+        chess_piece::Color,
+        event::{GameEvent, LoggedEvent},
+        Game, GameAction,
+    };
+
+    fn after(moves: &[(&str, &str)]) -> Game {
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        for (from, to) in moves {
+            game.validate_and_make_move(from, to, ' ').unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_transpositions_hash_equal() {
+        let knights_first = after(&[("g1", "f3"), ("g8", "f6"), ("b1", "c3"), ("b8", "c6")]);
+        let queenside_first = after(&[("b1", "c3"), ("b8", "c6"), ("g1", "f3"), ("g8", "f6")]);
+        assert_eq!(knights_first.zobrist(), queenside_first.zobrist());
+        assert_ne!(
+            knights_first.zobrist(),
+            Game::new(Uuid::new_v4(), Color::WHITE).zobrist()
+        );
+    }
+
+    #[test]
+    fn test_hash_matches_loaded_fen() {
+        let game = after(&[("e2", "e4"), ("c7", "c5")]);
+        let mut loaded = Game::new(Uuid::new_v4(), Color::WHITE);
+        loaded.load_fen(&game.to_fen()).unwrap();
+        assert_eq!(game.zobrist(), loaded.zobrist());
+        // no pawn can take on c6, so the en passant square is ignored
+        let mut without = Game::new(Uuid::new_v4(), Color::WHITE);
+        without
+            .load_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+            .unwrap();
+        assert_eq!(game.zobrist(), without.zobrist());
+    }
+
+    #[test]
+    fn test_replay_positions_skips_taken_back_moves() {
+        let logged = |seq, event| LoggedEvent {
+            seq,
+            event,
+            created_at: String::new(),
+        };
+        let play = |from: &str, to: &str| GameEvent::Move {
+            from: from.to_string(),
+            to: to.to_string(),
+            promotion: ' ',
+        };
+        let action = |player: &str, action| GameEvent::Action {
+            player: player.to_string(),
+            action,
+        };
+        let events = vec![
+            logged(1, play("e2", "e4")),
+            logged(2, play("e7", "e5")),
+            logged(3, action("black", GameAction::RequestTakeback)),
+            logged(4, action("white", GameAction::AcceptTakeback)),
+            logged(5, play("c7", "c5")),
+        ];
+        let (positions, moves) = Game::new(Uuid::new_v4(), Color::WHITE)
+            .replay_positions(&events)
+            .unwrap();
+        assert_eq!(
+            moves,
+            vec![(1, "e2e4".to_string()), (2, "c7c5".to_string())]
+        );
+        let sicilian = after(&[("e2", "e4"), ("c7", "c5")]);
+        assert_eq!(positions.len(), 3);
+        assert_eq!(positions[2], (2, sicilian.zobrist()));
+    }
+
+    #[test]
+    fn test_side_to_move_and_castling_count() {
+        let there_and_back = after(&[("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")]);
+        assert_eq!(
+            there_and_back.zobrist(),
+            Game::new(Uuid::new_v4(), Color::WHITE).zobrist()
+        );
+        let king_walk = after(&[
+            ("e2", "e4"),
+            ("e7", "e5"),
+            ("e1", "e2"),
+            ("e8", "e7"),
+            ("e2", "e1"),
+            ("e7", "e8"),
+        ]);
+        assert_ne!(
+            king_walk.zobrist(),
+            after(&[("e2", "e4"), ("e7", "e5")]).zobrist()
+        );
+    }
+}
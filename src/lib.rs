@@ -1,5 +1,18 @@
+pub mod arena;
+pub mod backup;
+pub mod chat;
+pub mod correspondence;
 pub mod db;
 pub mod game;
+pub mod lobby;
+pub mod migrations;
+pub mod rating;
+pub mod scheduler;
 pub mod server;
+pub mod simul;
+pub mod stats;
+pub mod store;
+pub mod tournament;
+pub mod user;
 pub mod utils;
 pub mod ws;
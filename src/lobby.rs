@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use actix::Addr;
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    game::{
+        chess_piece::Color,
+        time_control::{TimeControl, TimeControlCategory},
+        Variant,
+    },
+    rating::RatingPool,
+    utils::error::{NO_SEEK_ERROR, OWN_SEEK_ERROR, RATING_RANGE_ERROR},
+    ws::{LobbyBroadcastMessage, LobbyConnection},
+};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorPreference {
+    White,
+    Black,
+    #[default]
+    Random,
+}
+impl ColorPreference {
+    /// The color the poster of a seek ends up playing.
+    pub fn resolve(&self) -> Color {
+        match self {
+            ColorPreference::White => Color::WHITE,
+            ColorPreference::Black => Color::BLACK,
+            ColorPreference::Random => {
+                let mut byte = [0];
+                rand_bytes(&mut byte).expect("Could not generate random bytes");
+                if byte[0] % 2 == 0 {
+                    Color::WHITE
+                } else {
+                    Color::BLACK
+                }
+            }
+        }
+    }
+}
+
+/// An open offer to play. It lives as long as the lobby connection it was
+/// posted from.
+#[derive(Serialize, Clone, Debug)]
+pub struct Seek {
+    pub seek_id: String,
+    pub user_id: String,
+    pub display_name: String,
+    pub rating: f64,
+    pub time_control: Option<TimeControl>,
+    pub variant: Variant,
+    pub color: ColorPreference,
+    pub rated: bool,
+    pub min_rating: Option<f64>,
+    pub max_rating: Option<f64>,
+    #[serde(skip)]
+    pub connection_id: Uuid,
+}
+impl Seek {
+    pub fn pool(&self) -> RatingPool {
+        RatingPool {
+            category: TimeControlCategory::of(self.time_control),
+            variant: self.variant,
+        }
+    }
+    pub fn accepts_rating(&self, rating: f64) -> bool {
+        let above_min = match self.min_rating {
+            None => true,
+            Some(min) => rating >= min,
+        };
+        let below_max = match self.max_rating {
+            None => true,
+            Some(max) => rating <= max,
+        };
+        above_min && below_max
+    }
+}
+
+pub struct LobbyMember {
+    pub user_id: String,
+    pub addr: Addr<LobbyConnection>,
+}
+
+#[derive(Default)]
+pub struct Lobby {
+    pub seeks: Vec<Seek>,
+    pub members: HashMap<Uuid, LobbyMember>,
+}
+impl Lobby {
+    pub fn seek(&self, seek_id: &str) -> Option<&Seek> {
+        self.seeks.iter().find(|seek| seek.seek_id == seek_id)
+    }
+    pub fn add_seek(&mut self, seek: Seek) {
+        self.seeks.push(seek);
+    }
+    /// Removes the seek for `user_id` to accept it, so that no one else can
+    /// accept it at the same time.
+    pub fn take_seek(
+        &mut self,
+        seek_id: &str,
+        user_id: &str,
+        rating: f64,
+    ) -> Result<Seek, &'static str> {
+        let index = match self.seeks.iter().position(|seek| seek.seek_id == seek_id) {
+            None => return Err(NO_SEEK_ERROR),
+            Some(index) => index,
+        };
+
+        let seek = &self.seeks[index];
+        if seek.user_id == user_id {
+            return Err(OWN_SEEK_ERROR);
+        }
+        if !seek.accepts_rating(rating) {
+            return Err(RATING_RANGE_ERROR);
+        }
+
+        Ok(self.seeks.remove(index))
+    }
+    /// Removes a seek of its own poster and returns whether there was one.
+    pub fn cancel_seek(&mut self, seek_id: &str, user_id: &str) -> bool {
+        let before = self.seeks.len();
+        self.seeks
+            .retain(|seek| seek.seek_id != seek_id || seek.user_id != user_id);
+        self.seeks.len() != before
+    }
+    /// Forgets a lobby connection and returns the ids of the seeks that expired with it.
+    pub fn remove_member(&mut self, connection_id: Uuid) -> Vec<String> {
+        self.members.remove(&connection_id);
+
+        let (expired, open): (Vec<Seek>, Vec<Seek>) = self
+            .seeks
+            .drain(..)
+            .partition(|seek| seek.connection_id == connection_id);
+        self.seeks = open;
+        expired.into_iter().map(|seek| seek.seek_id).collect()
+    }
+    pub fn broadcast<T: Serialize>(&self, message: &T) {
+        let text = serde_json::to_string(message).unwrap();
+        self.members
+            .values()
+            .for_each(|e| e.addr.do_send(LobbyBroadcastMessage::new(text.clone())));
+    }
+    pub fn send_to_user<T: Serialize>(&self, user_id: &str, message: &T) {
+        let text = serde_json::to_string(message).unwrap();
+        self.members
+            .values()
+            .filter(|e| e.user_id == user_id)
+            .for_each(|e| e.addr.do_send(LobbyBroadcastMessage::new(text.clone())));
+    }
+}
+
+#[cfg(test)]
+mod test_lobby {
+    use uuid::Uuid;
+
+    use crate::game::Variant;
+
+    use super::{ColorPreference, Lobby, Seek};
+
+    fn seek(user_id: &str, connection_id: Uuid) -> Seek {
+        Seek {
+            seek_id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            display_name: user_id.to_string(),
+            rating: 1500.0,
+            time_control: None,
+            variant: Variant::Standard,
+            color: ColorPreference::Random,
+            rated: true,
+            min_rating: Some(1400.0),
+            max_rating: Some(1600.0),
+            connection_id,
+        }
+    }
+
+    #[test]
+    fn test_take_seek() {
+        let mut lobby = Lobby::default();
+        let posted = seek("alice", Uuid::new_v4());
+        let seek_id = posted.seek_id.clone();
+        lobby.add_seek(posted);
+
+        assert!(lobby.take_seek(&seek_id, "alice", 1500.0).is_err());
+        assert!(lobby.take_seek(&seek_id, "bob", 1700.0).is_err());
+        assert!(lobby.take_seek(&seek_id, "bob", 1550.0).is_ok());
+        // a seek can only be accepted once
+        assert!(lobby.take_seek(&seek_id, "carol", 1550.0).is_err());
+    }
+
+    #[test]
+    fn test_seeks_expire_with_connection() {
+        let mut lobby = Lobby::default();
+        let connection_id = Uuid::new_v4();
+        lobby.add_seek(seek("alice", connection_id));
+        lobby.add_seek(seek("alice", connection_id));
+        lobby.add_seek(seek("bob", Uuid::new_v4()));
+
+        assert_eq!(lobby.remove_member(connection_id).len(), 2);
+        assert_eq!(lobby.seeks.len(), 1);
+        assert_eq!(lobby.seeks[0].user_id, "bob");
+    }
+
+    #[test]
+    fn test_cancel_seek() {
+        let mut lobby = Lobby::default();
+        let posted = seek("alice", Uuid::new_v4());
+        let seek_id = posted.seek_id.clone();
+        lobby.add_seek(posted);
+
+        assert!(!lobby.cancel_seek(&seek_id, "bob"));
+        assert!(lobby.cancel_seek(&seek_id, "alice"));
+        assert!(lobby.seeks.is_empty());
+    }
+}
@@ -1,29 +1,114 @@
-use std::{env, sync::Arc};
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::cookie::time::{self, OffsetDateTime};
+use actix_web::{delete, error, get, post, web, App, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 use chess_voting::{
-    game::chess_piece::Color,
+    arena::Arena,
+    backup::{self, parse_games, EXPORT_PAGE_SIZE},
+    chat::{check_chat_message, ChatScope},
+    correspondence::{
+        deadline, format_timestamp, on_vacation, parse_timestamp, vacation_days_used,
+        MAX_DAYS_PER_MOVE, VACATION_DAYS_PER_YEAR,
+    },
+    db::{connect_db, GameFilter},
+    game::{
+        chess_piece::Color, eco::is_eco_prefix, event::GameEvent,
+        time_control::TimeControlCategory, Game, GameResult, Players,
+    },
+    migrations,
+    rating::{RatedGame, RatingPool, PROVISIONAL_DEVIATION},
+    scheduler,
     server::Server,
+    tournament::Tournament,
+    user::{User, UserRole},
     utils::{
-        error::INTERNAL_SERVER_ERROR,
-        middleware::Authentication,
-        request::{verify_jwt, FinishRequest, StartRequest},
-        response::{serialize_field, GameState},
+        auth::{generate_secret, hash_password, hash_secret, Permission, Principal},
+        error::{
+            COMPETITION_GAME_DELETE_ERROR, GAME_FINISHED_ERROR, INTERNAL_SERVER_ERROR,
+            NO_GAME_ERROR, NO_INVITE_ERROR, NO_SEAT_ERROR, ON_VACATION_ERROR,
+            RATED_GAME_DELETE_ERROR, TOO_MANY_SPECTATORS_ERROR, VACATION_LIMIT_ERROR,
+        },
+        middleware::RequirePermission,
+        request::{
+            issue_jwt, verify_jwt, AcceptInviteRequest, ActionRequest, AdminChatRequest,
+            ArenaQuery, BackupQuery, ChallengeRequest, ConnectQuery, CreateApiKeyRequest,
+            CreateArenaRequest, CreateTournamentRequest, CreateUserRequest, DeleteGameQuery,
+            ExplorerQuery, FinishRequest, GameSort, GameStatus, GamesQuery, LeaderboardQuery,
+            LobbyQuery, ModerationRequest, ReplayQuery, SimulHostQuery, SimulStartRequest,
+            SpectatorSettingsRequest, StartRequest, UserStatsQuery, VacationRequest,
+        },
+        response::{
+            serialize_field, ApiKeyCreatedResponse, ArchivedGameResponse, AwaitingMoveResponse,
+            ExplorerMoveResponse, ExplorerResponse, GameState, GamesPage, InviteAcceptedResponse,
+            InviteCreatedResponse, ReplayResponse, SimulCreatedResponse, UserCreatedResponse,
+            UserResponse,
+        },
     },
-    ws::WebSocketConnection,
+    ws::{ArenaConnection, LobbyConnection, Role, SimulHostConnection, WebSocketConnection},
 };
 use dotenv::dotenv;
+use futures_util::stream;
 use log::{error, info, warn};
 use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use uuid::Uuid;
 
+const DEFAULT_LEADERBOARD_SIZE: u32 = 50;
+const MAX_LEADERBOARD_SIZE: u32 = 200;
+const DEFAULT_GAMES_PAGE_SIZE: u32 = 20;
+const MAX_GAMES_PAGE_SIZE: u32 = 100;
+const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    env_logger::Builder::from_default_env().init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    // `--export FILE` and `--import FILE` back up or restore all games
+    match backup::parse_args(&args) {
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+        Ok(Some(command)) => {
+            let server = Server::new().await;
+            if let Err(e) = backup::run(&server, command).await {
+                error!("Backup failed: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Ok(None) => (),
+    }
+    // `--migrate [VERSION]` and `--rollback [VERSION]` only change the schema
+    match migrations::parse_args(&args) {
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+        Ok(Some(command)) => {
+            let conn = connect_db(&env::var("ENV").expect("Missing ENV env var")).await;
+            match migrations::run(&conn, command).await {
+                Err(e) => {
+                    error!("Migration failed: {}", e);
+                    std::process::exit(1);
+                }
+                Ok(version) => {
+                    info!("Database schema is at version {}", version);
+                    return Ok(());
+                }
+            }
+        }
+        Ok(None) => (),
+    }
+
     let url = env::var("URL").expect("Missing URL env var");
     let port = env::var("PORT").expect("Missing PORT env var");
-    env_logger::Builder::from_default_env().init();
     info!("Server listening on port {}", port);
 
     let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
@@ -35,20 +120,88 @@ async fn main() -> std::io::Result<()> {
         .unwrap();
 
     let server = web::Data::new(Server::new().await);
+    match server.clone().into_inner().restore_games().await {
+        Err(e) => error!("Could not restore games: {}", e),
+        Ok(restored) => info!("Restored {} unfinished games", restored),
+    }
+    let explorer_server = server.clone().into_inner();
+    actix::spawn(async move {
+        match explorer_server.explore_stored_games().await {
+            Err(e) => error!("Could not add stored games to the explorer: {}", e),
+            Ok(explored) => info!("Added {} stored games to the explorer", explored),
+        }
+    });
+    scheduler::start(server.clone().into_inner());
     HttpServer::new(move || {
         App::new()
             .app_data(server.clone())
             .service(health)
             .service(
                 web::scope("game")
-                    .wrap(Authentication {})
                     .service(get_ids)
+                    .service(broadcast_chat)
                     .service(get_game_history)
+                    .service(get_game_events)
+                    .service(replay_game)
                     .service(get_game_state)
+                    .service(get_game_stats)
                     .service(start_game)
-                    .service(finish_game),
+                    .service(create_challenge)
+                    .service(accept_invite)
+                    .service(finish_game)
+                    .service(delete_game)
+                    .service(perform_action)
+                    .service(set_spectator_settings)
+                    .service(get_chat)
+                    .service(moderate_chat),
+            )
+            .service(web::scope("simul").service(start_simul).service(get_simul))
+            .service(
+                web::scope("tournaments")
+                    .service(create_tournament)
+                    .service(get_tournament),
+            )
+            .service(
+                web::scope("games")
+                    .service(search_games)
+                    .service(get_awaiting_games),
+            )
+            .service(
+                web::scope("arenas")
+                    .service(create_arena)
+                    .service(get_arena),
+            )
+            .service(
+                web::scope("ws")
+                    .service(connect_ws)
+                    .service(connect_simul_ws)
+                    .service(connect_lobby_ws)
+                    .service(connect_arena_ws),
+            )
+            .service(
+                web::scope("users")
+                    .service(create_user)
+                    .service(take_vacation)
+                    .service(get_user)
+                    .service(get_user_games)
+                    .service(get_user_stats)
+                    .service(get_user_ratings),
+            )
+            .service(web::scope("ratings").service(get_leaderboard))
+            .service(get_explorer)
+            .service(
+                web::scope("backup")
+                    .app_data(web::PayloadConfig::new(MAX_IMPORT_SIZE))
+                    .service(export_games)
+                    .service(import_games),
+            )
+            .service(web::scope("lobby").service(get_seeks))
+            .service(
+                web::scope("keys")
+                    .service(create_api_key)
+                    .service(get_api_keys)
+                    .service(revoke_api_key),
             )
-            .service(web::scope("ws").service(connect_ws))
     })
     .bind_openssl(format!("{}:{}", url, port), builder)?
     .run()
@@ -60,21 +213,10 @@ async fn health() -> HttpResponse {
     HttpResponse::Ok().body("OK".to_string())
 }
 
-#[get("/ids")]
-async fn get_ids(req: HttpRequest, server: web::Data<Server>) -> HttpResponse {
-    let jwt = req.headers().get("jwt");
-    if jwt.is_none() {
-        error!("No JWT provided");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    }
-
-    if verify_jwt(jwt.unwrap().to_str().unwrap()).is_err() {
-        error!("Incorrect JWT provided: {}", jwt.unwrap().to_str().unwrap());
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    }
-
+#[get("/ids", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn get_ids(server: web::Data<Server>) -> HttpResponse {
     info!("Getting all active games...");
-    match server.db.get_active_games().await {
+    match server.store.get_active_games().await {
         Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
         Ok(game) => {
             info!("Fetched ids, found {} active games", game.len());
@@ -83,21 +225,125 @@ async fn get_ids(req: HttpRequest, server: web::Data<Server>) -> HttpResponse {
     }
 }
 
-#[get("/{game_id}/history")]
-async fn get_game_history(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+#[get(
+    "/{game_id}/history",
+    wrap = "RequirePermission(Permission::ViewGames)"
+)]
+async fn get_game_history(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
     info!("Checking game history...");
     let game_id = path.into_inner();
-    match server.db.get_moves(&game_id).await {
+    match server.viewable_game(&game_id, &principal).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body(NO_GAME_ERROR);
+        }
+        Ok(Some(_)) => (),
+    }
+    // spectators only get the moves they would see over the websocket
+    let visible_plies = Uuid::parse_str(&game_id)
+        .ok()
+        .and_then(|id| server.visible_game(id, &principal))
+        .map(|game| game.ply());
+    match server.store.get_moves(&game_id).await {
         Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
-        Ok(moves) => {
+        Ok(mut moves) => {
+            if let Some(plies) = visible_plies {
+                moves.retain(|m| m.ply <= plies);
+            }
             info!("Fetched moves from history, got {} moves", moves.len());
             HttpResponse::Ok().json(moves)
         }
     }
 }
 
-#[get("/{game_id}/current_state")]
-async fn get_game_state(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+#[get(
+    "/{game_id}/events",
+    wrap = "RequirePermission(Permission::ManageGames)"
+)]
+async fn get_game_events(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+    info!("Getting game events...");
+    let game_id = path.into_inner();
+    match server.store.get_events(&game_id, 0).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(events) => {
+            info!("Fetched {} events of game {}", events.len(), game_id);
+            HttpResponse::Ok().json(events)
+        }
+    }
+}
+
+#[get(
+    "/{game_id}/replay",
+    wrap = "RequirePermission(Permission::ManageGames)"
+)]
+async fn replay_game(
+    path: web::Path<String>,
+    query: web::Query<ReplayQuery>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Replaying game...");
+    let game_id = path.into_inner();
+    match server.rebuild_game(&game_id, query.seq).await {
+        Err(NO_GAME_ERROR) => {
+            warn!("Could not find game {} to replay", game_id);
+            HttpResponse::NotFound().body(NO_GAME_ERROR)
+        }
+        Err(e) => {
+            error!("Could not replay game {}: {}", game_id, e);
+            HttpResponse::InternalServerError().body(e)
+        }
+        Ok((game, seq)) => HttpResponse::Ok().json(ReplayResponse {
+            seq,
+            ply: game.ply(),
+            fen: game.to_fen(),
+            result: game.game_result.map(|result| result.to_str()),
+        }),
+    }
+}
+
+#[get("/{game_id}/stats", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_game_stats(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Getting game stats...");
+    let game_id = path.into_inner();
+    if Uuid::parse_str(&game_id).is_err() {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+    match server.viewable_game(&game_id, &principal).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body(NO_GAME_ERROR);
+        }
+        Ok(Some(_)) => (),
+    }
+    match server.game_stats(&game_id).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a game with id {}", game_id);
+            HttpResponse::NotFound().body(NO_GAME_ERROR)
+        }
+        Ok(Some(stats)) => HttpResponse::Ok().json(stats),
+    }
+}
+
+#[get(
+    "/{game_id}/current_state",
+    wrap = "RequirePermission(Permission::ViewGames)"
+)]
+async fn get_game_state(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
     info!("Checking current game state...");
     let game_id: Uuid;
     match Uuid::parse_str(&path.into_inner()) {
@@ -108,8 +354,7 @@ async fn get_game_state(path: web::Path<String>, server: web::Data<Server>) -> H
         Ok(res) => game_id = res,
     }
 
-    let games = server.games.read().unwrap();
-    let game = match games.get(&game_id) {
+    let game = match server.visible_game(game_id, &principal) {
         None => {
             warn!("Could not find a game with id {}", game_id);
             return HttpResponse::NoContent().body("Could not find your current game");
@@ -120,13 +365,15 @@ async fn get_game_state(path: web::Path<String>, server: web::Data<Server>) -> H
     let game_state = GameState {
         state: serialize_field(&game.field),
         admin_color: game.admin_color.to_str(),
+        eco: game.opening.map(|opening| opening.eco.to_string()),
+        opening: game.opening.map(|opening| opening.name.to_string()),
     };
 
     info!("Fetched the game state");
     HttpResponse::Ok().json(game_state)
 }
 
-#[post("/start")]
+#[post("/start", wrap = "RequirePermission(Permission::CreateGames)")]
 async fn start_game(req: web::Json<StartRequest>, server: web::Data<Server>) -> HttpResponse {
     info!("Starting new game...");
     let uuid = Uuid::new_v4();
@@ -137,7 +384,35 @@ async fn start_game(req: web::Json<StartRequest>, server: web::Data<Server>) ->
         _ => return HttpResponse::BadRequest().body("Bad Request"),
     };
 
-    match server.add_game(uuid, admin_color).await {
+    let players = Players {
+        white: req.white_user_id.clone(),
+        black: req.black_user_id.clone(),
+    };
+    for user_id in [&players.white, &players.black].into_iter().flatten() {
+        match server.store.get_user(user_id).await {
+            Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+            Ok(None) => {
+                warn!("Could not find a user with id {}", user_id);
+                return HttpResponse::BadRequest().body("Bad Request");
+            }
+            Ok(Some(_)) => (),
+        }
+    }
+
+    if let Some(days) = req.days_per_move {
+        if req.time_control.is_some() || days == 0 || days > MAX_DAYS_PER_MOVE {
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+    }
+
+    let mut game = Game::new(uuid, admin_color);
+    game.players = players;
+    game.set_time_control(req.time_control);
+    game.days_per_move = req.days_per_move;
+    game.variant = req.variant.unwrap_or_default();
+    game.rated = req.rated.unwrap_or(true);
+
+    match server.add_game(game).await {
         Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
         Ok(_) => {
             info!("Created new game with id {}", uuid.to_string());
@@ -146,7 +421,317 @@ async fn start_game(req: web::Json<StartRequest>, server: web::Data<Server>) ->
     }
 }
 
-#[post("/{game_id}/finish")]
+#[post("/start", wrap = "RequirePermission(Permission::CreateGames)")]
+async fn start_simul(
+    req: web::Json<SimulStartRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Starting new simul...");
+    // the host plays every board with their own account
+    let host_user_id = match &principal.user_id {
+        None => return HttpResponse::BadRequest().body("Bad Request"),
+        Some(user_id) => user_id.clone(),
+    };
+    let mut host_colors = vec![];
+    for color in &req.host_colors {
+        match Color::from_name(color) {
+            None => return HttpResponse::BadRequest().body("Bad Request"),
+            Some(color) => host_colors.push(color),
+        }
+    }
+    if host_colors.is_empty() || host_colors.len() != req.player_user_ids.len() {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+    for user_id in &req.player_user_ids {
+        match server.store.get_user(user_id).await {
+            Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+            Ok(None) => {
+                warn!("Could not find a user with id {}", user_id);
+                return HttpResponse::BadRequest().body("Bad Request");
+            }
+            Ok(Some(_)) => (),
+        }
+    }
+    let boards = host_colors
+        .into_iter()
+        .zip(req.player_user_ids.iter().cloned())
+        .collect();
+
+    let simul_id = Uuid::new_v4();
+    match server
+        .add_simul(
+            simul_id,
+            req.host_name.clone(),
+            host_user_id,
+            boards,
+            req.walking_order.unwrap_or(false),
+            req.host_time_budget_secs,
+        )
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(game_ids) => {
+            info!(
+                "Created new simul with id {} and {} boards",
+                simul_id,
+                game_ids.len()
+            );
+            HttpResponse::Ok().json(SimulCreatedResponse {
+                simul_id: simul_id.to_string(),
+                game_ids: game_ids.iter().map(|id| id.to_string()).collect(),
+            })
+        }
+    }
+}
+
+#[get("/{simul_id}", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_simul(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+    info!("Checking simul status...");
+    let simul_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(id) => id,
+    };
+
+    let games = server.games.read().unwrap();
+    let simuls = server.simuls.read().unwrap();
+    match simuls.get(&simul_id) {
+        None => {
+            warn!("Could not find a simul with id {}", simul_id);
+            HttpResponse::NotFound().body("Could not find your simul")
+        }
+        Some(simul) => HttpResponse::Ok().json(simul.status(&games)),
+    }
+}
+
+#[post("/challenge", wrap = "RequirePermission(Permission::CreateGames)")]
+async fn create_challenge(
+    req: web::Json<ChallengeRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Creating new private game...");
+    let user_id = match principal.user_id {
+        None => return HttpResponse::BadRequest().body("Bad Request"),
+        Some(user_id) => user_id,
+    };
+    if let Some(days) = req.days_per_move {
+        if req.time_control.is_some() || days == 0 || days > MAX_DAYS_PER_MOVE {
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+    }
+    if req
+        .password
+        .as_ref()
+        .is_some_and(|password| password.is_empty())
+    {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+
+    let color = req.color.unwrap_or_default().resolve();
+    let game_id = Uuid::new_v4();
+    let mut game = Game::new(game_id, color);
+    game.players.set(color, user_id.clone());
+    game.set_time_control(req.time_control);
+    game.days_per_move = req.days_per_move;
+    game.variant = req.variant.unwrap_or_default();
+    game.rated = req.rated.unwrap_or(true);
+    game.private = true;
+    if server.add_game(game).await.is_err() {
+        return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR);
+    }
+
+    let token = generate_secret();
+    let expires_at = req.expires_in_mins.map(|mins| {
+        format_timestamp(OffsetDateTime::now_utc() + time::Duration::minutes(mins as i64))
+    });
+    match server
+        .store
+        .create_invite(
+            &hash_secret(&token),
+            &game_id.to_string(),
+            &user_id,
+            &color.opposite().to_str(),
+            req.password.as_deref().map(hash_password),
+            expires_at.clone(),
+        )
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(_) => {
+            info!("User {} created private game {}", user_id, game_id);
+            HttpResponse::Ok().json(InviteCreatedResponse {
+                game_id: game_id.to_string(),
+                color: color.to_str(),
+                token,
+                expires_at,
+            })
+        }
+    }
+}
+
+#[post("/invite/{token}", wrap = "RequirePermission(Permission::CreateGames)")]
+async fn accept_invite(
+    path: web::Path<String>,
+    req: web::Json<AcceptInviteRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Accepting invite...");
+    let user_id = match principal.user_id {
+        None => return HttpResponse::BadRequest().body("Bad Request"),
+        Some(user_id) => user_id,
+    };
+
+    match server
+        .accept_invite(&path.into_inner(), &user_id, req.password.as_deref())
+        .await
+    {
+        Err(INTERNAL_SERVER_ERROR) => {
+            HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR)
+        }
+        Err(NO_INVITE_ERROR) => {
+            warn!("User {} opened an unknown invite", user_id);
+            HttpResponse::NotFound().body(NO_INVITE_ERROR)
+        }
+        Err(e) => {
+            warn!("User {} could not accept invite: {}", user_id, e);
+            HttpResponse::Forbidden().body(e)
+        }
+        Ok((game_id, color)) => HttpResponse::Ok().json(InviteAcceptedResponse {
+            game_id: game_id.to_string(),
+            color: color.to_str(),
+        }),
+    }
+}
+
+#[post("/create", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn create_tournament(
+    req: web::Json<CreateTournamentRequest>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Creating new tournament...");
+    let req = req.into_inner();
+    let mut player_ids: Vec<String> = vec![];
+    for user_id in req.player_ids {
+        if !player_ids.contains(&user_id) {
+            player_ids.push(user_id);
+        }
+    }
+    if player_ids.len() < 2 || req.rounds == Some(0) {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+    for user_id in &player_ids {
+        match server.store.get_user(user_id).await {
+            Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+            Ok(None) => {
+                warn!("Could not find a user with id {}", user_id);
+                return HttpResponse::BadRequest().body("Bad Request");
+            }
+            Ok(Some(_)) => (),
+        }
+    }
+
+    let tournament_id = Uuid::new_v4();
+    let mut tournament =
+        Tournament::new(tournament_id, req.name, req.format, player_ids, req.rounds);
+    tournament.time_control = req.time_control;
+    tournament.variant = req.variant.unwrap_or_default();
+    tournament.rated = req.rated.unwrap_or(true);
+
+    match server.add_tournament(tournament).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(_) => {
+            info!("Created new tournament with id {}", tournament_id);
+            let tournaments = server.tournaments.read().unwrap();
+            match tournaments.get(&tournament_id) {
+                None => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+                Some(tournament) => HttpResponse::Ok().json(tournament.status()),
+            }
+        }
+    }
+}
+
+#[get("/{tournament_id}", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_tournament(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+    info!("Checking tournament status...");
+    let tournament_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(id) => id,
+    };
+
+    let tournaments = server.tournaments.read().unwrap();
+    match tournaments.get(&tournament_id) {
+        None => {
+            warn!("Could not find a tournament with id {}", tournament_id);
+            HttpResponse::NotFound().body("Could not find your tournament")
+        }
+        Some(tournament) => HttpResponse::Ok().json(tournament.status()),
+    }
+}
+
+#[post("/create", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn create_arena(
+    req: web::Json<CreateArenaRequest>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Creating new arena...");
+    let req = req.into_inner();
+    if req.duration_mins == 0 || req.time_control.initial_secs == 0 {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+
+    let arena_id = Uuid::new_v4();
+    let mut arena = Arena::new(
+        arena_id,
+        req.name,
+        req.time_control,
+        Duration::from_secs(req.duration_mins * 60),
+    );
+    arena.variant = req.variant.unwrap_or_default();
+    arena.rated = req.rated.unwrap_or(true);
+    let status = arena.status(Instant::now());
+
+    match server.add_arena(arena).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(_) => {
+            info!("Created new arena with id {}", arena_id);
+            HttpResponse::Ok().json(status)
+        }
+    }
+}
+
+#[get("/{arena_id}", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_arena(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+    info!("Checking arena status...");
+    let arena_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(id) => id,
+    };
+
+    let arenas = server.arenas.read().unwrap();
+    match arenas.get(&arena_id) {
+        None => {
+            warn!("Could not find an arena with id {}", arena_id);
+            HttpResponse::NotFound().body("Could not find your arena")
+        }
+        Some(arena) => HttpResponse::Ok().json(arena.status(Instant::now())),
+    }
+}
+
+#[post(
+    "/{game_id}/finish",
+    wrap = "RequirePermission(Permission::ManageGames)"
+)]
 async fn finish_game(
     path: web::Path<String>,
     req: web::Json<FinishRequest>,
@@ -161,12 +746,66 @@ async fn finish_game(
         Ok(id) => id,
     };
 
+    // a finished game may still be in memory until nobody watches it anymore
+    let over_in_memory = server
+        .games
+        .read()
+        .unwrap()
+        .get(&game_id)
+        .map(|game| game.is_over());
+    let over = match over_in_memory {
+        Some(over) => over,
+        None => match server.store.get_result(&game_id.to_string()).await {
+            Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+            Ok(result) => result.is_some(),
+        },
+    };
+    if over {
+        warn!("Game {} is already finished", game_id);
+        return HttpResponse::Conflict().body(GAME_FINISHED_ERROR);
+    }
+
+    // only results in the usual notation can be rated
+    let result = GameResult::from_name(&req.game_result);
+    let rated = server.games.read().unwrap().get(&game_id).and_then(|game| {
+        let mut game = game.clone();
+        game.game_result = result;
+        RatedGame::from_game(&game)
+    });
+
+    // the room may still be logging earlier events, so continue after its last one
+    let seq = server
+        .rooms
+        .read()
+        .unwrap()
+        .get(&game_id)
+        .map(|room| room.log_seq + 1);
     server.remove_game(game_id);
-    match server
-        .db
-        .finish_game(&req.game_result, &game_id.to_string())
+    let finish = GameEvent::Finish {
+        result: req.game_result.clone(),
+        termination: "admin".to_string(),
+    };
+    if server
+        .store
+        .append_event(&game_id.to_string(), seq, &finish)
         .await
+        .is_err()
     {
+        return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR);
+    }
+    let finished = match result {
+        Some(result) => {
+            server
+                .record_result(&game_id.to_string(), result, "admin", rated)
+                .await
+        }
+        None => server
+            .store
+            .finish_game(&req.game_result, "admin", &game_id.to_string())
+            .await
+            .map(|_| ()),
+    };
+    match finished {
         Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
         Ok(_) => {
             info!("Finished DB game with result {:?}", req.game_result);
@@ -175,11 +814,11 @@ async fn finish_game(
     }
 }
 
-#[get("/connect/{game_id}")]
-async fn connect_ws(
+#[delete("/{game_id}", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn delete_game(
     path: web::Path<String>,
-    req: HttpRequest,
-    stream: web::Payload,
+    query: web::Query<DeleteGameQuery>,
+    principal: Principal,
     server: web::Data<Server>,
 ) -> HttpResponse {
     let game_id = match Uuid::parse_str(&path.into_inner()) {
@@ -187,14 +826,1107 @@ async fn connect_ws(
             error!("Could not parse Uuid from request: {}", e);
             return HttpResponse::BadRequest().body("Bad Request");
         }
-        Ok(res) => res,
+        Ok(id) => id,
     };
-    let connection = WebSocketConnection::new(game_id, Arc::clone(&server));
-    let resp = ws::start(connection, &req, stream);
-    if let Err(e) = resp {
-        error!("Could not connect to websocket: {}", e);
-        return HttpResponse::InternalServerError().body("Internal server error");
+    match server.delete_game(game_id, query.hard).await {
+        Err(RATED_GAME_DELETE_ERROR) => HttpResponse::BadRequest().body(RATED_GAME_DELETE_ERROR),
+        Err(COMPETITION_GAME_DELETE_ERROR) => {
+            HttpResponse::BadRequest().body(COMPETITION_GAME_DELETE_ERROR)
+        }
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(false) => {
+            warn!("Could not find a game with id {}", game_id);
+            HttpResponse::NotFound().body(NO_GAME_ERROR)
+        }
+        Ok(true) => {
+            info!(
+                "{} deleted game {}{}",
+                principal.name(),
+                game_id,
+                if query.hard { " for good" } else { "" }
+            );
+            HttpResponse::Ok().finish()
+        }
     }
+}
 
-    resp.unwrap()
+#[post(
+    "/{game_id}/action",
+    wrap = "RequirePermission(Permission::CreateGames)"
+)]
+async fn perform_action(
+    path: web::Path<String>,
+    req: web::Json<ActionRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Performing game action...");
+    let game_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(id) => id,
+    };
+
+    // players act for the seat they own, never for the one they name
+    let seat = match server.games.read().unwrap().get(&game_id) {
+        None => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body("Could not find your game");
+        }
+        Some(game) => principal
+            .user_id
+            .as_deref()
+            .and_then(|user_id| game.players.seat(user_id)),
+    };
+    let player = match seat {
+        None => {
+            warn!("{} is not seated at game {}", principal.name(), game_id);
+            return HttpResponse::Forbidden().body(NO_SEAT_ERROR);
+        }
+        Some(player) => player,
+    };
+
+    let mut rooms = server.rooms.write().unwrap();
+    let room = match rooms.get_mut(&game_id) {
+        None => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body("Could not find your game");
+        }
+        Some(room) => room,
+    };
+
+    match room.perform_action(player, req.action) {
+        Err(e) => HttpResponse::BadRequest().body(e),
+        Ok(_) => {
+            info!(
+                "Performed {:?} for {} in game {}",
+                req.action,
+                player.to_str(),
+                game_id
+            );
+            HttpResponse::Ok().body("OK".to_string())
+        }
+    }
+}
+
+#[post(
+    "/{game_id}/spectators",
+    wrap = "RequirePermission(Permission::ManageGames)"
+)]
+async fn set_spectator_settings(
+    path: web::Path<String>,
+    req: web::Json<SpectatorSettingsRequest>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Updating spectator settings...");
+    let game_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(id) => id,
+    };
+
+    let mut rooms = server.rooms.write().unwrap();
+    let room = match rooms.get_mut(&game_id) {
+        None => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body("Could not find your game");
+        }
+        Some(room) => room,
+    };
+
+    room.set_spectator_delay(Duration::from_secs(req.delay_secs));
+    room.max_spectators = req.max_spectators;
+    info!(
+        "Spectators of game {} now have a {}s delay, at most {} of them",
+        game_id, req.delay_secs, req.max_spectators
+    );
+    HttpResponse::Ok().body("OK".to_string())
+}
+
+#[get("/{game_id}/chat", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_chat(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Fetching chat history...");
+    let game_id = path.into_inner();
+    let game = match server.viewable_game(&game_id, &principal).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body(NO_GAME_ERROR);
+        }
+        Ok(Some(game)) => game,
+    };
+    // the players' chat is only for the players and moderators
+    let seated = principal
+        .user_id
+        .as_deref()
+        .is_some_and(|user_id| game.players.contains(user_id));
+    let players_chat = seated || principal.role.has_permission(Permission::ModerateChat);
+    match server.store.get_chat(&game_id).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(mut messages) => {
+            if !players_chat {
+                messages.retain(|message| message.scope != ChatScope::Players.to_str());
+            }
+            info!("Fetched {} chat messages", messages.len());
+            HttpResponse::Ok().json(messages)
+        }
+    }
+}
+
+#[post(
+    "/{game_id}/chat/moderate",
+    wrap = "RequirePermission(Permission::ModerateChat)"
+)]
+async fn moderate_chat(
+    path: web::Path<String>,
+    req: web::Json<ModerationRequest>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Moderating chat...");
+    let (game_id, connection_id) = match (
+        Uuid::parse_str(&path.into_inner()),
+        Uuid::parse_str(&req.connection_id),
+    ) {
+        (Ok(game_id), Ok(connection_id)) => (game_id, connection_id),
+        _ => {
+            error!("Could not parse Uuid from request");
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+    };
+
+    let mut rooms = server.rooms.write().unwrap();
+    let room = match rooms.get_mut(&game_id) {
+        None => {
+            warn!("Could not find a game with id {}", game_id);
+            return HttpResponse::NotFound().body("Could not find your game");
+        }
+        Some(room) => room,
+    };
+
+    match room.moderate(connection_id, req.action) {
+        Err(e) => HttpResponse::BadRequest().body(e),
+        Ok(_) => {
+            info!(
+                "{:?} connection {} in game {}",
+                req.action, connection_id, game_id
+            );
+            HttpResponse::Ok().body("OK".to_string())
+        }
+    }
+}
+
+#[post(
+    "/chat/broadcast",
+    wrap = "RequirePermission(Permission::ModerateChat)"
+)]
+async fn broadcast_chat(
+    req: web::Json<AdminChatRequest>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Broadcasting admin chat message to all games...");
+    let message = match check_chat_message(&req.message) {
+        Err(e) => return HttpResponse::BadRequest().body(e),
+        Ok(message) => message,
+    };
+
+    let mut rooms = server.rooms.write().unwrap();
+    for room in rooms.values_mut() {
+        room.send_chat(
+            ChatScope::All,
+            "admin".to_string(),
+            None,
+            message.to_string(),
+        );
+    }
+
+    info!("Sent admin chat message to {} games", rooms.len());
+    HttpResponse::Ok().body("OK".to_string())
+}
+
+#[get("/connect/{game_id}")]
+async fn connect_ws(
+    path: web::Path<String>,
+    query: web::Query<ConnectQuery>,
+    req: HttpRequest,
+    stream: web::Payload,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let game_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(res) => res,
+    };
+    let (private, players) = match server.games.read().unwrap().get(&game_id) {
+        None => (false, Players::default()),
+        Some(game) => (game.private, game.players.clone()),
+    };
+    let claims = match query.token.as_deref().map(verify_jwt) {
+        None => None,
+        Some(Err(_)) => return HttpResponse::Unauthorized().body("Unauthorized"),
+        Some(Ok(claims)) => Some(claims),
+    };
+    let user_id = claims.as_ref().map(|claims| claims.sub.as_str());
+
+    // the role follows from who connects, only players of the game and the
+    // voting frontend get to see moves without the spectator delay
+    let seat = match query.seat.as_deref() {
+        None => user_id.and_then(|user_id| players.seat(user_id)),
+        Some(seat) => match Color::from_name(seat) {
+            None => return HttpResponse::BadRequest().body("Bad Request"),
+            Some(color)
+                if user_id.is_some() && players.get(color).map(String::as_str) == user_id =>
+            {
+                Some(color)
+            }
+            Some(color) => {
+                warn!(
+                    "Unauthorized connection as {} to game {}",
+                    color.to_str(),
+                    game_id
+                );
+                return HttpResponse::Forbidden().body("Forbidden");
+            }
+        },
+    };
+    // the voting frontend may move for either side, so not in games between users
+    let voter = claims
+        .as_ref()
+        .is_some_and(|claims| claims.role.has_permission(Permission::ManageGames))
+        && !private
+        && players == Players::default();
+    let role = match (query.spectate.unwrap_or(false), seat) {
+        (false, Some(color)) => Role::Player(color),
+        (false, None) if voter => Role::Voter,
+        _ => Role::Spectator,
+    };
+    let allowed = match role {
+        Role::Spectator => match user_id {
+            None => !private,
+            Some(user_id) => !private || players.contains(user_id),
+        },
+        _ => true,
+    };
+    if !allowed {
+        warn!(
+            "Unauthorized connection as {} to game {}",
+            role.to_str(),
+            game_id
+        );
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    if role == Role::Spectator {
+        let rooms = server.rooms.read().unwrap();
+        if let Some(room) = rooms.get(&game_id) {
+            if room.spectator_count() >= room.max_spectators {
+                warn!("Spectator limit reached for game {}", game_id);
+                return HttpResponse::ServiceUnavailable().body(TOO_MANY_SPECTATORS_ERROR);
+            }
+        }
+    }
+
+    let connection =
+        WebSocketConnection::new(game_id, Arc::clone(&server), query.last_seen_seq, role);
+    let resp = ws::start(connection, &req, stream);
+    if let Err(e) = resp {
+        error!("Could not connect to websocket: {}", e);
+        return HttpResponse::InternalServerError().body("Internal server error");
+    }
+
+    resp.unwrap()
+}
+
+#[post("", wrap = "RequirePermission(Permission::ManageUsers)")]
+async fn create_user(req: web::Json<CreateUserRequest>, server: web::Data<Server>) -> HttpResponse {
+    info!("Creating new user...");
+    if req.display_name.trim().is_empty() || !req.email.contains('@') {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+
+    let user = User {
+        user_id: Uuid::new_v4().to_string(),
+        display_name: req.display_name.trim().to_string(),
+        email: req.email.trim().to_string(),
+        role: UserRole::for_email(&req.email).to_str(),
+    };
+    if server.store.create_user(&user).await.is_err() {
+        return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR);
+    }
+
+    match issue_jwt(&user) {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(token) => {
+            info!("Created new user with id {}", user.user_id);
+            HttpResponse::Ok().json(UserCreatedResponse { user, token })
+        }
+    }
+}
+
+#[get("/{user_id}", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_user(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Getting user...");
+    let user_id = path.into_inner();
+    let show_email = principal.user_id.as_ref() == Some(&user_id)
+        || principal.role.has_permission(Permission::ManageUsers);
+    match server.store.get_user(&user_id).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a user with id {}", user_id);
+            HttpResponse::NotFound().body("Could not find your user")
+        }
+        Ok(Some(user)) => HttpResponse::Ok().json(UserResponse::new(user, show_email)),
+    }
+}
+
+#[get("/{user_id}/stats", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_user_stats(
+    path: web::Path<String>,
+    query: web::Query<UserStatsQuery>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Getting user stats...");
+    let user_id = path.into_inner();
+    if query
+        .year
+        .is_some_and(|year| !(1970..=9999).contains(&year))
+    {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+    match server.store.get_user(&user_id).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a user with id {}", user_id);
+            return HttpResponse::NotFound().body("Could not find your user");
+        }
+        Ok(Some(_)) => (),
+    }
+    match server.user_stats(&user_id, query.year).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(stats) => HttpResponse::Ok().json(stats),
+    }
+}
+
+#[post("/vacation", wrap = "RequirePermission(Permission::CreateGames)")]
+async fn take_vacation(
+    req: web::Json<VacationRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Taking vacation...");
+    let user_id = match principal.user_id {
+        None => return HttpResponse::BadRequest().body("Bad Request"),
+        Some(user_id) => user_id,
+    };
+    if req.days == 0 {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+
+    let vacations = match server.store.get_vacations(&user_id).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(vacations) => vacations,
+    };
+    let now = OffsetDateTime::now_utc();
+    if on_vacation(&vacations, now) {
+        return HttpResponse::BadRequest().body(ON_VACATION_ERROR);
+    }
+    if vacation_days_used(&vacations, now) + req.days as i64 > VACATION_DAYS_PER_YEAR {
+        return HttpResponse::BadRequest().body(VACATION_LIMIT_ERROR);
+    }
+
+    let ends_at = now + time::Duration::days(req.days as i64);
+    match server
+        .store
+        .add_vacation(&user_id, &format_timestamp(now), &format_timestamp(ends_at))
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(_) => {
+            info!("User {} is on vacation for {} days", user_id, req.days);
+            HttpResponse::Ok().body(format_timestamp(ends_at))
+        }
+    }
+}
+
+// timestamps are stored in UTC, so filters have to be too for them to compare
+fn utc_timestamp(timestamp: &Option<String>) -> Result<Option<String>, ()> {
+    match timestamp.as_deref().map(parse_timestamp) {
+        None => Ok(None),
+        Some(None) => Err(()),
+        Some(Some(timestamp)) => Ok(Some(format_timestamp(
+            timestamp.to_offset(time::UtcOffset::UTC),
+        ))),
+    }
+}
+
+#[get("", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn search_games(
+    principal: Principal,
+    query: web::Query<GamesQuery>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Searching games: {:?}", query);
+    let (created_from, created_until) =
+        match (utc_timestamp(&query.from), utc_timestamp(&query.until)) {
+            (Ok(from), Ok(until)) => (from, until),
+            _ => return HttpResponse::BadRequest().body("Bad Request"),
+        };
+    if let Some(result) = &query.result {
+        if GameResult::from_name(result).is_none() {
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+    }
+    if let Some(eco) = &query.eco {
+        if !is_eco_prefix(eco) {
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+    }
+    let position_hash = match (&query.fen, &query.hash) {
+        (None, None) => None,
+        (Some(fen), None) => {
+            let mut position = Game::new(Uuid::nil(), Color::WHITE);
+            if let Err(e) = position.load_fen(fen) {
+                return HttpResponse::BadRequest().body(e);
+            }
+            Some(position.zobrist())
+        }
+        (None, Some(hash)) => match u64::from_str_radix(hash, 16) {
+            Err(_) => return HttpResponse::BadRequest().body("Bad Request"),
+            Ok(hash) => Some(hash),
+        },
+        (Some(_), Some(_)) => return HttpResponse::BadRequest().body("Bad Request"),
+    };
+
+    let filter = GameFilter {
+        finished: query.status.map(|status| status == GameStatus::Finished),
+        player: query.player.clone(),
+        result: query.result.clone(),
+        variant: query.variant,
+        eco: query.eco.clone(),
+        created_from,
+        created_until,
+        position_hash,
+        include_private: principal.role.has_permission(Permission::ManageGames),
+        viewer: principal.user_id,
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_GAMES_PAGE_SIZE)
+        .clamp(1, MAX_GAMES_PAGE_SIZE);
+    let newest_first = query.sort.unwrap_or_default() == GameSort::Newest;
+    match server
+        .store
+        .search_games(&filter, newest_first, per_page, (page - 1) * per_page)
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok((total, games)) => HttpResponse::Ok().json(GamesPage {
+            total,
+            page,
+            per_page,
+            games: games.into_iter().map(ArchivedGameResponse::new).collect(),
+        }),
+    }
+}
+
+#[get("/awaiting-my-move", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_awaiting_games(principal: Principal, server: web::Data<Server>) -> HttpResponse {
+    info!("Getting games awaiting a move...");
+    let user_id = match principal.user_id {
+        None => return HttpResponse::BadRequest().body("Bad Request"),
+        Some(user_id) => user_id,
+    };
+
+    let games = match server.store.get_awaiting_games(&user_id).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(games) => games,
+    };
+    let vacations = match server.store.get_vacations(&user_id).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(vacations) => vacations,
+    };
+
+    let games: Vec<AwaitingMoveResponse> = games
+        .into_iter()
+        .map(|game| {
+            let since = game.last_moved_at.as_ref().unwrap_or(&game.created_at);
+            let deadline = match (game.days_per_move, parse_timestamp(since)) {
+                (Some(days), Some(since)) => {
+                    Some(format_timestamp(deadline(since, days, &vacations)))
+                }
+                _ => None,
+            };
+            AwaitingMoveResponse {
+                game_id: game.game_id,
+                color: game.color,
+                opponent_id: game.opponent_id,
+                days_per_move: game.days_per_move,
+                last_move_at: game.last_moved_at,
+                deadline,
+            }
+        })
+        .collect();
+    info!(
+        "Found {} games awaiting a move of user {}",
+        games.len(),
+        user_id
+    );
+    HttpResponse::Ok().json(games)
+}
+
+#[get("/{user_id}/games", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_user_games(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Getting games of user...");
+    let user_id = path.into_inner();
+    let include_private = principal.role.has_permission(Permission::ManageGames);
+    match server
+        .store
+        .get_user_games(&user_id, include_private, principal.user_id.as_deref())
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(games) => {
+            info!("Fetched {} games of user {}", games.len(), user_id);
+            HttpResponse::Ok().json(games)
+        }
+    }
+}
+
+#[post("", wrap = "RequirePermission(Permission::ManageApiKeys)")]
+async fn create_api_key(
+    req: web::Json<CreateApiKeyRequest>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Creating new api key...");
+    if req.label.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Bad Request");
+    }
+
+    let key_id = Uuid::new_v4().to_string();
+    let key = generate_secret();
+    if server
+        .store
+        .create_api_key(&key_id, &req.label, &hash_secret(&key), &req.role.to_str())
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR);
+    }
+
+    info!(
+        "{} created api key {} with role {}",
+        principal.name(),
+        req.label,
+        req.role.to_str()
+    );
+    HttpResponse::Ok().json(ApiKeyCreatedResponse {
+        key_id,
+        label: req.label.clone(),
+        role: req.role,
+        key,
+    })
+}
+
+#[get("", wrap = "RequirePermission(Permission::ManageApiKeys)")]
+async fn get_api_keys(server: web::Data<Server>) -> HttpResponse {
+    info!("Getting all api keys...");
+    match server.store.get_api_keys().await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(keys) => HttpResponse::Ok().json(keys),
+    }
+}
+
+#[delete("/{key_id}", wrap = "RequirePermission(Permission::ManageApiKeys)")]
+async fn revoke_api_key(
+    path: web::Path<String>,
+    principal: Principal,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let key_id = path.into_inner();
+    match server.store.revoke_api_key(&key_id).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(false) => {
+            warn!("Could not find an active api key with id {}", key_id);
+            HttpResponse::NotFound().body("Could not find your api key")
+        }
+        Ok(true) => {
+            info!("{} revoked api key {}", principal.name(), key_id);
+            HttpResponse::Ok().finish()
+        }
+    }
+}
+
+#[get(
+    "/{user_id}/ratings",
+    wrap = "RequirePermission(Permission::ViewGames)"
+)]
+async fn get_user_ratings(path: web::Path<String>, server: web::Data<Server>) -> HttpResponse {
+    info!("Getting rating history of user...");
+    let user_id = path.into_inner();
+    match server.store.get_rating_history(&user_id).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(history) => HttpResponse::Ok().json(history),
+    }
+}
+
+#[get("/leaderboard", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_leaderboard(
+    query: web::Query<LeaderboardQuery>,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let pool = RatingPool {
+        category: query.category.unwrap_or(TimeControlCategory::Blitz),
+        variant: query.variant.unwrap_or_default(),
+    };
+    info!("Getting leaderboard of {}...", pool.to_str());
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LEADERBOARD_SIZE)
+        .min(MAX_LEADERBOARD_SIZE);
+    match server
+        .store
+        .get_leaderboard(pool, PROVISIONAL_DEVIATION, limit)
+        .await
+    {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(leaderboard) => HttpResponse::Ok().json(leaderboard),
+    }
+}
+
+#[get("/explorer", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_explorer(query: web::Query<ExplorerQuery>, server: web::Data<Server>) -> HttpResponse {
+    info!("Exploring position {}...", query.fen);
+    let mut position = Game::new(Uuid::nil(), Color::WHITE);
+    if let Err(e) = position.load_fen(&query.fen) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let hash = position.zobrist();
+
+    match server.store.get_explorer_moves(hash).await {
+        Err(_) => HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(moves) => HttpResponse::Ok().json(ExplorerResponse {
+            fen: query.fen.clone(),
+            hash: format!("{:016x}", hash),
+            games: moves.iter().map(|played| played.games).sum(),
+            moves: moves.into_iter().map(ExplorerMoveResponse::new).collect(),
+        }),
+    }
+}
+
+#[get("/export", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn export_games(query: web::Query<BackupQuery>, server: web::Data<Server>) -> HttpResponse {
+    info!("Exporting all games as {:?}...", query.format);
+    let format = query.format;
+    // one page of games per chunk, `None` once the last page was sent
+    let pages = stream::unfold(
+        Some(None),
+        move |cursor: Option<Option<(String, String)>>| {
+            let server = server.clone();
+            async move {
+                let cursor = cursor?;
+                let (games, next) =
+                    match server.export_games(cursor.as_ref(), EXPORT_PAGE_SIZE).await {
+                        Err(e) => return Some((Err(error::ErrorInternalServerError(e)), None)),
+                        Ok(page) => page,
+                    };
+                let mut chunk = String::new();
+                for game in games {
+                    match game.render(format) {
+                        Err(e) => error!("Could not export game {}: {}", game.game_id, e),
+                        Ok(text) => chunk.push_str(&text),
+                    }
+                }
+                Some((
+                    Ok::<_, actix_web::Error>(web::Bytes::from(chunk)),
+                    next.map(Some),
+                ))
+            }
+        },
+    );
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(pages)
+}
+
+#[post("/import", wrap = "RequirePermission(Permission::ManageGames)")]
+async fn import_games(
+    query: web::Query<BackupQuery>,
+    body: String,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    info!("Importing games as {:?}...", query.format);
+    let report = server.import_games(parse_games(&body, query.format)).await;
+    info!(
+        "Imported {} games, skipped {}, {} failed",
+        report.imported,
+        report.skipped,
+        report.failed.len()
+    );
+    HttpResponse::Ok().json(report)
+}
+
+#[get("/seeks", wrap = "RequirePermission(Permission::ViewGames)")]
+async fn get_seeks(server: web::Data<Server>) -> HttpResponse {
+    info!("Getting open seeks...");
+    let seeks = server.lobby.read().unwrap().seeks.clone();
+    HttpResponse::Ok().json(seeks)
+}
+
+#[get("/lobby")]
+async fn connect_lobby_ws(
+    query: web::Query<LobbyQuery>,
+    req: HttpRequest,
+    stream: web::Payload,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let claims = match verify_jwt(&query.token) {
+        Err(_) => return HttpResponse::Unauthorized().body("Unauthorized"),
+        Ok(claims) => claims,
+    };
+    if !claims.role.has_permission(Permission::CreateGames) {
+        warn!("User {} may not join the lobby", claims.sub);
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+    let user = match server.store.get_user(&claims.sub).await {
+        Err(_) => return HttpResponse::InternalServerError().body(INTERNAL_SERVER_ERROR),
+        Ok(None) => {
+            warn!("Could not find a user with id {}", claims.sub);
+            return HttpResponse::NotFound().body("Could not find your user");
+        }
+        Ok(Some(user)) => user,
+    };
+
+    let connection = LobbyConnection::new(user.user_id, user.display_name, Arc::clone(&server));
+    let resp = ws::start(connection, &req, stream);
+    if let Err(e) = resp {
+        error!("Could not connect to websocket: {}", e);
+        return HttpResponse::InternalServerError().body("Internal server error");
+    }
+
+    resp.unwrap()
+}
+
+#[get("/arena/{arena_id}")]
+async fn connect_arena_ws(
+    path: web::Path<String>,
+    query: web::Query<ArenaQuery>,
+    req: HttpRequest,
+    stream: web::Payload,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let arena_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(res) => res,
+    };
+    if !server.arenas.read().unwrap().contains_key(&arena_id) {
+        warn!("Could not find an arena with id {}", arena_id);
+        return HttpResponse::NotFound().body("Could not find your arena");
+    }
+
+    // a token is only needed to play, anyone may watch the standings
+    let user_id = match &query.token {
+        None => None,
+        Some(token) => {
+            let claims = match verify_jwt(token) {
+                Err(_) => return HttpResponse::Unauthorized().body("Unauthorized"),
+                Ok(claims) => claims,
+            };
+            if !claims.role.has_permission(Permission::CreateGames) {
+                warn!("User {} may not play in arenas", claims.sub);
+                return HttpResponse::Forbidden().body("Forbidden");
+            }
+            Some(claims.sub)
+        }
+    };
+
+    let connection = ArenaConnection::new(arena_id, user_id, Arc::clone(&server));
+    let resp = ws::start(connection, &req, stream);
+    if let Err(e) = resp {
+        error!("Could not connect to websocket: {}", e);
+        return HttpResponse::InternalServerError().body("Internal server error");
+    }
+
+    resp.unwrap()
+}
+
+#[get("/simul/{simul_id}")]
+async fn connect_simul_ws(
+    path: web::Path<String>,
+    query: web::Query<SimulHostQuery>,
+    req: HttpRequest,
+    stream: web::Payload,
+    server: web::Data<Server>,
+) -> HttpResponse {
+    let simul_id = match Uuid::parse_str(&path.into_inner()) {
+        Err(e) => {
+            error!("Could not parse Uuid from request: {}", e);
+            return HttpResponse::BadRequest().body("Bad Request");
+        }
+        Ok(res) => res,
+    };
+    let claims = match verify_jwt(&query.token) {
+        Err(_) => return HttpResponse::Unauthorized().body("Unauthorized"),
+        Ok(claims) => claims,
+    };
+    let host_user_id = match server.simuls.read().unwrap().get(&simul_id) {
+        None => {
+            warn!("Could not find a simul with id {}", simul_id);
+            return HttpResponse::NotFound().body("Could not find your simul");
+        }
+        Some(simul) => simul.host_user_id.clone(),
+    };
+    if claims.sub != host_user_id {
+        warn!("User {} is not the host of simul {}", claims.sub, simul_id);
+        return HttpResponse::Forbidden().body("Forbidden");
+    }
+
+    let connection = SimulHostConnection::new(simul_id, Arc::clone(&server));
+    let resp = ws::start(connection, &req, stream);
+    if let Err(e) = resp {
+        error!("Could not connect to websocket: {}", e);
+        return HttpResponse::InternalServerError().body("Internal server error");
+    }
+
+    resp.unwrap()
+}
+
+#[cfg(test)]
+mod test_handlers {
+    use std::{env, sync::Arc};
+
+    use actix_web::{test, web, App};
+    use chess_voting::{
+        game::{chess_piece::Color, Game, GameResult},
+        server::Server,
+        store::MemoryStore,
+        user::{User, UserRole},
+        utils::request::issue_jwt,
+    };
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use super::{finish_game, get_chat, get_game_history, get_game_stats, get_user};
+
+    fn user(user_id: &str) -> User {
+        User {
+            user_id: user_id.to_string(),
+            display_name: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role: UserRole::Player.to_str(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_user() {
+        env::set_var("JWT_SECRET", "test-secret");
+        let server = Server::with_store(Arc::new(MemoryStore::default()));
+        let (alice, bob) = (user("alice"), user("bob"));
+        server.store.create_user(&alice).await.unwrap();
+        server.store.create_user(&bob).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(server))
+                .service(web::scope("users").service(get_user)),
+        )
+        .await;
+
+        // only the user themselves sees their email
+        for (viewer, email) in [(&alice, Some("alice@example.com")), (&bob, None)] {
+            let req = test::TestRequest::get()
+                .uri("/users/alice")
+                .insert_header((
+                    "Authorization",
+                    format!("Bearer {}", issue_jwt(viewer).unwrap()),
+                ))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["display_name"], "alice");
+            assert_eq!(body["email"].as_str(), email);
+        }
+
+        let req = test::TestRequest::get().uri("/users/carol").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_get_chat() {
+        env::set_var("JWT_SECRET", "test-secret");
+        let server = Server::with_store(Arc::new(MemoryStore::default()));
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        game.players.white = Some("alice".to_string());
+        game.players.black = Some("bob".to_string());
+        server.store.create_game(&game).await.unwrap();
+        let game_id = game.id.to_string();
+        server
+            .store
+            .insert_chat(&game_id, "players", "alice", "good luck")
+            .await
+            .unwrap();
+        server
+            .store
+            .insert_chat(&game_id, "all", "carol", "hi everyone")
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(server))
+                .service(web::scope("game").service(get_chat)),
+        )
+        .await;
+
+        let mut admin = user("dave");
+        admin.role = UserRole::Admin.to_str();
+        // the players' chat is only shown to the players and moderators
+        let everything = vec!["players", "all"];
+        for (viewer, scopes) in [
+            (user("bob"), everything.clone()),
+            (admin, everything),
+            (user("carol"), vec!["all"]),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/game/{}/chat", game_id))
+                .insert_header((
+                    "Authorization",
+                    format!("Bearer {}", issue_jwt(&viewer).unwrap()),
+                ))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let seen: Vec<&str> = body
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["scope"].as_str().unwrap())
+                .collect();
+            assert_eq!(seen, scopes);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_private_game() {
+        env::set_var("JWT_SECRET", "test-secret");
+        let server = Server::with_store(Arc::new(MemoryStore::default()));
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        game.players.white = Some("alice".to_string());
+        game.players.black = Some("bob".to_string());
+        game.private = true;
+        server.store.create_game(&game).await.unwrap();
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(server)).service(
+                web::scope("game")
+                    .service(get_game_history)
+                    .service(get_game_stats)
+                    .service(get_chat),
+            ),
+        )
+        .await;
+
+        let mut admin = user("dave");
+        admin.role = UserRole::Admin.to_str();
+        // to everyone but its players and admins a private game does not exist
+        for (viewer, status) in [(user("bob"), 200), (admin, 200), (user("carol"), 404)] {
+            for path in ["history", "stats", "chat"] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/game/{}/{}", game.id, path))
+                    .insert_header((
+                        "Authorization",
+                        format!("Bearer {}", issue_jwt(&viewer).unwrap()),
+                    ))
+                    .to_request();
+                let res = test::call_service(&app, req).await;
+                assert_eq!(res.status(), status, "{} of {}", path, viewer.user_id);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_finish_game_once() {
+        env::set_var("JWT_SECRET", "test-secret");
+        let server = Arc::new(Server::with_store(Arc::new(MemoryStore::default())));
+        let mut game = Game::new(Uuid::new_v4(), Color::WHITE);
+        game.players.white = Some("alice".to_string());
+        game.players.black = Some("bob".to_string());
+        game.rated = true;
+        let game_id = game.id;
+        server.add_game(game).await.unwrap();
+        let mut resigned = Game::new(Uuid::new_v4(), Color::WHITE);
+        resigned.game_result = Some(GameResult::BlackWon);
+        let resigned_id = resigned.id;
+        server.add_game(resigned).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(server.clone()))
+                .service(web::scope("game").service(finish_game)),
+        )
+        .await;
+
+        let mut admin = user("dave");
+        admin.role = UserRole::Admin.to_str();
+        let finish = |game_id: Uuid| {
+            test::TestRequest::post()
+                .uri(&format!("/game/{}/finish", game_id))
+                .insert_header((
+                    "Authorization",
+                    format!("Bearer {}", issue_jwt(&admin).unwrap()),
+                ))
+                .set_json(serde_json::json!({ "game_result": "1-0" }))
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, finish(game_id)).await.status(),
+            200
+        );
+        assert_eq!(
+            server
+                .store
+                .get_rating_history("alice")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // neither the stored result nor the ratings change a second time
+        assert_eq!(
+            test::call_service(&app, finish(game_id)).await.status(),
+            409
+        );
+        assert_eq!(
+            server
+                .store
+                .get_rating_history("alice")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        // nor for a game that ended but is still in memory
+        assert_eq!(
+            test::call_service(&app, finish(resigned_id)).await.status(),
+            409
+        );
+    }
 }
@@ -0,0 +1,362 @@
+use std::time::Duration;
+
+use actix::clock::sleep;
+use actix_web::cookie::time::{Duration as TimeDuration, OffsetDateTime};
+use libsql::{params, Connection};
+use log::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    correspondence::{format_timestamp, parse_timestamp},
+    utils::error::{
+        INTERNAL_SERVER_ERROR, MIGRATION_LOCK_ERROR, ROLLBACK_TARGET_ERROR, UNKNOWN_VERSION_ERROR,
+    },
+};
+
+// how long to wait for another process to finish migrating
+const LOCK_ATTEMPTS: u32 = 60;
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// a lock older than this was left behind by a crashed process
+const STALE_LOCK_MINS: i64 = 10;
+
+/// A schema change, embedded in the binary from `src/migrations`.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("migrations/", $name, ".up.sql")),
+            down: include_str!(concat!("migrations/", $name, ".down.sql")),
+        }
+    };
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_initial"),
+    migration!(2, "0002_games"),
+    migration!(3, "0003_users"),
+    migration!(4, "0004_tournaments"),
+    migration!(5, "0005_correspondence"),
+    migration!(6, "0006_move_ply"),
+    migration!(7, "0007_game_events"),
+    migration!(8, "0008_game_archive"),
+    migration!(9, "0009_explorer"),
+    migration!(10, "0010_openings"),
+    migration!(11, "0011_move_times"),
+    migration!(12, "0012_game_cleanup"),
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // migrate up to the given version, or the latest one
+    Migrate(Option<u32>),
+    // roll back down to the given version, or by one migration
+    Rollback(Option<u32>),
+}
+
+/// Parses the command line of the binary, `None` means starting the server.
+pub fn parse_args(args: &[String]) -> Result<Option<Command>, String> {
+    let target = match args.get(1) {
+        None => None,
+        Some(version) => Some(
+            version
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid schema version: {}", version))?,
+        ),
+    };
+    if args.len() > 2 {
+        return Err(format!("Unexpected argument: {}", args[2]));
+    }
+    match args.first().map(String::as_str) {
+        None => Ok(None),
+        Some("--migrate") => Ok(Some(Command::Migrate(target))),
+        Some("--rollback") => Ok(Some(Command::Rollback(target))),
+        Some(arg) => Err(format!("Unknown argument: {}", arg)),
+    }
+}
+
+/// The migrations to run to get from `current` to `target`, in order,
+/// together with whether they are applied (up) or reverted (down).
+fn plan(current: u32, target: u32) -> Vec<(&'static Migration, bool)> {
+    if target >= current {
+        MIGRATIONS
+            .iter()
+            .filter(|migration| migration.version > current && migration.version <= target)
+            .map(|migration| (migration, true))
+            .collect()
+    } else {
+        MIGRATIONS
+            .iter()
+            .rev()
+            .filter(|migration| migration.version <= current && migration.version > target)
+            .map(|migration| (migration, false))
+            .collect()
+    }
+}
+
+pub async fn run(conn: &Connection, command: Command) -> Result<u32, &'static str> {
+    let lock_id = lock(conn).await?;
+    let result = match command {
+        Command::Migrate(target) => migrate_locked(conn, target.unwrap_or(latest_version())).await,
+        Command::Rollback(target) => rollback_locked(conn, target).await,
+    };
+    unlock(conn, &lock_id).await;
+    result
+}
+
+/// Brings the schema to the latest version, used on startup.
+pub async fn migrate(conn: &Connection) -> Result<u32, &'static str> {
+    run(conn, Command::Migrate(None)).await
+}
+
+async fn rollback_locked(conn: &Connection, target: Option<u32>) -> Result<u32, &'static str> {
+    let current = current_version(conn).await?;
+    match target {
+        None => migrate_locked(conn, current.saturating_sub(1)).await,
+        Some(target) if target < current => migrate_locked(conn, target).await,
+        Some(target) => {
+            error!(
+                "Cannot roll back to schema version {}, the database is at {}",
+                target, current
+            );
+            Err(ROLLBACK_TARGET_ERROR)
+        }
+    }
+}
+
+async fn migrate_locked(conn: &Connection, target: u32) -> Result<u32, &'static str> {
+    let current = current_version(conn).await?;
+    if current > latest_version() || target > latest_version() {
+        error!(
+            "Schema version {} requested, this binary knows up to {} and the database is at {}",
+            target,
+            latest_version(),
+            current
+        );
+        return Err(UNKNOWN_VERSION_ERROR);
+    }
+    for (migration, up) in plan(current, target) {
+        apply(conn, migration, up).await?;
+    }
+    Ok(target)
+}
+
+async fn apply(conn: &Connection, migration: &Migration, up: bool) -> Result<(), &'static str> {
+    info!(
+        "{} migration {}",
+        if up { "Applying" } else { "Reverting" },
+        migration.name
+    );
+    let result = async {
+        let tx = conn.transaction().await?;
+        if up {
+            tx.execute_batch(migration.up).await?;
+            tx.execute(
+                "INSERT INTO SchemaVersion(version, name, applied_at) VALUES(?1, ?2, ?3)",
+                params![
+                    migration.version,
+                    migration.name,
+                    format_timestamp(OffsetDateTime::now_utc())
+                ],
+            )
+            .await?;
+        } else {
+            tx.execute_batch(migration.down).await?;
+            tx.execute(
+                "DELETE FROM SchemaVersion WHERE version = ?1",
+                params![migration.version],
+            )
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+    result.map_err(|e| {
+        error!("Could not run migration {}: {}", migration.name, e);
+        INTERNAL_SERVER_ERROR
+    })
+}
+
+pub async fn current_version(conn: &Connection) -> Result<u32, &'static str> {
+    let result = async {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS SchemaVersion(
+            version INTEGER PRIMARY KEY,
+            name TEXT,
+            applied_at TEXT)",
+            (),
+        )
+        .await?;
+        let mut rows = conn
+            .query("SELECT MAX(version) FROM SchemaVersion", ())
+            .await?;
+        match rows.next().await? {
+            None => Ok(0),
+            Some(row) => Ok(row.get::<Option<u32>>(0)?.unwrap_or(0)),
+        }
+    }
+    .await;
+    result.map_err(|e: libsql::Error| {
+        error!("Could not read schema version: {}", e);
+        INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Takes the migration lock so that only one process changes the schema,
+/// waiting for a running migration and taking over locks left by crashes.
+async fn lock(conn: &Connection) -> Result<String, &'static str> {
+    if let Err(e) = conn
+        .execute(
+            "CREATE TABLE IF NOT EXISTS SchemaLock(
+            lock_id INTEGER PRIMARY KEY CHECK (lock_id = 1),
+            locked_by TEXT,
+            locked_at TEXT)",
+            (),
+        )
+        .await
+    {
+        error!("Could not create schema lock table: {}", e);
+        return Err(INTERNAL_SERVER_ERROR);
+    }
+
+    let lock_id = Uuid::new_v4().to_string();
+    for _ in 0..LOCK_ATTEMPTS {
+        let now = OffsetDateTime::now_utc();
+        let inserted = conn
+            .execute(
+                "INSERT INTO SchemaLock(lock_id, locked_by, locked_at) VALUES(1, ?1, ?2)
+                ON CONFLICT(lock_id) DO NOTHING",
+                params![lock_id.clone(), format_timestamp(now)],
+            )
+            .await;
+        match inserted {
+            Err(e) => {
+                error!("Could not take schema lock: {}", e);
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Ok(1) => return Ok(lock_id),
+            Ok(_) => (),
+        }
+
+        if let Ok(mut rows) = conn.query("SELECT locked_at FROM SchemaLock", ()).await {
+            if let Ok(Some(row)) = rows.next().await {
+                let locked_at = row.get::<String>(0).ok();
+                let stale = match locked_at.as_deref().and_then(parse_timestamp) {
+                    None => true,
+                    Some(locked_at) => locked_at < now - TimeDuration::minutes(STALE_LOCK_MINS),
+                };
+                if stale {
+                    warn!("Releasing stale schema lock from {:?}", locked_at);
+                    let _ = conn.execute("DELETE FROM SchemaLock", ()).await;
+                    continue;
+                }
+            }
+        }
+        info!("Waiting for another process to finish migrating...");
+        sleep(LOCK_RETRY_INTERVAL).await;
+    }
+    Err(MIGRATION_LOCK_ERROR)
+}
+
+async fn unlock(conn: &Connection, lock_id: &str) {
+    if let Err(e) = conn
+        .execute(
+            "DELETE FROM SchemaLock WHERE locked_by = ?1",
+            params![lock_id],
+        )
+        .await
+    {
+        error!("Could not release schema lock: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod test_migrations {
+    use libsql::Builder;
+
+    use crate::utils::error::ROLLBACK_TARGET_ERROR;
+
+    use super::{current_version, latest_version, parse_args, plan, run, Command, MIGRATIONS};
+
+    #[test]
+    fn test_versions_are_contiguous() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1);
+            assert!(migration
+                .name
+                .starts_with(&format!("{:04}_", migration.version)));
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let versions = |steps: Vec<(&super::Migration, bool)>| {
+            steps
+                .iter()
+                .map(|(migration, up)| (migration.version, *up))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(plan(1, 3)), vec![(2, true), (3, true)]);
+        assert_eq!(versions(plan(3, 1)), vec![(3, false), (2, false)]);
+        assert_eq!(versions(plan(2, 2)), vec![]);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_args(&args(&[])), Ok(None));
+        assert_eq!(
+            parse_args(&args(&["--migrate"])),
+            Ok(Some(Command::Migrate(None)))
+        );
+        assert_eq!(
+            parse_args(&args(&["--rollback", "2"])),
+            Ok(Some(Command::Rollback(Some(2))))
+        );
+        assert!(parse_args(&args(&["--rollback", "two"])).is_err());
+        assert!(parse_args(&args(&["--serve"])).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_migrate_and_rollback() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        assert_eq!(
+            run(&conn, Command::Migrate(None)).await,
+            Ok(latest_version())
+        );
+        assert_eq!(current_version(&conn).await, Ok(latest_version()));
+        // every down migration undoes its up migration
+        assert_eq!(run(&conn, Command::Rollback(Some(0))).await, Ok(0));
+        assert_eq!(
+            run(&conn, Command::Migrate(None)).await,
+            Ok(latest_version())
+        );
+
+        assert_eq!(
+            run(&conn, Command::Rollback(None)).await,
+            Ok(latest_version() - 1)
+        );
+        // a rollback never applies pending migrations
+        assert_eq!(
+            run(&conn, Command::Rollback(Some(latest_version()))).await,
+            Err(ROLLBACK_TARGET_ERROR)
+        );
+        assert_eq!(current_version(&conn).await, Ok(latest_version() - 1));
+        assert!(run(&conn, Command::Migrate(Some(latest_version() + 1)))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS Move;
+DROP TABLE IF EXISTS Game;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS Game(
+    game_id TEXT PRIMARY KEY,
+    admin_color TEXT,
+    result TEXT,
+    created_at TEXT);
+
+CREATE TABLE IF NOT EXISTS Move(
+    move_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id VARCHAR(255),
+    turn INTEGER,
+    player VARCHAR(10),
+    move_notation VARCHAR(10),
+    created_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
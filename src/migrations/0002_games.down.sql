@@ -0,0 +1,9 @@
+DROP TABLE Chat;
+DROP TABLE Action;
+DROP TABLE Simul;
+
+ALTER TABLE Game DROP COLUMN rated;
+ALTER TABLE Game DROP COLUMN time_control;
+ALTER TABLE Game DROP COLUMN variant;
+ALTER TABLE Game DROP COLUMN simul_id;
+ALTER TABLE Game DROP COLUMN termination;
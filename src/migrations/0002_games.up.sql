@@ -0,0 +1,31 @@
+ALTER TABLE Game ADD COLUMN termination TEXT;
+ALTER TABLE Game ADD COLUMN simul_id TEXT;
+ALTER TABLE Game ADD COLUMN variant TEXT;
+ALTER TABLE Game ADD COLUMN time_control TEXT;
+ALTER TABLE Game ADD COLUMN rated INTEGER;
+
+CREATE TABLE Simul(
+    simul_id TEXT PRIMARY KEY,
+    host_name TEXT,
+    walking_order INTEGER,
+    host_time_budget_secs INTEGER,
+    created_at TEXT);
+
+CREATE TABLE Action(
+    action_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id VARCHAR(255),
+    player VARCHAR(10),
+    action VARCHAR(20),
+    created_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
+
+CREATE TABLE Chat(
+    chat_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id VARCHAR(255),
+    scope VARCHAR(20),
+    author VARCHAR(255),
+    message TEXT,
+    created_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
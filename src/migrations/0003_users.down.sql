@@ -0,0 +1,7 @@
+DROP TABLE RatingHistory;
+DROP TABLE Rating;
+DROP TABLE ApiKey;
+DROP TABLE User;
+
+ALTER TABLE Game DROP COLUMN black_user_id;
+ALTER TABLE Game DROP COLUMN white_user_id;
@@ -0,0 +1,42 @@
+ALTER TABLE Game ADD COLUMN white_user_id TEXT;
+ALTER TABLE Game ADD COLUMN black_user_id TEXT;
+
+CREATE TABLE User(
+    user_id TEXT PRIMARY KEY,
+    display_name TEXT,
+    email TEXT UNIQUE,
+    role TEXT,
+    created_at TEXT);
+
+CREATE TABLE ApiKey(
+    key_id TEXT PRIMARY KEY,
+    label TEXT,
+    key_hash TEXT UNIQUE,
+    role TEXT,
+    created_at TEXT,
+    revoked_at TEXT);
+
+CREATE TABLE Rating(
+    user_id TEXT,
+    pool TEXT,
+    rating REAL,
+    deviation REAL,
+    volatility REAL,
+    games INTEGER,
+    updated_at TEXT,
+    PRIMARY KEY(user_id, pool),
+    FOREIGN KEY(user_id) REFERENCES User(user_id)
+);
+
+CREATE TABLE RatingHistory(
+    history_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT,
+    pool TEXT,
+    game_id TEXT,
+    rating REAL,
+    deviation REAL,
+    volatility REAL,
+    created_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES User(user_id),
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
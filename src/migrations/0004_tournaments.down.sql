@@ -0,0 +1,5 @@
+DROP TABLE ArenaGame;
+DROP TABLE Arena;
+DROP TABLE TournamentPairing;
+DROP TABLE TournamentPlayer;
+DROP TABLE Tournament;
@@ -0,0 +1,41 @@
+CREATE TABLE Tournament(
+    tournament_id TEXT PRIMARY KEY,
+    name TEXT,
+    format TEXT,
+    rounds INTEGER,
+    created_at TEXT);
+
+CREATE TABLE TournamentPlayer(
+    tournament_id TEXT,
+    user_id TEXT,
+    seed INTEGER,
+    PRIMARY KEY(tournament_id, user_id),
+    FOREIGN KEY(tournament_id) REFERENCES Tournament(tournament_id),
+    FOREIGN KEY(user_id) REFERENCES User(user_id)
+);
+
+CREATE TABLE TournamentPairing(
+    pairing_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tournament_id TEXT,
+    round INTEGER,
+    white_user_id TEXT,
+    black_user_id TEXT,
+    game_id TEXT,
+    result TEXT,
+    FOREIGN KEY(tournament_id) REFERENCES Tournament(tournament_id)
+);
+
+CREATE TABLE Arena(
+    arena_id TEXT PRIMARY KEY,
+    name TEXT,
+    time_control TEXT,
+    duration_secs INTEGER,
+    created_at TEXT);
+
+CREATE TABLE ArenaGame(
+    arena_id TEXT,
+    game_id TEXT,
+    PRIMARY KEY(arena_id, game_id),
+    FOREIGN KEY(arena_id) REFERENCES Arena(arena_id),
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
@@ -0,0 +1,5 @@
+DROP TABLE Invite;
+DROP TABLE Vacation;
+
+ALTER TABLE Game DROP COLUMN private;
+ALTER TABLE Game DROP COLUMN days_per_move;
@@ -0,0 +1,22 @@
+ALTER TABLE Game ADD COLUMN days_per_move INTEGER;
+ALTER TABLE Game ADD COLUMN private INTEGER;
+
+CREATE TABLE Vacation(
+    vacation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT,
+    starts_at TEXT,
+    ends_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES User(user_id)
+);
+
+CREATE TABLE Invite(
+    token_hash TEXT PRIMARY KEY,
+    game_id TEXT,
+    created_by TEXT,
+    seat TEXT,
+    password_hash TEXT,
+    expires_at TEXT,
+    accepted_by TEXT,
+    created_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
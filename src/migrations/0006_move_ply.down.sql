@@ -0,0 +1,3 @@
+DROP INDEX MoveGamePly;
+
+ALTER TABLE Move DROP COLUMN ply;
@@ -0,0 +1,8 @@
+ALTER TABLE Move ADD COLUMN ply INTEGER;
+
+UPDATE Move SET ply = (
+    SELECT COUNT(*) FROM Move M
+    WHERE M.game_id = Move.game_id AND M.move_id <= Move.move_id
+);
+
+CREATE UNIQUE INDEX MoveGamePly ON Move(game_id, ply);
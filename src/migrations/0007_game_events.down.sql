@@ -0,0 +1,3 @@
+DROP TABLE GameSnapshot;
+DROP INDEX GameEventSeq;
+DROP TABLE GameEvent;
@@ -0,0 +1,21 @@
+CREATE TABLE GameEvent(
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id TEXT,
+    seq INTEGER,
+    kind TEXT,
+    payload TEXT,
+    created_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
+
+CREATE UNIQUE INDEX GameEventSeq ON GameEvent(game_id, seq);
+
+CREATE TABLE GameSnapshot(
+    game_id TEXT,
+    seq INTEGER,
+    ply INTEGER,
+    fen TEXT,
+    created_at TEXT,
+    PRIMARY KEY(game_id, seq),
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
@@ -0,0 +1,11 @@
+DROP INDEX GamePositionHash;
+DROP TABLE GamePosition;
+
+DROP INDEX GameEco;
+DROP INDEX GameVariant;
+DROP INDEX GameResult;
+DROP INDEX GameBlackUser;
+DROP INDEX GameWhiteUser;
+DROP INDEX GameCreatedAt;
+
+ALTER TABLE Game DROP COLUMN eco;
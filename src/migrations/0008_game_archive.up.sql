@@ -0,0 +1,18 @@
+ALTER TABLE Game ADD COLUMN eco TEXT;
+
+CREATE INDEX GameCreatedAt ON Game(created_at);
+CREATE INDEX GameWhiteUser ON Game(white_user_id, created_at);
+CREATE INDEX GameBlackUser ON Game(black_user_id, created_at);
+CREATE INDEX GameResult ON Game(result, created_at);
+CREATE INDEX GameVariant ON Game(variant, created_at);
+CREATE INDEX GameEco ON Game(eco, created_at);
+
+CREATE TABLE GamePosition(
+    game_id TEXT,
+    ply INTEGER,
+    position_hash INTEGER,
+    PRIMARY KEY(game_id, ply),
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
+
+CREATE INDEX GamePositionHash ON GamePosition(position_hash);
@@ -0,0 +1,4 @@
+DROP TABLE ExplorerMove;
+DROP TABLE ExplorerGame;
+
+ALTER TABLE Move DROP COLUMN uci;
@@ -0,0 +1,20 @@
+ALTER TABLE Move ADD COLUMN uci TEXT;
+
+CREATE TABLE ExplorerGame(
+    game_id TEXT PRIMARY KEY,
+    indexed_at TEXT,
+    FOREIGN KEY(game_id) REFERENCES Game(game_id)
+);
+
+CREATE TABLE ExplorerMove(
+    position_hash INTEGER,
+    uci TEXT,
+    notation TEXT,
+    games INTEGER,
+    white_wins INTEGER,
+    draws INTEGER,
+    black_wins INTEGER,
+    rating_sum REAL,
+    rated_games INTEGER,
+    PRIMARY KEY(position_hash, uci)
+);
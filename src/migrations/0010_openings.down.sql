@@ -0,0 +1,4 @@
+ALTER TABLE GameSnapshot DROP COLUMN opening;
+ALTER TABLE GameSnapshot DROP COLUMN eco;
+
+ALTER TABLE Game DROP COLUMN opening;
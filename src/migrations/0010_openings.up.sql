@@ -0,0 +1,4 @@
+ALTER TABLE Game ADD COLUMN opening TEXT;
+
+ALTER TABLE GameSnapshot ADD COLUMN eco TEXT;
+ALTER TABLE GameSnapshot ADD COLUMN opening TEXT;
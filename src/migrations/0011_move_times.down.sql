@@ -0,0 +1 @@
+ALTER TABLE Move DROP COLUMN time_spent_ms;
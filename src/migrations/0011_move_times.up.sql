@@ -0,0 +1 @@
+ALTER TABLE Move ADD COLUMN time_spent_ms INTEGER;
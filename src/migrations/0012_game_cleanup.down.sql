@@ -0,0 +1,2 @@
+ALTER TABLE ExplorerGame DROP COLUMN average_rating;
+ALTER TABLE Game DROP COLUMN deleted_at;
@@ -0,0 +1,2 @@
+ALTER TABLE Game ADD COLUMN deleted_at TEXT;
+ALTER TABLE ExplorerGame ADD COLUMN average_rating REAL;
@@ -0,0 +1,232 @@
+use std::{env, f64::consts::PI};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{time_control::TimeControlCategory, Game, GameResult, Variant};
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+// Ratings less certain than this are provisional and left off the leaderboard
+pub const PROVISIONAL_DEVIATION: f64 = 110.0;
+
+// Constrains how much the volatility can change, Glickman suggests 0.3 to 1.2
+const TAU: f64 = 0.5;
+// Converts between the Glicko and the Glicko-2 scale
+const SCALE: f64 = 173.7178;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+/// A Glicko-2 rating as described in http://www.glicko.net/glicko/glicko2.pdf
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+impl Default for Rating {
+    fn default() -> Rating {
+        Rating {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+impl Rating {
+    /// Rates one rating period given the opponents' ratings and our scores
+    /// against them (1 for a win, 0.5 for a draw, 0 for a loss).
+    pub fn update(&self, results: &[(Rating, f64)]) -> Rating {
+        let mu = (self.rating - DEFAULT_RATING) / SCALE;
+        let phi = self.deviation / SCALE;
+
+        if results.is_empty() {
+            let phi_star = (phi * phi + self.volatility * self.volatility).sqrt();
+            return Rating {
+                deviation: (phi_star * SCALE).min(DEFAULT_DEVIATION),
+                ..*self
+            };
+        }
+
+        let mut inverse_variance = 0.0;
+        let mut improvement = 0.0;
+        for (opponent, score) in results {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+            let g = g(opponent.deviation / SCALE);
+            let expected = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+            inverse_variance += g * g * expected * (1.0 - expected);
+            improvement += g * (score - expected);
+        }
+        let v = 1.0 / inverse_variance;
+        let delta = v * improvement;
+
+        let volatility = self.new_volatility(phi, v, delta);
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+
+        Rating {
+            rating: new_mu * SCALE + DEFAULT_RATING,
+            deviation: (new_phi * SCALE).min(DEFAULT_DEVIATION),
+            volatility,
+        }
+    }
+    // step 5 of the paper, finding the root with the Illinois algorithm
+    fn new_volatility(&self, phi: f64, v: f64, delta: f64) -> f64 {
+        let a = (self.volatility * self.volatility).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+                - (x - a) / (TAU * TAU)
+        };
+
+        let mut lower = a;
+        let mut upper = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+
+        let mut f_lower = f(lower);
+        let mut f_upper = f(upper);
+        while (upper - lower).abs() > CONVERGENCE_TOLERANCE {
+            let new = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+            let f_new = f(new);
+            if f_new * f_upper <= 0.0 {
+                lower = upper;
+                f_lower = f_upper;
+            } else {
+                f_lower /= 2.0;
+            }
+            upper = new;
+            f_upper = f_new;
+        }
+
+        (lower / 2.0).exp()
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+/// Whether games against bot accounts and simul games count for ratings, read
+/// from the RATE_BOT_GAMES and RATE_SIMUL_GAMES env vars. Both default to unrated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RatingConfig {
+    pub rate_bot_games: bool,
+    pub rate_simul_games: bool,
+}
+impl RatingConfig {
+    pub fn from_env() -> RatingConfig {
+        let flag = |name: &str| env::var(name).is_ok_and(|value| value == "true");
+        RatingConfig {
+            rate_bot_games: flag("RATE_BOT_GAMES"),
+            rate_simul_games: flag("RATE_SIMUL_GAMES"),
+        }
+    }
+}
+
+/// Ratings are kept separately for every time control category and variant.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct RatingPool {
+    pub category: TimeControlCategory,
+    pub variant: Variant,
+}
+impl RatingPool {
+    pub fn of(game: &Game) -> RatingPool {
+        RatingPool {
+            category: TimeControlCategory::of(game.time_control),
+            variant: game.variant,
+        }
+    }
+    pub fn to_str(&self) -> String {
+        format!("{}_{}", self.category.to_str(), self.variant.to_str())
+    }
+}
+
+/// Everything needed to rate a finished game, so the update can run after the
+/// game itself is gone from memory.
+#[derive(Clone, Debug)]
+pub struct RatedGame {
+    pub game_id: String,
+    pub white: String,
+    pub black: String,
+    pub result: GameResult,
+    pub pool: RatingPool,
+}
+impl RatedGame {
+    pub fn from_game(game: &Game) -> Option<RatedGame> {
+        if !game.rated {
+            return None;
+        }
+
+        Some(RatedGame {
+            game_id: game.id.to_string(),
+            white: game.players.white.clone()?,
+            black: game.players.black.clone()?,
+            result: game.game_result?,
+            pool: RatingPool::of(game),
+        })
+    }
+    pub fn white_score(&self) -> f64 {
+        match self.result {
+            GameResult::WhiteWon => 1.0,
+            GameResult::BlackWon => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+    /// Both players' ratings after the game, white's first.
+    pub fn rate(&self, white: Rating, black: Rating) -> (Rating, Rating) {
+        let white_score = self.white_score();
+        (
+            white.update(&[(black, white_score)]),
+            black.update(&[(white, 1.0 - white_score)]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_rating {
+    use super::Rating;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn test_glickman_example() {
+        let player = rating(1500.0, 200.0);
+        let results = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+
+        let updated = player.update(&results);
+        assert!((updated.rating - 1464.06).abs() < 0.01);
+        assert!((updated.deviation - 151.52).abs() < 0.01);
+        assert!((updated.volatility - 0.05999).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_draw_between_equals() {
+        let updated = Rating::default().update(&[(Rating::default(), 0.5)]);
+        assert!((updated.rating - 1500.0).abs() < 0.0001);
+        assert!(updated.deviation < 350.0);
+    }
+
+    #[test]
+    fn test_inactivity_increases_deviation() {
+        let player = rating(1500.0, 50.0);
+        assert!(player.update(&[]).deviation > 50.0);
+        assert_eq!(Rating::default().update(&[]).deviation, 350.0);
+    }
+}
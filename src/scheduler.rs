@@ -0,0 +1,46 @@
+use std::{env, sync::Arc, time::Duration};
+
+use log::{error, info};
+
+use crate::server::Server;
+
+// How often the background jobs run
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When games nobody moves in are cleaned up, read from the
+/// GAME_INACTIVITY_TIMEOUT_MINS env var. Unset or `0` keeps them forever.
+#[derive(Clone, Copy, Debug)]
+pub struct CleanupConfig {
+    pub inactivity_timeout: Option<Duration>,
+}
+impl CleanupConfig {
+    pub fn from_env() -> CleanupConfig {
+        let mins = env::var("GAME_INACTIVITY_TIMEOUT_MINS")
+            .ok()
+            .and_then(|mins| mins.parse::<u64>().ok())
+            .unwrap_or(0);
+        CleanupConfig {
+            inactivity_timeout: (mins > 0).then(|| Duration::from_secs(mins * 60)),
+        }
+    }
+}
+
+/// Runs the periodic background jobs of the server for as long as it is up.
+pub fn start(server: Arc<Server>) {
+    actix::spawn(async move {
+        let mut interval = actix::clock::interval(SCHEDULER_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = server.forfeit_expired_games().await {
+                error!("Could not forfeit expired correspondence games: {}", e);
+            }
+            if let Err(e) = server.end_stale_games().await {
+                error!("Could not end stale games: {}", e);
+            }
+            let evicted = server.evict_finished_games();
+            if evicted > 0 {
+                info!("Removed {} finished games from memory", evicted);
+            }
+        }
+    });
+}
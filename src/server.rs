@@ -1,47 +1,79 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
-use log::error;
+use actix_web::cookie::time::OffsetDateTime;
+use log::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
+    arena::Arena,
+    backup::{ExportedGame, ImportFailure, ImportReport},
+    chat::{ChatFilter, WordListFilter},
+    correspondence::{deadline, format_timestamp},
     db::DB,
-    game::{chess_piece::Color, Game},
-    utils::error::INTERNAL_SERVER_ERROR,
+    game::{chess_piece::Color, event::GameEvent, Game, GameResult, Termination},
+    lobby::Lobby,
+    rating::{RatedGame, RatingConfig},
+    scheduler::CleanupConfig,
+    simul::Simul,
+    stats::{GameStats, StatsCache, UserStats},
+    store::Store,
+    tournament::Tournament,
+    user::UserRole,
+    utils::{
+        auth::{hash_secret, verify_password, Permission, Principal},
+        error::{
+            COMPETITION_GAME_DELETE_ERROR, INTERNAL_SERVER_ERROR, INVITE_EXPIRED_ERROR,
+            INVITE_USED_ERROR, NOT_IN_ARENA_ERROR, NO_GAME_ERROR, NO_INVITE_ERROR, NO_SEAT_ERROR,
+            OWN_INVITE_ERROR, RATED_GAME_DELETE_ERROR, WRONG_PASSWORD_ERROR,
+        },
+        response::ArenaMessage,
+    },
     ws::WebSocketRoom,
 };
 
 pub struct Server {
     pub games: Arc<RwLock<HashMap<Uuid, Game>>>,
     pub rooms: Arc<RwLock<HashMap<Uuid, WebSocketRoom>>>,
-    pub db: Arc<DB>,
+    pub simuls: Arc<RwLock<HashMap<Uuid, Simul>>>,
+    pub lobby: Arc<RwLock<Lobby>>,
+    pub tournaments: Arc<RwLock<HashMap<Uuid, Tournament>>>,
+    pub arenas: Arc<RwLock<HashMap<Uuid, Arena>>>,
+    pub store: Arc<dyn Store>,
+    pub chat_filter: Box<dyn ChatFilter>,
+    pub rating_config: RatingConfig,
+    pub stats: Arc<RwLock<StatsCache>>,
+    pub cleanup_config: CleanupConfig,
 }
 impl Server {
     pub async fn new() -> Server {
+        Server::with_store(Arc::new(DB::new().await))
+    }
+    /// A server that keeps everything it persists in `store` instead of the
+    /// database.
+    pub fn with_store(store: Arc<dyn Store>) -> Server {
         Server {
             games: Arc::new(RwLock::new(HashMap::new())),
             rooms: Arc::new(RwLock::new(HashMap::new())),
-            db: Arc::new(DB::new().await),
+            simuls: Arc::new(RwLock::new(HashMap::new())),
+            lobby: Arc::new(RwLock::new(Lobby::default())),
+            tournaments: Arc::new(RwLock::new(HashMap::new())),
+            arenas: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            chat_filter: Box::new(WordListFilter::from_env()),
+            rating_config: RatingConfig::from_env(),
+            stats: Arc::new(RwLock::new(StatsCache::default())),
+            cleanup_config: CleanupConfig::from_env(),
         }
     }
-    pub async fn add_game(
-        self: &Arc<Self>,
-        game_id: Uuid,
-        color: Color,
-    ) -> Result<(), &'static str> {
-        self.db
-            .create_game(&game_id.to_string(), &color.to_str())
-            .await?;
+    pub async fn add_game(self: &Arc<Self>, game: Game) -> Result<(), &'static str> {
+        let game_id = game.id;
+        self.store.create_game(&game).await?;
 
-        if self
-            .games
-            .write()
-            .unwrap()
-            .insert(game_id, Game::new(game_id, color))
-            .is_some()
-        {
+        if self.games.write().unwrap().insert(game_id, game).is_some() {
             error!("A game with that id already exists: {}", game_id);
             return Err(INTERNAL_SERVER_ERROR);
         }
@@ -55,10 +87,907 @@ impl Server {
             error!("A game with that id already exists: {}", game_id);
             return Err(INTERNAL_SERVER_ERROR);
         }
+        if let Some(room) = self.rooms.write().unwrap().get_mut(&game_id) {
+            room.record_position();
+        }
+
+        Ok(())
+    }
+    /// Creates one game per entry of `boards`, the host's color and the user
+    /// playing against them, all played by the same host. With `walking_order`
+    /// the host moves from board to board in order, with a clock of
+    /// `host_time_budget_secs` per board if given.
+    pub async fn add_simul(
+        self: &Arc<Self>,
+        simul_id: Uuid,
+        host_name: String,
+        host_user_id: String,
+        boards_to_create: Vec<(Color, String)>,
+        walking_order: bool,
+        host_time_budget_secs: Option<u64>,
+    ) -> Result<Vec<Uuid>, &'static str> {
+        let mut boards = vec![];
+        for (host_color, player_id) in boards_to_create {
+            let game_id = Uuid::new_v4();
+            let mut game = Game::new(game_id, host_color);
+            game.players.set(host_color, host_user_id.clone());
+            game.players.set(host_color.opposite(), player_id);
+            game.rated = self.rating_config.rate_simul_games;
+            self.add_game(game).await?;
+            boards.push((game_id, host_color));
+        }
+
+        let game_ids: Vec<Uuid> = boards.iter().map(|(game_id, _)| *game_id).collect();
+        let game_id_strs: Vec<String> = game_ids.iter().map(|id| id.to_string()).collect();
+        self.store
+            .create_simul(
+                &simul_id.to_string(),
+                &host_name,
+                walking_order,
+                host_time_budget_secs,
+                &game_id_strs,
+            )
+            .await?;
+
+        let mut rooms = self.rooms.write().unwrap();
+        for game_id in &game_ids {
+            if let Some(room) = rooms.get_mut(game_id) {
+                room.simul_id = Some(simul_id);
+            }
+        }
+        drop(rooms);
+
+        let mut simul = Simul::new(simul_id, host_name, host_user_id, boards);
+        if walking_order {
+            simul = simul.with_walking_order(host_time_budget_secs.map(Duration::from_secs));
+        }
+        self.simuls.write().unwrap().insert(simul_id, simul);
+        Ok(game_ids)
+    }
+    /// Registers a tournament and pairs its first round.
+    pub async fn add_tournament(
+        self: &Arc<Self>,
+        tournament: Tournament,
+    ) -> Result<(), &'static str> {
+        let tournament_id = tournament.id;
+        self.store
+            .create_tournament(
+                &tournament_id.to_string(),
+                &tournament.name,
+                &tournament.format.to_str(),
+                tournament.rounds,
+                &tournament.players,
+            )
+            .await?;
+
+        self.tournaments
+            .write()
+            .unwrap()
+            .insert(tournament_id, tournament);
+        self.start_next_round(tournament_id).await
+    }
+    /// Pairs the next round of a tournament and creates a game for every board.
+    async fn start_next_round(self: &Arc<Self>, tournament_id: Uuid) -> Result<(), &'static str> {
+        let (pairings, time_control, variant, rated) = {
+            let mut tournaments = self.tournaments.write().unwrap();
+            let tournament = match tournaments.get_mut(&tournament_id) {
+                None => {
+                    error!("Could not find tournament {}", tournament_id);
+                    return Err(INTERNAL_SERVER_ERROR);
+                }
+                Some(tournament) => tournament,
+            };
+            let pairings = tournament.pair_next_round();
+            (
+                pairings,
+                tournament.time_control,
+                tournament.variant,
+                tournament.rated,
+            )
+        };
+
+        let tournament_id_str = tournament_id.to_string();
+        for pairing in pairings {
+            let game_id = match &pairing.black {
+                // byes are decided right away
+                None => None,
+                Some(black) => {
+                    let game_id = Uuid::new_v4();
+                    let mut game = Game::new(game_id, Color::WHITE);
+                    game.players.white = Some(pairing.white.clone());
+                    game.players.black = Some(black.clone());
+                    game.set_time_control(time_control);
+                    game.variant = variant;
+                    game.rated = rated;
+                    self.add_game(game).await?;
+
+                    if let Some(tournament) =
+                        self.tournaments.write().unwrap().get_mut(&tournament_id)
+                    {
+                        tournament.set_game(pairing.round, &pairing.white, game_id.to_string());
+                    }
+                    Some(game_id.to_string())
+                }
+            };
+
+            self.store
+                .insert_pairing(
+                    &tournament_id_str,
+                    pairing.round,
+                    &pairing.white,
+                    pairing.black.as_deref(),
+                    game_id.as_deref(),
+                    pairing.result.map(|result| result.to_str()),
+                )
+                .await?;
+        }
+
+        info!("Started a new round of tournament {}", tournament_id);
+        Ok(())
+    }
+    /// Feeds the result of a finished game into its tournament, if it belongs to
+    /// one, and pairs the next round once every game of the current one is over.
+    async fn tournament_game_finished(
+        self: &Arc<Self>,
+        game_id: &str,
+        result: GameResult,
+    ) -> Result<(), &'static str> {
+        let next_round = {
+            let mut tournaments = self.tournaments.write().unwrap();
+            let tournament = match tournaments
+                .values_mut()
+                .find(|tournament| tournament.contains_game(game_id))
+            {
+                None => return Ok(()),
+                Some(tournament) => tournament,
+            };
+
+            let round_finished = tournament.record_result(game_id, result);
+            if tournament.is_finished() {
+                info!("Tournament {} is finished", tournament.id);
+            }
+            (round_finished && !tournament.is_finished()).then_some(tournament.id)
+        };
+
+        self.store.finish_pairing(game_id, &result.to_str()).await?;
+        if let Some(tournament_id) = next_round {
+            self.start_next_round(tournament_id).await?;
+        }
+        Ok(())
+    }
+    /// Registers an arena and schedules its end.
+    pub async fn add_arena(self: &Arc<Self>, arena: Arena) -> Result<(), &'static str> {
+        let arena_id = arena.id;
+        let duration = arena.ends_at.saturating_duration_since(Instant::now());
+        self.store
+            .create_arena(
+                &arena_id.to_string(),
+                &arena.name,
+                &arena.time_control.to_str(),
+                duration.as_secs(),
+            )
+            .await?;
+        self.arenas.write().unwrap().insert(arena_id, arena);
+
+        let server = Arc::clone(self);
+        actix::spawn(async move {
+            actix::clock::sleep(duration).await;
+            server.end_arena(arena_id);
+        });
+        Ok(())
+    }
+    /// Stops pairing once the arena's time is up. Games still running keep counting.
+    fn end_arena(&self, arena_id: Uuid) {
+        let mut arenas = self.arenas.write().unwrap();
+        if let Some(arena) = arenas.get_mut(&arena_id) {
+            info!("Arena {} is over", arena_id);
+            arena.queue.clear();
+            arena.broadcast(&ArenaMessage::Status(arena.status(Instant::now())));
+        }
+    }
+    pub async fn join_arena(
+        self: &Arc<Self>,
+        arena_id: Uuid,
+        user_id: &str,
+    ) -> Result<(), &'static str> {
+        let pool = match self.arenas.read().unwrap().get(&arena_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(arena) => arena.pool(),
+        };
+        let rating = self.store.get_rating(user_id, pool).await?;
+
+        if let Some(arena) = self.arenas.write().unwrap().get_mut(&arena_id) {
+            arena.join(user_id, rating.unwrap_or_default().rating, Instant::now())?;
+            info!("User {} joined arena {}", user_id, arena_id);
+        }
+        self.pair_arena(arena_id).await
+    }
+    pub fn withdraw_from_arena(&self, arena_id: Uuid, user_id: &str) -> Result<(), &'static str> {
+        let mut arenas = self.arenas.write().unwrap();
+        let arena = match arenas.get_mut(&arena_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(arena) => arena,
+        };
+
+        arena.withdraw(user_id)?;
+        info!("User {} withdrew from arena {}", user_id, arena_id);
+        arena.broadcast(&ArenaMessage::Status(arena.status(Instant::now())));
+        Ok(())
+    }
+    /// Pairs everyone waiting in an arena and starts their games.
+    async fn pair_arena(self: &Arc<Self>, arena_id: Uuid) -> Result<(), &'static str> {
+        let (boards, time_control, variant, rated) = {
+            let mut arenas = self.arenas.write().unwrap();
+            let arena = match arenas.get_mut(&arena_id) {
+                None => {
+                    error!("Could not find arena {}", arena_id);
+                    return Err(INTERNAL_SERVER_ERROR);
+                }
+                Some(arena) => arena,
+            };
+            (
+                arena.pair(Instant::now()),
+                arena.time_control,
+                arena.variant,
+                arena.rated,
+            )
+        };
+
+        for (white, black) in boards {
+            let game_id = Uuid::new_v4();
+            let mut game = Game::new(game_id, Color::WHITE);
+            game.players.white = Some(white.clone());
+            game.players.black = Some(black.clone());
+            game.set_time_control(Some(time_control));
+            game.variant = variant;
+            game.rated = rated;
+            self.add_game(game).await?;
+            self.store
+                .insert_arena_game(&arena_id.to_string(), &game_id.to_string())
+                .await?;
+
+            let mut arenas = self.arenas.write().unwrap();
+            if let Some(arena) = arenas.get_mut(&arena_id) {
+                arena.start_game(game_id.to_string(), &white, &black);
+                for (user_id, color, opponent) in [
+                    (&white, Color::WHITE, &black),
+                    (&black, Color::BLACK, &white),
+                ] {
+                    arena.send_to_user(
+                        user_id,
+                        &ArenaMessage::GameStarted {
+                            game_id: game_id.to_string(),
+                            color: color.to_str(),
+                            opponent: opponent.clone(),
+                        },
+                    );
+                }
+            }
+            info!(
+                "Paired {} and {} in arena {}, started game {}",
+                white, black, arena_id, game_id
+            );
+        }
+
+        if let Some(arena) = self.arenas.read().unwrap().get(&arena_id) {
+            arena.broadcast(&ArenaMessage::Status(arena.status(Instant::now())));
+        }
+        Ok(())
+    }
+    /// Halves the clock of `user_id` in their arena game for a bonus point on a win.
+    pub fn berserk(
+        &self,
+        arena_id: Uuid,
+        game_id: Uuid,
+        user_id: &str,
+    ) -> Result<(), &'static str> {
+        let color = match self.arenas.read().unwrap().get(&arena_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(arena) => match arena.games.get(&game_id.to_string()) {
+                None => return Err(NOT_IN_ARENA_ERROR),
+                Some(game) => game.seat(user_id).ok_or(NO_SEAT_ERROR)?,
+            },
+        };
+
+        match self.rooms.write().unwrap().get_mut(&game_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(room) => room.berserk(color)?,
+        }
+
+        if let Some(arena) = self.arenas.write().unwrap().get_mut(&arena_id) {
+            arena.set_berserk(&game_id.to_string(), color);
+            arena.broadcast(&ArenaMessage::Status(arena.status(Instant::now())));
+        }
+        Ok(())
+    }
+    /// Scores a finished arena game and pairs its players again.
+    async fn arena_game_finished(
+        self: &Arc<Self>,
+        game_id: &str,
+        result: GameResult,
+    ) -> Result<(), &'static str> {
+        let arena_id = {
+            let mut arenas = self.arenas.write().unwrap();
+            let arena = match arenas
+                .values_mut()
+                .find(|arena| arena.games.contains_key(game_id))
+            {
+                None => return Ok(()),
+                Some(arena) => arena,
+            };
+
+            arena.record_result(game_id, result, Instant::now());
+            arena.id
+        };
+
+        self.pair_arena(arena_id).await
+    }
+    /// Seats `user_id` in the open seat of the private game behind an invite
+    /// token. Only the first one to accept it gets the seat.
+    pub async fn accept_invite(
+        &self,
+        token: &str,
+        user_id: &str,
+        password: Option<&str>,
+    ) -> Result<(Uuid, Color), &'static str> {
+        let token_hash = hash_secret(token);
+        let invite = match self.store.get_invite(&token_hash).await? {
+            None => return Err(NO_INVITE_ERROR),
+            Some(invite) => invite,
+        };
+        if invite.accepted_by.is_some() {
+            return Err(INVITE_USED_ERROR);
+        }
+        if invite.is_expired(OffsetDateTime::now_utc()) {
+            return Err(INVITE_EXPIRED_ERROR);
+        }
+        if invite.created_by == user_id {
+            return Err(OWN_INVITE_ERROR);
+        }
+        if let Some(password_hash) = &invite.password_hash {
+            if !password.is_some_and(|password| verify_password(password, password_hash)) {
+                return Err(WRONG_PASSWORD_ERROR);
+            }
+        }
+        let (game_id, seat) = match (Uuid::parse_str(&invite.game_id), invite.seat()) {
+            (Ok(game_id), Some(seat)) => (game_id, seat),
+            _ => {
+                error!("Invite to game {} is broken", invite.game_id);
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+        };
 
+        if !self.store.accept_invite(&token_hash, user_id).await? {
+            return Err(INVITE_USED_ERROR);
+        }
+        match self.games.write().unwrap().get_mut(&game_id) {
+            None => return Err(NO_INVITE_ERROR),
+            Some(game) => game.players.set(seat, user_id.to_string()),
+        }
+        self.store
+            .set_player(&invite.game_id, seat, user_id)
+            .await?;
+
+        info!("User {} accepted the invite to game {}", user_id, game_id);
+        Ok((game_id, seat))
+    }
+    /// Ends every correspondence game whose player to move let their deadline pass.
+    pub async fn forfeit_expired_games(self: &Arc<Self>) -> Result<(), &'static str> {
+        let now = OffsetDateTime::now_utc();
+        for game in self.store.get_correspondence_games().await? {
+            let pending = match game.pending_move() {
+                None => {
+                    error!("Could not read the move times of game {}", game.game_id);
+                    continue;
+                }
+                Some(pending) => pending,
+            };
+            let vacations = match &pending.user_id {
+                None => vec![],
+                Some(user_id) => self.store.get_vacations(user_id).await?,
+            };
+            if deadline(pending.since, pending.days_per_move, &vacations) > now {
+                continue;
+            }
+
+            info!(
+                "Player {} missed the deadline in game {}",
+                pending.to_move.to_str(),
+                pending.game_id
+            );
+            let in_memory = match Uuid::parse_str(&pending.game_id) {
+                Err(_) => false,
+                Ok(game_id) => match self.rooms.write().unwrap().get_mut(&game_id) {
+                    None => false,
+                    Some(room) => {
+                        if let Err(e) = room.time_out(pending.to_move) {
+                            error!("Could not forfeit game {}: {}", pending.game_id, e);
+                        }
+                        true
+                    }
+                },
+            };
+            if !in_memory {
+                let flag = GameEvent::Flag {
+                    player: pending.to_move.to_str(),
+                };
+                self.store
+                    .append_event(&pending.game_id, None, &flag)
+                    .await?;
+                self.record_result(
+                    &pending.game_id,
+                    GameResult::won_by(pending.to_move.opposite()),
+                    &Termination::Timeout.to_str(),
+                    None,
+                )
+                .await?;
+            }
+        }
         Ok(())
     }
+    /// Persists how a game ended and rates it, if it was a rated game. Every way
+    /// of finishing a game goes through here, except for a final move, which is
+    /// saved together with its result.
+    pub async fn record_result(
+        self: &Arc<Self>,
+        game_id: &str,
+        result: GameResult,
+        termination: &str,
+        rated: Option<RatedGame>,
+    ) -> Result<(), &'static str> {
+        let finished = self
+            .store
+            .finish_game(&result.to_str(), termination, game_id)
+            .await?;
+        if !finished {
+            warn!("Game {} already has a result", game_id);
+            return Ok(());
+        }
+        self.result_recorded(game_id, result, rated).await
+    }
+    /// Rates the game, updates its tournament or arena and adds it to the
+    /// opening explorer once the result is stored.
+    pub async fn result_recorded(
+        self: &Arc<Self>,
+        game_id: &str,
+        result: GameResult,
+        rated: Option<RatedGame>,
+    ) -> Result<(), &'static str> {
+        if let Some(game) = self.store.get_game(game_id).await? {
+            let mut stats = self.stats.write().unwrap();
+            stats.forget_game(game_id);
+            stats.forget_players(&game.players);
+        }
+        // the explorer averages the ratings the players had going into the game
+        let mut average_rating = None;
+        if let Some(rated) = rated {
+            let white = self.store.get_rating(&rated.white, rated.pool).await?;
+            let black = self.store.get_rating(&rated.black, rated.pool).await?;
+            average_rating =
+                Some((white.unwrap_or_default().rating + black.unwrap_or_default().rating) / 2.0);
+            self.update_ratings(rated).await?;
+        }
+        self.tournament_game_finished(game_id, result).await?;
+        self.arena_game_finished(game_id, result).await?;
+        self.store
+            .add_to_explorer(game_id, &result.to_str(), average_rating)
+            .await
+    }
+    /// Adds the finished games that are missing from the opening explorer,
+    /// indexing their positions again from their event logs. Games from before
+    /// the event log only count as explored.
+    pub async fn explore_stored_games(&self) -> Result<usize, &'static str> {
+        let mut explored = 0;
+        for (game_id, result) in self.store.get_unexplored_games().await? {
+            let game = match self.store.get_game(&game_id).await? {
+                None => continue,
+                Some(game) => game,
+            };
+            let events = self.store.get_events(&game_id, 0).await?;
+            match game.replay_positions(&events) {
+                Err(e) => {
+                    error!("Could not index the positions of game {}: {}", game_id, e);
+                    continue;
+                }
+                Ok((positions, moves)) if !moves.is_empty() => {
+                    self.store
+                        .save_positions(&game_id, &positions, &moves)
+                        .await?;
+                }
+                Ok(_) => (),
+            }
+            self.store.add_to_explorer(&game_id, &result, None).await?;
+            explored += 1;
+        }
+        Ok(explored)
+    }
+    /// One page of games to export with their moves, and the cursor of the
+    /// next page unless this was the last one.
+    pub async fn export_games(
+        &self,
+        after: Option<&(String, String)>,
+        limit: u32,
+    ) -> Result<(Vec<ExportedGame>, Option<(String, String)>), &'static str> {
+        let page = self.store.get_export_page(after, limit).await?;
+        let next = match page.last() {
+            Some(last) if page.len() == limit as usize => {
+                Some((last.created_at.clone(), last.game_id.clone()))
+            }
+            _ => None,
+        };
+        let mut games = vec![];
+        for mut game in page {
+            match self.stored_moves(&game.game_id).await? {
+                None => error!("Not exporting game {}", game.game_id),
+                Some(moves) => {
+                    game.moves = moves;
+                    games.push(game);
+                }
+            }
+        }
+        Ok((games, next))
+    }
+    /// The moves of a game in UCI notation. Moves from before UCI was recorded
+    /// are taken from the event log, `None` if that does not match the moves.
+    async fn stored_moves(&self, game_id: &str) -> Result<Option<Vec<String>>, &'static str> {
+        let ucis = self.store.get_move_ucis(game_id).await?;
+        if let Some(moves) = ucis.iter().cloned().collect::<Option<Vec<String>>>() {
+            return Ok(Some(moves));
+        }
+        let setup = match self.store.get_game(game_id).await? {
+            None => return Ok(None),
+            Some(setup) => setup,
+        };
+        let events = self.store.get_events(game_id, 0).await?;
+        let moves: Vec<String> = match setup.replay_positions(&events) {
+            Ok((_, moves)) => moves.into_iter().map(|(_, uci)| uci).collect(),
+            Err(e) => {
+                error!("Could not replay game {}: {}", game_id, e);
+                return Ok(None);
+            }
+        };
+        if moves.len() != ucis.len() {
+            error!(
+                "Game {} has {} moves stored and {} in its event log",
+                game_id,
+                ucis.len(),
+                moves.len()
+            );
+            return Ok(None);
+        }
+        Ok(Some(moves))
+    }
+    /// Captures, material and move times of a game, `None` if there is no such game.
+    pub async fn game_stats(&self, game_id: &str) -> Result<Option<GameStats>, &'static str> {
+        if let Some(stats) = self.stats.read().unwrap().game(game_id) {
+            return Ok(Some(stats));
+        }
+        let setup = match self.store.get_game(game_id).await? {
+            None => return Ok(None),
+            Some(setup) => setup,
+        };
+        let finished = self.store.get_result(game_id).await?.is_some();
+        let moves = self
+            .stored_moves(game_id)
+            .await?
+            .ok_or(INTERNAL_SERVER_ERROR)?;
+        let times = self.store.get_move_times(game_id).await?;
+        let stats = GameStats::compute(game_id, setup, &moves, times, finished).map_err(|e| {
+            error!("Could not compute the stats of game {}: {}", game_id, e);
+            INTERNAL_SERVER_ERROR
+        })?;
+        self.stats.write().unwrap().add_game(stats.clone());
+        Ok(Some(stats))
+    }
+    /// The stats of a player over their finished games, of one year or of all time.
+    pub async fn user_stats(
+        &self,
+        user_id: &str,
+        year: Option<i32>,
+    ) -> Result<UserStats, &'static str> {
+        if let Some(stats) = self.stats.read().unwrap().user(user_id, year) {
+            return Ok(stats);
+        }
+        // private games count as well, the stats only show totals
+        let games = self.store.get_user_games(user_id, true, None).await?;
+        let stats = UserStats::compute(user_id, year, &games);
+        self.stats.write().unwrap().add_user(stats.clone());
+        Ok(stats)
+    }
+    /// Restores exported games after replaying every move. Games that exist
+    /// already are skipped, finished ones are added to the opening explorer.
+    /// Unfinished games are picked up on the next start.
+    pub async fn import_games(
+        &self,
+        games: Vec<Result<ExportedGame, &'static str>>,
+    ) -> ImportReport {
+        let mut report = ImportReport::default();
+        self.stats.write().unwrap().forget_users();
+        for (index, game) in games.into_iter().enumerate() {
+            let failure = |game_id: Option<&str>, error: &str| ImportFailure {
+                game: index + 1,
+                game_id: game_id.map(str::to_string),
+                error: error.to_string(),
+            };
+            let game = match game {
+                Err(e) => {
+                    report.failed.push(failure(None, e));
+                    continue;
+                }
+                Ok(game) => game,
+            };
+            let start_hash = match game.setup() {
+                Err(e) => {
+                    report.failed.push(failure(Some(&game.game_id), e));
+                    continue;
+                }
+                Ok(setup) => setup.zobrist(),
+            };
+            let moves = match game.replay() {
+                Err(e) => {
+                    report.failed.push(failure(Some(&game.game_id), e));
+                    continue;
+                }
+                Ok((_, moves)) => moves,
+            };
+            match self.store.import_game(&game, start_hash, &moves).await {
+                Err(e) => report.failed.push(failure(Some(&game.game_id), e)),
+                Ok(false) => report.skipped += 1,
+                Ok(true) => {
+                    report.imported += 1;
+                    if let Some(result) = &game.result {
+                        if let Err(e) = self
+                            .store
+                            .add_to_explorer(&game.game_id, result, None)
+                            .await
+                        {
+                            error!(
+                                "Could not add imported game {} to the explorer: {}",
+                                game.game_id, e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        report
+    }
+    /// Updates both players' ratings in the pool of a finished rated game.
+    pub async fn update_ratings(&self, game: RatedGame) -> Result<(), &'static str> {
+        if !self.rating_config.rate_bot_games {
+            for user_id in [&game.white, &game.black] {
+                let user = self.store.get_user(user_id).await?;
+                if user.is_some_and(|user| user.role() == UserRole::Bot) {
+                    info!("Not rating game {} against a bot", game.game_id);
+                    return Ok(());
+                }
+            }
+        }
+
+        let [(white, new_white), (black, new_black)] = match self.store.rate_game(&game).await? {
+            None => {
+                warn!("Game {} was rated already", game.game_id);
+                return Ok(());
+            }
+            Some(ratings) => ratings,
+        };
+        info!(
+            "Rated game {} in {}: white {:.0} -> {:.0}, black {:.0} -> {:.0}",
+            game.game_id,
+            game.pool.to_str(),
+            white.rating,
+            new_white.rating,
+            black.rating,
+            new_black.rating
+        );
+        Ok(())
+    }
+    /// Rebuilds a game from its latest snapshot and the events after it, up to
+    /// the event `until_seq` if given. Returns the game and its last event.
+    pub async fn rebuild_game(
+        &self,
+        game_id: &str,
+        until_seq: Option<u64>,
+    ) -> Result<(Game, u64), &'static str> {
+        let game = self.store.get_game(game_id).await?.ok_or(NO_GAME_ERROR)?;
+        let snapshot = self.store.get_snapshot(game_id, until_seq).await?;
+        let after = snapshot.as_ref().map_or(0, |snapshot| snapshot.seq);
+        let mut events = self.store.get_events(game_id, after).await?;
+        if let Some(until_seq) = until_seq {
+            events.retain(|logged| logged.seq <= until_seq);
+        }
+
+        let last_seq = events.last().map_or(after, |logged| logged.seq);
+        Ok((game.replay(snapshot.as_ref(), &events)?, last_seq))
+    }
+    /// Loads the unfinished games of the store after a restart. Their clocks
+    /// start over, since only the moves are logged and not the time they took.
+    pub async fn restore_games(self: &Arc<Self>) -> Result<usize, &'static str> {
+        let mut restored = 0;
+        for active in self.store.get_active_games().await? {
+            let (game, log_seq) = match self.rebuild_game(&active.game_id, None).await {
+                Err(e) => {
+                    error!("Could not restore game {}: {}", active.game_id, e);
+                    continue;
+                }
+                Ok(rebuilt) => rebuilt,
+            };
+
+            let game_id = game.id;
+            let mut room = WebSocketRoom::new(game_id, Arc::clone(self));
+            room.resume(log_seq);
+            self.games.write().unwrap().insert(game_id, game);
+            room.record_position();
+            room.arm_flag_timer();
+            self.rooms.write().unwrap().insert(game_id, room);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+    /// The game as `principal` may see it: live for its players and admins, with
+    /// the spectator delay for everyone else. `None` if it is not in memory or
+    /// private to others.
+    pub fn visible_game(&self, game_id: Uuid, principal: &Principal) -> Option<Game> {
+        // rooms before games, the same order moves take them in
+        let delayed = match self.rooms.read().unwrap().get(&game_id) {
+            None => None,
+            Some(room) => room.delayed_game(Instant::now()),
+        };
+        let games = self.games.read().unwrap();
+        let game = games.get(&game_id)?;
+        let live = plays_or_manages(game, principal);
+        if game.private && !live {
+            return None;
+        }
+        match (live, delayed) {
+            (false, Some(delayed)) => Some(delayed),
+            _ => Some(game.clone()),
+        }
+    }
+    /// The stored game if `principal` may look at it, `None` if there is no
+    /// such game or it is private to others.
+    pub async fn viewable_game(
+        &self,
+        game_id: &str,
+        principal: &Principal,
+    ) -> Result<Option<Game>, &'static str> {
+        let game = self.store.get_game(game_id).await?;
+        Ok(game.filter(|game| !game.private || plays_or_manages(game, principal)))
+    }
+    /// Drops a game and its room from memory, disconnecting everyone in it.
     pub fn remove_game(&self, game_id: Uuid) {
         self.games.write().unwrap().remove(&game_id);
+        if let Some(room) = self.rooms.write().unwrap().remove(&game_id) {
+            room.close();
+        }
     }
+    /// Drops finished games from memory once nobody is connected to them.
+    /// Returns how many were dropped.
+    pub fn evict_finished_games(&self) -> usize {
+        let finished: Vec<Uuid> = {
+            let rooms = self.rooms.read().unwrap();
+            let games = self.games.read().unwrap();
+            games
+                .iter()
+                .filter(|(_, game)| game.is_over())
+                .filter(|(game_id, _)| match rooms.get(game_id) {
+                    None => true,
+                    Some(room) => room.is_idle(),
+                })
+                .map(|(game_id, _)| *game_id)
+                .collect()
+        };
+        for game_id in &finished {
+            self.remove_game(*game_id);
+        }
+        finished.len()
+    }
+    /// Stores that a game was aborted and drops it from memory.
+    pub async fn record_abort(&self, game_id: Uuid) -> Result<(), &'static str> {
+        self.store.abort_game(&game_id.to_string()).await?;
+        self.remove_game(game_id);
+        info!("Aborted game {}", game_id);
+        Ok(())
+    }
+    /// Ends the real-time games between users nobody moved in for the
+    /// inactivity timeout, if there is one.
+    /// Games in which not both players moved yet are aborted, the others are
+    /// lost by the player to move.
+    pub async fn end_stale_games(self: &Arc<Self>) -> Result<(), &'static str> {
+        let timeout = match self.cleanup_config.inactivity_timeout {
+            None => return Ok(()),
+            Some(timeout) => timeout,
+        };
+        let before = format_timestamp(OffsetDateTime::now_utc() - timeout);
+        for stale in self.store.get_stale_games(&before).await? {
+            let game_id = match Uuid::parse_str(&stale.game_id) {
+                Err(_) => {
+                    error!("Stale game {} has an invalid id", stale.game_id);
+                    continue;
+                }
+                Ok(game_id) => game_id,
+            };
+            // the room may still be logging earlier events, so continue after its last one
+            let seq = self
+                .rooms
+                .read()
+                .unwrap()
+                .get(&game_id)
+                .map(|room| room.log_seq + 1);
+            let game = self.games.read().unwrap().get(&game_id).cloned();
+
+            if stale.moves < 2 {
+                let abort = GameEvent::Finish {
+                    result: "*".to_string(),
+                    termination: Termination::Aborted.to_str(),
+                };
+                self.store.append_event(&stale.game_id, seq, &abort).await?;
+                self.record_abort(game_id).await?;
+                continue;
+            }
+
+            let to_move = match &game {
+                Some(game) => game.next_to_move,
+                // white moves first
+                None if stale.moves % 2 == 0 => Color::WHITE,
+                None => Color::BLACK,
+            };
+            let result = GameResult::won_by(to_move.opposite());
+            let rated = game.and_then(|mut game| {
+                game.game_result = Some(result);
+                RatedGame::from_game(&game)
+            });
+            info!(
+                "Player {} abandoned game {}",
+                to_move.to_str(),
+                stale.game_id
+            );
+            self.remove_game(game_id);
+            let termination = Termination::Abandoned.to_str();
+            let finish = GameEvent::Finish {
+                result: result.to_str(),
+                termination: termination.clone(),
+            };
+            self.store
+                .append_event(&stale.game_id, seq, &finish)
+                .await?;
+            self.record_result(&stale.game_id, result, &termination, rated)
+                .await?;
+        }
+        Ok(())
+    }
+    /// Hides a game like an aborted one, or removes it for good if `hard`.
+    /// Rated games are kept, since the ratings of their players build on them,
+    /// and so are games of tournaments and simuls, which their pairings and
+    /// boards point to. Returns false if there is no such game.
+    pub async fn delete_game(&self, game_id: Uuid, hard: bool) -> Result<bool, &'static str> {
+        let id = game_id.to_string();
+        if hard && self.store.is_rated_game(&id).await? {
+            return Err(RATED_GAME_DELETE_ERROR);
+        }
+        if hard && self.store.is_competition_game(&id).await? {
+            return Err(COMPETITION_GAME_DELETE_ERROR);
+        }
+        let players = self.store.get_game(&id).await?.map(|game| game.players);
+        self.remove_game(game_id);
+        let deleted = match hard {
+            true => self.store.delete_game(&id).await?,
+            false => self.store.soft_delete_game(&id).await?,
+        };
+        if let Some(players) = players {
+            let mut stats = self.stats.write().unwrap();
+            stats.forget_game(&id);
+            stats.forget_players(&players);
+        }
+        Ok(deleted)
+    }
+}
+
+/// Whether `principal` plays in `game` or may manage every game.
+fn plays_or_manages(game: &Game, principal: &Principal) -> bool {
+    principal.role.has_permission(Permission::ManageGames)
+        || principal
+            .user_id
+            .as_deref()
+            .is_some_and(|user_id| game.players.contains(user_id))
 }
@@ -0,0 +1,452 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use actix::Addr;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    game::{chess_piece::Color, Game, GameResult},
+    utils::error::{HOST_MUST_MOVE_ERROR, HOST_NOT_AT_BOARD_ERROR, WAIT_FOR_HOST_ERROR},
+    ws::{SimulBroadcastMessage, SimulHostConnection},
+};
+
+/// A simultaneous exhibition: one host playing every board against a different player.
+pub struct Simul {
+    pub id: Uuid,
+    pub host_name: String,
+    pub host_user_id: String,
+    pub boards: Vec<SimulBoard>,
+    pub host_connections: Vec<Addr<SimulHostConnection>>,
+    pub walking_order: Option<WalkingOrder>,
+}
+
+/// Over-the-board style rules: the host walks from board to board in a fixed
+/// order and may only move on the board they are standing at. A player may only
+/// move after the host has been at their board since the player's last move, and
+/// the host's clock on a board only runs while the host is standing there.
+#[derive(Clone, Debug)]
+pub struct WalkingOrder {
+    pub cursor: usize,
+    pub arrived_at: Instant,
+    pub host_time_budget: Option<Duration>,
+    pub host_time_used: HashMap<Uuid, Duration>,
+    // boards the host has moved on or passed since the player last moved
+    pub visited: HashSet<Uuid>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulBoard {
+    pub game_id: Uuid,
+    pub host_color: Color,
+    // when the host last became the side to move on this board
+    pub waiting_since: Instant,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimulScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub ongoing: u32,
+}
+impl SimulScore {
+    pub fn to_str(&self) -> String {
+        format!("+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BoardStatus {
+    pub game_id: String,
+    pub host_color: String,
+    pub host_to_move: bool,
+    pub result: Option<String>,
+    pub host_time_left_ms: Option<u128>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SimulStatus {
+    pub simul_id: String,
+    pub host_name: String,
+    pub score: String,
+    pub results: SimulScore,
+    pub queue: Vec<String>,
+    pub cursor: Option<String>,
+    pub boards: Vec<BoardStatus>,
+}
+
+impl Simul {
+    pub fn new(
+        id: Uuid,
+        host_name: String,
+        host_user_id: String,
+        boards: Vec<(Uuid, Color)>,
+    ) -> Simul {
+        let now = Instant::now();
+        let walking_order = None;
+        Simul {
+            id,
+            host_name,
+            host_user_id,
+            boards: boards
+                .into_iter()
+                .map(|(game_id, host_color)| SimulBoard {
+                    game_id,
+                    host_color,
+                    waiting_since: now,
+                })
+                .collect(),
+            host_connections: vec![],
+            walking_order,
+        }
+    }
+    /// Switches the simul to walking order rules, with the host starting at the
+    /// first board.
+    pub fn with_walking_order(mut self, host_time_budget: Option<Duration>) -> Simul {
+        self.walking_order = Some(WalkingOrder {
+            cursor: 0,
+            arrived_at: Instant::now(),
+            host_time_budget,
+            host_time_used: HashMap::new(),
+            visited: HashSet::new(),
+        });
+        self
+    }
+    pub fn cursor_board(&self) -> Option<&SimulBoard> {
+        self.walking_order
+            .as_ref()
+            .and_then(|walking| self.boards.get(walking.cursor))
+    }
+    /// Whether `mover` may move on the given board right now.
+    pub fn check_move(&self, game_id: Uuid, mover: Color) -> Result<(), &'static str> {
+        let (walking, board) = match (&self.walking_order, self.board(game_id)) {
+            (Some(walking), Some(board)) => (walking, board),
+            _ => return Ok(()),
+        };
+
+        if mover == board.host_color {
+            if self.cursor_board().map(|b| b.game_id) != Some(game_id) {
+                return Err(HOST_NOT_AT_BOARD_ERROR);
+            }
+        } else if !walking.visited.contains(&game_id) {
+            return Err(WAIT_FOR_HOST_ERROR);
+        }
+
+        Ok(())
+    }
+    /// The host moves on to the next board without moving, which is only allowed
+    /// while the player at the current board has not replied yet.
+    pub fn pass(&mut self, games: &HashMap<Uuid, Game>) -> Result<(), &'static str> {
+        let board = match self.cursor_board() {
+            None => return Ok(()),
+            Some(board) => board.clone(),
+        };
+        if games
+            .get(&board.game_id)
+            .is_some_and(|game| game.game_result.is_none() && game.next_to_move == board.host_color)
+        {
+            return Err(HOST_MUST_MOVE_ERROR);
+        }
+
+        if let Some(walking) = self.walking_order.as_mut() {
+            walking.visited.insert(board.game_id);
+        }
+        self.advance(games);
+        Ok(())
+    }
+    fn advance(&mut self, games: &HashMap<Uuid, Game>) {
+        let boards = &self.boards;
+        let walking = match self.walking_order.as_mut() {
+            None => return,
+            Some(walking) => walking,
+        };
+
+        let now = Instant::now();
+        if let Some(board) = boards.get(walking.cursor) {
+            *walking.host_time_used.entry(board.game_id).or_default() += now - walking.arrived_at;
+        }
+        walking.arrived_at = now;
+
+        for step in 1..=boards.len() {
+            let next = (walking.cursor + step) % boards.len();
+            if is_ongoing(games, boards[next].game_id) {
+                walking.cursor = next;
+                return;
+            }
+        }
+    }
+    pub fn host_time_left(&self, game_id: Uuid) -> Option<Duration> {
+        let walking = self.walking_order.as_ref()?;
+        let budget = walking.host_time_budget?;
+
+        let mut used = walking
+            .host_time_used
+            .get(&game_id)
+            .copied()
+            .unwrap_or_default();
+        if self.cursor_board().map(|b| b.game_id) == Some(game_id) {
+            used += walking.arrived_at.elapsed();
+        }
+        Some(budget.saturating_sub(used))
+    }
+    /// The board the host is standing at, if the host ran out of time on it.
+    pub fn flagged_board(&self, games: &HashMap<Uuid, Game>) -> Option<&SimulBoard> {
+        let board = self.cursor_board()?;
+        if !is_ongoing(games, board.game_id)
+            || self.host_time_left(board.game_id) != Some(Duration::ZERO)
+        {
+            return None;
+        }
+
+        Some(board)
+    }
+    pub fn board(&self, game_id: Uuid) -> Option<&SimulBoard> {
+        self.boards.iter().find(|board| board.game_id == game_id)
+    }
+    /// Called whenever something happens on one of the boards, with the color of
+    /// whoever moved if it was a move. Keeps track of since when the host has been
+    /// waited on and where the host is walking to.
+    pub fn board_updated(
+        &mut self,
+        game_id: Uuid,
+        mover: Option<Color>,
+        games: &HashMap<Uuid, Game>,
+    ) {
+        let host_color = match self.board(game_id) {
+            None => return,
+            Some(board) => board.host_color,
+        };
+
+        match mover {
+            None => (),
+            Some(mover) if mover == host_color => {
+                if let Some(walking) = self.walking_order.as_mut() {
+                    walking.visited.insert(game_id);
+                }
+                self.advance(games);
+            }
+            Some(_) => {
+                if let Some(board) = self.boards.iter_mut().find(|b| b.game_id == game_id) {
+                    board.waiting_since = Instant::now();
+                }
+                if let Some(walking) = self.walking_order.as_mut() {
+                    walking.visited.remove(&game_id);
+                }
+            }
+        }
+
+        // never keep the host standing at a finished board
+        if let Some(board) = self.cursor_board() {
+            if !is_ongoing(games, board.game_id) {
+                self.advance(games);
+            }
+        }
+    }
+    pub fn send_to_hosts<T: Serialize>(&self, message: &T) {
+        let text = serde_json::to_string(message).unwrap();
+        self.host_connections
+            .iter()
+            .for_each(|e| e.do_send(SimulBroadcastMessage::new(text.clone())));
+    }
+    /// The boards where it is the host's turn, the one waiting the longest first.
+    pub fn queue(&self, games: &HashMap<Uuid, Game>) -> Vec<Uuid> {
+        let mut waiting: Vec<&SimulBoard> = self
+            .boards
+            .iter()
+            .filter(|board| match games.get(&board.game_id) {
+                None => false,
+                Some(game) => game.game_result.is_none() && game.next_to_move == board.host_color,
+            })
+            .collect();
+        // sort is stable, so boards waiting equally long stay in board order
+        waiting.sort_by_key(|board| board.waiting_since);
+
+        waiting.iter().map(|board| board.game_id).collect()
+    }
+    pub fn score(&self, games: &HashMap<Uuid, Game>) -> SimulScore {
+        let mut score = SimulScore::default();
+        for board in &self.boards {
+            match games.get(&board.game_id).and_then(|game| game.game_result) {
+                None => score.ongoing += 1,
+                Some(GameResult::Draw) => score.draws += 1,
+                Some(result) if result == GameResult::won_by(board.host_color) => score.wins += 1,
+                Some(_) => score.losses += 1,
+            }
+        }
+
+        score
+    }
+    pub fn status(&self, games: &HashMap<Uuid, Game>) -> SimulStatus {
+        let score = self.score(games);
+        SimulStatus {
+            simul_id: self.id.to_string(),
+            host_name: self.host_name.clone(),
+            score: score.to_str(),
+            results: score,
+            queue: self
+                .queue(games)
+                .iter()
+                .map(|game_id| game_id.to_string())
+                .collect(),
+            cursor: self.cursor_board().map(|board| board.game_id.to_string()),
+            boards: self
+                .boards
+                .iter()
+                .map(|board| {
+                    let game = games.get(&board.game_id);
+                    BoardStatus {
+                        game_id: board.game_id.to_string(),
+                        host_color: board.host_color.to_str(),
+                        host_to_move: game.is_some_and(|game| {
+                            game.game_result.is_none() && game.next_to_move == board.host_color
+                        }),
+                        result: game.and_then(|game| game.game_result.map(|res| res.to_str())),
+                        host_time_left_ms: self
+                            .host_time_left(board.game_id)
+                            .map(|left| left.as_millis()),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+fn is_ongoing(games: &HashMap<Uuid, Game>, game_id: Uuid) -> bool {
+    games
+        .get(&game_id)
+        .is_some_and(|game| game.game_result.is_none())
+}
+
+#[cfg(test)]
+mod test_simul {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use uuid::Uuid;
+
+    use crate::game::{chess_piece::Color, Game, GameAction, GameResult};
+
+    use super::Simul;
+
+    #[test]
+    fn test_walking_order() {
+        let (simul, mut games) = simul_with_games(vec![Color::WHITE, Color::BLACK]);
+        let mut simul = simul.with_walking_order(None);
+        let ids: Vec<Uuid> = simul.boards.iter().map(|board| board.game_id).collect();
+
+        // the host stands at board 0 and the player on board 1 has to wait for them
+        assert!(simul.check_move(ids[1], Color::BLACK).is_err());
+        assert!(simul.check_move(ids[1], Color::WHITE).is_err());
+        assert!(simul.check_move(ids[0], Color::WHITE).is_ok());
+
+        games
+            .get_mut(&ids[0])
+            .unwrap()
+            .validate_and_make_move("e2", "e4", ' ')
+            .unwrap();
+        simul.board_updated(ids[0], Some(Color::WHITE), &games);
+        assert_eq!(simul.cursor_board().unwrap().game_id, ids[1]);
+        assert!(simul.check_move(ids[0], Color::BLACK).is_ok());
+
+        // nobody has moved on board 1 yet, so the host passes back to board 0
+        simul.pass(&games).unwrap();
+        assert!(simul.check_move(ids[1], Color::WHITE).is_ok());
+        assert_eq!(simul.cursor_board().unwrap().game_id, ids[0]);
+
+        // the host may pass on board 0 as well, but not once the player on board 1 replied
+        assert!(simul.pass(&games).is_ok());
+        games
+            .get_mut(&ids[1])
+            .unwrap()
+            .validate_and_make_move("d2", "d4", ' ')
+            .unwrap();
+        simul.board_updated(ids[1], Some(Color::WHITE), &games);
+        assert!(simul.pass(&games).is_err());
+        assert!(simul.check_move(ids[1], Color::WHITE).is_err());
+    }
+
+    #[test]
+    fn test_host_time_budget() {
+        let (simul, games) = simul_with_games(vec![Color::WHITE, Color::WHITE]);
+        let simul = simul.with_walking_order(Some(Duration::ZERO));
+        let ids: Vec<Uuid> = simul.boards.iter().map(|board| board.game_id).collect();
+
+        assert_eq!(simul.flagged_board(&games).unwrap().game_id, ids[0]);
+        assert_eq!(simul.host_time_left(ids[1]), Some(Duration::ZERO));
+    }
+
+    fn simul_with_games(host_colors: Vec<Color>) -> (Simul, HashMap<Uuid, Game>) {
+        let boards: Vec<(Uuid, Color)> = host_colors
+            .into_iter()
+            .map(|color| (Uuid::new_v4(), color))
+            .collect();
+        let games = boards
+            .iter()
+            .map(|(id, color)| (*id, Game::new(*id, *color)))
+            .collect();
+        (
+            Simul::new(
+                Uuid::new_v4(),
+                "host".to_string(),
+                "host_id".to_string(),
+                boards,
+            ),
+            games,
+        )
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let (mut simul, mut games) =
+            simul_with_games(vec![Color::WHITE, Color::BLACK, Color::WHITE]);
+        let ids: Vec<Uuid> = simul.boards.iter().map(|board| board.game_id).collect();
+
+        // host is white on boards 0 and 2, so those wait for the host in board order
+        assert_eq!(simul.queue(&games), vec![ids[0], ids[2]]);
+
+        // the player on board 1 moves first, then the host moves on board 0
+        let start = Instant::now();
+        simul.boards[0].waiting_since = start;
+        simul.boards[2].waiting_since = start + Duration::from_secs(1);
+        games
+            .get_mut(&ids[1])
+            .unwrap()
+            .validate_and_make_move("e2", "e4", ' ')
+            .unwrap();
+        simul.boards[1].waiting_since = start + Duration::from_secs(2);
+        games
+            .get_mut(&ids[0])
+            .unwrap()
+            .validate_and_make_move("d2", "d4", ' ')
+            .unwrap();
+
+        assert_eq!(simul.queue(&games), vec![ids[2], ids[1]]);
+    }
+
+    #[test]
+    fn test_score() {
+        let (simul, mut games) =
+            simul_with_games(vec![Color::WHITE, Color::BLACK, Color::WHITE, Color::BLACK]);
+        let ids: Vec<Uuid> = simul.boards.iter().map(|board| board.game_id).collect();
+
+        games.get_mut(&ids[0]).unwrap().game_result = Some(GameResult::WhiteWon);
+        games.get_mut(&ids[1]).unwrap().game_result = Some(GameResult::WhiteWon);
+        games
+            .get_mut(&ids[2])
+            .unwrap()
+            .perform_action(Color::BLACK, GameAction::Resign)
+            .unwrap();
+
+        let score = simul.score(&games);
+        assert_eq!(
+            (score.wins, score.draws, score.losses, score.ongoing),
+            (2, 0, 1, 1)
+        );
+        assert_eq!(score.to_str(), "+2 =0 -1");
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::UserGame,
+    game::{
+        chess_piece::{Color, Piece},
+        Game, Players,
+    },
+    utils::error::INVALID_SAN_ERROR,
+};
+
+// How many openings of a player are listed
+pub const FAVORITE_OPENINGS: usize = 5;
+// Either part of the cache is emptied once it holds this many entries
+const MAX_CACHED_STATS: usize = 10_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MoveTime {
+    pub ply: u32,
+    pub player: String,
+    pub time_spent_ms: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Captures {
+    pub white: u32,
+    pub black: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GameStats {
+    pub game_id: String,
+    pub moves: u32,
+    pub finished: bool,
+    pub captures: Captures,
+    // white's material minus black's in pawns, from the start and after every move
+    pub material: Vec<i32>,
+    pub move_times: Vec<MoveTime>,
+}
+
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::PAWN => 1,
+        Piece::KNIGHT | Piece::BISHOP => 3,
+        Piece::ROOK => 5,
+        Piece::QUEEN => 9,
+        Piece::KING => 0,
+    }
+}
+
+impl Game {
+    /// White's material minus black's, counted in pawns.
+    pub fn material_balance(&self) -> i32 {
+        self.field
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|square| match square.color {
+                Color::WHITE => piece_value(square.piece),
+                Color::BLACK => -piece_value(square.piece),
+            })
+            .sum()
+    }
+    fn pieces_of(&self, color: Color) -> usize {
+        self.field
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|square| square.color == color)
+            .count()
+    }
+}
+
+impl GameStats {
+    /// Replays the moves of a game, given in UCI notation, from its setup.
+    pub fn compute(
+        game_id: &str,
+        mut game: Game,
+        moves: &[String],
+        move_times: Vec<MoveTime>,
+        finished: bool,
+    ) -> Result<GameStats, &'static str> {
+        let mut captures = Captures::default();
+        let mut material = vec![game.material_balance()];
+        for uci in moves {
+            let (from, to, promotion) = match (uci.get(..2), uci.get(2..4), uci.get(4..)) {
+                (Some(from), Some(to), Some(promotion)) => (
+                    from,
+                    to,
+                    promotion.to_ascii_uppercase().chars().next().unwrap_or(' '),
+                ),
+                _ => return Err(INVALID_SAN_ERROR),
+            };
+            let mover = game.next_to_move;
+            let opponent_pieces = game.pieces_of(mover.opposite());
+            game.validate_and_make_move(from, to, promotion)?;
+            if game.pieces_of(mover.opposite()) < opponent_pieces {
+                match mover {
+                    Color::WHITE => captures.white += 1,
+                    Color::BLACK => captures.black += 1,
+                }
+            }
+            material.push(game.material_balance());
+        }
+        Ok(GameStats {
+            game_id: game_id.to_string(),
+            moves: moves.len() as u32,
+            finished,
+            captures,
+            material,
+            move_times,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorResults {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OpeningStats {
+    pub eco: String,
+    pub name: String,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// What a player did in their finished games, of one year or of all time.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UserStats {
+    pub user_id: String,
+    pub year: Option<i32>,
+    pub games: u32,
+    pub as_white: ColorResults,
+    pub as_black: ColorResults,
+    pub favorite_openings: Vec<OpeningStats>,
+    pub longest_win_streak: u32,
+}
+
+impl UserStats {
+    /// `games` are the games of the player, newest first like they come from the DB.
+    pub fn compute(user_id: &str, year: Option<i32>, games: &[UserGame]) -> UserStats {
+        let prefix = year.map(|year| format!("{:04}-", year));
+        let mut finished: Vec<&UserGame> = games
+            .iter()
+            .filter(|game| game.result.is_some())
+            .filter(|game| match &prefix {
+                None => true,
+                Some(prefix) => game.created_at.starts_with(prefix.as_str()),
+            })
+            .collect();
+        finished.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        let mut stats = UserStats {
+            user_id: user_id.to_string(),
+            year,
+            games: finished.len() as u32,
+            as_white: ColorResults::default(),
+            as_black: ColorResults::default(),
+            favorite_openings: vec![],
+            longest_win_streak: 0,
+        };
+        let mut openings: Vec<OpeningStats> = vec![];
+        let mut streak = 0;
+        for game in finished {
+            let won = match (game.color.as_str(), game.result.as_deref()) {
+                ("white", Some("1-0")) | ("black", Some("0-1")) => Some(true),
+                ("white", Some("0-1")) | ("black", Some("1-0")) => Some(false),
+                _ => None,
+            };
+            let results = match game.color.as_str() {
+                "white" => &mut stats.as_white,
+                _ => &mut stats.as_black,
+            };
+            results.games += 1;
+            match won {
+                Some(true) => results.wins += 1,
+                Some(false) => results.losses += 1,
+                None => results.draws += 1,
+            }
+
+            streak = match won {
+                Some(true) => streak + 1,
+                _ => 0,
+            };
+            stats.longest_win_streak = stats.longest_win_streak.max(streak);
+
+            if let (Some(eco), Some(name)) = (&game.eco, &game.opening) {
+                let index = match openings
+                    .iter()
+                    .position(|opening| &opening.eco == eco && &opening.name == name)
+                {
+                    Some(index) => index,
+                    None => {
+                        openings.push(OpeningStats {
+                            eco: eco.clone(),
+                            name: name.clone(),
+                            games: 0,
+                            wins: 0,
+                            draws: 0,
+                            losses: 0,
+                        });
+                        openings.len() - 1
+                    }
+                };
+                let opening = &mut openings[index];
+                opening.games += 1;
+                match won {
+                    Some(true) => opening.wins += 1,
+                    Some(false) => opening.losses += 1,
+                    None => opening.draws += 1,
+                }
+            }
+        }
+        openings.sort_by(|a, b| b.games.cmp(&a.games).then_with(|| a.eco.cmp(&b.eco)));
+        openings.truncate(FAVORITE_OPENINGS);
+        stats.favorite_openings = openings;
+        stats
+    }
+}
+
+/// Computed stats, kept until they may change. Stats of a game are only
+/// cached once it is finished, those of a player until they finish a game.
+#[derive(Default)]
+pub struct StatsCache {
+    games: HashMap<String, GameStats>,
+    users: HashMap<(String, Option<i32>), UserStats>,
+}
+impl StatsCache {
+    pub fn game(&self, game_id: &str) -> Option<GameStats> {
+        self.games.get(game_id).cloned()
+    }
+    pub fn add_game(&mut self, stats: GameStats) {
+        if !stats.finished {
+            return;
+        }
+        if self.games.len() >= MAX_CACHED_STATS {
+            self.games.clear();
+        }
+        self.games.insert(stats.game_id.clone(), stats);
+    }
+    pub fn user(&self, user_id: &str, year: Option<i32>) -> Option<UserStats> {
+        self.users.get(&(user_id.to_string(), year)).cloned()
+    }
+    pub fn add_user(&mut self, stats: UserStats) {
+        if self.users.len() >= MAX_CACHED_STATS {
+            self.users.clear();
+        }
+        self.users
+            .insert((stats.user_id.clone(), stats.year), stats);
+    }
+    pub fn forget_game(&mut self, game_id: &str) {
+        self.games.remove(game_id);
+    }
+    /// Drops the stats of both players, whose games changed.
+    pub fn forget_players(&mut self, players: &Players) {
+        self.users.retain(|(user_id, _), _| {
+            players.white.as_ref() != Some(user_id) && players.black.as_ref() != Some(user_id)
+        });
+    }
+    pub fn forget_users(&mut self) {
+        self.users.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_stats {
+    use uuid::Uuid;
+
+    use crate::{
+        db::UserGame,
+        game::{chess_piece::Color, Game, Players},
+    };
+
+    use super::{Captures, GameStats, StatsCache, UserStats};
+
+    fn user_game(
+        color: &str,
+        result: Option<&str>,
+        eco: Option<&str>,
+        created_at: &str,
+    ) -> UserGame {
+        UserGame {
+            game_id: Uuid::new_v4().to_string(),
+            color: color.to_string(),
+            opponent_id: None,
+            result: result.map(str::to_string),
+            termination: None,
+            eco: eco.map(str::to_string),
+            opening: eco.map(|eco| format!("Opening {}", eco)),
+            created_at: created_at.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_game_stats() {
+        let game = Game::new(Uuid::new_v4(), Color::WHITE);
+        let moves = ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a2", "a1a2"]
+            .map(str::to_string)
+            .to_vec();
+        let stats = GameStats::compute("id", game, &moves, vec![], false).unwrap();
+        assert_eq!(stats.moves, 7);
+        assert_eq!(stats.captures, Captures { white: 2, black: 2 });
+        assert_eq!(stats.material, vec![0, 0, 0, 1, 0, 0, -1, 8]);
+
+        let illegal = ["e2e5".to_string()];
+        let game = Game::new(Uuid::new_v4(), Color::WHITE);
+        assert!(GameStats::compute("id", game, &illegal, vec![], false).is_err());
+    }
+
+    #[test]
+    fn test_user_stats() {
+        // newest first
+        let games = [
+            user_game("white", None, Some("C20"), "2025-03-01T00:00:00Z"),
+            user_game("black", Some("0-1"), Some("B20"), "2025-02-04T00:00:00Z"),
+            user_game("white", Some("1-0"), Some("C20"), "2025-02-03T00:00:00Z"),
+            user_game(
+                "white",
+                Some("1/2-1/2"),
+                Some("C20"),
+                "2025-02-02T00:00:00Z",
+            ),
+            user_game("black", Some("1-0"), None, "2024-12-31T00:00:00Z"),
+            user_game("white", Some("1-0"), Some("C20"), "2024-12-30T00:00:00Z"),
+            user_game("black", Some("0-1"), Some("B20"), "2024-12-29T00:00:00Z"),
+        ];
+        let stats = UserStats::compute("alice", None, &games);
+        assert_eq!(stats.games, 6);
+        assert_eq!(
+            (
+                stats.as_white.wins,
+                stats.as_white.draws,
+                stats.as_white.losses
+            ),
+            (2, 1, 0)
+        );
+        assert_eq!(
+            (
+                stats.as_black.wins,
+                stats.as_black.draws,
+                stats.as_black.losses
+            ),
+            (2, 0, 1)
+        );
+        assert_eq!(stats.longest_win_streak, 2);
+        assert_eq!(stats.favorite_openings[0].eco, "C20");
+        assert_eq!(stats.favorite_openings[0].games, 3);
+        assert_eq!(stats.favorite_openings[1].games, 2);
+
+        let stats = UserStats::compute("alice", Some(2024), &games);
+        assert_eq!(stats.games, 3);
+        assert_eq!(stats.longest_win_streak, 2);
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut cache = StatsCache::default();
+        let game = Game::new(Uuid::new_v4(), Color::WHITE);
+        let active = GameStats::compute("active", game.clone(), &[], vec![], false).unwrap();
+        cache.add_game(active);
+        assert!(cache.game("active").is_none());
+        let finished = GameStats::compute("finished", game, &[], vec![], true).unwrap();
+        cache.add_game(finished.clone());
+        assert_eq!(cache.game("finished"), Some(finished));
+
+        cache.add_user(UserStats::compute("alice", None, &[]));
+        cache.add_user(UserStats::compute("bob", Some(2025), &[]));
+        cache.forget_players(&Players {
+            white: Some("alice".to_string()),
+            black: None,
+        });
+        assert!(cache.user("alice", None).is_none());
+        assert!(cache.user("bob", Some(2025)).is_some());
+    }
+}
@@ -0,0 +1,1578 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use actix_web::cookie::time::OffsetDateTime;
+use async_trait::async_trait;
+
+use crate::{
+    backup::{ExportedGame, ReplayedMove},
+    correspondence::{format_timestamp, parse_timestamp, Vacation},
+    db::{
+        ApiKey, ArchivedGame, AwaitingGame, ChatMessage, CorrespondenceGame, DBGame, ExplorerMove,
+        GameFilter, Invite, LeaderboardEntry, Move, RatingHistoryEntry, StaleGame, UserGame,
+    },
+    game::{
+        chess_piece::Color,
+        eco::Opening,
+        event::{GameEvent, LoggedEvent, Snapshot},
+        Game, Players, Termination,
+    },
+    rating::{RatedGame, Rating, RatingPool},
+    stats::MoveTime,
+    user::User,
+    utils::error::INTERNAL_SERVER_ERROR,
+};
+
+/// A move to persist. Plies count the half moves of a game from 1 and are
+/// unique per game, `finished` holds the result and termination of the game
+/// if the move ended it, so both are saved together or not at all. The move
+/// is logged as the event `seq`, together with a FEN snapshot every few moves.
+/// `position_hash` is the Zobrist hash of the position after the move, and
+/// `opening` the opening the game is in after it.
+pub struct NewMove<'a> {
+    pub ply: u32,
+    pub turn: u32,
+    pub move_notation: &'a str,
+    pub player: &'a str,
+    pub finished: Option<(&'a str, &'a str)>,
+    pub seq: u64,
+    pub event: &'a GameEvent,
+    pub snapshot: Option<&'a str>,
+    pub position_hash: u64,
+    pub opening: Option<Opening>,
+    // how long the player thought, if the game has a clock
+    pub time_spent_ms: Option<u64>,
+}
+
+/// Where games and their moves are persisted. `DB` stores them in a local
+/// SQLite file or in Turso, `MemoryStore` keeps them in memory for tests.
+#[async_trait]
+pub trait GameStore: Send + Sync {
+    async fn create_game(&self, game: &Game) -> Result<(), &'static str>;
+    /// Stores how a game ended. Returns false if it already had a result, or
+    /// there is no such game.
+    async fn finish_game(
+        &self,
+        result: &str,
+        termination: &str,
+        id: &str,
+    ) -> Result<bool, &'static str>;
+    /// Ends a game without a result and hides it like a deleted one.
+    async fn abort_game(&self, id: &str) -> Result<(), &'static str>;
+    async fn insert_move(&self, id: &str, new_move: NewMove<'_>) -> Result<(), &'static str>;
+    async fn get_moves(&self, id: &str) -> Result<Vec<Move>, &'static str>;
+    async fn get_active_games(&self) -> Result<Vec<DBGame>, &'static str>;
+    /// A game as it was created, before any event.
+    async fn get_game(&self, id: &str) -> Result<Option<Game>, &'static str>;
+    /// Appends to the event log of a game, `seq` is the next free one if not given.
+    async fn append_event(
+        &self,
+        id: &str,
+        seq: Option<u64>,
+        event: &GameEvent,
+    ) -> Result<u64, &'static str>;
+    async fn get_events(&self, id: &str, after_seq: u64) -> Result<Vec<LoggedEvent>, &'static str>;
+    /// The latest snapshot, or the latest one up to `until_seq`.
+    async fn get_snapshot(
+        &self,
+        id: &str,
+        until_seq: Option<u64>,
+    ) -> Result<Option<Snapshot>, &'static str>;
+    /// Drops the snapshots of moves that were taken back.
+    async fn delete_snapshots(&self, id: &str, after_ply: u32) -> Result<(), &'static str>;
+    async fn insert_chat(
+        &self,
+        id: &str,
+        scope: &str,
+        author: &str,
+        message: &str,
+    ) -> Result<(), &'static str>;
+    async fn get_chat(&self, id: &str) -> Result<Vec<ChatMessage>, &'static str>;
+    async fn insert_action(&self, id: &str, player: &str, action: &str)
+        -> Result<(), &'static str>;
+    /// Takes back the last `count` moves, `opening` is the one the game is back in.
+    async fn delete_last_moves(
+        &self,
+        id: &str,
+        count: u32,
+        opening: Option<Opening>,
+    ) -> Result<(), &'static str>;
+    async fn get_rating(
+        &self,
+        user_id: &str,
+        pool: RatingPool,
+    ) -> Result<Option<Rating>, &'static str>;
+    /// Rates a finished game: updates both players' ratings in its pool and
+    /// appends them to their history, all at once or not at all. Returns the
+    /// ratings before and after the game, white's first, or `None` if the
+    /// game was rated already.
+    async fn rate_game(
+        &self,
+        game: &RatedGame,
+    ) -> Result<Option<[(Rating, Rating); 2]>, &'static str>;
+    /// Adds the moves of a finished game to the opening explorer. Private games
+    /// are left out and every game counts once, however often it is added.
+    async fn add_to_explorer(
+        &self,
+        game_id: &str,
+        result: &str,
+        average_rating: Option<f64>,
+    ) -> Result<(), &'static str>;
+    async fn finish_pairing(&self, game_id: &str, result: &str) -> Result<(), &'static str>;
+    async fn create_invite(
+        &self,
+        token_hash: &str,
+        game_id: &str,
+        created_by: &str,
+        seat: &str,
+        password_hash: Option<String>,
+        expires_at: Option<String>,
+    ) -> Result<(), &'static str>;
+    async fn get_invite(&self, token_hash: &str) -> Result<Option<Invite>, &'static str>;
+    /// Claims an invite for `user_id` and returns whether it was still open.
+    async fn accept_invite(&self, token_hash: &str, user_id: &str) -> Result<bool, &'static str>;
+    async fn set_player(
+        &self,
+        game_id: &str,
+        color: Color,
+        user_id: &str,
+    ) -> Result<(), &'static str>;
+    async fn create_user(&self, user: &User) -> Result<(), &'static str>;
+    async fn get_user(&self, id: &str) -> Result<Option<User>, &'static str>;
+}
+
+/// The archive of stored games, besides the games themselves.
+#[async_trait]
+pub trait ArchiveStore: Send + Sync {
+    /// The games of user `id`, private ones only if `include_private` is set or
+    /// `viewer` played in them.
+    async fn get_user_games(
+        &self,
+        id: &str,
+        include_private: bool,
+        viewer: Option<&str>,
+    ) -> Result<Vec<UserGame>, &'static str>;
+    /// One page of the games matching `filter`, together with how many match in total.
+    async fn search_games(
+        &self,
+        filter: &GameFilter,
+        newest_first: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(u64, Vec<ArchivedGame>), &'static str>;
+    /// Hides a game from the archive, the stats and the explorer, and ends it
+    /// if it is still running. Returns false if there is no such game.
+    async fn soft_delete_game(&self, id: &str) -> Result<bool, &'static str>;
+    /// Removes a game and everything recorded about it for good. Returns false
+    /// if there is no such game.
+    async fn delete_game(&self, id: &str) -> Result<bool, &'static str>;
+    /// Whether a game changed the ratings of its players.
+    async fn is_rated_game(&self, id: &str) -> Result<bool, &'static str>;
+    /// Whether a game was played in a tournament or a simul.
+    async fn is_competition_game(&self, id: &str) -> Result<bool, &'static str>;
+    /// The games created after `after`, a `(created_at, game_id)` pair, in
+    /// the order they were created. Their moves are left empty.
+    async fn get_export_page(
+        &self,
+        after: Option<&(String, String)>,
+        limit: u32,
+    ) -> Result<Vec<ExportedGame>, &'static str>;
+    /// The UCI notation of every move of a game, `None` for moves saved before it was recorded.
+    async fn get_move_ucis(&self, id: &str) -> Result<Vec<Option<String>>, &'static str>;
+    /// The result of a game, `None` while it is running.
+    async fn get_result(&self, id: &str) -> Result<Option<String>, &'static str>;
+    /// How long each move of a game took, as far as it had a clock.
+    async fn get_move_times(&self, id: &str) -> Result<Vec<MoveTime>, &'static str>;
+    /// Restores an exported game with its moves, event log, positions and
+    /// snapshots in one go. Returns false if the game already exists.
+    async fn import_game(
+        &self,
+        game: &ExportedGame,
+        start_hash: u64,
+        moves: &[ReplayedMove],
+    ) -> Result<bool, &'static str>;
+    /// Unfinished games of users without days per move in which nobody moved
+    /// since `before`. Voting games have no seated users and never go stale.
+    async fn get_stale_games(&self, before: &str) -> Result<Vec<StaleGame>, &'static str>;
+}
+
+/// The opening explorer, built from the positions of finished games.
+#[async_trait]
+pub trait ExplorerStore: Send + Sync {
+    async fn get_explorer_moves(
+        &self,
+        position_hash: u64,
+    ) -> Result<Vec<ExplorerMove>, &'static str>;
+    /// Finished public games that are not in the explorer yet, with their results.
+    async fn get_unexplored_games(&self) -> Result<Vec<(String, String)>, &'static str>;
+    /// Replaces the position index of a game, and fills in the UCI notation
+    /// of moves saved before it was recorded.
+    async fn save_positions(
+        &self,
+        id: &str,
+        positions: &[(u32, u64)],
+        moves: &[(u32, String)],
+    ) -> Result<(), &'static str>;
+}
+
+/// Deadlines of correspondence games and the vacations that pause them.
+#[async_trait]
+pub trait CorrespondenceStore: Send + Sync {
+    async fn get_correspondence_games(&self) -> Result<Vec<CorrespondenceGame>, &'static str>;
+    /// Unfinished games of a user in which it is their turn.
+    async fn get_awaiting_games(&self, user_id: &str) -> Result<Vec<AwaitingGame>, &'static str>;
+    async fn add_vacation(
+        &self,
+        user_id: &str,
+        starts_at: &str,
+        ends_at: &str,
+    ) -> Result<(), &'static str>;
+    async fn get_vacations(&self, user_id: &str) -> Result<Vec<Vacation>, &'static str>;
+}
+
+/// Simuls, tournaments and arenas, and the games played in them.
+#[async_trait]
+pub trait CompetitionStore: Send + Sync {
+    async fn create_simul(
+        &self,
+        id: &str,
+        host_name: &str,
+        walking_order: bool,
+        host_time_budget_secs: Option<u64>,
+        game_ids: &[String],
+    ) -> Result<(), &'static str>;
+    async fn create_tournament(
+        &self,
+        id: &str,
+        name: &str,
+        format: &str,
+        rounds: u32,
+        players: &[String],
+    ) -> Result<(), &'static str>;
+    async fn insert_pairing(
+        &self,
+        tournament_id: &str,
+        round: u32,
+        white_user_id: &str,
+        black_user_id: Option<&str>,
+        game_id: Option<&str>,
+        result: Option<String>,
+    ) -> Result<(), &'static str>;
+    async fn create_arena(
+        &self,
+        id: &str,
+        name: &str,
+        time_control: &str,
+        duration_secs: u64,
+    ) -> Result<(), &'static str>;
+    async fn insert_arena_game(&self, arena_id: &str, game_id: &str) -> Result<(), &'static str>;
+}
+
+/// API keys, and the rating history and leaderboard of user accounts.
+#[async_trait]
+pub trait AccountStore: Send + Sync {
+    async fn create_api_key(
+        &self,
+        id: &str,
+        label: &str,
+        key_hash: &str,
+        role: &str,
+    ) -> Result<(), &'static str>;
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, &'static str>;
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, &'static str>;
+    /// Returns whether there was an unrevoked key with that id.
+    async fn revoke_api_key(&self, id: &str) -> Result<bool, &'static str>;
+    async fn get_rating_history(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<RatingHistoryEntry>, &'static str>;
+    async fn get_leaderboard(
+        &self,
+        pool: RatingPool,
+        max_deviation: f64,
+        limit: u32,
+    ) -> Result<Vec<LeaderboardEntry>, &'static str>;
+}
+
+/// Everything the server persists, which `DB` and `MemoryStore` both keep.
+pub trait Store:
+    GameStore + ArchiveStore + ExplorerStore + CorrespondenceStore + CompetitionStore + AccountStore
+{
+}
+impl<T> Store for T where
+    T: GameStore
+        + ArchiveStore
+        + ExplorerStore
+        + CorrespondenceStore
+        + CompetitionStore
+        + AccountStore
+{
+}
+
+struct StoredMove {
+    played: Move,
+    uci: Option<String>,
+    time_spent_ms: Option<u64>,
+    created_at: String,
+}
+
+struct StoredGame {
+    game: Game,
+    created_at: String,
+    result: Option<String>,
+    termination: Option<String>,
+    eco: Option<String>,
+    opening: Option<String>,
+    simul_id: Option<String>,
+    tournament_id: Option<String>,
+    deleted: bool,
+    moves: Vec<StoredMove>,
+    events: Vec<LoggedEvent>,
+    snapshots: Vec<Snapshot>,
+    // the Zobrist hash of the position after every ply, from the start position on
+    positions: Vec<(u32, u64)>,
+    chat: Vec<ChatMessage>,
+}
+impl StoredGame {
+    fn new(game: &Game, created_at: String) -> StoredGame {
+        StoredGame {
+            game: game.clone(),
+            created_at,
+            result: None,
+            termination: None,
+            eco: None,
+            opening: None,
+            simul_id: None,
+            tournament_id: None,
+            deleted: false,
+            moves: vec![],
+            events: vec![],
+            snapshots: vec![],
+            positions: vec![(game.ply(), game.zobrist())],
+            chat: vec![],
+        }
+    }
+    fn log(&mut self, seq: Option<u64>, event: &GameEvent) -> Result<u64, &'static str> {
+        let last = self.events.last().map_or(0, |logged| logged.seq);
+        let seq = seq.unwrap_or(last + 1);
+        if seq <= last {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        self.events.push(LoggedEvent {
+            seq,
+            event: event.clone(),
+            created_at: format_timestamp(OffsetDateTime::now_utc()),
+        });
+        Ok(seq)
+    }
+    fn set_opening(&mut self, opening: Option<Opening>) {
+        self.eco = opening.map(|opening| opening.eco.to_string());
+        self.opening = opening.map(|opening| opening.name.to_string());
+    }
+    fn is_running(&self) -> bool {
+        self.result.is_none() && self.termination.is_none() && !self.deleted
+    }
+    fn last_move(&self) -> Option<&StoredMove> {
+        self.moves.last()
+    }
+    // the same rule as `private_condition` of the database
+    fn is_visible(&self, include_private: bool, viewer: Option<&str>) -> bool {
+        include_private
+            || !self.game.private
+            || viewer.is_some_and(|viewer| self.game.players.contains(viewer))
+    }
+}
+
+/// Keeps everything in memory. The opening explorer is only built from the
+/// database, and tournaments and arenas keep their pairings in memory already.
+#[derive(Default)]
+pub struct MemoryStore {
+    games: RwLock<HashMap<String, StoredGame>>,
+    // by user and pool, with the number of rated games
+    ratings: RwLock<HashMap<(String, String), (Rating, u32)>>,
+    // by user, oldest first
+    rating_history: RwLock<Vec<(String, RatingHistoryEntry)>>,
+    // by token hash
+    invites: RwLock<HashMap<String, Invite>>,
+    users: RwLock<HashMap<String, User>>,
+    // by key hash, oldest first
+    api_keys: RwLock<Vec<(String, ApiKey)>>,
+    // by user, with their start and end
+    vacations: RwLock<HashMap<String, Vec<(String, String)>>>,
+}
+
+#[async_trait]
+impl GameStore for MemoryStore {
+    async fn create_game(&self, game: &Game) -> Result<(), &'static str> {
+        let mut games = self.games.write().unwrap();
+        let id = game.id.to_string();
+        if games.contains_key(&id) {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        games.insert(
+            id,
+            StoredGame::new(game, format_timestamp(OffsetDateTime::now_utc())),
+        );
+        Ok(())
+    }
+    async fn finish_game(
+        &self,
+        result: &str,
+        termination: &str,
+        id: &str,
+    ) -> Result<bool, &'static str> {
+        // like an UPDATE, finishing an unknown or finished game changes nothing
+        match self.games.write().unwrap().get_mut(id) {
+            Some(game) if game.result.is_none() => {
+                game.result = Some(result.to_string());
+                game.termination = Some(termination.to_string());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    async fn abort_game(&self, id: &str) -> Result<(), &'static str> {
+        if let Some(game) = self.games.write().unwrap().get_mut(id) {
+            if game.result.is_none() {
+                game.termination = Some(Termination::Aborted.to_str());
+                game.deleted = true;
+            }
+        }
+        Ok(())
+    }
+    async fn insert_move(&self, id: &str, new_move: NewMove<'_>) -> Result<(), &'static str> {
+        let mut games = self.games.write().unwrap();
+        let game = games.get_mut(id).ok_or(INTERNAL_SERVER_ERROR)?;
+        if game.moves.iter().any(|m| m.played.ply == new_move.ply) {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        game.log(Some(new_move.seq), new_move.event)?;
+        if let Some(fen) = new_move.snapshot {
+            game.snapshots.push(Snapshot {
+                seq: new_move.seq,
+                ply: new_move.ply,
+                fen: fen.to_string(),
+                eco: new_move.opening.map(|opening| opening.eco.to_string()),
+                opening: new_move.opening.map(|opening| opening.name.to_string()),
+            });
+        }
+        game.moves.push(StoredMove {
+            played: Move {
+                ply: new_move.ply,
+                move_notation: new_move.move_notation.to_string(),
+                turn: new_move.turn,
+                player: new_move.player.to_string(),
+            },
+            uci: new_move.event.uci(),
+            time_spent_ms: new_move.time_spent_ms,
+            created_at: format_timestamp(OffsetDateTime::now_utc()),
+        });
+        game.positions.push((new_move.ply, new_move.position_hash));
+        game.set_opening(new_move.opening);
+        if let Some((result, termination)) = new_move.finished {
+            game.result = Some(result.to_string());
+            game.termination = Some(termination.to_string());
+        }
+        Ok(())
+    }
+    async fn get_moves(&self, id: &str) -> Result<Vec<Move>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|game| game.moves.iter().map(|m| m.played.clone()).collect())
+            .unwrap_or_default())
+    }
+    async fn get_active_games(&self) -> Result<Vec<DBGame>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, game)| game.is_running())
+            .map(|(id, game)| DBGame {
+                game_id: id.clone(),
+                created_at: game.created_at.clone(),
+                last_moved: game.last_move().map(|last| last.played.player.clone()),
+                admin_color: game.game.admin_color.to_str(),
+            })
+            .collect())
+    }
+    async fn get_game(&self, id: &str) -> Result<Option<Game>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|stored| stored.game.clone()))
+    }
+    async fn append_event(
+        &self,
+        id: &str,
+        seq: Option<u64>,
+        event: &GameEvent,
+    ) -> Result<u64, &'static str> {
+        match self.games.write().unwrap().get_mut(id) {
+            None => Err(INTERNAL_SERVER_ERROR),
+            Some(game) => game.log(seq, event),
+        }
+    }
+    async fn get_events(&self, id: &str, after_seq: u64) -> Result<Vec<LoggedEvent>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|game| {
+                game.events
+                    .iter()
+                    .filter(|logged| logged.seq > after_seq)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+    async fn get_snapshot(
+        &self,
+        id: &str,
+        until_seq: Option<u64>,
+    ) -> Result<Option<Snapshot>, &'static str> {
+        Ok(self.games.read().unwrap().get(id).and_then(|game| {
+            game.snapshots
+                .iter()
+                .rev()
+                .find(|snapshot| match until_seq {
+                    None => true,
+                    Some(until_seq) => snapshot.seq <= until_seq,
+                })
+                .cloned()
+        }))
+    }
+    async fn delete_snapshots(&self, id: &str, after_ply: u32) -> Result<(), &'static str> {
+        if let Some(game) = self.games.write().unwrap().get_mut(id) {
+            game.snapshots.retain(|snapshot| snapshot.ply <= after_ply);
+        }
+        Ok(())
+    }
+    async fn insert_chat(
+        &self,
+        id: &str,
+        scope: &str,
+        author: &str,
+        message: &str,
+    ) -> Result<(), &'static str> {
+        let mut games = self.games.write().unwrap();
+        let game = games.get_mut(id).ok_or(INTERNAL_SERVER_ERROR)?;
+        game.chat.push(ChatMessage {
+            scope: scope.to_string(),
+            author: author.to_string(),
+            message: message.to_string(),
+            created_at: format_timestamp(OffsetDateTime::now_utc()),
+        });
+        Ok(())
+    }
+    async fn get_chat(&self, id: &str) -> Result<Vec<ChatMessage>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|game| game.chat.clone())
+            .unwrap_or_default())
+    }
+    async fn insert_action(
+        &self,
+        id: &str,
+        _player: &str,
+        _action: &str,
+    ) -> Result<(), &'static str> {
+        // actions are only kept for the record, nothing reads them back
+        match self.games.read().unwrap().contains_key(id) {
+            false => Err(INTERNAL_SERVER_ERROR),
+            true => Ok(()),
+        }
+    }
+    async fn delete_last_moves(
+        &self,
+        id: &str,
+        count: u32,
+        opening: Option<Opening>,
+    ) -> Result<(), &'static str> {
+        if let Some(game) = self.games.write().unwrap().get_mut(id) {
+            let kept = game.moves.len().saturating_sub(count as usize);
+            game.moves.truncate(kept);
+            // the positions of the moves taken back were never reached
+            let last_ply = game.last_move().map_or(0, |last| last.played.ply);
+            game.positions.retain(|(ply, _)| *ply <= last_ply);
+            game.set_opening(opening);
+        }
+        Ok(())
+    }
+    async fn get_rating(
+        &self,
+        user_id: &str,
+        pool: RatingPool,
+    ) -> Result<Option<Rating>, &'static str> {
+        Ok(self
+            .ratings
+            .read()
+            .unwrap()
+            .get(&(user_id.to_string(), pool.to_str()))
+            .map(|(rating, _)| *rating))
+    }
+    async fn rate_game(
+        &self,
+        game: &RatedGame,
+    ) -> Result<Option<[(Rating, Rating); 2]>, &'static str> {
+        let mut ratings = self.ratings.write().unwrap();
+        let mut history = self.rating_history.write().unwrap();
+        if history
+            .iter()
+            .any(|(_, entry)| entry.game_id == game.game_id)
+        {
+            return Ok(None);
+        }
+        let rating = |user_id: &str| {
+            ratings
+                .get(&(user_id.to_string(), game.pool.to_str()))
+                .map_or(Rating::default(), |(rating, _)| *rating)
+        };
+        let (white, black) = (rating(&game.white), rating(&game.black));
+        let (new_white, new_black) = game.rate(white, black);
+        let now = format_timestamp(OffsetDateTime::now_utc());
+        for (user_id, rating) in [(&game.white, new_white), (&game.black, new_black)] {
+            let entry = ratings
+                .entry((user_id.clone(), game.pool.to_str()))
+                .or_insert((rating, 0));
+            *entry = (rating, entry.1 + 1);
+            history.push((
+                user_id.clone(),
+                RatingHistoryEntry {
+                    pool: game.pool.to_str(),
+                    game_id: game.game_id.clone(),
+                    rating: rating.rating,
+                    deviation: rating.deviation,
+                    created_at: now.clone(),
+                },
+            ));
+        }
+        Ok(Some([(white, new_white), (black, new_black)]))
+    }
+    async fn add_to_explorer(
+        &self,
+        _game_id: &str,
+        _result: &str,
+        _average_rating: Option<f64>,
+    ) -> Result<(), &'static str> {
+        // the explorer is only built from the database
+        Ok(())
+    }
+    async fn finish_pairing(&self, _game_id: &str, _result: &str) -> Result<(), &'static str> {
+        // tournaments keep their results in memory already
+        Ok(())
+    }
+    async fn create_invite(
+        &self,
+        token_hash: &str,
+        game_id: &str,
+        created_by: &str,
+        seat: &str,
+        password_hash: Option<String>,
+        expires_at: Option<String>,
+    ) -> Result<(), &'static str> {
+        let mut invites = self.invites.write().unwrap();
+        if invites.contains_key(token_hash) {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        invites.insert(
+            token_hash.to_string(),
+            Invite {
+                game_id: game_id.to_string(),
+                created_by: created_by.to_string(),
+                seat: seat.to_string(),
+                password_hash,
+                expires_at,
+                accepted_by: None,
+            },
+        );
+        Ok(())
+    }
+    async fn get_invite(&self, token_hash: &str) -> Result<Option<Invite>, &'static str> {
+        Ok(self.invites.read().unwrap().get(token_hash).cloned())
+    }
+    async fn accept_invite(&self, token_hash: &str, user_id: &str) -> Result<bool, &'static str> {
+        match self.invites.write().unwrap().get_mut(token_hash) {
+            Some(invite) if invite.accepted_by.is_none() => {
+                invite.accepted_by = Some(user_id.to_string());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    async fn set_player(
+        &self,
+        game_id: &str,
+        color: Color,
+        user_id: &str,
+    ) -> Result<(), &'static str> {
+        if let Some(game) = self.games.write().unwrap().get_mut(game_id) {
+            game.game.players.set(color, user_id.to_string());
+        }
+        Ok(())
+    }
+    async fn create_user(&self, user: &User) -> Result<(), &'static str> {
+        let mut users = self.users.write().unwrap();
+        if users.contains_key(&user.user_id) {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        users.insert(user.user_id.clone(), user.clone());
+        Ok(())
+    }
+    async fn get_user(&self, id: &str) -> Result<Option<User>, &'static str> {
+        Ok(self.users.read().unwrap().get(id).cloned())
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for MemoryStore {
+    async fn get_user_games(
+        &self,
+        id: &str,
+        include_private: bool,
+        viewer: Option<&str>,
+    ) -> Result<Vec<UserGame>, &'static str> {
+        let games = self.games.read().unwrap();
+        let mut user_games: Vec<(&String, UserGame)> = games
+            .iter()
+            .filter(|(_, game)| !game.deleted && game.is_visible(include_private, viewer))
+            .filter_map(|(game_id, game)| {
+                let color = game.game.players.seat(id)?;
+                let opponent_id = match color {
+                    Color::WHITE => game.game.players.black.clone(),
+                    Color::BLACK => game.game.players.white.clone(),
+                };
+                let user_game = UserGame {
+                    game_id: game_id.clone(),
+                    color: color.to_str(),
+                    opponent_id,
+                    result: game.result.clone(),
+                    termination: game.termination.clone(),
+                    eco: game.eco.clone(),
+                    opening: game.opening.clone(),
+                    created_at: game.created_at.clone(),
+                };
+                Some((&game.created_at, user_game))
+            })
+            .collect();
+        user_games.sort_by(|(a, _), (b, _)| b.cmp(a));
+        Ok(user_games.into_iter().map(|(_, game)| game).collect())
+    }
+    async fn search_games(
+        &self,
+        filter: &GameFilter,
+        newest_first: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(u64, Vec<ArchivedGame>), &'static str> {
+        let games = self.games.read().unwrap();
+        let mut found: Vec<(&String, &StoredGame)> = games
+            .iter()
+            .filter(|(_, game)| !game.deleted)
+            .filter(|(_, game)| match filter.finished {
+                None => true,
+                Some(finished) => game.result.is_some() == finished,
+            })
+            .filter(|(_, game)| match &filter.player {
+                None => true,
+                Some(player) => game.game.players.contains(player),
+            })
+            .filter(|(_, game)| filter.result.is_none() || game.result == filter.result)
+            .filter(|(_, game)| {
+                filter
+                    .variant
+                    .is_none_or(|variant| game.game.variant == variant)
+            })
+            .filter(|(_, game)| match (&filter.eco, &game.eco) {
+                (None, _) => true,
+                (Some(prefix), Some(eco)) => eco.starts_with(prefix.as_str()),
+                (Some(_), None) => false,
+            })
+            .filter(|(_, game)| {
+                filter
+                    .created_from
+                    .as_ref()
+                    .is_none_or(|from| game.created_at >= *from)
+                    && filter
+                        .created_until
+                        .as_ref()
+                        .is_none_or(|until| game.created_at < *until)
+            })
+            .filter(|(_, game)| {
+                filter
+                    .position_hash
+                    .is_none_or(|hash| game.positions.iter().any(|(_, reached)| *reached == hash))
+            })
+            .filter(|(_, game)| game.is_visible(filter.include_private, filter.viewer.as_deref()))
+            .collect();
+        found.sort_by(|(a_id, a), (b_id, b)| {
+            let by_time = match newest_first {
+                true => b.created_at.cmp(&a.created_at),
+                false => a.created_at.cmp(&b.created_at),
+            };
+            by_time.then(a_id.cmp(b_id))
+        });
+
+        let total = found.len() as u64;
+        let page = found
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(game_id, game)| ArchivedGame {
+                game_id: game_id.clone(),
+                white_user_id: game.game.players.white.clone(),
+                black_user_id: game.game.players.black.clone(),
+                result: game.result.clone(),
+                termination: game.termination.clone(),
+                variant: Some(game.game.variant.to_str()),
+                time_control: game.game.time_control.map(|tc| tc.to_str()),
+                rated: Some(game.game.rated as i64),
+                eco: game.eco.clone(),
+                opening: game.opening.clone(),
+                moves: game.moves.len() as u32,
+                created_at: game.created_at.clone(),
+            })
+            .collect();
+        Ok((total, page))
+    }
+    async fn soft_delete_game(&self, id: &str) -> Result<bool, &'static str> {
+        match self.games.write().unwrap().get_mut(id) {
+            Some(game) if !game.deleted => {
+                game.deleted = true;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    async fn delete_game(&self, id: &str) -> Result<bool, &'static str> {
+        let mut games = self.games.write().unwrap();
+        self.invites
+            .write()
+            .unwrap()
+            .retain(|_, invite| invite.game_id != id);
+        Ok(games.remove(id).is_some())
+    }
+    async fn is_rated_game(&self, id: &str) -> Result<bool, &'static str> {
+        Ok(self
+            .rating_history
+            .read()
+            .unwrap()
+            .iter()
+            .any(|(_, entry)| entry.game_id == id))
+    }
+    async fn is_competition_game(&self, id: &str) -> Result<bool, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .is_some_and(|game| game.simul_id.is_some() || game.tournament_id.is_some()))
+    }
+    async fn get_export_page(
+        &self,
+        after: Option<&(String, String)>,
+        limit: u32,
+    ) -> Result<Vec<ExportedGame>, &'static str> {
+        let games = self.games.read().unwrap();
+        let mut page: Vec<(&String, &StoredGame)> = games
+            .iter()
+            .filter(|(_, game)| !game.deleted)
+            .filter(|(game_id, game)| match after {
+                None => true,
+                Some((created_at, after_id)) => {
+                    (&game.created_at, *game_id) > (created_at, after_id)
+                }
+            })
+            .collect();
+        page.sort_by(|(a_id, a), (b_id, b)| (&a.created_at, a_id).cmp(&(&b.created_at, b_id)));
+        Ok(page
+            .into_iter()
+            .take(limit as usize)
+            .map(|(game_id, game)| ExportedGame {
+                game_id: game_id.clone(),
+                admin_color: game.game.admin_color.to_str(),
+                white_user_id: game.game.players.white.clone(),
+                black_user_id: game.game.players.black.clone(),
+                variant: Some(game.game.variant.to_str()),
+                time_control: game.game.time_control.map(|tc| tc.to_str()),
+                days_per_move: game.game.days_per_move,
+                rated: game.game.rated,
+                private: game.game.private,
+                result: game.result.clone(),
+                termination: game.termination.clone(),
+                eco: game.eco.clone(),
+                opening: game.opening.clone(),
+                created_at: game.created_at.clone(),
+                moves: vec![],
+            })
+            .collect())
+    }
+    async fn get_move_ucis(&self, id: &str) -> Result<Vec<Option<String>>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|game| game.moves.iter().map(|m| m.uci.clone()).collect())
+            .unwrap_or_default())
+    }
+    async fn get_result(&self, id: &str) -> Result<Option<String>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .and_then(|game| game.result.clone()))
+    }
+    async fn get_move_times(&self, id: &str) -> Result<Vec<MoveTime>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|game| {
+                game.moves
+                    .iter()
+                    .map(|m| MoveTime {
+                        ply: m.played.ply,
+                        player: m.played.player.clone(),
+                        time_spent_ms: m.time_spent_ms,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+    async fn import_game(
+        &self,
+        game: &ExportedGame,
+        start_hash: u64,
+        moves: &[ReplayedMove],
+    ) -> Result<bool, &'static str> {
+        let mut games = self.games.write().unwrap();
+        if games.contains_key(&game.game_id) {
+            return Ok(false);
+        }
+        let mut stored = StoredGame::new(&game.setup()?, game.created_at.clone());
+        stored.positions = vec![(0, start_hash)];
+        for (seq, played) in (1..).zip(moves) {
+            stored.moves.push(StoredMove {
+                played: Move {
+                    ply: played.ply,
+                    move_notation: played.notation.clone(),
+                    turn: played.turn,
+                    player: played.player.to_string(),
+                },
+                uci: played.event.uci(),
+                time_spent_ms: None,
+                created_at: game.created_at.clone(),
+            });
+            stored.log(Some(seq), &played.event)?;
+            stored.positions.push((played.ply, played.position_hash));
+            if let Some(fen) = &played.snapshot {
+                stored.snapshots.push(Snapshot {
+                    seq,
+                    ply: played.ply,
+                    fen: fen.clone(),
+                    eco: played.opening.map(|opening| opening.eco.to_string()),
+                    opening: played.opening.map(|opening| opening.name.to_string()),
+                });
+            }
+        }
+        // results that were not reached on the board, like a resignation
+        if let Some(result) = &game.result {
+            let event = GameEvent::Finish {
+                result: result.clone(),
+                termination: game.termination.clone().unwrap_or_default(),
+            };
+            stored.log(None, &event)?;
+        }
+        stored.result = game.result.clone();
+        stored.termination = game.termination.clone();
+        stored.set_opening(moves.last().and_then(|last| last.opening));
+        games.insert(game.game_id.clone(), stored);
+        Ok(true)
+    }
+    async fn get_stale_games(&self, before: &str) -> Result<Vec<StaleGame>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, game)| game.is_running() && game.game.days_per_move.is_none())
+            .filter(|(_, game)| game.game.players != Players::default())
+            .filter(|(_, game)| {
+                let last_moved_at = game.last_move().map(|last| &last.created_at);
+                last_moved_at.unwrap_or(&game.created_at).as_str() < before
+            })
+            .map(|(game_id, game)| StaleGame {
+                game_id: game_id.clone(),
+                moves: game.moves.len() as u32,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ExplorerStore for MemoryStore {
+    async fn get_explorer_moves(
+        &self,
+        _position_hash: u64,
+    ) -> Result<Vec<ExplorerMove>, &'static str> {
+        Ok(vec![])
+    }
+    async fn get_unexplored_games(&self) -> Result<Vec<(String, String)>, &'static str> {
+        Ok(vec![])
+    }
+    async fn save_positions(
+        &self,
+        id: &str,
+        positions: &[(u32, u64)],
+        moves: &[(u32, String)],
+    ) -> Result<(), &'static str> {
+        if let Some(game) = self.games.write().unwrap().get_mut(id) {
+            game.positions = positions.to_vec();
+            for (ply, uci) in moves {
+                if let Some(m) = game.moves.iter_mut().find(|m| m.played.ply == *ply) {
+                    m.uci = Some(uci.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CorrespondenceStore for MemoryStore {
+    async fn get_correspondence_games(&self) -> Result<Vec<CorrespondenceGame>, &'static str> {
+        Ok(self
+            .games
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, game)| game.is_running())
+            .filter_map(|(game_id, game)| {
+                Some(CorrespondenceGame {
+                    game_id: game_id.clone(),
+                    days_per_move: game.game.days_per_move?,
+                    white_user_id: game.game.players.white.clone(),
+                    black_user_id: game.game.players.black.clone(),
+                    created_at: game.created_at.clone(),
+                    last_moved: game.last_move().map(|last| last.played.player.clone()),
+                    last_moved_at: game.last_move().map(|last| last.created_at.clone()),
+                })
+            })
+            .collect())
+    }
+    async fn get_awaiting_games(&self, user_id: &str) -> Result<Vec<AwaitingGame>, &'static str> {
+        let games = self.games.read().unwrap();
+        let mut awaiting: Vec<AwaitingGame> = games
+            .iter()
+            .filter(|(_, game)| game.is_running())
+            .filter_map(|(game_id, game)| {
+                let color = game.game.players.seat(user_id)?;
+                let last_moved = game.last_move().map(|last| last.played.player.as_str());
+                let to_move = match last_moved {
+                    Some("WHITE") => Color::BLACK,
+                    _ => Color::WHITE,
+                };
+                if color != to_move {
+                    return None;
+                }
+                Some(AwaitingGame {
+                    game_id: game_id.clone(),
+                    color: color.to_str(),
+                    opponent_id: match color {
+                        Color::WHITE => game.game.players.black.clone(),
+                        Color::BLACK => game.game.players.white.clone(),
+                    },
+                    days_per_move: game.game.days_per_move,
+                    created_at: game.created_at.clone(),
+                    last_moved_at: game.last_move().map(|last| last.created_at.clone()),
+                })
+            })
+            .collect();
+        awaiting.sort_by(|a, b| {
+            let since = |game: &AwaitingGame| {
+                game.last_moved_at
+                    .clone()
+                    .unwrap_or_else(|| game.created_at.clone())
+            };
+            since(a).cmp(&since(b))
+        });
+        Ok(awaiting)
+    }
+    async fn add_vacation(
+        &self,
+        user_id: &str,
+        starts_at: &str,
+        ends_at: &str,
+    ) -> Result<(), &'static str> {
+        self.vacations
+            .write()
+            .unwrap()
+            .entry(user_id.to_string())
+            .or_default()
+            .push((starts_at.to_string(), ends_at.to_string()));
+        Ok(())
+    }
+    async fn get_vacations(&self, user_id: &str) -> Result<Vec<Vacation>, &'static str> {
+        let mut vacations: Vec<Vacation> = self
+            .vacations
+            .read()
+            .unwrap()
+            .get(user_id)
+            .map(|vacations| {
+                vacations
+                    .iter()
+                    .filter_map(|(starts_at, ends_at)| {
+                        Some(Vacation {
+                            starts_at: parse_timestamp(starts_at)?,
+                            ends_at: parse_timestamp(ends_at)?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        vacations.sort_by_key(|vacation| vacation.starts_at);
+        Ok(vacations)
+    }
+}
+
+#[async_trait]
+impl CompetitionStore for MemoryStore {
+    async fn create_simul(
+        &self,
+        id: &str,
+        _host_name: &str,
+        _walking_order: bool,
+        _host_time_budget_secs: Option<u64>,
+        game_ids: &[String],
+    ) -> Result<(), &'static str> {
+        let mut games = self.games.write().unwrap();
+        for game_id in game_ids {
+            if let Some(game) = games.get_mut(game_id) {
+                game.simul_id = Some(id.to_string());
+            }
+        }
+        Ok(())
+    }
+    async fn create_tournament(
+        &self,
+        _id: &str,
+        _name: &str,
+        _format: &str,
+        _rounds: u32,
+        _players: &[String],
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+    async fn insert_pairing(
+        &self,
+        tournament_id: &str,
+        _round: u32,
+        _white_user_id: &str,
+        _black_user_id: Option<&str>,
+        game_id: Option<&str>,
+        _result: Option<String>,
+    ) -> Result<(), &'static str> {
+        let mut games = self.games.write().unwrap();
+        if let Some(game) = game_id.and_then(|game_id| games.get_mut(game_id)) {
+            game.tournament_id = Some(tournament_id.to_string());
+        }
+        Ok(())
+    }
+    async fn create_arena(
+        &self,
+        _id: &str,
+        _name: &str,
+        _time_control: &str,
+        _duration_secs: u64,
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+    async fn insert_arena_game(&self, _arena_id: &str, _game_id: &str) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountStore for MemoryStore {
+    async fn create_api_key(
+        &self,
+        id: &str,
+        label: &str,
+        key_hash: &str,
+        role: &str,
+    ) -> Result<(), &'static str> {
+        let mut keys = self.api_keys.write().unwrap();
+        if keys
+            .iter()
+            .any(|(hash, key)| hash == key_hash || key.key_id == id)
+        {
+            return Err(INTERNAL_SERVER_ERROR);
+        }
+        keys.push((
+            key_hash.to_string(),
+            ApiKey {
+                key_id: id.to_string(),
+                label: label.to_string(),
+                role: role.to_string(),
+                created_at: format_timestamp(OffsetDateTime::now_utc()),
+                revoked_at: None,
+            },
+        ));
+        Ok(())
+    }
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, &'static str> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(hash, _)| hash == key_hash)
+            .map(|(_, key)| key.clone()))
+    }
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, &'static str> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+    async fn revoke_api_key(&self, id: &str) -> Result<bool, &'static str> {
+        let mut keys = self.api_keys.write().unwrap();
+        match keys.iter_mut().find(|(_, key)| key.key_id == id) {
+            Some((_, key)) if key.revoked_at.is_none() => {
+                key.revoked_at = Some(format_timestamp(OffsetDateTime::now_utc()));
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    async fn get_rating_history(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<RatingHistoryEntry>, &'static str> {
+        Ok(self
+            .rating_history
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| id == user_id)
+            .map(|(_, entry)| entry.clone())
+            .collect())
+    }
+    async fn get_leaderboard(
+        &self,
+        pool: RatingPool,
+        max_deviation: f64,
+        limit: u32,
+    ) -> Result<Vec<LeaderboardEntry>, &'static str> {
+        let users = self.users.read().unwrap();
+        let mut leaderboard: Vec<LeaderboardEntry> = self
+            .ratings
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|((_, rated_pool), (rating, _))| {
+                *rated_pool == pool.to_str() && rating.deviation <= max_deviation
+            })
+            .filter_map(|((user_id, _), (rating, games))| {
+                Some(LeaderboardEntry {
+                    user_id: user_id.clone(),
+                    display_name: users.get(user_id)?.display_name.clone(),
+                    rating: rating.rating,
+                    deviation: rating.deviation,
+                    games: *games,
+                })
+            })
+            .collect();
+        leaderboard.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        leaderboard.truncate(limit as usize);
+        Ok(leaderboard)
+    }
+}
+
+#[cfg(test)]
+mod test_store {
+    use uuid::Uuid;
+
+    use crate::{
+        db::{GameFilter, DB},
+        game::{chess_piece::Color, eco::Opening, event::GameEvent, Game, GameResult},
+        rating::{RatedGame, Rating, RatingPool},
+        user::{User, UserRole},
+    };
+
+    use super::{MemoryStore, NewMove, Store};
+
+    fn event(from: &str, to: &str) -> GameEvent {
+        GameEvent::Move {
+            from: from.to_string(),
+            to: to.to_string(),
+            promotion: ' ',
+        }
+    }
+
+    fn new_move<'a>(
+        ply: u32,
+        notation: &'a str,
+        player: &'a str,
+        event: &'a GameEvent,
+    ) -> NewMove<'a> {
+        NewMove {
+            ply,
+            turn: ply.div_ceil(2),
+            move_notation: notation,
+            player,
+            finished: None,
+            seq: ply as u64,
+            event,
+            snapshot: None,
+            position_hash: ply as u64,
+            opening: None,
+            time_spent_ms: None,
+        }
+    }
+
+    // every implementation has to behave the same
+    async fn check_store(store: &dyn Store) {
+        let game = Game::new(Uuid::new_v4(), Color::BLACK);
+        let id = game.id.to_string();
+        store.create_game(&game).await.unwrap();
+        let stored = store.get_game(&id).await.unwrap().unwrap();
+        assert_eq!(stored.admin_color, Color::BLACK);
+
+        let (e4, e5) = (event("e2", "e4"), event("e7", "e5"));
+        store
+            .insert_move(&id, new_move(1, "e4", "WHITE", &e4))
+            .await
+            .unwrap();
+        let with_snapshot = NewMove {
+            snapshot: Some("fen"),
+            opening: Opening::find("C20", "King's Pawn Game"),
+            ..new_move(2, "e5", "BLACK", &e5)
+        };
+        store.insert_move(&id, with_snapshot).await.unwrap();
+        // a ply can only be saved once, the move and the result are rejected together
+        let d5 = event("d7", "d5");
+        let duplicate = NewMove {
+            finished: Some(("WHITE", "checkmate")),
+            seq: 3,
+            ..new_move(2, "d5", "BLACK", &d5)
+        };
+        assert!(store.insert_move(&id, duplicate).await.is_err());
+        let moves = store.get_moves(&id).await.unwrap();
+        assert_eq!(
+            moves
+                .iter()
+                .map(|m| m.move_notation.as_str())
+                .collect::<Vec<_>>(),
+            vec!["e4", "e5"]
+        );
+
+        let active = store.get_active_games().await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].game_id, id);
+        assert_eq!(active[0].last_moved.as_deref(), Some("BLACK"));
+
+        // events without a sequence number are appended at the end
+        let flag = GameEvent::Flag {
+            player: "white".to_string(),
+        };
+        assert_eq!(store.append_event(&id, None, &flag).await, Ok(3));
+        assert!(store.append_event(&id, Some(3), &flag).await.is_err());
+        let events = store.get_events(&id, 1).await.unwrap();
+        assert_eq!(
+            events.iter().map(|logged| logged.seq).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(events[0].event, e5);
+
+        let snapshot = store.get_snapshot(&id, None).await.unwrap().unwrap();
+        assert_eq!((snapshot.seq, snapshot.ply), (2, 2));
+        assert_eq!(snapshot.eco.as_deref(), Some("C20"));
+        assert!(store.get_snapshot(&id, Some(1)).await.unwrap().is_none());
+        store.delete_snapshots(&id, 1).await.unwrap();
+        assert!(store.get_snapshot(&id, None).await.unwrap().is_none());
+
+        let qh5 = event("d1", "h5");
+        let mate = NewMove {
+            finished: Some(("WHITE", "checkmate")),
+            seq: 4,
+            ..new_move(3, "Qh5", "WHITE", &qh5)
+        };
+        store.insert_move(&id, mate).await.unwrap();
+        assert!(store.get_active_games().await.unwrap().is_empty());
+        // the result is only stored once
+        assert!(!store.finish_game("0-1", "resignation", &id).await.unwrap());
+        assert_eq!(store.get_moves(&id).await.unwrap()[2].ply, 3);
+        store.delete_last_moves(&id, 2, None).await.unwrap();
+        assert_eq!(store.get_moves(&id).await.unwrap().len(), 1);
+
+        store
+            .insert_chat(&id, "players", "alice", "gg")
+            .await
+            .unwrap();
+        let chat = store.get_chat(&id).await.unwrap();
+        assert_eq!(chat.len(), 1);
+        assert_eq!(
+            (chat[0].author.as_str(), chat[0].message.as_str()),
+            ("alice", "gg")
+        );
+
+        let user = User {
+            user_id: "bob".to_string(),
+            display_name: "Bob".to_string(),
+            email: "bob@example.com".to_string(),
+            role: UserRole::Player.to_str(),
+        };
+        store.create_user(&user).await.unwrap();
+        assert!(store.create_user(&user).await.is_err());
+        assert_eq!(
+            store.get_user("bob").await.unwrap().unwrap().email,
+            user.email
+        );
+
+        let alice = User {
+            user_id: "alice".to_string(),
+            display_name: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            ..user
+        };
+        store.create_user(&alice).await.unwrap();
+        let pool = RatingPool::of(&game);
+        assert!(store.get_rating("bob", pool).await.unwrap().is_none());
+        let rated = RatedGame {
+            game_id: id.clone(),
+            white: "alice".to_string(),
+            black: "bob".to_string(),
+            result: GameResult::WhiteWon,
+            pool,
+        };
+        let [(_, alice), (before, bob)] = store.rate_game(&rated).await.unwrap().unwrap();
+        assert_eq!(before, Rating::default());
+        assert!(alice.rating > bob.rating);
+        assert_eq!(store.get_rating("bob", pool).await.unwrap(), Some(bob));
+        // a game is only rated once
+        assert_eq!(store.rate_game(&rated).await, Ok(None));
+        assert_eq!(store.get_rating("bob", pool).await.unwrap(), Some(bob));
+        // the next game starts from the saved ratings
+        let rematch = Game::new(Uuid::new_v4(), Color::WHITE);
+        store.create_game(&rematch).await.unwrap();
+        let rated_rematch = RatedGame {
+            game_id: rematch.id.to_string(),
+            ..rated
+        };
+        let [(before, _), _] = store.rate_game(&rated_rematch).await.unwrap().unwrap();
+        assert_eq!(before, alice);
+
+        // an invite can only be accepted once
+        store
+            .create_invite("hash", &id, "alice", "BLACK", None, None)
+            .await
+            .unwrap();
+        assert!(store.accept_invite("hash", "bob").await.unwrap());
+        assert!(!store.accept_invite("hash", "carol").await.unwrap());
+        let invite = store.get_invite("hash").await.unwrap().unwrap();
+        assert_eq!(invite.accepted_by.as_deref(), Some("bob"));
+        store.set_player(&id, Color::BLACK, "bob").await.unwrap();
+        let stored = store.get_game(&id).await.unwrap().unwrap();
+        assert_eq!(stored.players.black.as_deref(), Some("bob"));
+
+        let by_bob = GameFilter {
+            player: Some("bob".to_string()),
+            ..GameFilter::default()
+        };
+        let (total, games) = store.search_games(&by_bob, true, 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(games[0].game_id, id);
+        let user_games = store.get_user_games("bob", false, None).await.unwrap();
+        assert_eq!(user_games.len(), 1);
+        assert_eq!(user_games[0].color, "black");
+
+        assert!(store.is_rated_game(&id).await.unwrap());
+        assert_eq!(store.get_rating_history("bob").await.unwrap().len(), 2);
+        let leaderboard = store.get_leaderboard(pool, 350.0, 10).await.unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|entry| entry.display_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Alice", "Bob"]
+        );
+        assert_eq!(leaderboard[0].games, 2);
+
+        store
+            .create_api_key("key", "ci", "key hash", "admin")
+            .await
+            .unwrap();
+        let key = store.get_api_key("key hash").await.unwrap().unwrap();
+        assert_eq!(key.role(), UserRole::Admin);
+        assert!(store.revoke_api_key("key").await.unwrap());
+        assert!(!store.revoke_api_key("key").await.unwrap());
+        assert!(store.get_api_keys().await.unwrap()[0].revoked_at.is_some());
+
+        let unrated = Game::new(Uuid::new_v4(), Color::WHITE);
+        let unrated_id = unrated.id.to_string();
+        store.create_game(&unrated).await.unwrap();
+        assert!(!store.is_competition_game(&unrated_id).await.unwrap());
+        assert!(store.delete_game(&unrated_id).await.unwrap());
+        assert!(store.get_game(&unrated_id).await.unwrap().is_none());
+        assert!(!store.delete_game(&unrated_id).await.unwrap());
+
+        let simul_game = Game::new(Uuid::new_v4(), Color::WHITE);
+        let simul_id = simul_game.id.to_string();
+        store.create_game(&simul_game).await.unwrap();
+        store
+            .create_simul("simul", "alice", false, None, &[simul_id.clone()])
+            .await
+            .unwrap();
+        assert!(store.is_competition_game(&simul_id).await.unwrap());
+        let tournament_game = Game::new(Uuid::new_v4(), Color::WHITE);
+        let tournament_id = tournament_game.id.to_string();
+        store.create_game(&tournament_game).await.unwrap();
+        store
+            .create_tournament("tournament", "Open", "swiss", 1, &[])
+            .await
+            .unwrap();
+        store
+            .insert_pairing("tournament", 1, "alice", None, None, None)
+            .await
+            .unwrap();
+        assert!(!store.is_competition_game(&tournament_id).await.unwrap());
+        store
+            .insert_pairing(
+                "tournament",
+                1,
+                "alice",
+                Some("bob"),
+                Some(&tournament_id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(store.is_competition_game(&tournament_id).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+
+    #[actix_web::test]
+    async fn test_local_store() {
+        check_store(&DB::local(":memory:").await).await;
+    }
+}
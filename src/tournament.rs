@@ -0,0 +1,593 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::game::{chess_piece::Color, time_control::TimeControl, GameResult, Variant};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    RoundRobin,
+    Swiss,
+}
+impl TournamentFormat {
+    pub fn to_str(&self) -> String {
+        match self {
+            TournamentFormat::RoundRobin => "round_robin".to_string(),
+            TournamentFormat::Swiss => "swiss".to_string(),
+        }
+    }
+}
+
+/// One board of a round. A pairing without black is a bye, which counts as a win.
+#[derive(Serialize, Clone, Debug)]
+pub struct Pairing {
+    pub round: u32,
+    pub white: String,
+    pub black: Option<String>,
+    pub game_id: Option<String>,
+    #[serde(serialize_with = "serialize_result")]
+    pub result: Option<GameResult>,
+}
+impl Pairing {
+    fn score_of(&self, player: &str) -> Option<f64> {
+        let white_score = match self.result? {
+            GameResult::WhiteWon => 1.0,
+            GameResult::BlackWon => 0.0,
+            GameResult::Draw => 0.5,
+        };
+        if self.white == player {
+            Some(white_score)
+        } else if self.black.as_deref() == Some(player) {
+            Some(1.0 - white_score)
+        } else {
+            None
+        }
+    }
+    fn opponent_of(&self, player: &str) -> Option<&str> {
+        if self.white == player {
+            self.black.as_deref()
+        } else if self.black.as_deref() == Some(player) {
+            Some(&self.white)
+        } else {
+            None
+        }
+    }
+}
+
+fn serialize_result<S: serde::Serializer>(
+    result: &Option<GameResult>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match result {
+        None => serializer.serialize_none(),
+        Some(result) => serializer.serialize_some(&result.to_str()),
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TournamentStatus {
+    pub tournament_id: String,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub rounds: u32,
+    pub current_round: u32,
+    pub finished: bool,
+    pub pairings: Vec<Pairing>,
+    pub standings: Vec<Standing>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub user_id: String,
+    pub points: f64,
+    pub buchholz: f64,
+    pub sonneborn_berger: f64,
+}
+
+pub struct Tournament {
+    pub id: Uuid,
+    pub name: String,
+    pub format: TournamentFormat,
+    // user ids in seeding order, strongest first
+    pub players: Vec<String>,
+    pub rounds: u32,
+    pub current_round: u32,
+    pub pairings: Vec<Pairing>,
+    pub time_control: Option<TimeControl>,
+    pub variant: Variant,
+    pub rated: bool,
+}
+
+impl Tournament {
+    pub fn new(
+        id: Uuid,
+        name: String,
+        format: TournamentFormat,
+        players: Vec<String>,
+        rounds: Option<u32>,
+    ) -> Tournament {
+        let rounds = match format {
+            // everyone plays everyone, with a bye each round for an odd field
+            TournamentFormat::RoundRobin => (players.len() + players.len() % 2 - 1) as u32,
+            // enough rounds to leave a single player with a perfect score
+            TournamentFormat::Swiss => {
+                rounds.unwrap_or_else(|| (players.len() as f64).log2().ceil().max(1.0) as u32)
+            }
+        };
+
+        Tournament {
+            id,
+            name,
+            format,
+            players,
+            rounds,
+            current_round: 0,
+            pairings: vec![],
+            time_control: None,
+            variant: Variant::Standard,
+            rated: true,
+        }
+    }
+    pub fn status(&self) -> TournamentStatus {
+        TournamentStatus {
+            tournament_id: self.id.to_string(),
+            name: self.name.clone(),
+            format: self.format,
+            rounds: self.rounds,
+            current_round: self.current_round,
+            finished: self.is_finished(),
+            pairings: self.pairings.clone(),
+            standings: self.standings(),
+        }
+    }
+    pub fn is_finished(&self) -> bool {
+        self.current_round == self.rounds && self.round_finished()
+    }
+    pub fn round_finished(&self) -> bool {
+        self.pairings
+            .iter()
+            .filter(|pairing| pairing.round == self.current_round)
+            .all(|pairing| pairing.result.is_some())
+    }
+    pub fn contains_game(&self, game_id: &str) -> bool {
+        self.pairings
+            .iter()
+            .any(|pairing| pairing.game_id.as_deref() == Some(game_id))
+    }
+    /// Stores the result of a tournament game and returns whether the current
+    /// round is complete with it.
+    pub fn record_result(&mut self, game_id: &str, result: GameResult) -> bool {
+        if let Some(pairing) = self
+            .pairings
+            .iter_mut()
+            .find(|pairing| pairing.game_id.as_deref() == Some(game_id))
+        {
+            pairing.result = Some(result);
+        }
+        self.round_finished()
+    }
+    /// Pairs the next round and returns its pairings. Byes already carry their
+    /// result, every other pairing still needs a game.
+    pub fn pair_next_round(&mut self) -> Vec<Pairing> {
+        self.current_round += 1;
+        let round = self.current_round;
+
+        let boards = match self.format {
+            TournamentFormat::RoundRobin => round_robin_round(&self.players, round),
+            TournamentFormat::Swiss => self.swiss_round(),
+        };
+        let pairings: Vec<Pairing> = boards
+            .into_iter()
+            .map(|(white, black)| Pairing {
+                round,
+                result: black.is_none().then_some(GameResult::WhiteWon),
+                white,
+                black,
+                game_id: None,
+            })
+            .collect();
+
+        self.pairings.extend(pairings.iter().cloned());
+        pairings
+    }
+    pub fn set_game(&mut self, round: u32, white: &str, game_id: String) {
+        if let Some(pairing) = self
+            .pairings
+            .iter_mut()
+            .find(|pairing| pairing.round == round && pairing.white == white)
+        {
+            pairing.game_id = Some(game_id);
+        }
+    }
+    fn points(&self, player: &str) -> f64 {
+        self.pairings
+            .iter()
+            .filter_map(|pairing| pairing.score_of(player))
+            .sum()
+    }
+    /// Standings by points, then Buchholz, then Sonneborn-Berger, then seed.
+    pub fn standings(&self) -> Vec<Standing> {
+        let points: HashMap<&str, f64> = self
+            .players
+            .iter()
+            .map(|player| (player.as_str(), self.points(player)))
+            .collect();
+
+        let mut standings: Vec<(usize, Standing)> = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seed, player)| {
+                let mut buchholz = 0.0;
+                let mut sonneborn_berger = 0.0;
+                for pairing in &self.pairings {
+                    let (opponent, score) =
+                        match (pairing.opponent_of(player), pairing.score_of(player)) {
+                            (Some(opponent), Some(score)) => (opponent, score),
+                            _ => continue,
+                        };
+                    let opponent_points = points.get(opponent).copied().unwrap_or_default();
+                    buchholz += opponent_points;
+                    sonneborn_berger += score * opponent_points;
+                }
+
+                let standing = Standing {
+                    user_id: player.clone(),
+                    points: points[player.as_str()],
+                    buchholz,
+                    sonneborn_berger,
+                };
+                (seed, standing)
+            })
+            .collect();
+
+        standings.sort_by(|(seed_a, a), (seed_b, b)| {
+            b.points
+                .total_cmp(&a.points)
+                .then(b.buchholz.total_cmp(&a.buchholz))
+                .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+                .then(seed_a.cmp(seed_b))
+        });
+        standings
+            .into_iter()
+            .map(|(_, standing)| standing)
+            .collect()
+    }
+    /// Dutch system: players are ranked by score and seed, and within every score
+    /// group the top half plays the bottom half. Rematches are avoided by trying
+    /// the next best opponent, floating players down to the next score group if needed.
+    fn swiss_round(&self) -> Vec<(String, Option<String>)> {
+        let mut ranked: Vec<(usize, &String, f64)> = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seed, player)| (seed, player, self.points(player)))
+            .collect();
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+
+        let mut boards = vec![];
+        if ranked.len() % 2 == 1 {
+            // the lowest ranked player who did not have a bye yet sits out
+            let bye = ranked
+                .iter()
+                .rposition(|(_, player, _)| !self.had_bye(player))
+                .unwrap_or(ranked.len() - 1);
+            let (_, player, _) = ranked.remove(bye);
+            boards.push((player.clone(), None));
+        }
+
+        let played: HashSet<(&str, &str)> = self
+            .pairings
+            .iter()
+            .filter_map(|pairing| Some((pairing.white.as_str(), pairing.black.as_deref()?)))
+            .flat_map(|(white, black)| [(white, black), (black, white)])
+            .collect();
+        let ranked: Vec<(&str, f64)> = ranked
+            .iter()
+            .map(|(_, player, points)| (player.as_str(), *points))
+            .collect();
+
+        // without any way around it, rematches are better than not pairing at all
+        let pairs = pair_dutch(&ranked, &played)
+            .unwrap_or_else(|| pair_dutch(&ranked, &HashSet::new()).unwrap_or_default());
+        for (higher, lower) in pairs {
+            let (white, black) = match self.color_for(higher, lower) {
+                Color::WHITE => (higher, lower),
+                Color::BLACK => (lower, higher),
+            };
+            boards.push((white.to_string(), Some(black.to_string())));
+        }
+        boards
+    }
+    fn had_bye(&self, player: &str) -> bool {
+        self.pairings
+            .iter()
+            .any(|pairing| pairing.white == player && pairing.black.is_none())
+    }
+    /// The color the higher ranked player of a pairing gets: whoever played white
+    /// less often gets white, otherwise the higher ranked player alternates.
+    fn color_for(&self, higher: &str, lower: &str) -> Color {
+        let balance = |player: &str| -> i32 {
+            self.pairings
+                .iter()
+                .filter(|pairing| pairing.black.is_some())
+                .map(|pairing| {
+                    if pairing.white == player {
+                        1
+                    } else if pairing.black.as_deref() == Some(player) {
+                        -1
+                    } else {
+                        0
+                    }
+                })
+                .sum()
+        };
+
+        match balance(higher).cmp(&balance(lower)) {
+            std::cmp::Ordering::Less => Color::WHITE,
+            std::cmp::Ordering::Greater => Color::BLACK,
+            std::cmp::Ordering::Equal => {
+                let last_white = self
+                    .pairings
+                    .iter()
+                    .rev()
+                    .filter(|pairing| pairing.black.is_some())
+                    .find_map(|pairing| {
+                        pairing.opponent_of(higher).map(|_| pairing.white == higher)
+                    });
+                match last_white {
+                    Some(true) => Color::BLACK,
+                    _ => Color::WHITE,
+                }
+            }
+        }
+    }
+}
+
+/// Pairs `ranked` (best first) top half against bottom half within each score
+/// group, backtracking whenever that would lead to a rematch.
+fn pair_dutch<'a>(
+    ranked: &[(&'a str, f64)],
+    played: &HashSet<(&str, &str)>,
+) -> Option<Vec<(&'a str, &'a str)>> {
+    let (player, points) = match ranked.first() {
+        None => return Some(vec![]),
+        Some(first) => *first,
+    };
+
+    // within the score group the preferred opponent is the top of the bottom
+    // half, then the rest of the bottom half, then the top half from below,
+    // and only then players from lower score groups
+    let group_size = ranked.iter().filter(|(_, p)| *p == points).count();
+    let half = group_size / 2;
+    let candidates = (half.max(1)..group_size)
+        .chain((1..half.max(1)).rev())
+        .chain(group_size..ranked.len());
+
+    for candidate in candidates {
+        let opponent = ranked[candidate].0;
+        if played.contains(&(player, opponent)) {
+            continue;
+        }
+
+        let rest: Vec<(&str, f64)> = ranked
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0 && *i != candidate)
+            .map(|(_, entry)| *entry)
+            .collect();
+        if let Some(mut pairs) = pair_dutch(&rest, played) {
+            pairs.insert(0, (player, opponent));
+            return Some(pairs);
+        }
+    }
+
+    None
+}
+
+/// Round `round` (starting at 1) of the circle method: the first player stays
+/// put while everyone else rotates one seat per round.
+fn round_robin_round(players: &[String], round: u32) -> Vec<(String, Option<String>)> {
+    let mut seats: Vec<Option<&String>> = players.iter().map(Some).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+    if seats.len() < 2 {
+        return vec![];
+    }
+    let n = seats.len();
+    seats[1..].rotate_right((round as usize - 1) % (n - 1));
+
+    (0..n / 2)
+        .filter_map(|board| {
+            let (a, b) = (seats[board], seats[n - 1 - board]);
+            // alternate colors between rounds, and between boards within a round
+            let (white, black) = if (board + round as usize) % 2 == 1 {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            match (white, black) {
+                (Some(white), Some(black)) => Some((white.clone(), Some(black.clone()))),
+                (Some(player), None) | (None, Some(player)) => Some((player.clone(), None)),
+                (None, None) => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_tournament {
+    use std::collections::HashSet;
+
+    use uuid::Uuid;
+
+    use crate::game::GameResult;
+
+    use super::{Tournament, TournamentFormat};
+
+    fn players(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("p{}", i)).collect()
+    }
+
+    fn play_round(tournament: &mut Tournament, result: impl Fn(&str, &str) -> GameResult) {
+        let pairings = tournament.pair_next_round();
+        for (board, pairing) in pairings.iter().enumerate() {
+            if let Some(black) = &pairing.black {
+                let game_id = format!("{}-{}", pairing.round, board);
+                tournament.set_game(pairing.round, &pairing.white, game_id.clone());
+                tournament.record_result(&game_id, result(&pairing.white, black));
+            }
+        }
+        assert!(tournament.round_finished());
+    }
+
+    fn opponents(tournament: &Tournament) -> Vec<(String, String)> {
+        tournament
+            .pairings
+            .iter()
+            .filter_map(|pairing| Some((pairing.white.clone(), pairing.black.clone()?)))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_everyone_meets_once() {
+        for count in [4, 5] {
+            let mut tournament = Tournament::new(
+                Uuid::new_v4(),
+                "club".to_string(),
+                TournamentFormat::RoundRobin,
+                players(count),
+                None,
+            );
+            assert_eq!(tournament.rounds as usize, count + count % 2 - 1);
+
+            for _ in 0..tournament.rounds {
+                play_round(&mut tournament, |_, _| GameResult::Draw);
+            }
+            assert!(tournament.is_finished());
+
+            let games = opponents(&tournament);
+            let unique: HashSet<(String, String)> = games
+                .iter()
+                .map(|(a, b)| {
+                    if a < b {
+                        (a.clone(), b.clone())
+                    } else {
+                        (b.clone(), a.clone())
+                    }
+                })
+                .collect();
+            assert_eq!(games.len(), count * (count - 1) / 2);
+            assert_eq!(unique.len(), games.len());
+        }
+    }
+
+    #[test]
+    fn test_swiss_avoids_rematches() {
+        let mut tournament = Tournament::new(
+            Uuid::new_v4(),
+            "open".to_string(),
+            TournamentFormat::Swiss,
+            players(7),
+            Some(4),
+        );
+
+        // the better seed always wins
+        for _ in 0..4 {
+            play_round(&mut tournament, |white, black| {
+                if white < black {
+                    GameResult::WhiteWon
+                } else {
+                    GameResult::BlackWon
+                }
+            });
+        }
+
+        let games = opponents(&tournament);
+        let unique: HashSet<(String, String)> = games
+            .iter()
+            .map(|(a, b)| {
+                if a < b {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                }
+            })
+            .collect();
+        assert_eq!(unique.len(), games.len());
+
+        // nobody gets a second bye while others have not had one
+        let byes: Vec<&String> = tournament
+            .pairings
+            .iter()
+            .filter(|pairing| pairing.black.is_none())
+            .map(|pairing| &pairing.white)
+            .collect();
+        assert_eq!(byes.iter().collect::<HashSet<_>>().len(), byes.len());
+        assert_eq!(tournament.standings()[0].user_id, "p0");
+    }
+
+    #[test]
+    fn test_swiss_first_round_pairs_halves() {
+        let mut tournament = Tournament::new(
+            Uuid::new_v4(),
+            "open".to_string(),
+            TournamentFormat::Swiss,
+            players(4),
+            None,
+        );
+        let pairings = tournament.pair_next_round();
+
+        let mut boards: Vec<(String, String)> = pairings
+            .iter()
+            .map(|pairing| {
+                let black = pairing.black.clone().unwrap();
+                if pairing.white < black {
+                    (pairing.white.clone(), black)
+                } else {
+                    (black, pairing.white.clone())
+                }
+            })
+            .collect();
+        boards.sort();
+        assert_eq!(
+            boards,
+            vec![
+                ("p0".to_string(), "p2".to_string()),
+                ("p1".to_string(), "p3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tiebreaks() {
+        let mut tournament = Tournament::new(
+            Uuid::new_v4(),
+            "club".to_string(),
+            TournamentFormat::RoundRobin,
+            players(3),
+            None,
+        );
+        // p0 beats everyone, p1 and p2 draw
+        for _ in 0..tournament.rounds {
+            play_round(&mut tournament, |white, black| match (white, black) {
+                ("p0", _) => GameResult::WhiteWon,
+                (_, "p0") => GameResult::BlackWon,
+                _ => GameResult::Draw,
+            });
+        }
+
+        let standings = tournament.standings();
+        // byes count as points, but not towards tiebreaks
+        assert_eq!(standings[0].user_id, "p0");
+        assert_eq!(standings[0].points, 3.0);
+        assert_eq!(standings[0].buchholz, 3.0);
+        assert_eq!(standings[0].sonneborn_berger, 3.0);
+        assert_eq!(standings[1].points, 1.5);
+        assert_eq!(standings[1].buchholz, 4.5);
+        assert_eq!(standings[1].sonneborn_berger, 0.75);
+    }
+}
@@ -0,0 +1,57 @@
+use std::env;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    Admin,
+    Player,
+    Viewer,
+    Bot,
+}
+impl UserRole {
+    pub fn to_str(&self) -> String {
+        match self {
+            UserRole::Admin => "admin".to_string(),
+            UserRole::Player => "player".to_string(),
+            UserRole::Viewer => "viewer".to_string(),
+            UserRole::Bot => "bot".to_string(),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<UserRole> {
+        match name {
+            "admin" => Some(UserRole::Admin),
+            "player" => Some(UserRole::Player),
+            "viewer" => Some(UserRole::Viewer),
+            "bot" => Some(UserRole::Bot),
+            _ => None,
+        }
+    }
+    /// New accounts are admins if their email is in the comma separated
+    /// ADMIN_EMAILS env var, and players otherwise.
+    pub fn for_email(email: &str) -> UserRole {
+        let admin_emails = env::var("ADMIN_EMAILS").unwrap_or_default();
+        if admin_emails
+            .split(',')
+            .any(|admin| admin.trim().eq_ignore_ascii_case(email.trim()))
+        {
+            UserRole::Admin
+        } else {
+            UserRole::Player
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct User {
+    pub user_id: String,
+    pub display_name: String,
+    pub email: String,
+    pub role: String,
+}
+impl User {
+    pub fn role(&self) -> UserRole {
+        UserRole::from_name(&self.role).unwrap_or(UserRole::Player)
+    }
+}
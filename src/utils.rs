@@ -1,3 +1,4 @@
+pub mod auth;
 pub mod convert_notation;
 pub mod error;
 pub mod middleware;
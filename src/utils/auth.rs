@@ -0,0 +1,163 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload, error::ErrorUnauthorized, Error, FromRequest, HttpMessage, HttpRequest,
+};
+use openssl::{hash::MessageDigest, memcmp, pkcs5::pbkdf2_hmac, rand::rand_bytes, sha::sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::user::UserRole;
+
+/// What a route requires from its caller. Every route declares exactly one.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ViewGames,
+    CreateGames,
+    ManageGames,
+    ModerateChat,
+    ManageUsers,
+    ManageApiKeys,
+}
+impl UserRole {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            UserRole::Admin => true,
+            UserRole::Player | UserRole::Bot => {
+                matches!(permission, Permission::ViewGames | Permission::CreateGames)
+            }
+            UserRole::Viewer => permission == Permission::ViewGames,
+        }
+    }
+}
+
+/// Whoever made a request, resolved from either a JWT or an API key. Handlers
+/// behind `RequirePermission` can take it as an argument.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub user_id: Option<String>,
+    pub api_key_label: Option<String>,
+    pub role: UserRole,
+}
+impl Principal {
+    pub fn name(&self) -> String {
+        match (&self.user_id, &self.api_key_label) {
+            (Some(user_id), _) => format!("user {}", user_id),
+            (None, Some(label)) => format!("api key {}", label),
+            (None, None) => "anonymous".to_string(),
+        }
+    }
+}
+impl FromRequest for Principal {
+    type Error = Error;
+    type Future = Ready<Result<Principal, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| ErrorUnauthorized("Unauthorized")),
+        )
+    }
+}
+
+// Work factor of password hashes
+const PASSWORD_HASH_ITERATIONS: usize = 100_000;
+
+/// Only the hash of an API key or invite token is stored, the secret itself is
+/// shown once on creation.
+pub fn hash_secret(key: &str) -> String {
+    to_hex(&sha256(key.as_bytes()))
+}
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0; 32];
+    rand_bytes(&mut bytes).expect("Could not generate random bytes");
+    to_hex(&bytes)
+}
+
+/// Salted PBKDF2 hash of a password, stored as `salt$hash` in hex.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0; 16];
+    rand_bytes(&mut salt).expect("Could not generate random bytes");
+    format!("{}${}", to_hex(&salt), to_hex(&derive_key(password, &salt)))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let (salt, hash) = match password_hash.split_once('$') {
+        None => return false,
+        Some(parts) => parts,
+    };
+    match (from_hex(salt), from_hex(hash)) {
+        (Some(salt), Some(hash)) => {
+            let key = derive_key(password, &salt);
+            key.len() == hash.len() && memcmp::eq(&key, &hash)
+        }
+        _ => false,
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        salt,
+        PASSWORD_HASH_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut key,
+    )
+    .expect("Could not hash password");
+    key
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test_auth {
+    use crate::user::UserRole;
+
+    use super::{generate_secret, hash_password, hash_secret, verify_password, Permission};
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(UserRole::Admin.has_permission(Permission::ManageApiKeys));
+        assert!(UserRole::Bot.has_permission(Permission::CreateGames));
+        assert!(!UserRole::Player.has_permission(Permission::ManageGames));
+        assert!(!UserRole::Viewer.has_permission(Permission::CreateGames));
+        assert!(UserRole::Viewer.has_permission(Permission::ViewGames));
+    }
+
+    #[test]
+    fn test_secret_hash() {
+        let key = generate_secret();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, generate_secret());
+        assert_eq!(hash_secret(&key), hash_secret(&key));
+        assert_eq!(
+            hash_secret("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("hunter2");
+        assert_ne!(hash, hash_password("hunter2"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        assert!(!verify_password("hunter2", "not a hash"));
+    }
+}
@@ -9,4 +9,61 @@ pub const INVALID_FROM_FIELD: &'static str = "The from field in your requests bo
 pub const INVALID_TO_FIELD: &'static str = "The to field in your requests body is incorrect";
 pub const INVALID_CASTLE_ERROR: &'static str = "That castle move is invalid";
 pub const CHECK_ERROR: &'static str = "Your king is in check";
+pub const GAME_FINISHED_ERROR: &'static str = "The game is already finished";
+pub const NOT_YOUR_TURN_ERROR: &'static str = "It is not your turn";
+pub const PREMOVE_ERROR: &'static str = "You can only premove while your opponent is to move";
+pub const INVALID_GAME_MESSAGE_ERROR: &'static str = "That is not a valid game message";
+pub const SPECTATOR_ERROR: &'static str = "Spectators cannot take part in the game";
+pub const VOTER_ONLY_ERROR: &'static str = "Only voters can decide the result of a game";
+pub const TOO_MANY_SPECTATORS_ERROR: &'static str = "This game has reached its spectator limit";
+pub const EMPTY_CHAT_ERROR: &'static str = "Your chat message is empty";
+pub const CHAT_TOO_LONG_ERROR: &'static str = "Your chat message is too long";
+pub const CHAT_RATE_LIMIT_ERROR: &'static str = "You are sending chat messages too fast";
+pub const CHAT_NOT_SAVED_ERROR: &'static str =
+    "Your chat message could not be saved, please try again";
+pub const MUTED_ERROR: &'static str = "You have been muted";
+pub const NO_CONNECTION_ERROR: &'static str = "There is no such connection in this game";
+pub const INVALID_SIMUL_MOVE_ERROR: &'static str = "That is not a move on one of your boards";
+pub const HOST_NOT_AT_BOARD_ERROR: &'static str = "The host is not at this board";
+pub const WAIT_FOR_HOST_ERROR: &'static str = "Wait for the host to come to your board";
+pub const HOST_MUST_MOVE_ERROR: &'static str = "You have to move before passing this board";
+pub const NO_SEEK_ERROR: &'static str = "That seek is no longer open";
+pub const OWN_SEEK_ERROR: &'static str = "You cannot accept your own seek";
+pub const RATING_RANGE_ERROR: &'static str = "Your rating is outside the range of that seek";
+pub const INVALID_LOBBY_MESSAGE_ERROR: &'static str = "That is not a valid lobby message";
+pub const NO_CLOCK_ERROR: &'static str = "This game is played without a clock";
+pub const BERSERK_ERROR: &'static str = "You can only berserk once and before your first move";
+pub const OUT_OF_TIME_ERROR: &'static str = "You ran out of time";
+pub const ARENA_OVER_ERROR: &'static str = "This arena is over";
+pub const NOT_IN_ARENA_ERROR: &'static str = "You have not joined this arena";
+pub const ARENA_LOGIN_ERROR: &'static str = "Connect with a token to play in the arena";
+pub const INVALID_ARENA_MESSAGE_ERROR: &'static str = "That is not a valid arena message";
+pub const VACATION_LIMIT_ERROR: &'static str = "You do not have that many vacation days left";
+pub const ON_VACATION_ERROR: &'static str = "You are already on vacation";
+pub const NO_INVITE_ERROR: &'static str = "Could not find that invite";
+pub const INVITE_USED_ERROR: &'static str = "This invite has already been accepted";
+pub const INVITE_EXPIRED_ERROR: &'static str = "This invite has expired";
+pub const OWN_INVITE_ERROR: &'static str = "You cannot accept your own invite";
+pub const WRONG_PASSWORD_ERROR: &'static str = "Wrong password for this invite";
+pub const MIGRATION_LOCK_ERROR: &'static str = "Another process is migrating the database";
+pub const UNKNOWN_VERSION_ERROR: &'static str = "Unknown schema version";
+pub const ROLLBACK_TARGET_ERROR: &'static str =
+    "Can only roll back to a version older than the current one";
+pub const MOVE_PENDING_ERROR: &'static str = "The previous move is still being saved";
+pub const MOVE_NOT_SAVED_ERROR: &'static str = "The move could not be saved, please try again";
+pub const NO_GAME_ERROR: &'static str = "There is no game with this id";
+pub const INVALID_FEN_ERROR: &'static str = "Invalid FEN";
+pub const INVALID_EVENT_ERROR: &'static str = "Invalid game event";
+pub const INVALID_SAN_ERROR: &'static str = "Invalid move notation";
+pub const INVALID_PGN_ERROR: &'static str = "Invalid PGN";
+pub const INVALID_EXPORT_ERROR: &'static str = "Invalid exported game";
+pub const ABORT_ERROR: &'static str = "A game can only be aborted before both players moved";
+pub const RATED_GAME_DELETE_ERROR: &'static str = "Rated games cannot be deleted";
+pub const COMPETITION_GAME_DELETE_ERROR: &'static str =
+    "Games of tournaments and simuls cannot be deleted";
+pub const NO_SEAT_ERROR: &'static str = "You are not seated at this game";
+pub const NO_DRAW_OFFER_ERROR: &'static str = "There is no draw offer to respond to";
+pub const NO_TAKEBACK_OFFER_ERROR: &'static str = "There is no takeback request to respond to";
+pub const OWN_OFFER_ERROR: &'static str = "You cannot respond to your own offer";
+pub const NOTHING_TO_TAKE_BACK_ERROR: &'static str = "There is no move to take back";
 pub const INTERNAL_SERVER_ERROR: &'static str = "Internal server error";
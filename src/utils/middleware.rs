@@ -1,41 +1,57 @@
 use actix_web::{
     body::EitherBody,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpResponse,
+    web, Error, HttpMessage, HttpResponse,
 };
-use futures_util::{future::LocalBoxFuture, FutureExt};
-use log::error;
+use futures_util::future::LocalBoxFuture;
+use log::{error, warn};
 use std::{
     env,
     future::{ready, Ready},
+    rc::Rc,
 };
 
-pub fn auth_middleware() {}
+use crate::{
+    server::Server,
+    user::UserRole,
+    utils::{
+        auth::{hash_secret, Permission, Principal},
+        request::verify_jwt,
+    },
+};
 
-pub struct Authentication;
-pub struct AuthMiddleware<S> {
-    service: S,
+/// Resolves the caller to a `Principal` and rejects the request unless its role
+/// grants `Permission`. Callers authenticate with either an `Authorization: Bearer`
+/// JWT or an `x-api-key` header. The key in the API_KEY env var is always accepted
+/// as an admin key, so the first keys can be created with it.
+pub struct RequirePermission(pub Permission);
+pub struct PermissionMiddleware<S> {
+    service: Rc<S>,
+    permission: Permission,
 }
 
-impl<S, B> Transform<S, ServiceRequest> for Authentication
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
     type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type InitError = ();
-    type Transform = AuthMiddleware<S>;
+    type Transform = PermissionMiddleware<S>;
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthMiddleware { service }))
+        ready(Ok(PermissionMiddleware {
+            service: Rc::new(service),
+            permission: self.0,
+        }))
     }
 }
-impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
+impl<S, B> Service<ServiceRequest> for PermissionMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -46,37 +62,76 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let api_key = req.headers().get("x-api-key");
+        let service = Rc::clone(&self.service);
+        let permission = self.permission;
 
-        match api_key {
-            None => {
-                error!("No API_KEY provided");
-                let http_res = HttpResponse::Unauthorized().body("Unauthorized");
-                let (http_req, _) = req.into_parts();
-                let res = ServiceResponse::new(http_req, http_res);
-                return (async move { Ok(res.map_into_right_body()) }).boxed_local();
-            }
-            Some(user_api_key) => {
-                if user_api_key.to_str().expect("Non ASCII values in api key")
-                    != env::var("API_KEY").expect("No API_KEY env var provided")
-                {
-                    error!(
-                        "Incorrect api key provided: {}",
-                        user_api_key.to_str().unwrap()
-                    );
+        Box::pin(async move {
+            let principal = match resolve_principal(&req).await {
+                Err(e) => {
+                    error!("Could not authenticate request to {}: {}", req.path(), e);
                     let http_res = HttpResponse::Unauthorized().body("Unauthorized");
-                    let (http_req, _) = req.into_parts();
-                    let res = ServiceResponse::new(http_req, http_res);
-                    return (async move { Ok(res.map_into_right_body()) }).boxed_local();
+                    return Ok(req.into_response(http_res).map_into_right_body());
                 }
-            }
-        }
+                Ok(principal) => principal,
+            };
 
-        let fut = self.service.call(req);
+            if !principal.role.has_permission(permission) {
+                warn!(
+                    "{} is missing permission {:?} for {}",
+                    principal.name(),
+                    permission,
+                    req.path()
+                );
+                let http_res = HttpResponse::Forbidden().body("Forbidden");
+                return Ok(req.into_response(http_res).map_into_right_body());
+            }
 
-        Box::pin(async move {
-            let res = fut.await?;
+            req.extensions_mut().insert(principal);
+            let res = service.call(req).await?;
             Ok(res.map_into_left_body())
         })
     }
 }
+
+async fn resolve_principal(req: &ServiceRequest) -> Result<Principal, &'static str> {
+    if let Some(auth) = req.headers().get("Authorization") {
+        let token = auth
+            .to_str()
+            .ok()
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .ok_or("Malformed Authorization header")?;
+        let claims = verify_jwt(token)?;
+        return Ok(Principal {
+            user_id: Some(claims.sub),
+            api_key_label: None,
+            role: claims.role,
+        });
+    }
+
+    let api_key = req
+        .headers()
+        .get("x-api-key")
+        .ok_or("No credentials provided")?
+        .to_str()
+        .map_err(|_| "Non ASCII values in api key")?;
+    if env::var("API_KEY").is_ok_and(|bootstrap_key| bootstrap_key == api_key) {
+        return Ok(Principal {
+            user_id: None,
+            api_key_label: Some("bootstrap".to_string()),
+            role: UserRole::Admin,
+        });
+    }
+
+    let server = req
+        .app_data::<web::Data<Server>>()
+        .ok_or("No server configured")?;
+    match server.store.get_api_key(&hash_secret(api_key)).await? {
+        Some(key) if key.revoked_at.is_none() => Ok(Principal {
+            user_id: None,
+            role: key.role(),
+            api_key_label: Some(key.label),
+        }),
+        Some(_) => Err("Revoked api key"),
+        None => Err("Unknown api key"),
+    }
+}
@@ -1,16 +1,86 @@
-use std::env;
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
 use log::error;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug)]
+use crate::{
+    backup::Format,
+    game::{
+        time_control::{TimeControl, TimeControlCategory},
+        GameAction, Variant,
+    },
+    lobby::ColorPreference,
+    tournament::TournamentFormat,
+    user::{User, UserRole},
+    utils::response::ClockResponse,
+};
+
+// How long an issued JWT stays valid
+const JWT_LIFETIME_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MoveRequest {
     pub from: String,
     pub to: String,
     pub promotion: String,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ActionRequest {
+    pub action: GameAction,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChatRequest {
+    pub chat: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PremoveRequest {
+    pub premove: MoveRequest,
+}
+
+// `{"cancel_premove": true}` drops the currently queued premove
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CancelPremoveRequest {
+    pub cancel_premove: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ClientMessage {
+    Move(MoveRequest),
+    Action(ActionRequest),
+    Chat(ChatRequest),
+    Premove(PremoveRequest),
+    CancelPremove(CancelPremoveRequest),
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    Mute,
+    Unmute,
+    Kick,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerationRequest {
+    pub connection_id: String,
+    pub action: ModerationAction,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AdminChatRequest {
+    pub message: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MoveResponse {
     pub player: String,
@@ -20,6 +90,91 @@ pub struct MoveResponse {
     pub en_passant: bool,
     pub result: Option<String>,
     pub move_notation: String,
+    pub clock: Option<ClockResponse>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayQuery {
+    pub seq: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameStatus {
+    Active,
+    Finished,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameSort {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+/// Filters of the game archive. `from` and `until` are RFC 3339 timestamps,
+/// `eco` can also be the start of a code like `B9`, and a position is given
+/// either as a FEN or as its Zobrist hash in hex.
+#[derive(Deserialize, Debug)]
+pub struct GamesQuery {
+    pub status: Option<GameStatus>,
+    pub player: Option<String>,
+    pub result: Option<String>,
+    pub variant: Option<Variant>,
+    pub eco: Option<String>,
+    pub from: Option<String>,
+    pub until: Option<String>,
+    pub fen: Option<String>,
+    pub hash: Option<String>,
+    pub sort: Option<GameSort>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExplorerQuery {
+    pub fen: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteGameQuery {
+    // remove the game for good instead of hiding it
+    #[serde(default)]
+    pub hard: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserStatsQuery {
+    pub year: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BackupQuery {
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Only the host of a simul may connect to it, with their token.
+#[derive(Deserialize, Debug)]
+pub struct SimulHostQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConnectQuery {
+    pub last_seen_seq: Option<u64>,
+    pub seat: Option<String>,
+    pub spectate: Option<bool>,
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpectatorSettingsRequest {
+    pub delay_secs: u64,
+    pub max_spectators: usize,
 }
 
 #[derive(Deserialize, Debug)]
@@ -30,14 +185,203 @@ pub struct FinishRequest {
 #[derive(Deserialize, Debug)]
 pub struct StartRequest {
     pub admin_color: String,
+    pub white_user_id: Option<String>,
+    pub black_user_id: Option<String>,
+    pub time_control: Option<TimeControl>,
+    pub days_per_move: Option<u32>,
+    pub variant: Option<Variant>,
+    pub rated: Option<bool>,
+}
+
+/// A private game against whoever opens the returned invite link first.
+#[derive(Deserialize, Debug)]
+pub struct ChallengeRequest {
+    pub color: Option<ColorPreference>,
+    pub time_control: Option<TimeControl>,
+    pub days_per_move: Option<u32>,
+    pub variant: Option<Variant>,
+    pub rated: Option<bool>,
+    pub password: Option<String>,
+    pub expires_in_mins: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AcceptInviteRequest {
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VacationRequest {
+    pub days: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateTournamentRequest {
+    pub name: String,
+    pub format: TournamentFormat,
+    // in seeding order, strongest first
+    pub player_ids: Vec<String>,
+    pub rounds: Option<u32>,
+    pub time_control: Option<TimeControl>,
+    pub variant: Option<Variant>,
+    pub rated: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LeaderboardQuery {
+    pub category: Option<TimeControlCategory>,
+    pub variant: Option<Variant>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateApiKeyRequest {
+    pub label: String,
+    pub role: UserRole,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateUserRequest {
+    pub display_name: String,
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimulStartRequest {
+    pub host_name: String,
+    pub host_colors: Vec<String>,
+    // the user playing the host on each board, in the order of `host_colors`
+    pub player_user_ids: Vec<String>,
+    pub walking_order: Option<bool>,
+    pub host_time_budget_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimulMoveRequest {
+    pub game_id: String,
+    #[serde(flatten)]
+    pub move_request: MoveRequest,
 }
 
 #[derive(Deserialize, Debug)]
+pub struct SimulPassRequest {
+    pub pass: bool,
+}
+
+/// Everything the simul host can send over their websocket.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SimulHostRequest {
+    Move(SimulMoveRequest),
+    Pass(SimulPassRequest),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SeekParams {
+    pub time_control: Option<TimeControl>,
+    pub variant: Option<Variant>,
+    pub color: Option<ColorPreference>,
+    pub rated: Option<bool>,
+    pub min_rating: Option<f64>,
+    pub max_rating: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SeekRequest {
+    pub seek: SeekParams,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AcceptSeekRequest {
+    pub accept: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CancelSeekRequest {
+    pub cancel: String,
+}
+
+/// Everything a client can send over the lobby websocket.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LobbyRequest {
+    Seek(SeekRequest),
+    Accept(AcceptSeekRequest),
+    Cancel(CancelSeekRequest),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LobbyQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateArenaRequest {
+    pub name: String,
+    pub time_control: TimeControl,
+    pub duration_mins: u64,
+    pub variant: Option<Variant>,
+    pub rated: Option<bool>,
+}
+
+/// Joins the arena with `true`, withdraws from it with `false`.
+#[derive(Deserialize, Debug)]
+pub struct ArenaJoinRequest {
+    pub join: bool,
+}
+
+/// Berserks in the arena game with the given id.
+#[derive(Deserialize, Debug)]
+pub struct ArenaBerserkRequest {
+    pub berserk: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ArenaRequest {
+    Join(ArenaJoinRequest),
+    Berserk(ArenaBerserkRequest),
+}
+
+/// Without a token the arena websocket only receives the standings.
+#[derive(Deserialize, Debug)]
+pub struct ArenaQuery {
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Claims {
+    pub sub: String,
     pub email: String,
+    pub role: UserRole,
+    pub exp: u64,
+}
+
+pub fn issue_jwt(user: &User) -> Result<String, &'static str> {
+    let secret = env::var("JWT_SECRET").expect("Missing JWT_SECRET env var");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let claims = Claims {
+        sub: user.user_id.clone(),
+        email: user.email.clone(),
+        role: user.role(),
+        exp: now + JWT_LIFETIME_SECS,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )
+    .map_err(|err| {
+        error!("Could not issue JWT for user {}: {}", user.user_id, err);
+        "Could not issue JWT"
+    })
 }
 
-pub fn verify_jwt(raw_jwt: &str) -> Result<(), &'static str> {
+pub fn verify_jwt(raw_jwt: &str) -> Result<Claims, &'static str> {
     let secret = env::var("JWT_SECRET").expect("Missing JWT_SECRET env var");
     let header = decode_header(raw_jwt).map_err(|_| "Invalid JWT header")?;
 
@@ -51,16 +395,38 @@ pub fn verify_jwt(raw_jwt: &str) -> Result<(), &'static str> {
     );
 
     match token_result {
-        Ok(data) => {
-            if data.claims.email != "d.steblin.dev@gmail.com" {
-                error!("Invalid JWT email address: {}", data.claims.email);
-                return Err("Invalid JWT email address");
-            }
-            Ok(())
-        }
+        Ok(data) => Ok(data.claims),
         Err(err) => {
             error!("Invalid JWT token: {}", err);
-            return Err("Invalid JWT token");
+            Err("Invalid JWT token")
         }
     }
 }
+
+#[cfg(test)]
+mod test_request {
+    use super::ClientMessage;
+
+    fn parse(text: &str) -> Option<ClientMessage> {
+        serde_json::from_str::<ClientMessage>(text).ok()
+    }
+
+    #[test]
+    fn test_premove_messages() {
+        assert!(matches!(
+            parse(r#"{"premove": {"from": "e7", "to": "e5", "promotion": "Q"}}"#),
+            Some(ClientMessage::Premove(_))
+        ));
+        assert!(matches!(
+            parse(r#"{"cancel_premove": true}"#),
+            Some(ClientMessage::CancelPremove(_))
+        ));
+
+        // none of these may be taken for a cancellation
+        assert!(parse("{}").is_none());
+        assert!(parse(r#"{"premove": null}"#).is_none());
+        assert!(parse(r#"{"premove": {"from": "e7"}}"#).is_none());
+        assert!(parse(r#"{"premov": {"from": "e7", "to": "e5", "promotion": "Q"}}"#).is_none());
+        assert!(parse(r#"{"cancel_premove": true, "premove": null}"#).is_none());
+    }
+}
@@ -1,11 +1,338 @@
-use serde::Serialize;
+use std::time::Instant;
 
-use crate::game::chess_piece::{ChessPiece, Color, Piece};
+use serde::{Deserialize, Serialize};
+
+use crate::arena::ArenaStatus;
+use crate::chat::ChatScope;
+use crate::db::{ArchivedGame, ExplorerMove};
+use crate::game::{
+    chess_piece::{ChessPiece, Color, Piece},
+    clock::Clock,
+    Game, GameAction, MoveRecord, Players,
+};
+use crate::lobby::Seek;
+use crate::simul::SimulStatus;
+use crate::user::{User, UserRole};
+
+use super::request::{MoveRequest, MoveResponse};
+
+/// A game as it was after the event `seq`.
+#[derive(Serialize, Debug)]
+pub struct ReplayResponse {
+    pub seq: u64,
+    pub ply: u32,
+    pub fen: String,
+    pub result: Option<String>,
+}
 
 #[derive(Serialize, Debug)]
 pub struct GameState {
     pub admin_color: String,
     pub state: Vec<Vec<String>>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SyncResponse {
+    pub admin_color: String,
+    pub state: Vec<Vec<String>>,
+    pub next_to_move: String,
+    pub moves: Vec<MoveRecord>,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+    pub draw_offer: Option<String>,
+    pub takeback_offer: Option<String>,
+    pub players: Players,
+    pub clock: Option<ClockResponse>,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+}
+
+impl SyncResponse {
+    pub fn new(game: &Game) -> SyncResponse {
+        SyncResponse {
+            admin_color: game.admin_color.to_str(),
+            state: serialize_field(&game.field),
+            next_to_move: game.next_to_move.to_str(),
+            moves: game.move_history.clone(),
+            result: game.game_result.map(|res| res.to_str()),
+            termination: game.termination.map(|t| t.to_str()),
+            draw_offer: game.draw_offer.map(|color| color.to_str()),
+            takeback_offer: game.takeback_offer.map(|color| color.to_str()),
+            players: game.players.clone(),
+            clock: game
+                .clock
+                .as_ref()
+                .map(|clock| ClockResponse::new(clock, Instant::now())),
+            eco: game.opening.map(|opening| opening.eco.to_string()),
+            opening: game.opening.map(|opening| opening.name.to_string()),
+        }
+    }
+}
+
+/// Time left on both sides of a clock, as of when the message was sent.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ClockResponse {
+    pub white_ms: u128,
+    pub black_ms: u128,
+    pub running: Option<String>,
+}
+
+impl ClockResponse {
+    pub fn new(clock: &Clock, now: Instant) -> ClockResponse {
+        ClockResponse {
+            white_ms: clock.remaining(Color::WHITE, now).as_millis(),
+            black_ms: clock.remaining(Color::BLACK, now).as_millis(),
+            running: clock.running().map(|color| color.to_str()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ActionResponse {
+    pub player: String,
+    pub action: GameAction,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatResponse {
+    pub scope: ChatScope,
+    pub author: String,
+    pub connection_id: Option<String>,
+    pub message: String,
+}
+
+/// Everything the server pushes over a game websocket, tagged with `type` so
+/// clients can tell a move from a state sync.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Move(MoveResponse),
+    Sync(SyncResponse),
+    Action(ActionResponse),
+    Chat(ChatResponse),
+    PremoveRejected {
+        premove: MoveRequest,
+        message: String,
+    },
+    ViewerCount {
+        spectators: usize,
+    },
+    // the clock as it is now, after replaying missed events
+    Clock(ClockResponse),
+    Flag {
+        player: String,
+        result: String,
+    },
+    Berserk {
+        player: String,
+        clock: ClockResponse,
+    },
+    // the winner the voting frontend announced
+    Finish {
+        winner: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// What the lobby websocket receives
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyMessage {
+    Seeks { seeks: Vec<Seek> },
+    SeekAdded(Seek),
+    SeekRemoved { seek_id: String },
+    GameStarted { game_id: String, color: String },
+    Error { message: String },
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArchivedGameResponse {
+    pub game_id: String,
+    pub white_user_id: Option<String>,
+    pub black_user_id: Option<String>,
+    pub result: Option<String>,
+    pub termination: Option<String>,
+    pub variant: Option<String>,
+    pub time_control: Option<String>,
+    pub rated: bool,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+    pub moves: u32,
+    pub created_at: String,
+}
+
+impl ArchivedGameResponse {
+    pub fn new(game: ArchivedGame) -> ArchivedGameResponse {
+        ArchivedGameResponse {
+            game_id: game.game_id,
+            white_user_id: game.white_user_id,
+            black_user_id: game.black_user_id,
+            result: game.result,
+            termination: game.termination,
+            variant: game.variant,
+            time_control: game.time_control,
+            rated: game.rated == Some(1),
+            eco: game.eco,
+            opening: game.opening,
+            moves: game.moves,
+            created_at: game.created_at,
+        }
+    }
+}
+
+/// One page of the game archive, `total` counts the matches on all pages.
+#[derive(Serialize, Debug)]
+pub struct GamesPage {
+    pub total: u64,
+    pub page: u32,
+    pub per_page: u32,
+    pub games: Vec<ArchivedGameResponse>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExplorerMoveResponse {
+    pub uci: String,
+    pub notation: String,
+    pub games: u64,
+    pub white_percent: f64,
+    pub draw_percent: f64,
+    pub black_percent: f64,
+    // only rated games count towards it
+    pub average_rating: Option<f64>,
+}
+
+impl ExplorerMoveResponse {
+    pub fn new(played: ExplorerMove) -> ExplorerMoveResponse {
+        let percent = |count: u64| (count as f64 * 1000.0 / played.games as f64).round() / 10.0;
+        ExplorerMoveResponse {
+            white_percent: percent(played.white_wins),
+            draw_percent: percent(played.draws),
+            black_percent: percent(played.black_wins),
+            average_rating: (played.rated_games > 0)
+                .then(|| (played.rating_sum / played.rated_games as f64).round()),
+            uci: played.uci,
+            notation: played.notation,
+            games: played.games,
+        }
+    }
+}
+
+/// The moves played from a position, most popular first. `hash` can be used
+/// to search the game archive for the position.
+#[derive(Serialize, Debug)]
+pub struct ExplorerResponse {
+    pub fen: String,
+    pub hash: String,
+    pub games: u64,
+    pub moves: Vec<ExplorerMoveResponse>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AwaitingMoveResponse {
+    pub game_id: String,
+    pub color: String,
+    pub opponent_id: Option<String>,
+    pub days_per_move: Option<u32>,
+    pub last_move_at: Option<String>,
+    // only correspondence games have one
+    pub deadline: Option<String>,
+}
+
+/// What the websocket of an arena receives
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ArenaMessage {
+    Status(ArenaStatus),
+    GameStarted {
+        game_id: String,
+        color: String,
+        opponent: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// What the host websocket of a simul receives
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SimulMessage {
+    BoardEvent {
+        game_id: String,
+        event: serde_json::Value,
+    },
+    Status(SimulStatus),
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiKeyCreatedResponse {
+    pub key_id: String,
+    pub label: String,
+    pub role: UserRole,
+    // only ever shown here, the DB keeps a hash
+    pub key: String,
+}
+
+/// A user as others see them, the email is only shown to the user and admins.
+#[derive(Serialize, Debug)]
+pub struct UserResponse {
+    pub user_id: String,
+    pub display_name: String,
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+impl UserResponse {
+    pub fn new(user: User, show_email: bool) -> UserResponse {
+        UserResponse {
+            user_id: user.user_id,
+            display_name: user.display_name,
+            role: user.role,
+            email: show_email.then_some(user.email),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct UserCreatedResponse {
+    pub user: User,
+    pub token: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InviteCreatedResponse {
+    pub game_id: String,
+    // the color of the challenger
+    pub color: String,
+    pub token: String,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InviteAcceptedResponse {
+    pub game_id: String,
+    pub color: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SimulCreatedResponse {
+    pub simul_id: String,
+    pub game_ids: Vec<String>,
+}
+
+/// A server message together with its position in the room's event stream.
+/// Clients remember the last `seq` they saw to resume after a reconnect.
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub message: ServerMessage,
 }
 
 pub fn serialize_field(field: &Vec<Vec<Option<ChessPiece>>>) -> Vec<Vec<String>> {
@@ -1,23 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 use actix::{Actor, StreamHandler};
-use actix_web_actors::ws;
+use actix_web::rt::task::JoinHandle;
+use actix_web_actors::ws::{self, CloseCode, CloseReason};
 use log::{error, info};
 use uuid::Uuid;
 
-use crate::utils::request::MoveRequest;
+use crate::utils::error::{
+    ARENA_LOGIN_ERROR, CHAT_NOT_SAVED_ERROR, CHAT_RATE_LIMIT_ERROR, GAME_FINISHED_ERROR,
+    INTERNAL_SERVER_ERROR, INVALID_ARENA_MESSAGE_ERROR, INVALID_GAME_MESSAGE_ERROR,
+    INVALID_LOBBY_MESSAGE_ERROR, INVALID_SIMUL_MOVE_ERROR, MOVE_NOT_SAVED_ERROR,
+    MOVE_PENDING_ERROR, MUTED_ERROR, NOT_IN_ARENA_ERROR, NOT_YOUR_TURN_ERROR, NO_CONNECTION_ERROR,
+    NO_SEAT_ERROR, NO_SEEK_ERROR, OUT_OF_TIME_ERROR, PREMOVE_ERROR, PROMOTION_ERROR,
+    SPECTATOR_ERROR, VOTER_ONLY_ERROR,
+};
+use crate::utils::request::{
+    ArenaRequest, ClientMessage, LobbyRequest, ModerationAction, MoveRequest, SeekParams,
+    SimulHostRequest, SimulMoveRequest,
+};
+use crate::utils::response::{
+    ActionResponse, ArenaMessage, ChatResponse, ClockResponse, Event, LobbyMessage, ServerMessage,
+    SimulMessage, SyncResponse,
+};
 use crate::{
-    game::{chess_piece::Color, GameResult},
+    arena::ArenaMember,
+    chat::{check_chat_message, ChatRateLimiter, ChatScope},
+    game::{
+        chess_piece::Color,
+        event::{GameEvent, SNAPSHOT_INTERVAL},
+        Game, GameAction, GameResult, Players, Termination,
+    },
+    lobby::{LobbyMember, Seek},
+    rating::RatedGame,
     server::Server,
+    store::NewMove,
     utils::request::MoveResponse,
 };
 
+// How many past events a room keeps around for reconnecting clients. Anyone
+// further behind than that gets a full snapshot instead.
+const EVENT_BUFFER_SIZE: usize = 512;
+
+// Used when the SPECTATOR_DELAY_SECS and MAX_SPECTATORS env vars are not set
+const DEFAULT_SPECTATOR_DELAY_SECS: u64 = 0;
+const DEFAULT_MAX_SPECTATORS: usize = 500;
+
+// How often a simul host connection checks whether the host ran out of time
+const SIMUL_FLAG_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    // the voting frontend, which may move for either side
+    Voter,
+    Player(Color),
+    Spectator,
+}
+impl Role {
+    pub fn seat(&self) -> Option<Color> {
+        match self {
+            Role::Player(color) => Some(*color),
+            _ => None,
+        }
+    }
+    pub fn to_str(&self) -> String {
+        match self {
+            Role::Voter => "voter".to_string(),
+            Role::Player(color) => color.to_str(),
+            Role::Spectator => "spectator".to_string(),
+        }
+    }
+    pub fn chat_scope(&self) -> ChatScope {
+        match self {
+            Role::Spectator => ChatScope::Spectators,
+            _ => ChatScope::Players,
+        }
+    }
+}
+
 #[derive(Clone)]
+pub struct RoomConnection {
+    id: Uuid,
+    addr: Addr<WebSocketConnection>,
+    role: Role,
+    muted: bool,
+    chat_limiter: ChatRateLimiter,
+}
+
 pub struct WebSocketRoom {
     game_id: Uuid,
-    connections: Vec<Addr<WebSocketConnection>>,
+    connections: Vec<RoomConnection>,
     server: Arc<Server>,
+    seq: u64,
+    // the seq a room restored after a restart started from; clients may have
+    // seen events up to and past it that are no longer in memory
+    resumed_seq: u64,
+    events: VecDeque<(u64, String)>,
+    premoves: HashMap<Color, MoveRequest>,
+    // the ply of a move that is applied but not saved yet, with the game before it
+    unsaved: Option<(u32, Game)>,
+    // the sequence number of the last event in the game's log
+    pub log_seq: u64,
+    pub spectator_delay: Duration,
+    // the game as it was published, kept for as long as spectators are behind
+    positions: VecDeque<(Instant, Game)>,
+    pub max_spectators: usize,
+    pub simul_id: Option<Uuid>,
+    // ends the game once the running clock is out of time
+    flag_timer: Option<JoinHandle<()>>,
+    // the last queued store write, which the next one waits for
+    last_write: Option<JoinHandle<()>>,
 }
 
 impl Actor for WebSocketRoom {
@@ -30,101 +126,912 @@ impl WebSocketRoom {
             game_id,
             connections: Vec::new(),
             server,
+            seq: 0,
+            resumed_seq: 0,
+            events: VecDeque::new(),
+            premoves: HashMap::new(),
+            unsaved: None,
+            log_seq: 0,
+            spectator_delay: Duration::from_secs(
+                env::var("SPECTATOR_DELAY_SECS")
+                    .ok()
+                    .and_then(|delay| delay.parse().ok())
+                    .unwrap_or(DEFAULT_SPECTATOR_DELAY_SECS),
+            ),
+            positions: VecDeque::new(),
+            max_spectators: env::var("MAX_SPECTATORS")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(DEFAULT_MAX_SPECTATORS),
+            simul_id: None,
+            flag_timer: None,
+            last_write: None,
         }
     }
-    pub fn add_connection(&mut self, addr: Addr<WebSocketConnection>) {
-        self.connections.push(addr);
+    pub fn add_connection(&mut self, id: Uuid, addr: Addr<WebSocketConnection>, role: Role) {
+        self.connections.push(RoomConnection {
+            id,
+            addr,
+            role,
+            muted: false,
+            chat_limiter: ChatRateLimiter::default(),
+        });
+        if role == Role::Spectator {
+            self.broadcast_viewer_count();
+        }
     }
     pub fn remove_connection(&mut self, addr: Addr<WebSocketConnection>) {
-        self.connections.retain(|conn| *conn != addr);
+        let spectator_left = self
+            .connections
+            .iter()
+            .any(|conn| conn.addr == addr && conn.role == Role::Spectator);
+        self.connections.retain(|conn| conn.addr != addr);
+        if spectator_left {
+            self.broadcast_viewer_count();
+        }
     }
-    pub fn broadcast_message(&self, text: String) {
-        if text == "white" || text == "black" {
-            self.connections.iter().for_each(|e| {
-                let _ = e.do_send(FinishBroadcastMessage::new(text.clone()));
-            });
+    /// Whether nobody is connected and no move is waiting to be saved.
+    pub fn is_idle(&self) -> bool {
+        self.connections.is_empty() && self.unsaved.is_none()
+    }
+    /// Disconnects everyone, once the game is removed from memory.
+    pub fn close(&self) {
+        if let Some(timer) = &self.flag_timer {
+            timer.abort();
+        }
+        for connection in &self.connections {
+            connection.addr.do_send(CloseMessage);
+        }
+    }
+    pub fn set_spectator_delay(&mut self, delay: Duration) {
+        self.spectator_delay = delay;
+        self.positions.clear();
+        self.record_position();
+    }
+    /// Remembers the game as clients see it right now, which spectators get to
+    /// see once the delay has passed.
+    pub fn record_position(&mut self) {
+        if self.spectator_delay.is_zero() {
+            self.positions.clear();
             return;
         }
-        if let Ok(move_request) = serde_json::from_str::<MoveRequest>(&text) {
-            let mut games = self.server.games.write().unwrap();
-            let game = match games.get_mut(&self.game_id) {
+        let game = match self.server.games.read().unwrap().get(&self.game_id) {
+            None => return,
+            Some(game) => game.clone(),
+        };
+        let now = Instant::now();
+        self.positions.push_back((now, game));
+        // only the newest position older than the delay can still be shown
+        while self.positions.len() > 1 && self.positions[1].0 + self.spectator_delay <= now {
+            self.positions.pop_front();
+        }
+    }
+    /// The game as spectators see it at `now`, `None` if they see it live.
+    pub fn delayed_game(&self, now: Instant) -> Option<Game> {
+        if self.spectator_delay.is_zero() {
+            return None;
+        }
+        self.positions
+            .iter()
+            .rev()
+            .find(|(at, _)| *at + self.spectator_delay <= now)
+            .or(self.positions.front())
+            .map(|(_, game)| game.clone())
+    }
+    pub fn spectator_count(&self) -> usize {
+        self.connections
+            .iter()
+            .filter(|conn| conn.role == Role::Spectator)
+            .count()
+    }
+    fn broadcast_viewer_count(&self) {
+        let text = serde_json::to_string(&ServerMessage::ViewerCount {
+            spectators: self.spectator_count(),
+        })
+        .unwrap();
+        self.connections
+            .iter()
+            .for_each(|e| e.addr.do_send(BroadcastMessage::new(text.clone())));
+    }
+    /// Returns the messages a freshly connected client needs to catch up.
+    /// If the client tells us the last sequence number it saw and we still have
+    /// everything after it, only the missed events are replayed, followed by the
+    /// clock as it is now, otherwise the client gets a snapshot of the whole game.
+    pub fn sync_messages(&self, last_seen_seq: Option<u64>) -> Vec<String> {
+        // a move is only part of the game for clients once it is published
+        let games = self.server.games.read().unwrap();
+        let game = match self.unsaved.as_ref().map(|(_, before)| before) {
+            Some(before) => before,
+            None => match games.get(&self.game_id) {
                 None => {
                     error!(
-                        "Could not find game when broadcasting message with id {}",
+                        "Could not find game when syncing connection with id {}",
                         self.game_id
                     );
-                    return;
+                    return vec![];
                 }
                 Some(game) => game,
-            };
+            },
+        };
 
-            let promotion_piece = match move_request.promotion.chars().next() {
-                None => {
-                    error!("No promotion piece char specified");
-                    return;
+        if let Some(last_seen_seq) = last_seen_seq {
+            let oldest_seq = self.events.front().map_or(self.seq + 1, |(seq, _)| *seq);
+            // a seq from before a restart, or one ahead of the room, gets a snapshot
+            let resumed = self.resumed_seq == 0 || last_seen_seq > self.resumed_seq;
+            if resumed && last_seen_seq <= self.seq && last_seen_seq + 1 >= oldest_seq {
+                let mut messages: Vec<String> = self
+                    .events
+                    .iter()
+                    .filter(|(seq, _)| *seq > last_seen_seq)
+                    .map(|(_, text)| text.clone())
+                    .collect();
+                // the replayed moves only carry the clock as it was back then
+                if let Some(clock) = &game.clock {
+                    let clock = ServerMessage::Clock(ClockResponse::new(clock, Instant::now()));
+                    messages.push(serde_json::to_string(&clock).unwrap());
                 }
-                Some(pr) => pr,
-            };
+                return messages;
+            }
+        }
 
-            if let Err(e) =
-                &game.validate_and_make_move(&move_request.from, &move_request.to, promotion_piece)
-            {
-                error!("Not a valid move: {}", e);
+        let snapshot = Event {
+            seq: self.seq,
+            message: ServerMessage::Sync(SyncResponse::new(game)),
+        };
+        vec![serde_json::to_string(&snapshot).unwrap()]
+    }
+    fn publish(&mut self, message: ServerMessage) {
+        let is_move = matches!(message, ServerMessage::Move(_));
+        self.seq += 1;
+        let text = serde_json::to_string(&Event {
+            seq: self.seq,
+            message,
+        })
+        .unwrap();
+
+        self.events.push_back((self.seq, text.clone()));
+        if self.events.len() > EVENT_BUFFER_SIZE {
+            self.events.pop_front();
+        }
+
+        // spectators see everything with a delay, so they cannot relay moves to a player
+        self.connections.iter().for_each(|e| match e.role {
+            Role::Spectator if !self.spectator_delay.is_zero() => e.addr.do_send(
+                DelayedBroadcastMessage::new(text.clone(), self.spectator_delay),
+            ),
+            _ => e.addr.do_send(BroadcastMessage::new(text.clone())),
+        });
+
+        self.record_position();
+        if let Some(simul_id) = self.simul_id {
+            self.forward_to_simul_host(simul_id, &text, is_move);
+        }
+    }
+    fn forward_to_simul_host(&self, simul_id: Uuid, text: &str, is_move: bool) {
+        // games before simuls, the same order make_move takes them in
+        let games = self.server.games.read().unwrap();
+        let mut simuls = self.server.simuls.write().unwrap();
+        let simul = match simuls.get_mut(&simul_id) {
+            None => {
+                error!("Could not find simul {} of game {}", simul_id, self.game_id);
                 return;
             }
-            info!("Move {} is valid", &game.previous_move);
+            Some(simul) => simul,
+        };
+
+        let mover = games
+            .get(&self.game_id)
+            .filter(|_| is_move)
+            .map(|game| game.next_to_move.opposite());
+        simul.board_updated(self.game_id, mover, &games);
+
+        simul.send_to_hosts(&SimulMessage::BoardEvent {
+            game_id: self.game_id.to_string(),
+            event: serde_json::from_str(text).unwrap(),
+        });
+        simul.send_to_hosts(&SimulMessage::Status(simul.status(&games)));
+    }
+    // the seat of a color and the voting frontend, which moves for both sides
+    fn send_to_movers(&self, color: Color, message: ServerMessage) {
+        let text = serde_json::to_string(&message).unwrap();
+        self.connections
+            .iter()
+            .filter(|e| e.role == Role::Player(color) || e.role == Role::Voter)
+            .for_each(|e| e.addr.do_send(BroadcastMessage::new(text.clone())));
+    }
+    fn send_to_seat(&self, seat: Color, message: ServerMessage) {
+        let text = serde_json::to_string(&message).unwrap();
+        self.connections
+            .iter()
+            .filter(|e| e.role == Role::Player(seat))
+            .for_each(|e| e.addr.do_send(BroadcastMessage::new(text.clone())));
+    }
+    pub fn broadcast_message(
+        &mut self,
+        text: String,
+        connection_id: Uuid,
+    ) -> Result<(), &'static str> {
+        let role = match self.connections.iter().find(|e| e.id == connection_id) {
+            None => return Err(NO_CONNECTION_ERROR),
+            Some(connection) => connection.role,
+        };
+        let message = serde_json::from_str::<ClientMessage>(&text);
+
+        // chatting is the only thing spectators are allowed to do
+        if let Ok(ClientMessage::Chat(chat_request)) = message {
+            return self.chat(connection_id, chat_request.chat);
+        }
+        if role == Role::Spectator {
+            return Err(SPECTATOR_ERROR);
+        }
+        let seat = role.seat();
+        if text == "white" || text == "black" {
+            // only the voting frontend ends games by naming the winner
+            if role != Role::Voter {
+                return Err(VOTER_ONLY_ERROR);
+            }
+            self.publish(ServerMessage::Finish { winner: text });
+            return Ok(());
+        }
+        match message {
+            Ok(ClientMessage::Chat(_)) => Ok(()),
+            Ok(ClientMessage::Move(move_request)) => self.make_move(move_request, seat),
+            Ok(ClientMessage::Action(action_request)) => {
+                let player = seat.ok_or(NO_SEAT_ERROR)?;
+                self.perform_action(player, action_request.action)
+            }
+            Ok(ClientMessage::Premove(premove_request)) => {
+                let player = seat.ok_or(NO_SEAT_ERROR)?;
+                self.set_premove(player, Some(premove_request.premove))
+            }
+            Ok(ClientMessage::CancelPremove(cancel_request)) => {
+                let player = seat.ok_or(NO_SEAT_ERROR)?;
+                match cancel_request.cancel_premove {
+                    true => self.set_premove(player, None),
+                    false => Ok(()),
+                }
+            }
+            Err(e) => {
+                error!("Could not parse websocket message {}: {}", text, e);
+                Err(INVALID_GAME_MESSAGE_ERROR)
+            }
+        }
+    }
+    fn chat(&mut self, connection_id: Uuid, message: String) -> Result<(), &'static str> {
+        let message = check_chat_message(&message)?;
+        let connection = match self.connections.iter_mut().find(|e| e.id == connection_id) {
+            None => return Err(NO_CONNECTION_ERROR),
+            Some(connection) => connection,
+        };
+        if connection.muted {
+            return Err(MUTED_ERROR);
+        }
+        if !connection.chat_limiter.allow(Instant::now()) {
+            return Err(CHAT_RATE_LIMIT_ERROR);
+        }
+        let role = connection.role;
+
+        let message = self.server.chat_filter.filter(message)?;
+        self.send_chat(
+            role.chat_scope(),
+            role.to_str(),
+            Some(connection_id),
+            message,
+        );
+        Ok(())
+    }
+    /// Stores the chat message and sends it to everyone in the given scope once
+    /// it is saved. Chat is not part of the event stream, so it is not replayed
+    /// on reconnects.
+    pub fn send_chat(
+        &mut self,
+        scope: ChatScope,
+        author: String,
+        connection_id: Option<Uuid>,
+        message: String,
+    ) {
+        let server = Arc::clone(&self.server);
+        let game_id = self.game_id;
+        self.queue_write(async move {
+            let saved = server
+                .store
+                .insert_chat(&game_id.to_string(), &scope.to_str(), &author, &message)
+                .await;
+            if let Some(room) = server.rooms.write().unwrap().get_mut(&game_id) {
+                room.chat_saved(scope, author, connection_id, message, saved);
+            }
+        });
+    }
+    /// Sends a stored chat message to its scope, or tells the sender that it
+    /// could not be saved.
+    fn chat_saved(
+        &mut self,
+        scope: ChatScope,
+        author: String,
+        connection_id: Option<Uuid>,
+        message: String,
+        saved: Result<(), &'static str>,
+    ) {
+        if let Err(e) = saved {
+            error!(
+                "Could not save chat message of game {}: {}",
+                self.game_id, e
+            );
+            let text = serde_json::to_string(&ServerMessage::Error {
+                message: CHAT_NOT_SAVED_ERROR.to_string(),
+            })
+            .unwrap();
+            self.connections
+                .iter()
+                .filter(|e| Some(e.id) == connection_id)
+                .for_each(|e| e.addr.do_send(BroadcastMessage::new(text.clone())));
+            return;
+        }
+
+        self.log_event(GameEvent::Chat {
+            scope: scope.to_str(),
+            author: author.clone(),
+            message: message.clone(),
+        });
+        let text = serde_json::to_string(&ServerMessage::Chat(ChatResponse {
+            scope,
+            author,
+            connection_id: connection_id.map(|id| id.to_string()),
+            message,
+        }))
+        .unwrap();
+        self.connections
+            .iter()
+            .filter(|e| match scope {
+                ChatScope::All => true,
+                ChatScope::Players => e.role != Role::Spectator,
+                ChatScope::Spectators => e.role == Role::Spectator,
+            })
+            .for_each(|e| e.addr.do_send(BroadcastMessage::new(text.clone())));
+    }
+    pub fn moderate(
+        &mut self,
+        connection_id: Uuid,
+        action: ModerationAction,
+    ) -> Result<(), &'static str> {
+        let connection = match self.connections.iter_mut().find(|e| e.id == connection_id) {
+            None => return Err(NO_CONNECTION_ERROR),
+            Some(connection) => connection,
+        };
+
+        match action {
+            ModerationAction::Mute => connection.muted = true,
+            ModerationAction::Unmute => connection.muted = false,
+            ModerationAction::Kick => connection.addr.do_send(KickMessage),
+        }
+        Ok(())
+    }
+    /// Continues the sequence numbers of a game restored after a restart, so
+    /// that the events published from now on are newer than those before it.
+    pub fn resume(&mut self, log_seq: u64) {
+        self.log_seq = log_seq;
+        self.seq = log_seq;
+        self.resumed_seq = log_seq;
+    }
+    fn next_log_seq(&mut self) -> u64 {
+        self.log_seq += 1;
+        self.log_seq
+    }
+    /// Runs a store write of the game once the writes queued before it are
+    /// done, so that its events reach the log in the order of their sequence
+    /// numbers.
+    fn queue_write<F>(&mut self, write: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let previous = self.last_write.take();
+        self.last_write = Some(actix::spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            write.await;
+        }));
+    }
+    /// Appends to the event log of the game in the order the events happened,
+    /// even though they are stored in the background.
+    fn log_event(&mut self, event: GameEvent) {
+        let seq = self.next_log_seq();
+        let store = self.server.store.clone();
+        let game_id = self.game_id.to_string();
+        self.queue_write(async move {
+            if let Err(e) = store.append_event(&game_id, Some(seq), &event).await {
+                error!("Could not log event {} of game {}: {}", seq, game_id, e);
+            }
+        });
+    }
+    fn check_not_pending(&self) -> Result<(), &'static str> {
+        match self.unsaved {
+            None => Ok(()),
+            Some(_) => Err(MOVE_PENDING_ERROR),
+        }
+    }
+    /// Applies a move and saves it, it is only published once it is stored.
+    pub fn make_move(
+        &mut self,
+        move_request: MoveRequest,
+        seat: Option<Color>,
+    ) -> Result<(), &'static str> {
+        self.check_not_pending()?;
+        let server = Arc::clone(&self.server);
+        let mut games = server.games.write().unwrap();
+        let game = match games.get_mut(&self.game_id) {
+            None => {
+                error!(
+                    "Could not find game when broadcasting message with id {}",
+                    self.game_id
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Some(game) => game,
+        };
+
+        // connections without a seat are the voting frontend, which may move for either side
+        if seat.is_some_and(|seat| seat != game.next_to_move) {
+            return Err(NOT_YOUR_TURN_ERROR);
+        }
+        let now = Instant::now();
+        if let Some(player) = game.clock.as_ref().and_then(|clock| clock.flagged(now)) {
+            drop(games);
+            self.time_out(player)?;
+            return Err(OUT_OF_TIME_ERROR);
+        }
+        if let Some(simul_id) = self.simul_id {
+            if let Some(simul) = server.simuls.read().unwrap().get(&simul_id) {
+                simul.check_move(self.game_id, game.next_to_move)?;
+            }
+        }
+
+        let promotion_piece = match move_request.promotion.chars().next() {
+            None => {
+                error!("No promotion piece char specified");
+                return Err(PROMOTION_ERROR);
+            }
+            Some(pr) => pr,
+        };
+
+        let before = game.clone();
+        if let Err(e) =
+            &game.validate_and_make_move(&move_request.from, &move_request.to, promotion_piece)
+        {
+            error!("Not a valid move: {}", e);
+            return Err(e);
+        }
+        info!("Move {} is valid", &game.previous_move);
+        let finished = game.game_result.is_some();
+        let mover = game.next_to_move.opposite();
+        let time_spent = game
+            .clock
+            .as_ref()
+            .and_then(|clock| clock.elapsed(mover, now));
+        if let Some(clock) = &mut game.clock {
+            if finished {
+                clock.stop(now);
+            } else {
+                clock.press(mover, now);
+            }
+        }
+
+        let player_str = match game.next_to_move {
+            Color::WHITE => "BLACK",
+            Color::BLACK => "WHITE",
+        };
+
+        let ply = game.ply();
+        let event = GameEvent::Move {
+            from: move_request.from.clone(),
+            to: move_request.to.clone(),
+            promotion: promotion_piece,
+        };
+        let snapshot = (ply % SNAPSHOT_INTERVAL == 0).then(|| game.to_fen());
+        let position_hash = game.zobrist();
+        let opening = game.opening;
+        let turn_number = game.turn_number;
+        let prev_move = game.previous_move.clone();
+        let finished_with = game.game_result.zip(game.termination);
+        let rated = RatedGame::from_game(game);
+        let move_response = MoveResponse {
+            move_notation: game.previous_move.clone(),
+            player: game.next_to_move.opposite_color(),
+            from: move_request.from,
+            to: move_request.to,
+            promotion: move_request.promotion,
+            en_passant: game.previous_move_was_enpassant,
+            result: game.game_result.map(|res| res.to_str()),
+            clock: game
+                .clock
+                .as_ref()
+                .map(|clock| ClockResponse::new(clock, now)),
+            eco: opening.map(|opening| opening.eco.to_string()),
+            opening: opening.map(|opening| opening.name.to_string()),
+        };
+        drop(games);
 
-            let player_str = match game.next_to_move {
-                Color::WHITE => "BLACK",
-                Color::BLACK => "WHITE",
+        self.unsaved = Some((ply, before));
+        let seq = self.next_log_seq();
+        let server = Arc::clone(&self.server);
+        let game_id = self.game_id;
+        self.queue_write(async move {
+            let id = game_id.to_string();
+            let (result, termination) = match finished_with {
+                None => (None, None),
+                Some((result, termination)) => (Some(result.to_str()), Some(termination.to_str())),
             };
+            let saved = server
+                .store
+                .insert_move(
+                    &id,
+                    NewMove {
+                        ply,
+                        turn: turn_number,
+                        move_notation: &prev_move,
+                        player: player_str,
+                        finished: result.as_deref().zip(termination.as_deref()),
+                        seq,
+                        event: &event,
+                        snapshot: snapshot.as_deref(),
+                        position_hash,
+                        opening,
+                        time_spent_ms: time_spent.map(|spent| spent.as_millis() as u64),
+                    },
+                )
+                .await;
+
+            let committed = match server.rooms.write().unwrap().get_mut(&game_id) {
+                None => false,
+                Some(room) => room.move_saved(ply, saved, move_response),
+            };
+            if let (true, Some((result, _))) = (committed, finished_with) {
+                info!(
+                    "Game ended with {}, finishing game automatically...",
+                    result.to_str()
+                );
+                let _ = server.result_recorded(&id, result, rated).await;
+            }
+        });
+        Ok(())
+    }
+    /// Publishes a move once it is stored, or restores the game as it was
+    /// before the move if it could not be saved. Returns whether it was saved.
+    fn move_saved(
+        &mut self,
+        ply: u32,
+        saved: Result<(), &'static str>,
+        move_response: MoveResponse,
+    ) -> bool {
+        let before = match self.unsaved.take() {
+            Some((unsaved_ply, before)) if unsaved_ply == ply => before,
+            unsaved => {
+                self.unsaved = unsaved;
+                return false;
+            }
+        };
+        let mover = before.next_to_move;
+
+        if let Err(e) = saved {
+            error!(
+                "Could not save move {} of game {}, rolling it back: {}",
+                ply, self.game_id, e
+            );
+            if let Some(game) = self.server.games.write().unwrap().get_mut(&self.game_id) {
+                *game = before;
+            }
+            self.arm_flag_timer();
+            self.send_to_movers(
+                mover,
+                ServerMessage::Error {
+                    message: MOVE_NOT_SAVED_ERROR.to_string(),
+                },
+            );
+            return false;
+        }
+
+        let finished = move_response.result.is_some();
+        self.publish(ServerMessage::Move(move_response));
+        self.arm_flag_timer();
+        if finished {
+            self.premoves.clear();
+        } else {
+            self.play_premove(mover.opposite());
+        }
+        true
+    }
+    fn set_premove(
+        &mut self,
+        player: Color,
+        premove: Option<MoveRequest>,
+    ) -> Result<(), &'static str> {
+        let games = self.server.games.read().unwrap();
+        let game = match games.get(&self.game_id) {
+            None => {
+                error!(
+                    "Could not find game when setting premove with id {}",
+                    self.game_id
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Some(game) => game,
+        };
+        if game.is_over() {
+            return Err(GAME_FINISHED_ERROR);
+        }
+        if game.next_to_move == player {
+            return Err(PREMOVE_ERROR);
+        }
+        drop(games);
+
+        match premove {
+            None => self.premoves.remove(&player),
+            Some(premove) => self.premoves.insert(player, premove),
+        };
+        Ok(())
+    }
+    // The premove only gets validated once it is actually the players turn,
+    // so it might turn out to be illegal after the opponents move
+    fn play_premove(&mut self, player: Color) {
+        let premove = match self.premoves.remove(&player) {
+            None => return,
+            Some(premove) => premove,
+        };
+
+        info!(
+            "Playing premove of {} in game {}",
+            player.to_str(),
+            self.game_id
+        );
+        if let Err(e) = self.make_move(premove.clone(), Some(player)) {
+            info!("Discarding premove of {}: {}", player.to_str(), e);
+            self.send_to_seat(
+                player,
+                ServerMessage::PremoveRejected {
+                    premove,
+                    message: e.to_string(),
+                },
+            );
+        }
+    }
+    pub fn perform_action(
+        &mut self,
+        player: Color,
+        action: GameAction,
+    ) -> Result<(), &'static str> {
+        self.check_not_pending()?;
+        let server = Arc::clone(&self.server);
+        let mut games = server.games.write().unwrap();
+        let game = match games.get_mut(&self.game_id) {
+            None => {
+                error!(
+                    "Could not find game when performing action with id {}",
+                    self.game_id
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Some(game) => game,
+        };
 
-            let db_clone = self.server.db.clone();
-            let turn_number = game.turn_number;
-            let prev_move = game.previous_move.clone();
-            let game_id = self.game_id.clone();
-            let result = game.game_result.clone();
-            actix::spawn(async move {
-                let _ = db_clone
-                    .insert_move(turn_number, &game_id.to_string(), &prev_move, player_str)
+        let taken_back = game.perform_action(player, action)?;
+        if let Some(clock) = game.clock.as_mut().filter(|_| game.game_result.is_some()) {
+            clock.stop(Instant::now());
+        }
+        self.log_event(GameEvent::Action {
+            player: player.to_str(),
+            action,
+        });
+        info!(
+            "Player {} performed {:?} in game {}",
+            player.to_str(),
+            action,
+            self.game_id
+        );
+
+        let server_clone = Arc::clone(&self.server);
+        let game_id = self.game_id;
+        let result = game.game_result;
+        let termination = game.termination;
+        let ply = game.ply();
+        let opening = game.opening;
+        let rated = RatedGame::from_game(game);
+        self.queue_write(async move {
+            let _ = server_clone
+                .store
+                .insert_action(&game_id.to_string(), &player.to_str(), &action.to_str())
+                .await;
+            if taken_back > 0 {
+                let _ = server_clone
+                    .store
+                    .delete_last_moves(&game_id.to_string(), taken_back as u32, opening)
                     .await;
-                match result {
-                    None => (),
-                    Some(GameResult::WhiteWon) => {
-                        info!("White won, finishing game automatically...");
-                        let _ = db_clone.finish_game("1-0", &game_id.to_string()).await;
-                    }
-                    Some(GameResult::BlackWon) => {
-                        info!("Black won, finishing game automatically...");
-                        let _ = db_clone.finish_game("0-1", &game_id.to_string()).await;
-                    }
+                let _ = server_clone
+                    .store
+                    .delete_snapshots(&game_id.to_string(), ply)
+                    .await;
+            }
+            if let (Some(result), Some(termination)) = (result, termination) {
+                let _ = server_clone
+                    .record_result(&game_id.to_string(), result, &termination.to_str(), rated)
+                    .await;
+            }
+            if termination == Some(Termination::Aborted) {
+                if let Err(e) = server_clone.record_abort(game_id).await {
+                    error!("Could not record the abort of game {}: {}", game_id, e);
                 }
-            });
+            }
+        });
 
-            let move_response = &MoveResponse {
-                move_notation: game.previous_move.clone(),
-                player: game.next_to_move.opposite_color(),
-                from: move_request.from,
-                to: move_request.to,
-                promotion: move_request.promotion,
-                en_passant: game.previous_move_was_enpassant,
-                result: match game.game_result {
-                    None => None,
-                    Some(res) => Some(res.to_str()),
-                },
-            };
+        let action_response = ActionResponse {
+            player: player.to_str(),
+            action,
+            result: game.game_result.map(|res| res.to_str()),
+            termination: game.termination.map(|t| t.to_str()),
+        };
+        let sync = (taken_back > 0).then(|| SyncResponse::new(game));
+        drop(games);
 
-            self.connections.iter().for_each(|e| {
-                let _ = e.do_send(BroadcastMessage::new(move_response.clone()));
-            });
+        // queued premoves were meant for a position that no longer exists
+        if taken_back > 0 || action_response.result.is_some() {
+            self.premoves.clear();
+        }
+        self.arm_flag_timer();
+
+        self.publish(ServerMessage::Action(action_response));
+        if let Some(sync) = sync {
+            self.publish(ServerMessage::Sync(sync));
+        }
+        Ok(())
+    }
+    pub fn time_out(&mut self, player: Color) -> Result<(), &'static str> {
+        self.check_not_pending()?;
+        let server = Arc::clone(&self.server);
+        let mut games = server.games.write().unwrap();
+        let game = match games.get_mut(&self.game_id) {
+            None => {
+                error!(
+                    "Could not find game when timing out with id {}",
+                    self.game_id
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Some(game) => game,
+        };
+
+        game.time_out(player)?;
+        if let Some(clock) = &mut game.clock {
+            clock.stop(Instant::now());
+        }
+        self.log_event(GameEvent::Flag {
+            player: player.to_str(),
+        });
+        info!(
+            "Player {} ran out of time in game {}",
+            player.to_str(),
+            self.game_id
+        );
+
+        let result = game.game_result.unwrap_or(GameResult::Draw);
+        let server_clone = Arc::clone(&self.server);
+        let game_id = self.game_id;
+        let rated = RatedGame::from_game(game);
+        self.queue_write(async move {
+            let _ = server_clone
+                .record_result(
+                    &game_id.to_string(),
+                    result,
+                    &Termination::Timeout.to_str(),
+                    rated,
+                )
+                .await;
+        });
+        drop(games);
+
+        self.premoves.clear();
+        self.arm_flag_timer();
+        self.publish(ServerMessage::Flag {
+            player: player.to_str(),
+            result: result.to_str(),
+        });
+        Ok(())
+    }
+    /// Sets the flag timer to go off when the running clock runs out, replacing
+    /// the previous one. Called whenever a move, takeback or berserk changed
+    /// the clock, so games are flagged whether anyone is connected or not.
+    pub fn arm_flag_timer(&mut self) {
+        if let Some(timer) = self.flag_timer.take() {
+            timer.abort();
+        }
+        let remaining = match self.server.games.read().unwrap().get(&self.game_id) {
+            Some(game) if !game.is_over() => game.clock.as_ref().and_then(|clock| {
+                clock
+                    .running()
+                    .map(|color| clock.remaining(color, Instant::now()))
+            }),
+            _ => None,
+        };
+        let remaining = match remaining {
+            None => return,
+            Some(remaining) => remaining,
+        };
+
+        let server = Arc::clone(&self.server);
+        let game_id = self.game_id;
+        self.flag_timer = Some(actix::spawn(async move {
+            actix::clock::sleep(remaining).await;
+            if let Some(room) = server.rooms.write().unwrap().get_mut(&game_id) {
+                room.check_flag();
+            }
+        }));
+    }
+    /// Ends the game if the side to move ran out of time.
+    fn check_flag(&mut self) {
+        // the move being saved was made in time, saving it sets a new timer
+        if self.unsaved.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let flagged = match self.server.games.read().unwrap().get(&self.game_id) {
+            None => None,
+            Some(game) => game.clock.as_ref().and_then(|clock| clock.flagged(now)),
+        };
+
+        match flagged {
+            None => self.arm_flag_timer(),
+            Some(player) => {
+                if let Err(e) = self.time_out(player) {
+                    error!("Could not time out {}: {}", player.to_str(), e);
+                }
+            }
         }
     }
+    pub fn berserk(&mut self, player: Color) -> Result<(), &'static str> {
+        self.check_not_pending()?;
+        let now = Instant::now();
+        let server = Arc::clone(&self.server);
+        let mut games = server.games.write().unwrap();
+        let game = match games.get_mut(&self.game_id) {
+            None => {
+                error!(
+                    "Could not find game when berserking with id {}",
+                    self.game_id
+                );
+                return Err(INTERNAL_SERVER_ERROR);
+            }
+            Some(game) => game,
+        };
+
+        game.berserk(player, now)?;
+        self.log_event(GameEvent::Berserk {
+            player: player.to_str(),
+        });
+        info!(
+            "Player {} berserked in game {}",
+            player.to_str(),
+            self.game_id
+        );
+        let clock = match &game.clock {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(clock) => ClockResponse::new(clock, now),
+        };
+        drop(games);
+
+        self.arm_flag_timer();
+        self.publish(ServerMessage::Berserk {
+            player: player.to_str(),
+            clock,
+        });
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
 pub struct WebSocketConnection {
+    id: Uuid,
     game_id: Uuid,
     server: Arc<Server>,
+    last_seen_seq: Option<u64>,
+    role: Role,
 }
 
 impl Actor for WebSocketConnection {
@@ -146,31 +1053,45 @@ impl Actor for WebSocketConnection {
             Some(room) => room,
         };
 
-        room.add_connection(addr);
+        room.add_connection(self.id, addr, self.role);
+        let delay = match self.role {
+            Role::Spectator => room.spectator_delay,
+            _ => Duration::ZERO,
+        };
+        for text in room.sync_messages(self.last_seen_seq) {
+            if delay.is_zero() {
+                ctx.text(text);
+            } else {
+                ctx.run_later(delay, move |_, ctx| ctx.text(text));
+            }
+        }
     }
     fn stopped(&mut self, ctx: &mut Self::Context) {
         let addr = ctx.address();
         info!("New websocket disconnected for game_id {}", &self.game_id);
 
         let mut rooms = self.server.rooms.write().unwrap();
-        let room = match rooms.get_mut(&self.game_id) {
-            None => {
-                error!(
-                    "Could not find game when removing connection with id {}",
-                    self.game_id
-                );
-                return;
-            }
-            Some(room) => room,
-        };
-
-        room.remove_connection(addr);
+        // the room is gone if the game was removed from memory
+        if let Some(room) = rooms.get_mut(&self.game_id) {
+            room.remove_connection(addr);
+        }
     }
 }
 
 impl WebSocketConnection {
-    pub fn new(game_id: Uuid, server: Arc<Server>) -> WebSocketConnection {
-        WebSocketConnection { game_id, server }
+    pub fn new(
+        game_id: Uuid,
+        server: Arc<Server>,
+        last_seen_seq: Option<u64>,
+        role: Role,
+    ) -> WebSocketConnection {
+        WebSocketConnection {
+            id: Uuid::new_v4(),
+            game_id,
+            server,
+            last_seen_seq,
+            role,
+        }
     }
 }
 
@@ -179,10 +1100,16 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketConnecti
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Text(text)) => {
-                let rooms = self.server.rooms.read().unwrap();
-                match rooms.get(&self.game_id) {
-                    None => (),
-                    Some(room) => room.broadcast_message(text.to_string()),
+                let mut rooms = self.server.rooms.write().unwrap();
+                let result = match rooms.get_mut(&self.game_id) {
+                    None => Ok(()),
+                    Some(room) => room.broadcast_message(text.to_string(), self.id),
+                };
+                if let Err(e) = result {
+                    let error = ServerMessage::Error {
+                        message: e.to_string(),
+                    };
+                    ctx.text(serde_json::to_string(&error).unwrap());
                 }
             }
             _ => (),
@@ -190,36 +1117,1457 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketConnecti
     }
 }
 
-pub struct BroadcastMessage(MoveResponse);
+pub struct BroadcastMessage(String);
 impl Message for BroadcastMessage {
     type Result = ();
 }
 impl BroadcastMessage {
-    pub fn new(move_response: MoveResponse) -> BroadcastMessage {
-        BroadcastMessage(move_response)
+    pub fn new(text: String) -> BroadcastMessage {
+        BroadcastMessage(text)
     }
 }
 impl Handler<BroadcastMessage> for WebSocketConnection {
     type Result = ();
 
     fn handle(&mut self, msg: BroadcastMessage, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_string::<MoveResponse>(&msg.0).unwrap());
+        ctx.text(msg.0);
     }
 }
 
-pub struct FinishBroadcastMessage(String);
-impl Message for FinishBroadcastMessage {
+pub struct DelayedBroadcastMessage(String, Duration);
+impl Message for DelayedBroadcastMessage {
     type Result = ();
 }
-impl FinishBroadcastMessage {
-    pub fn new(text: String) -> FinishBroadcastMessage {
-        FinishBroadcastMessage(text)
+impl DelayedBroadcastMessage {
+    pub fn new(text: String, delay: Duration) -> DelayedBroadcastMessage {
+        DelayedBroadcastMessage(text, delay)
     }
 }
-impl Handler<FinishBroadcastMessage> for WebSocketConnection {
+impl Handler<DelayedBroadcastMessage> for WebSocketConnection {
     type Result = ();
 
-    fn handle(&mut self, msg: FinishBroadcastMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+    fn handle(&mut self, msg: DelayedBroadcastMessage, ctx: &mut Self::Context) {
+        let DelayedBroadcastMessage(text, delay) = msg;
+        ctx.run_later(delay, move |_, ctx| ctx.text(text));
+    }
+}
+
+pub struct KickMessage;
+impl Message for KickMessage {
+    type Result = ();
+}
+impl Handler<KickMessage> for WebSocketConnection {
+    type Result = ();
+
+    fn handle(&mut self, _: KickMessage, ctx: &mut Self::Context) {
+        info!("Kicking connection {} from game {}", self.id, self.game_id);
+        ctx.close(Some(CloseReason {
+            code: CloseCode::Policy,
+            description: Some("You have been kicked".to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+pub struct CloseMessage;
+impl Message for CloseMessage {
+    type Result = ();
+}
+impl Handler<CloseMessage> for WebSocketConnection {
+    type Result = ();
+
+    fn handle(&mut self, _: CloseMessage, ctx: &mut Self::Context) {
+        ctx.close(Some(CloseReason {
+            code: CloseCode::Normal,
+            description: Some("The game was closed".to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+/// The websocket of a simul host, which sees the events of all boards in the
+/// simul and can move on every one of them.
+pub struct SimulHostConnection {
+    simul_id: Uuid,
+    server: Arc<Server>,
+}
+
+impl Actor for SimulHostConnection {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Simul host connected for simul {}", &self.simul_id);
+
+        let games = self.server.games.read().unwrap();
+        let mut simuls = self.server.simuls.write().unwrap();
+        let simul = match simuls.get_mut(&self.simul_id) {
+            None => {
+                error!(
+                    "Could not find simul when connecting host {}",
+                    self.simul_id
+                );
+                ctx.stop();
+                return;
+            }
+            Some(simul) => simul,
+        };
+
+        simul.host_connections.push(ctx.address());
+        let status = SimulMessage::Status(simul.status(&games));
+        ctx.text(serde_json::to_string(&status).unwrap());
+
+        if simul.walking_order.is_some() {
+            ctx.run_interval(SIMUL_FLAG_INTERVAL, |act, _| act.check_flag());
+        }
+    }
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        info!("Simul host disconnected for simul {}", &self.simul_id);
+
+        let addr = ctx.address();
+        let mut simuls = self.server.simuls.write().unwrap();
+        if let Some(simul) = simuls.get_mut(&self.simul_id) {
+            simul.host_connections.retain(|conn| *conn != addr);
+        }
+    }
+}
+
+impl SimulHostConnection {
+    pub fn new(simul_id: Uuid, server: Arc<Server>) -> SimulHostConnection {
+        SimulHostConnection { simul_id, server }
+    }
+    fn handle_message(&self, text: &str) -> Result<(), &'static str> {
+        match serde_json::from_str::<SimulHostRequest>(text) {
+            Err(e) => {
+                error!("Could not parse simul host message {}: {}", text, e);
+                Err(INVALID_SIMUL_MOVE_ERROR)
+            }
+            Ok(SimulHostRequest::Move(request)) => self.host_move(request),
+            Ok(SimulHostRequest::Pass(request)) if request.pass => self.host_pass(),
+            Ok(SimulHostRequest::Pass(_)) => Ok(()),
+        }
+    }
+    fn host_move(&self, request: SimulMoveRequest) -> Result<(), &'static str> {
+        let game_id = Uuid::parse_str(&request.game_id).map_err(|_| INVALID_SIMUL_MOVE_ERROR)?;
+
+        let host_color = match self.server.simuls.read().unwrap().get(&self.simul_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(simul) => match simul.board(game_id) {
+                None => return Err(INVALID_SIMUL_MOVE_ERROR),
+                Some(board) => board.host_color,
+            },
+        };
+
+        let mut rooms = self.server.rooms.write().unwrap();
+        match rooms.get_mut(&game_id) {
+            None => Err(INVALID_SIMUL_MOVE_ERROR),
+            Some(room) => room.make_move(request.move_request, Some(host_color)),
+        }
+    }
+    fn host_pass(&self) -> Result<(), &'static str> {
+        let games = self.server.games.read().unwrap();
+        let mut simuls = self.server.simuls.write().unwrap();
+        let simul = match simuls.get_mut(&self.simul_id) {
+            None => return Err(INTERNAL_SERVER_ERROR),
+            Some(simul) => simul,
+        };
+
+        simul.pass(&games)?;
+        simul.send_to_hosts(&SimulMessage::Status(simul.status(&games)));
+        Ok(())
+    }
+    /// Ends the game at the board the host is standing at once the host used up
+    /// their time budget there.
+    fn check_flag(&self) {
+        let games = self.server.games.read().unwrap();
+        let simuls = self.server.simuls.read().unwrap();
+        let flagged = simuls
+            .get(&self.simul_id)
+            .and_then(|simul| simul.flagged_board(&games))
+            .cloned();
+        drop(simuls);
+        drop(games);
+
+        if let Some(board) = flagged {
+            let mut rooms = self.server.rooms.write().unwrap();
+            if let Some(room) = rooms.get_mut(&board.game_id) {
+                // several host connections race for the same flag
+                let _ = room.time_out(board.host_color);
+            }
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SimulHostConnection {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                if let Err(e) = self.handle_message(&text) {
+                    let error = ServerMessage::Error {
+                        message: e.to_string(),
+                    };
+                    ctx.text(serde_json::to_string(&error).unwrap());
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct SimulBroadcastMessage(String);
+impl Message for SimulBroadcastMessage {
+    type Result = ();
+}
+impl SimulBroadcastMessage {
+    pub fn new(text: String) -> SimulBroadcastMessage {
+        SimulBroadcastMessage(text)
+    }
+}
+impl Handler<SimulBroadcastMessage> for SimulHostConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: SimulBroadcastMessage, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+pub struct LobbyConnection {
+    id: Uuid,
+    user_id: String,
+    display_name: String,
+    server: Arc<Server>,
+}
+
+impl Actor for LobbyConnection {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("User {} joined the lobby", &self.user_id);
+
+        let mut lobby = self.server.lobby.write().unwrap();
+        lobby.members.insert(
+            self.id,
+            LobbyMember {
+                user_id: self.user_id.clone(),
+                addr: ctx.address(),
+            },
+        );
+        let seeks = LobbyMessage::Seeks {
+            seeks: lobby.seeks.clone(),
+        };
+        ctx.text(serde_json::to_string(&seeks).unwrap());
+    }
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("User {} left the lobby", &self.user_id);
+
+        // open seeks only live as long as the connection they were posted from
+        let mut lobby = self.server.lobby.write().unwrap();
+        for seek_id in lobby.remove_member(self.id) {
+            lobby.broadcast(&LobbyMessage::SeekRemoved { seek_id });
+        }
+    }
+}
+
+impl LobbyConnection {
+    pub fn new(user_id: String, display_name: String, server: Arc<Server>) -> LobbyConnection {
+        LobbyConnection {
+            id: Uuid::new_v4(),
+            user_id,
+            display_name,
+            server,
+        }
+    }
+    fn handle_message(&self, text: &str, addr: Addr<LobbyConnection>) -> Result<(), &'static str> {
+        match serde_json::from_str::<LobbyRequest>(text) {
+            Err(e) => {
+                error!("Could not parse lobby message {}: {}", text, e);
+                Err(INVALID_LOBBY_MESSAGE_ERROR)
+            }
+            Ok(LobbyRequest::Seek(request)) => {
+                self.post_seek(request.seek, addr);
+                Ok(())
+            }
+            Ok(LobbyRequest::Accept(request)) => {
+                self.accept_seek(request.accept, addr);
+                Ok(())
+            }
+            Ok(LobbyRequest::Cancel(request)) => {
+                let mut lobby = self.server.lobby.write().unwrap();
+                if !lobby.cancel_seek(&request.cancel, &self.user_id) {
+                    return Err(NO_SEEK_ERROR);
+                }
+                lobby.broadcast(&LobbyMessage::SeekRemoved {
+                    seek_id: request.cancel,
+                });
+                Ok(())
+            }
+        }
+    }
+    fn post_seek(&self, params: SeekParams, addr: Addr<LobbyConnection>) {
+        let mut seek = Seek {
+            seek_id: Uuid::new_v4().to_string(),
+            user_id: self.user_id.clone(),
+            display_name: self.display_name.clone(),
+            rating: 0.0,
+            time_control: params.time_control,
+            variant: params.variant.unwrap_or_default(),
+            color: params.color.unwrap_or_default(),
+            rated: params.rated.unwrap_or(true),
+            min_rating: params.min_rating,
+            max_rating: params.max_rating,
+            connection_id: self.id,
+        };
+
+        let server = Arc::clone(&self.server);
+        actix::spawn(async move {
+            match server.store.get_rating(&seek.user_id, seek.pool()).await {
+                Err(e) => return send_lobby_error(&addr, e),
+                Ok(rating) => seek.rating = rating.unwrap_or_default().rating,
+            }
+
+            let mut lobby = server.lobby.write().unwrap();
+            // the poster might have left while we looked up their rating
+            if !lobby.members.contains_key(&seek.connection_id) {
+                return;
+            }
+            info!("User {} posted seek {}", seek.user_id, seek.seek_id);
+            lobby.add_seek(seek.clone());
+            lobby.broadcast(&LobbyMessage::SeekAdded(seek));
+        });
+    }
+    fn accept_seek(&self, seek_id: String, addr: Addr<LobbyConnection>) {
+        let server = Arc::clone(&self.server);
+        let user_id = self.user_id.clone();
+        actix::spawn(async move {
+            let pool = match server.lobby.read().unwrap().seek(&seek_id) {
+                None => return send_lobby_error(&addr, NO_SEEK_ERROR),
+                Some(seek) => seek.pool(),
+            };
+            let rating = match server.store.get_rating(&user_id, pool).await {
+                Err(e) => return send_lobby_error(&addr, e),
+                Ok(rating) => rating.unwrap_or_default().rating,
+            };
+
+            // taking the seek out of the lobby is what makes accepting it atomic
+            let seek = match server
+                .lobby
+                .write()
+                .unwrap()
+                .take_seek(&seek_id, &user_id, rating)
+            {
+                Err(e) => return send_lobby_error(&addr, e),
+                Ok(seek) => seek,
+            };
+
+            let poster_color = seek.color.resolve();
+            let players = match poster_color {
+                Color::WHITE => Players {
+                    white: Some(seek.user_id.clone()),
+                    black: Some(user_id.clone()),
+                },
+                Color::BLACK => Players {
+                    white: Some(user_id.clone()),
+                    black: Some(seek.user_id.clone()),
+                },
+            };
+            let game_id = Uuid::new_v4();
+            let mut game = Game::new(game_id, poster_color);
+            game.players = players;
+            game.set_time_control(seek.time_control);
+            game.variant = seek.variant;
+            game.rated = seek.rated;
+
+            if let Err(e) = server.add_game(game).await {
+                // put the seek back so the poster does not lose it
+                let mut lobby = server.lobby.write().unwrap();
+                if lobby.members.contains_key(&seek.connection_id) {
+                    lobby.add_seek(seek.clone());
+                }
+                return send_lobby_error(&addr, e);
+            }
+
+            info!(
+                "User {} accepted seek {} of user {}, started game {}",
+                user_id, seek.seek_id, seek.user_id, game_id
+            );
+            let lobby = server.lobby.read().unwrap();
+            lobby.broadcast(&LobbyMessage::SeekRemoved {
+                seek_id: seek.seek_id.clone(),
+            });
+            lobby.send_to_user(
+                &seek.user_id,
+                &LobbyMessage::GameStarted {
+                    game_id: game_id.to_string(),
+                    color: poster_color.to_str(),
+                },
+            );
+            lobby.send_to_user(
+                &user_id,
+                &LobbyMessage::GameStarted {
+                    game_id: game_id.to_string(),
+                    color: poster_color.opposite().to_str(),
+                },
+            );
+        });
+    }
+}
+
+fn send_lobby_error(addr: &Addr<LobbyConnection>, message: &str) {
+    let error = LobbyMessage::Error {
+        message: message.to_string(),
+    };
+    addr.do_send(LobbyBroadcastMessage::new(
+        serde_json::to_string(&error).unwrap(),
+    ));
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for LobbyConnection {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                if let Err(e) = self.handle_message(&text, ctx.address()) {
+                    send_lobby_error(&ctx.address(), e);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct LobbyBroadcastMessage(String);
+impl Message for LobbyBroadcastMessage {
+    type Result = ();
+}
+impl LobbyBroadcastMessage {
+    pub fn new(text: String) -> LobbyBroadcastMessage {
+        LobbyBroadcastMessage(text)
+    }
+}
+impl Handler<LobbyBroadcastMessage> for LobbyConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: LobbyBroadcastMessage, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+pub struct ArenaConnection {
+    id: Uuid,
+    arena_id: Uuid,
+    // None for connections that only watch the standings
+    user_id: Option<String>,
+    server: Arc<Server>,
+}
+
+impl Actor for ArenaConnection {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("New websocket connected for arena {}", &self.arena_id);
+
+        let mut arenas = self.server.arenas.write().unwrap();
+        let arena = match arenas.get_mut(&self.arena_id) {
+            None => {
+                error!("Could not find arena when connecting {}", self.arena_id);
+                ctx.stop();
+                return;
+            }
+            Some(arena) => arena,
+        };
+
+        arena.members.insert(
+            self.id,
+            ArenaMember {
+                user_id: self.user_id.clone(),
+                addr: ctx.address(),
+            },
+        );
+        let status = ArenaMessage::Status(arena.status(Instant::now()));
+        ctx.text(serde_json::to_string(&status).unwrap());
+    }
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("Websocket disconnected for arena {}", &self.arena_id);
+
+        let mut arenas = self.server.arenas.write().unwrap();
+        let arena = match arenas.get_mut(&self.arena_id) {
+            None => return,
+            Some(arena) => arena,
+        };
+        arena.members.remove(&self.id);
+
+        // players who leave the arena are no longer paired
+        if let Some(user_id) = &self.user_id {
+            let still_connected = arena
+                .members
+                .values()
+                .any(|member| member.user_id.as_ref() == Some(user_id));
+            if !still_connected && arena.withdraw(user_id).is_ok() {
+                arena.broadcast(&ArenaMessage::Status(arena.status(Instant::now())));
+            }
+        }
+    }
+}
+
+impl ArenaConnection {
+    pub fn new(arena_id: Uuid, user_id: Option<String>, server: Arc<Server>) -> ArenaConnection {
+        ArenaConnection {
+            id: Uuid::new_v4(),
+            arena_id,
+            user_id,
+            server,
+        }
+    }
+    fn handle_message(&self, text: &str, addr: Addr<ArenaConnection>) -> Result<(), &'static str> {
+        let request = match serde_json::from_str::<ArenaRequest>(text) {
+            Err(e) => {
+                error!("Could not parse arena message {}: {}", text, e);
+                return Err(INVALID_ARENA_MESSAGE_ERROR);
+            }
+            Ok(request) => request,
+        };
+        let user_id = match &self.user_id {
+            None => return Err(ARENA_LOGIN_ERROR),
+            Some(user_id) => user_id.clone(),
+        };
+
+        match request {
+            ArenaRequest::Join(request) if request.join => {
+                let server = Arc::clone(&self.server);
+                let arena_id = self.arena_id;
+                actix::spawn(async move {
+                    if let Err(e) = server.join_arena(arena_id, &user_id).await {
+                        send_arena_error(&addr, e);
+                    }
+                });
+                Ok(())
+            }
+            ArenaRequest::Join(_) => self.server.withdraw_from_arena(self.arena_id, &user_id),
+            ArenaRequest::Berserk(request) => {
+                let game_id = Uuid::parse_str(&request.berserk).map_err(|_| NOT_IN_ARENA_ERROR)?;
+                self.server.berserk(self.arena_id, game_id, &user_id)
+            }
+        }
+    }
+}
+
+fn send_arena_error(addr: &Addr<ArenaConnection>, message: &str) {
+    let error = ArenaMessage::Error {
+        message: message.to_string(),
+    };
+    addr.do_send(ArenaBroadcastMessage::new(
+        serde_json::to_string(&error).unwrap(),
+    ));
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ArenaConnection {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                if let Err(e) = self.handle_message(&text, ctx.address()) {
+                    send_arena_error(&ctx.address(), e);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct ArenaBroadcastMessage(String);
+impl Message for ArenaBroadcastMessage {
+    type Result = ();
+}
+impl ArenaBroadcastMessage {
+    pub fn new(text: String) -> ArenaBroadcastMessage {
+        ArenaBroadcastMessage(text)
+    }
+}
+impl Handler<ArenaBroadcastMessage> for ArenaConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: ArenaBroadcastMessage, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+#[cfg(test)]
+mod test_ws {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::{error::PayloadError, web::Bytes};
+    use actix_web_actors::ws;
+    use async_trait::async_trait;
+    use futures_util::{stream, StreamExt};
+    use uuid::Uuid;
+
+    use crate::{
+        backup::{ExportedGame, ReplayedMove},
+        correspondence::Vacation,
+        db::{
+            ApiKey, ArchivedGame, AwaitingGame, ChatMessage, CorrespondenceGame, DBGame,
+            ExplorerMove, GameFilter, Invite, LeaderboardEntry, Move, RatingHistoryEntry,
+            StaleGame, UserGame,
+        },
+        game::{
+            chess_piece::Color,
+            eco::Opening,
+            event::{GameEvent, LoggedEvent, Snapshot},
+            time_control::TimeControl,
+            Game, GameAction, GameResult, Players,
+        },
+        rating::{RatedGame, Rating, RatingPool},
+        server::Server,
+        stats::MoveTime,
+        store::{
+            AccountStore, ArchiveStore, CompetitionStore, CorrespondenceStore, ExplorerStore,
+            GameStore, MemoryStore, NewMove, Store,
+        },
+        user::{User, UserRole},
+        utils::{
+            auth::Principal,
+            error::{
+                CHAT_NOT_SAVED_ERROR, COMPETITION_GAME_DELETE_ERROR, INTERNAL_SERVER_ERROR,
+                VOTER_ONLY_ERROR,
+            },
+            request::MoveRequest,
+            response::ServerMessage,
+        },
+    };
+
+    use super::{Role, WebSocketConnection};
+
+    // stores everything but moves and chat, and is slow to log the first event if asked to
+    #[derive(Default)]
+    struct BrokenStore(MemoryStore, bool);
+
+    #[async_trait]
+    impl GameStore for BrokenStore {
+        async fn create_game(&self, game: &Game) -> Result<(), &'static str> {
+            self.0.create_game(game).await
+        }
+        async fn finish_game(
+            &self,
+            result: &str,
+            termination: &str,
+            id: &str,
+        ) -> Result<bool, &'static str> {
+            self.0.finish_game(result, termination, id).await
+        }
+        async fn abort_game(&self, id: &str) -> Result<(), &'static str> {
+            self.0.abort_game(id).await
+        }
+        async fn insert_move(&self, _: &str, _: NewMove<'_>) -> Result<(), &'static str> {
+            Err(INTERNAL_SERVER_ERROR)
+        }
+        async fn get_moves(&self, id: &str) -> Result<Vec<Move>, &'static str> {
+            self.0.get_moves(id).await
+        }
+        async fn get_active_games(&self) -> Result<Vec<DBGame>, &'static str> {
+            self.0.get_active_games().await
+        }
+        async fn get_game(&self, id: &str) -> Result<Option<Game>, &'static str> {
+            self.0.get_game(id).await
+        }
+        async fn append_event(
+            &self,
+            id: &str,
+            seq: Option<u64>,
+            event: &GameEvent,
+        ) -> Result<u64, &'static str> {
+            if self.1 && seq == Some(1) {
+                actix::clock::sleep(Duration::from_millis(20)).await;
+            }
+            self.0.append_event(id, seq, event).await
+        }
+        async fn get_events(
+            &self,
+            id: &str,
+            after_seq: u64,
+        ) -> Result<Vec<LoggedEvent>, &'static str> {
+            self.0.get_events(id, after_seq).await
+        }
+        async fn get_snapshot(
+            &self,
+            id: &str,
+            until_seq: Option<u64>,
+        ) -> Result<Option<Snapshot>, &'static str> {
+            self.0.get_snapshot(id, until_seq).await
+        }
+        async fn delete_snapshots(&self, id: &str, after_ply: u32) -> Result<(), &'static str> {
+            self.0.delete_snapshots(id, after_ply).await
+        }
+        async fn insert_chat(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+        ) -> Result<(), &'static str> {
+            Err(INTERNAL_SERVER_ERROR)
+        }
+        async fn get_chat(&self, id: &str) -> Result<Vec<ChatMessage>, &'static str> {
+            self.0.get_chat(id).await
+        }
+        async fn insert_action(
+            &self,
+            id: &str,
+            player: &str,
+            action: &str,
+        ) -> Result<(), &'static str> {
+            self.0.insert_action(id, player, action).await
+        }
+        async fn delete_last_moves(
+            &self,
+            id: &str,
+            count: u32,
+            opening: Option<Opening>,
+        ) -> Result<(), &'static str> {
+            self.0.delete_last_moves(id, count, opening).await
+        }
+        async fn get_rating(
+            &self,
+            user_id: &str,
+            pool: RatingPool,
+        ) -> Result<Option<Rating>, &'static str> {
+            self.0.get_rating(user_id, pool).await
+        }
+        async fn rate_game(
+            &self,
+            game: &RatedGame,
+        ) -> Result<Option<[(Rating, Rating); 2]>, &'static str> {
+            self.0.rate_game(game).await
+        }
+        async fn add_to_explorer(
+            &self,
+            game_id: &str,
+            result: &str,
+            average_rating: Option<f64>,
+        ) -> Result<(), &'static str> {
+            self.0
+                .add_to_explorer(game_id, result, average_rating)
+                .await
+        }
+        async fn finish_pairing(&self, game_id: &str, result: &str) -> Result<(), &'static str> {
+            self.0.finish_pairing(game_id, result).await
+        }
+        async fn create_invite(
+            &self,
+            token_hash: &str,
+            game_id: &str,
+            created_by: &str,
+            seat: &str,
+            password_hash: Option<String>,
+            expires_at: Option<String>,
+        ) -> Result<(), &'static str> {
+            self.0
+                .create_invite(
+                    token_hash,
+                    game_id,
+                    created_by,
+                    seat,
+                    password_hash,
+                    expires_at,
+                )
+                .await
+        }
+        async fn get_invite(&self, token_hash: &str) -> Result<Option<Invite>, &'static str> {
+            self.0.get_invite(token_hash).await
+        }
+        async fn accept_invite(
+            &self,
+            token_hash: &str,
+            user_id: &str,
+        ) -> Result<bool, &'static str> {
+            self.0.accept_invite(token_hash, user_id).await
+        }
+        async fn set_player(
+            &self,
+            game_id: &str,
+            color: Color,
+            user_id: &str,
+        ) -> Result<(), &'static str> {
+            self.0.set_player(game_id, color, user_id).await
+        }
+        async fn create_user(&self, user: &User) -> Result<(), &'static str> {
+            self.0.create_user(user).await
+        }
+        async fn get_user(&self, id: &str) -> Result<Option<User>, &'static str> {
+            self.0.get_user(id).await
+        }
+    }
+
+    #[async_trait]
+    impl ArchiveStore for BrokenStore {
+        async fn get_user_games(
+            &self,
+            id: &str,
+            include_private: bool,
+            viewer: Option<&str>,
+        ) -> Result<Vec<UserGame>, &'static str> {
+            self.0.get_user_games(id, include_private, viewer).await
+        }
+        async fn search_games(
+            &self,
+            filter: &GameFilter,
+            newest_first: bool,
+            limit: u32,
+            offset: u32,
+        ) -> Result<(u64, Vec<ArchivedGame>), &'static str> {
+            self.0
+                .search_games(filter, newest_first, limit, offset)
+                .await
+        }
+        async fn soft_delete_game(&self, id: &str) -> Result<bool, &'static str> {
+            self.0.soft_delete_game(id).await
+        }
+        async fn delete_game(&self, id: &str) -> Result<bool, &'static str> {
+            self.0.delete_game(id).await
+        }
+        async fn is_rated_game(&self, id: &str) -> Result<bool, &'static str> {
+            self.0.is_rated_game(id).await
+        }
+        async fn is_competition_game(&self, id: &str) -> Result<bool, &'static str> {
+            self.0.is_competition_game(id).await
+        }
+        async fn get_export_page(
+            &self,
+            after: Option<&(String, String)>,
+            limit: u32,
+        ) -> Result<Vec<ExportedGame>, &'static str> {
+            self.0.get_export_page(after, limit).await
+        }
+        async fn get_move_ucis(&self, id: &str) -> Result<Vec<Option<String>>, &'static str> {
+            self.0.get_move_ucis(id).await
+        }
+        async fn get_result(&self, id: &str) -> Result<Option<String>, &'static str> {
+            self.0.get_result(id).await
+        }
+        async fn get_move_times(&self, id: &str) -> Result<Vec<MoveTime>, &'static str> {
+            self.0.get_move_times(id).await
+        }
+        async fn import_game(
+            &self,
+            game: &ExportedGame,
+            start_hash: u64,
+            moves: &[ReplayedMove],
+        ) -> Result<bool, &'static str> {
+            self.0.import_game(game, start_hash, moves).await
+        }
+        async fn get_stale_games(&self, before: &str) -> Result<Vec<StaleGame>, &'static str> {
+            self.0.get_stale_games(before).await
+        }
+    }
+
+    #[async_trait]
+    impl ExplorerStore for BrokenStore {
+        async fn get_explorer_moves(
+            &self,
+            position_hash: u64,
+        ) -> Result<Vec<ExplorerMove>, &'static str> {
+            self.0.get_explorer_moves(position_hash).await
+        }
+        async fn get_unexplored_games(&self) -> Result<Vec<(String, String)>, &'static str> {
+            self.0.get_unexplored_games().await
+        }
+        async fn save_positions(
+            &self,
+            id: &str,
+            positions: &[(u32, u64)],
+            moves: &[(u32, String)],
+        ) -> Result<(), &'static str> {
+            self.0.save_positions(id, positions, moves).await
+        }
+    }
+
+    #[async_trait]
+    impl CorrespondenceStore for BrokenStore {
+        async fn get_correspondence_games(&self) -> Result<Vec<CorrespondenceGame>, &'static str> {
+            self.0.get_correspondence_games().await
+        }
+        async fn get_awaiting_games(
+            &self,
+            user_id: &str,
+        ) -> Result<Vec<AwaitingGame>, &'static str> {
+            self.0.get_awaiting_games(user_id).await
+        }
+        async fn add_vacation(
+            &self,
+            user_id: &str,
+            starts_at: &str,
+            ends_at: &str,
+        ) -> Result<(), &'static str> {
+            self.0.add_vacation(user_id, starts_at, ends_at).await
+        }
+        async fn get_vacations(&self, user_id: &str) -> Result<Vec<Vacation>, &'static str> {
+            self.0.get_vacations(user_id).await
+        }
+    }
+
+    #[async_trait]
+    impl CompetitionStore for BrokenStore {
+        async fn create_simul(
+            &self,
+            id: &str,
+            host_name: &str,
+            walking_order: bool,
+            host_time_budget_secs: Option<u64>,
+            game_ids: &[String],
+        ) -> Result<(), &'static str> {
+            self.0
+                .create_simul(
+                    id,
+                    host_name,
+                    walking_order,
+                    host_time_budget_secs,
+                    game_ids,
+                )
+                .await
+        }
+        async fn create_tournament(
+            &self,
+            id: &str,
+            name: &str,
+            format: &str,
+            rounds: u32,
+            players: &[String],
+        ) -> Result<(), &'static str> {
+            self.0
+                .create_tournament(id, name, format, rounds, players)
+                .await
+        }
+        async fn insert_pairing(
+            &self,
+            tournament_id: &str,
+            round: u32,
+            white_user_id: &str,
+            black_user_id: Option<&str>,
+            game_id: Option<&str>,
+            result: Option<String>,
+        ) -> Result<(), &'static str> {
+            self.0
+                .insert_pairing(
+                    tournament_id,
+                    round,
+                    white_user_id,
+                    black_user_id,
+                    game_id,
+                    result,
+                )
+                .await
+        }
+        async fn create_arena(
+            &self,
+            id: &str,
+            name: &str,
+            time_control: &str,
+            duration_secs: u64,
+        ) -> Result<(), &'static str> {
+            self.0
+                .create_arena(id, name, time_control, duration_secs)
+                .await
+        }
+        async fn insert_arena_game(
+            &self,
+            arena_id: &str,
+            game_id: &str,
+        ) -> Result<(), &'static str> {
+            self.0.insert_arena_game(arena_id, game_id).await
+        }
+    }
+
+    #[async_trait]
+    impl AccountStore for BrokenStore {
+        async fn create_api_key(
+            &self,
+            id: &str,
+            label: &str,
+            key_hash: &str,
+            role: &str,
+        ) -> Result<(), &'static str> {
+            self.0.create_api_key(id, label, key_hash, role).await
+        }
+        async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, &'static str> {
+            self.0.get_api_key(key_hash).await
+        }
+        async fn get_api_keys(&self) -> Result<Vec<ApiKey>, &'static str> {
+            self.0.get_api_keys().await
+        }
+        async fn revoke_api_key(&self, id: &str) -> Result<bool, &'static str> {
+            self.0.revoke_api_key(id).await
+        }
+        async fn get_rating_history(
+            &self,
+            user_id: &str,
+        ) -> Result<Vec<RatingHistoryEntry>, &'static str> {
+            self.0.get_rating_history(user_id).await
+        }
+        async fn get_leaderboard(
+            &self,
+            pool: RatingPool,
+            max_deviation: f64,
+            limit: u32,
+        ) -> Result<Vec<LeaderboardEntry>, &'static str> {
+            self.0.get_leaderboard(pool, max_deviation, limit).await
+        }
+    }
+
+    async fn play_e4(store: Arc<dyn Store>) -> (Arc<Server>, Uuid) {
+        let server = Arc::new(Server::with_store(store));
+        let game_id = Uuid::new_v4();
+        server
+            .add_game(Game::new(game_id, Color::WHITE))
+            .await
+            .unwrap();
+
+        let e4 = MoveRequest {
+            from: "e2".to_string(),
+            to: "e4".to_string(),
+            promotion: "Q".to_string(),
+        };
+        {
+            let mut rooms = server.rooms.write().unwrap();
+            let room = rooms.get_mut(&game_id).unwrap();
+            room.make_move(e4.clone(), None).unwrap();
+            // nothing else happens in the game until the move is saved
+            assert!(room.make_move(e4, None).is_err());
+        }
+
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        (server, game_id)
+    }
+
+    #[actix_web::test]
+    async fn test_saved_move() {
+        let store = Arc::new(MemoryStore::default());
+        let (server, game_id) = play_e4(store.clone()).await;
+
+        let moves = store.get_moves(&game_id.to_string()).await.unwrap();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].ply, 1);
+        let games = server.games.read().unwrap();
+        assert_eq!(games[&game_id].next_to_move, Color::BLACK);
+        assert_eq!(server.rooms.read().unwrap()[&game_id].seq, 1);
+    }
+
+    // opens a websocket connection without a client on the other end
+    async fn connect(server: &Arc<Server>, game_id: Uuid, role: Role) -> Uuid {
+        connect_recording(server, game_id, role).await.0
+    }
+
+    // like `connect`, but keeps the frames sent to the connection
+    async fn connect_recording(
+        server: &Arc<Server>,
+        game_id: Uuid,
+        role: Role,
+    ) -> (Uuid, Rc<RefCell<Vec<String>>>) {
+        let connection = WebSocketConnection::new(game_id, server.clone(), None, role);
+        let connection_id = connection.id;
+        let (_, frames) = ws::WebsocketContext::create_with_addr(
+            connection,
+            stream::pending::<Result<Bytes, PayloadError>>(),
+        );
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let frames_received = received.clone();
+        actix::spawn(frames.for_each(move |frame| {
+            if let Ok(frame) = frame {
+                let text = String::from_utf8_lossy(&frame).to_string();
+                frames_received.borrow_mut().push(text);
+            }
+            async {}
+        }));
+        actix::clock::sleep(Duration::from_millis(10)).await;
+        (connection_id, received)
+    }
+
+    #[actix_web::test]
+    async fn test_chat_is_sent_once_saved() {
+        for (store, saved) in [
+            (Arc::new(MemoryStore::default()) as Arc<dyn Store>, true),
+            (Arc::new(BrokenStore::default()) as Arc<dyn Store>, false),
+        ] {
+            let server = Arc::new(Server::with_store(store.clone()));
+            let game_id = Uuid::new_v4();
+            server
+                .add_game(Game::new(game_id, Color::WHITE))
+                .await
+                .unwrap();
+            let (player, received) =
+                connect_recording(&server, game_id, Role::Player(Color::WHITE)).await;
+
+            server
+                .rooms
+                .write()
+                .unwrap()
+                .get_mut(&game_id)
+                .unwrap()
+                .chat(player, "good luck".to_string())
+                .unwrap();
+            actix::clock::sleep(Duration::from_millis(50)).await;
+
+            // a message nobody can read back later is never shown
+            let received = received.borrow().join("");
+            assert_eq!(received.contains("good luck"), saved);
+            assert_eq!(received.contains(CHAT_NOT_SAVED_ERROR), !saved);
+            let id = game_id.to_string();
+            assert_eq!(store.get_chat(&id).await.unwrap().len(), saved as usize);
+            assert_eq!(
+                store.get_events(&id, 0).await.unwrap().len(),
+                saved as usize
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_only_voters_decide_the_result() {
+        let server = Arc::new(Server::with_store(Arc::new(MemoryStore::default())));
+        let game_id = Uuid::new_v4();
+        server
+            .add_game(Game::new(game_id, Color::WHITE))
+            .await
+            .unwrap();
+        let player = connect(&server, game_id, Role::Player(Color::WHITE)).await;
+        let voter = connect(&server, game_id, Role::Voter).await;
+
+        let mut rooms = server.rooms.write().unwrap();
+        let room = rooms.get_mut(&game_id).unwrap();
+        // a player cannot hand themselves the win
+        assert_eq!(
+            room.broadcast_message("white".to_string(), player),
+            Err(VOTER_ONLY_ERROR)
+        );
+        assert_eq!(room.seq, 0);
+        assert_eq!(room.broadcast_message("black".to_string(), voter), Ok(()));
+        assert_eq!(room.seq, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_restore_games() {
+        let store = Arc::new(MemoryStore::default());
+        let (_, game_id) = play_e4(store.clone()).await;
+
+        // a restarted server rebuilds the game from its event log
+        let server = Arc::new(Server::with_store(store));
+        assert_eq!(server.restore_games().await, Ok(1));
+        assert_eq!(server.games.read().unwrap()[&game_id].ply(), 1);
+        let mut rooms = server.rooms.write().unwrap();
+        let room = rooms.get_mut(&game_id).unwrap();
+        assert_eq!(room.log_seq, 1);
+        assert_eq!(room.seq, 1);
+
+        // a client of the old server gets a snapshot, whatever it saw last
+        for last_seen_seq in [0, 1, 5] {
+            let messages = room.sync_messages(Some(last_seen_seq));
+            assert_eq!(messages.len(), 1);
+            assert!(messages[0].starts_with(r#"{"seq":1,"type":"sync""#));
+        }
+        // but what is published after the restart is replayed
+        for _ in 0..2 {
+            room.publish(ServerMessage::Error {
+                message: "test".to_string(),
+            });
+        }
+        let messages = room.sync_messages(Some(2));
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with(r#"{"seq":3,"type":"error""#));
+    }
+
+    #[actix_web::test]
+    async fn test_hard_delete_competition_games() {
+        let store = Arc::new(MemoryStore::default());
+        let (server, simul_game) = play_e4(store.clone()).await;
+        let (_, tournament_game) = play_e4(store.clone()).await;
+        store
+            .create_simul("simul", "host", false, None, &[simul_game.to_string()])
+            .await
+            .unwrap();
+        store
+            .create_tournament("tournament", "Open", "swiss", 1, &[])
+            .await
+            .unwrap();
+        store
+            .insert_pairing(
+                "tournament",
+                1,
+                "alice",
+                Some("bob"),
+                Some(&tournament_game.to_string()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // their boards and pairings would point at nothing
+        for game_id in [simul_game, tournament_game] {
+            assert_eq!(
+                server.delete_game(game_id, true).await,
+                Err(COMPETITION_GAME_DELETE_ERROR)
+            );
+            assert!(store
+                .get_game(&game_id.to_string())
+                .await
+                .unwrap()
+                .is_some());
+            // hiding them is fine
+            assert_eq!(server.delete_game(game_id, false).await, Ok(true));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unsaved_move_is_rolled_back() {
+        let (server, game_id) = play_e4(Arc::new(BrokenStore::default())).await;
+
+        let games = server.games.read().unwrap();
+        assert_eq!(games[&game_id].next_to_move, Color::WHITE);
+        assert!(games[&game_id].move_history.is_empty());
+        let rooms = server.rooms.read().unwrap();
+        // the move was never published
+        assert_eq!(rooms[&game_id].seq, 0);
+        assert!(rooms[&game_id].unsaved.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_events_are_logged_in_order() {
+        let store = Arc::new(BrokenStore(MemoryStore::default(), true));
+        let server = Arc::new(Server::with_store(store.clone()));
+        let game_id = Uuid::new_v4();
+        server
+            .add_game(Game::new(game_id, Color::WHITE))
+            .await
+            .unwrap();
+        {
+            let mut rooms = server.rooms.write().unwrap();
+            let room = rooms.get_mut(&game_id).unwrap();
+            for player in ["white", "black"] {
+                room.log_event(GameEvent::Berserk {
+                    player: player.to_string(),
+                });
+            }
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        // the second event waits for the slow first one instead of overtaking it
+        let events = store.get_events(&game_id.to_string(), 0).await.unwrap();
+        let seqs: Vec<u64> = events.iter().map(|logged| logged.seq).collect();
+        assert_eq!(seqs, vec![1, 2]);
+    }
+
+    #[actix_web::test]
+    async fn test_abort() {
+        let store = Arc::new(MemoryStore::default());
+        let (server, game_id) = play_e4(store.clone()).await;
+        server
+            .rooms
+            .write()
+            .unwrap()
+            .get_mut(&game_id)
+            .unwrap()
+            .perform_action(Color::BLACK, GameAction::Abort)
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        // the game and its room are gone, and it is not restored after a restart
+        assert!(server.games.read().unwrap().is_empty());
+        assert!(server.rooms.read().unwrap().is_empty());
+        assert!(store.get_active_games().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_stale_voting_game_survives() {
+        let mut server = Server::with_store(Arc::new(MemoryStore::default()));
+        server.cleanup_config.inactivity_timeout = Some(Duration::ZERO);
+        let server = Arc::new(server);
+        let (voting_id, users_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut users_game = Game::new(users_id, Color::WHITE);
+        users_game.players = Players {
+            white: Some("alice".to_string()),
+            black: Some("bob".to_string()),
+        };
+        server
+            .add_game(Game::new(voting_id, Color::WHITE))
+            .await
+            .unwrap();
+        server.add_game(users_game).await.unwrap();
+        {
+            let mut rooms = server.rooms.write().unwrap();
+            for (game_id, seat) in [(voting_id, None), (users_id, Some(Color::WHITE))] {
+                let e4 = MoveRequest {
+                    from: "e2".to_string(),
+                    to: "e4".to_string(),
+                    promotion: "Q".to_string(),
+                };
+                rooms
+                    .get_mut(&game_id)
+                    .unwrap()
+                    .make_move(e4, seat)
+                    .unwrap();
+            }
+        }
+        // timestamps only order reliably across seconds
+        actix::clock::sleep(Duration::from_millis(1100)).await;
+
+        server.end_stale_games().await.unwrap();
+        let games = server.games.read().unwrap();
+        // voting games have no deadline, however long the crowd takes
+        assert!(!games[&voting_id].is_over());
+        assert!(!games.contains_key(&users_id));
+    }
+
+    #[actix_web::test]
+    async fn test_evict_finished_games() {
+        let (server, game_id) = play_e4(Arc::new(MemoryStore::default())).await;
+        assert_eq!(server.evict_finished_games(), 0);
+
+        server
+            .rooms
+            .write()
+            .unwrap()
+            .get_mut(&game_id)
+            .unwrap()
+            .perform_action(Color::BLACK, GameAction::Resign)
+            .unwrap();
+        assert_eq!(server.evict_finished_games(), 1);
+        assert!(server.games.read().unwrap().is_empty());
+        assert!(server.rooms.read().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_reconnect_with_clock() {
+        let server = Arc::new(Server::with_store(Arc::new(MemoryStore::default())));
+        let game_id = Uuid::new_v4();
+        let mut game = Game::new(game_id, Color::WHITE);
+        game.set_time_control(Some(TimeControl {
+            initial_secs: 180,
+            increment_secs: 2,
+        }));
+        server.add_game(game).await.unwrap();
+        {
+            let mut rooms = server.rooms.write().unwrap();
+            let e4 = MoveRequest {
+                from: "e2".to_string(),
+                to: "e4".to_string(),
+                promotion: "Q".to_string(),
+            };
+            rooms
+                .get_mut(&game_id)
+                .unwrap()
+                .make_move(e4, None)
+                .unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let rooms = server.rooms.read().unwrap();
+        let parse = |text: &String| serde_json::from_str::<serde_json::Value>(text).unwrap();
+        let check_clock = |clock: &serde_json::Value| {
+            // white's time only starts running after their first move
+            assert_eq!(clock["white_ms"], 180_000);
+            assert!(clock["black_ms"].as_u64().unwrap() <= 180_000);
+            assert!(clock["black_ms"].as_u64().unwrap() > 179_000);
+            assert_eq!(clock["running"], "black");
+        };
+
+        // a new client gets a snapshot with both clocks
+        let messages = rooms[&game_id].sync_messages(None);
+        assert_eq!(messages.len(), 1);
+        let snapshot = parse(&messages[0]);
+        assert_eq!(snapshot["type"], "sync");
+        assert_eq!(snapshot["seq"], 1);
+        check_clock(&snapshot["clock"]);
+
+        // a reconnecting client gets the missed move and the clock as it is now
+        let messages = rooms[&game_id].sync_messages(Some(0));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(parse(&messages[0])["type"], "move");
+        let clock = parse(&messages[1]);
+        assert_eq!(clock["type"], "clock");
+        check_clock(&clock);
+    }
+
+    #[actix_web::test]
+    async fn test_flag_without_connections() {
+        let store = Arc::new(MemoryStore::default());
+        let server = Arc::new(Server::with_store(store.clone()));
+        let game_id = Uuid::new_v4();
+        let mut game = Game::new(game_id, Color::WHITE);
+        game.set_time_control(Some(TimeControl {
+            initial_secs: 1,
+            increment_secs: 0,
+        }));
+        server.add_game(game).await.unwrap();
+        let e4 = MoveRequest {
+            from: "e2".to_string(),
+            to: "e4".to_string(),
+            promotion: "Q".to_string(),
+        };
+        server
+            .rooms
+            .write()
+            .unwrap()
+            .get_mut(&game_id)
+            .unwrap()
+            .make_move(e4, None)
+            .unwrap();
+
+        actix::clock::sleep(Duration::from_millis(500)).await;
+        assert!(server.games.read().unwrap()[&game_id].game_result.is_none());
+        // the room flags black once their second is up, nobody has to be connected
+        actix::clock::sleep(Duration::from_millis(700)).await;
+        assert_eq!(
+            server.games.read().unwrap()[&game_id].game_result,
+            Some(GameResult::WhiteWon)
+        );
+        assert!(store.get_active_games().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_spectator_delay() {
+        let server = Arc::new(Server::with_store(Arc::new(MemoryStore::default())));
+        let game_id = Uuid::new_v4();
+        let mut game = Game::new(game_id, Color::WHITE);
+        game.players = Players {
+            white: Some("alice".to_string()),
+            black: Some("bob".to_string()),
+        };
+        server.add_game(game).await.unwrap();
+        {
+            let mut rooms = server.rooms.write().unwrap();
+            let room = rooms.get_mut(&game_id).unwrap();
+            room.set_spectator_delay(Duration::from_secs(60));
+            let e4 = MoveRequest {
+                from: "e2".to_string(),
+                to: "e4".to_string(),
+                promotion: "Q".to_string(),
+            };
+            room.make_move(e4, Some(Color::WHITE)).unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let principal = |user_id: &str, role: UserRole| Principal {
+            user_id: Some(user_id.to_string()),
+            api_key_label: None,
+            role,
+        };
+        let ply_seen_by =
+            |principal: Principal| server.visible_game(game_id, &principal).unwrap().ply();
+        assert_eq!(ply_seen_by(principal("alice", UserRole::Player)), 1);
+        assert_eq!(ply_seen_by(principal("admin", UserRole::Admin)), 1);
+        // everyone else is still looking at the position before the move
+        assert_eq!(ply_seen_by(principal("carol", UserRole::Player)), 0);
+
+        // and does not get to see a private game at all
+        server
+            .games
+            .write()
+            .unwrap()
+            .get_mut(&game_id)
+            .unwrap()
+            .private = true;
+        assert!(server
+            .visible_game(game_id, &principal("carol", UserRole::Player))
+            .is_none());
+        assert_eq!(ply_seen_by(principal("alice", UserRole::Player)), 1);
     }
 }